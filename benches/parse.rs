@@ -0,0 +1,113 @@
+//! Benchmarks of parsing responses and serializing output, sized like the large archives parsed
+//! offline.
+//!
+//! The responses are synthesized, unless `WOWNOW_BENCH_FIXTURES` names a directory holding
+//! `summary` and `versions` responses, such as one written by `wownow --bench-fixtures`.
+#![warn(clippy::pedantic)]
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::{hint::black_box, path::PathBuf};
+use wownow::{
+    __bench::{synthetic_summary, synthetic_versions},
+    prelude::*,
+};
+
+/// How many products the synthesized summary lists, more than the real one ever has
+const SUMMARY_PRODUCTS: usize = 5_000;
+
+/// How many regions the synthesized versions response has
+const VERSIONS_REGIONS: usize = 1_000;
+
+/// The response named `name` in the fixtures directory, or `synthesized` if there is none.
+fn fixture(name: &str, synthesize: impl FnOnce() -> String) -> String {
+    match std::env::var_os("WOWNOW_BENCH_FIXTURES") {
+        Some(dir) => {
+            let path = PathBuf::from(dir).join(name);
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Error reading `{}`: {e}", path.display()))
+        }
+        None => synthesize(),
+    }
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    let summary = fixture("summary", || synthetic_summary(SUMMARY_PRODUCTS));
+    group.throughput(Throughput::Bytes(summary.len() as u64));
+    group.bench_function("summary", |b| {
+        b.iter(|| SummaryResponse::try_from(black_box(summary.as_str())).unwrap());
+    });
+
+    let versions = fixture("versions", || synthetic_versions(VERSIONS_REGIONS));
+    group.throughput(Throughput::Bytes(versions.len() as u64));
+    group.bench_function("versions", |b| {
+        b.iter(|| VersionsResponse::try_from(black_box(versions.as_str())).unwrap());
+    });
+    group.bench_function("versions_region_and_name", |b| {
+        b.iter(|| {
+            VersionsResponse::parse_columns(
+                black_box(versions.as_bytes()),
+                VersionsColumns::REGION | VersionsColumns::VERSIONS_NAME,
+            )
+            .unwrap()
+        });
+    });
+
+    group.finish();
+}
+
+/// Hex decoding as the parser first did it, a pair of digits at a time with `from_str_radix`, as
+/// the baseline for [`decode_hex16`].
+fn from_str_radix_hex16(hex: &str) -> Hex16 {
+    let mut bytes = [0; 16];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+    }
+    bytes
+}
+
+fn bench_hex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hex");
+
+    // three keys, as each versions record has
+    let keys = [
+        "47e9e06f8371afb141e22614a912acc8",
+        "74093d42ce367c7a67f2831dbf64088d",
+        "53020d32e1a25648c8e1eafd5771935f",
+    ];
+    group.throughput(Throughput::Bytes(
+        keys.iter().map(|key| key.len() as u64).sum(),
+    ));
+    group.bench_function("from_str_radix", |b| {
+        b.iter(|| keys.map(|key| from_str_radix_hex16(black_box(key))));
+    });
+    group.bench_function("decode_hex16", |b| {
+        b.iter(|| keys.map(|key| decode_hex16(black_box(key)).unwrap()));
+    });
+
+    group.finish();
+}
+
+fn bench_output(c: &mut Criterion) {
+    let mut group = c.benchmark_group("output");
+
+    let versions = fixture("versions", || synthetic_versions(VERSIONS_REGIONS));
+    let versions = VersionsResponse::try_from(versions.as_str()).unwrap();
+    group.bench_function("product", |b| {
+        b.iter(|| Product::try_from(("wow", black_box(&versions))).unwrap());
+    });
+
+    let mut fetch = VersionsFetch::new();
+    fetch.add_product(Product::try_from(("wow", &versions)).unwrap());
+    group.bench_function("json", |b| {
+        b.iter(|| serde_json::to_string(black_box(&fetch)).unwrap());
+    });
+    group.bench_function("json_pretty", |b| {
+        b.iter(|| serde_json::to_string_pretty(black_box(&fetch)).unwrap());
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_hex, bench_output);
+criterion_main!(benches);
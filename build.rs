@@ -0,0 +1,85 @@
+//! Records where the build came from, for the CLI's `--version --verbose`, and compiles the gRPC service
+//! definition, when the `grpc` feature is enabled
+use std::{env, path::Path, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // only the binary prints these
+    if env::var_os("CARGO_FEATURE_CLI").is_some() {
+        provenance();
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        // use a vendored protoc, so building doesn't require one to be installed
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc is available");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/wownow.proto").expect("proto/wownow.proto compiles");
+    }
+}
+
+/// Set the git commit, source date, target and enabled features as environment variables of the
+/// crate's compilation, for the binary to embed.
+fn provenance() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+    let manifest_dir = Path::new(&manifest_dir);
+    // only ask git when the crate is its own checkout. vendored into another repository, git
+    // would answer with that repository's commit, and a packaged crate has no checkout at all.
+    let checkout = manifest_dir.join(".git").exists();
+
+    // a commit or checkout changes these. watching a missing path would rerun the script on
+    // every build.
+    for path in ["HEAD", "refs", "packed-refs"] {
+        let path = manifest_dir.join(".git").join(path);
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(manifest_dir)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|output| output.trim().to_owned())
+            .filter(|output| !output.is_empty())
+    };
+    let commit = checkout
+        .then(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .flatten()
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    // the time the script last ran would only say when the build script was last rerun, so date
+    // the source instead: SOURCE_DATE_EPOCH if set, for reproducible builds, or else the commit's
+    let source_date = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .filter(|epoch| epoch.parse::<u64>().is_ok())
+        .or_else(|| {
+            checkout
+                .then(|| git(&["log", "-1", "--format=%ct"]))
+                .flatten()
+        })
+        .unwrap_or_default();
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            (feature != "DEFAULT").then(|| feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=WOWNOW_GIT_COMMIT={commit}");
+    println!("cargo:rustc-env=WOWNOW_SOURCE_DATE={source_date}");
+    println!(
+        "cargo:rustc-env=WOWNOW_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!("cargo:rustc-env=WOWNOW_FEATURES={}", features.join(","));
+}
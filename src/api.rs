@@ -1,62 +1,433 @@
-/// TCP API calls to the Blizzard TACT server
-use crate::response::{
-    base::Error as ResponseError, summary::Response as SummaryResponse,
-    versions::Response as VersionsResponse,
+//! TCP API calls to the Blizzard TACT server
+//!
+//! Every call is cancellation safe: each opens its own connection, which is closed as soon as the
+//! call's future is dropped (e.g., by a timeout or `tokio::select!`), and a call that is dropped
+//! has no other effect.
+//!
+//! The calls here need the `tcp` feature, on by default. Without it (e.g., on WebAssembly), make
+//! calls through a [`Client`](crate::client::Client) with another transport.
+#[cfg(feature = "client")]
+use crate::{
+    instrument,
+    response::base::{parse_response, Limits, Response as BaseResponse},
 };
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+use crate::{output::Error as OutputError, response::base::Error as ResponseError};
+#[cfg(feature = "tcp")]
+use crate::{
+    response::{
+        base::DEFAULT_MAX_BYTES, summary::Response as SummaryResponse,
+        versions::Response as VersionsResponse,
+    },
+    transport,
 };
+use std::fmt;
 
-const SOCKET_ADDR: (&str, u16) = ("us.version.battle.net", 1119);
+/// The host and port of the version server
+pub(crate) const SOCKET_ADDR: &str = "us.version.battle.net:1119";
 
+/// The protocol a request was made over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// The version server's plain TCP protocol, on port 1119
+    Tcp,
+
+    /// HTTP, to a proxy of the version server
+    Http,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp => f.write_str("TCP"),
+            Self::Http => f.write_str("HTTP"),
+        }
+    }
+}
+
+/// Where a failed request went, so errors from many requests (e.g., of a bulk fetch) can be told
+/// apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestContext {
+    /// The path of the endpoint, such as `v2/products/wow/versions`
+    pub path: String,
+
+    /// The host and port of the server
+    pub host: String,
+
+    /// The protocol of the request
+    pub protocol: Protocol,
+
+    /// Which attempt at the request failed, starting at 1
+    pub attempt: u32,
+}
+
+impl RequestContext {
+    /// The context of a first request for an endpoint to the version server, over TCP
+    #[must_use]
+    pub fn new(endpoint: &Endpoint) -> Self {
+        Self {
+            path: endpoint.path(),
+            host: SOCKET_ADDR.to_owned(),
+            protocol: Protocol::Tcp,
+            attempt: 1,
+        }
+    }
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` from {} over {} (attempt {})",
+            self.path, self.host, self.protocol, self.attempt
+        )
+    }
+}
+
+/// Errors that can occur when making an API call.
+///
+/// New variants may be added, so matches must have a wildcard arm. Each variant has a stable
+/// code, from [`Error::code`], for structured output.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
-    #[error(transparent)]
-    Io(#[from] std::io::Error),
+    /// An I/O error occurred while communicating with the server
+    #[error("error requesting {context}: {source}")]
+    Io {
+        /// The request that failed
+        context: RequestContext,
 
+        /// The I/O error
+        source: std::io::Error,
+    },
+
+    /// The server's response was not valid UTF-8
     #[error(transparent)]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    /// The server's response could not be parsed
+    #[error("error parsing response of {context}: {source}")]
+    Response {
+        /// The request whose response didn't parse
+        context: RequestContext,
+
+        /// The parse error
+        source: ResponseError,
+    },
+
+    /// A response isn't cached, and the cache may not contact the server
+    #[error("`{0}` is not cached, so is unavailable offline")]
+    NotCached(String),
+
+    /// The summary changed during every attempt to fetch versions consistent with it
+    #[error("summary changed during each of {0} attempts to fetch consistent versions")]
+    Inconsistent(u32),
+
+    /// The call didn't finish before its deadline
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+
+    /// A response parsed, but couldn't be made into output (e.g., a malformed versions name)
     #[error(transparent)]
-    Response(#[from] ResponseError),
+    Output(#[from] OutputError),
+
+    /// The server has no such product, so answered a request for it with an empty response
+    #[error("unknown product `{0}`")]
+    UnknownProduct(String),
+
+    /// The server answered with an older seqn than it had before, as a stale mirror would
+    #[error("seqn of `{path}` went back from {previous} to {seqn}")]
+    SeqnRegression {
+        /// The endpoint's path
+        path: String,
+
+        /// The seqn seen before
+        previous: u32,
+
+        /// The older seqn just received
+        seqn: u32,
+    },
 }
 
+impl Error {
+    /// A stable code identifying the kind of error, which won't change between versions:
+    ///
+    /// | Variant             | Code                |
+    /// |---------------------|---------------------|
+    /// | `Io`                | `io`                |
+    /// | `Utf8`              | `utf8`              |
+    /// | `Response`          | `response`          |
+    /// | `NotCached`         | `not_cached`        |
+    /// | `Inconsistent`      | `inconsistent`      |
+    /// | `DeadlineExceeded`  | `deadline_exceeded` |
+    /// | `Output`            | `output`            |
+    /// | `UnknownProduct`    | `unknown_product`   |
+    /// | `SeqnRegression`    | `seqn_regression`   |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io { .. } => "io",
+            Self::Utf8(_) => "utf8",
+            Self::Response { .. } => "response",
+            Self::NotCached(_) => "not_cached",
+            Self::Inconsistent(_) => "inconsistent",
+            Self::DeadlineExceeded => "deadline_exceeded",
+            Self::Output(_) => "output",
+            Self::UnknownProduct(_) => "unknown_product",
+            Self::SeqnRegression { .. } => "seqn_regression",
+        }
+    }
+
+    /// The request that failed, for errors of a request
+    #[must_use]
+    pub fn context(&self) -> Option<&RequestContext> {
+        match self {
+            Self::Io { context, .. } | Self::Response { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Record that the error happened on a later attempt at the request.
+    #[cfg(feature = "client")]
+    #[must_use]
+    pub(crate) fn with_attempt(mut self, attempt: u32) -> Self {
+        if let Self::Io { context, .. } | Self::Response { context, .. } = &mut self {
+            context.attempt = attempt;
+        }
+        self
+    }
+
+    /// An error shared by every call that waited on the same request, for one of them to return:
+    /// the error itself if it's the last holder, or else a copy. An I/O error keeps its kind and
+    /// message, but not its source.
+    #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+    pub(crate) fn from_shared(error: std::sync::Arc<Self>) -> Self {
+        std::sync::Arc::try_unwrap(error).unwrap_or_else(|error| match &*error {
+            Self::Io { context, source } => Self::Io {
+                context: context.clone(),
+                source: std::io::Error::new(source.kind(), source.to_string()),
+            },
+            Self::Utf8(source) => Self::Utf8(source.clone()),
+            Self::Response { context, source } => Self::Response {
+                context: context.clone(),
+                source: source.clone(),
+            },
+            Self::NotCached(path) => Self::NotCached(path.clone()),
+            Self::Inconsistent(attempts) => Self::Inconsistent(*attempts),
+            Self::DeadlineExceeded => Self::DeadlineExceeded,
+            Self::Output(source) => Self::Output(source.clone()),
+            Self::UnknownProduct(product) => Self::UnknownProduct(product.clone()),
+            Self::SeqnRegression {
+                path,
+                previous,
+                seqn,
+            } => Self::SeqnRegression {
+                path: path.clone(),
+                previous: *previous,
+                seqn: *seqn,
+            },
+        })
+    }
+}
+
+/// A result type for API calls
 pub type Result<T> = std::result::Result<T, Error>;
 
-enum Request {
+/// An endpoint of the version server
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// The summary of all products, at `v2/summary`
     Summary,
+
+    /// The versions of a product, at `v2/products/{product}/versions`
     Versions(String),
+
+    /// The CDNs serving a product, at `v2/products/{product}/cdns`
+    Cdns(String),
+
+    /// The versions of a product for background download (i.e., pre-loading of upcoming patches),
+    /// at `v2/products/{product}/bgdl`
+    Bgdl(String),
 }
 
-impl Request {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = match self {
-            Self::Summary => b"v2/summary".to_vec(),
-            Self::Versions(product) => format!("v2/products/{}/versions", product).into_bytes(),
-        };
-        bytes.extend(b"\r\n");
-        bytes
+impl Endpoint {
+    /// The path of this endpoint, as sent to the server
+    #[must_use]
+    pub fn path(&self) -> String {
+        match self {
+            Self::Summary => "v2/summary".to_owned(),
+            Self::Versions(product) => format!("v2/products/{product}/versions"),
+            Self::Cdns(product) => format!("v2/products/{product}/cdns"),
+            Self::Bgdl(product) => format!("v2/products/{product}/bgdl"),
+        }
+    }
+
+    /// The product of this endpoint, unless it's the summary
+    #[must_use]
+    pub fn product(&self) -> Option<&str> {
+        match self {
+            Self::Summary => None,
+            Self::Versions(product) | Self::Cdns(product) | Self::Bgdl(product) => Some(product),
+        }
     }
-}
 
-async fn tcp_send_and_recv(request: Request) -> Result<Vec<u8>> {
-    let mut stream = TcpStream::connect(SOCKET_ADDR).await?;
+    /// The endpoint with the given path, if it's one wownow knows of. The inverse of
+    /// [`Endpoint::path`].
+    #[must_use]
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path == "v2/summary" {
+            return Some(Self::Summary);
+        }
+        let (product, endpoint) = path.strip_prefix("v2/products/")?.split_once('/')?;
+        let product = product.to_owned();
+        match endpoint {
+            "versions" => Some(Self::Versions(product)),
+            "cdns" => Some(Self::Cdns(product)),
+            "bgdl" => Some(Self::Bgdl(product)),
+            _ => None,
+        }
+    }
+}
 
-    stream.write_all(&request.to_bytes()).await?;
+/// Check that a response isn't the empty one the server answers requests for unknown products
+/// with, which would otherwise fail to parse for lack of a header line.
+///
+/// # Errors
+///
+/// Returns `Error::UnknownProduct` if the endpoint is of a product and the response is empty.
+pub fn check_known_product(endpoint: &Endpoint, raw: &[u8]) -> Result<()> {
+    match endpoint.product() {
+        Some(product) if raw.trim_ascii().is_empty() => {
+            Err(Error::UnknownProduct(product.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
 
-    let mut buffer = Vec::new();
-    stream.read_to_end(&mut buffer).await?;
+/// Parse an endpoint's response within the limits, replacing invalid UTF-8 if lossy, with the
+/// request's context in any error.
+#[cfg(feature = "client")]
+pub(crate) fn parse<T>(
+    endpoint: &Endpoint,
+    raw: &[u8],
+    limits: Limits,
+    lossy: bool,
+    context: impl FnOnce() -> RequestContext,
+) -> Result<T>
+where
+    T: for<'a> TryFrom<BaseResponse<'a>, Error = ResponseError>,
+{
+    check_known_product(endpoint, raw)?;
+    let parsed = parse_response(raw, limits, lossy).map(|(response, replaced)| {
+        if replaced.is_some() {
+            instrument::record_lossy_parse(endpoint);
+        }
+        response
+    });
+    instrument::record_parse(endpoint, parsed).map_err(|source| Error::Response {
+        context: context(),
+        source,
+    })
+}
 
-    Ok(buffer)
+/// Get the raw, unparsed response of an endpoint.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+#[cfg(feature = "tcp")]
+pub async fn get_raw(endpoint: &Endpoint) -> Result<Vec<u8>> {
+    transport::tcp_send_and_recv(SOCKET_ADDR, endpoint, Vec::new(), DEFAULT_MAX_BYTES).await
 }
 
+/// Get the summary of all products.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the response cannot be parsed.
+#[cfg(feature = "tcp")]
 pub async fn get_summary() -> Result<SummaryResponse> {
-    let response = tcp_send_and_recv(Request::Summary).await?;
-    Ok(response.as_slice().try_into()?)
+    let response = transport::tcp_send_and_recv(
+        SOCKET_ADDR,
+        &Endpoint::Summary,
+        Vec::new(),
+        DEFAULT_MAX_BYTES,
+    )
+    .await?;
+    parse(
+        &Endpoint::Summary,
+        &response,
+        Limits::default(),
+        false,
+        || RequestContext::new(&Endpoint::Summary),
+    )
 }
 
+/// Get the versions of a product.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the response cannot be parsed.
+#[cfg(feature = "tcp")]
 pub async fn get_versions(product: &str) -> Result<VersionsResponse> {
-    let response = tcp_send_and_recv(Request::Versions(product.to_owned())).await?;
-    Ok(response.as_slice().try_into()?)
+    let endpoint = Endpoint::Versions(product.to_owned());
+    let response =
+        transport::tcp_send_and_recv(SOCKET_ADDR, &endpoint, Vec::new(), DEFAULT_MAX_BYTES).await?;
+    parse(&endpoint, &response, Limits::default(), false, || {
+        RequestContext::new(&endpoint)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_path() {
+        for endpoint in [
+            Endpoint::Summary,
+            Endpoint::Versions("wow".to_owned()),
+            Endpoint::Cdns("wow".to_owned()),
+            Endpoint::Bgdl("wow".to_owned()),
+        ] {
+            assert_eq!(Endpoint::from_path(&endpoint.path()), Some(endpoint));
+        }
+        assert_eq!(Endpoint::from_path("v2/products/wow/blobs"), None);
+        assert_eq!(Endpoint::from_path("v2/products/wow"), None);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_error_context() {
+        let error = Error::Io {
+            context: RequestContext::new(&Endpoint::Versions("wow".to_owned())),
+            source: std::io::ErrorKind::ConnectionRefused.into(),
+        }
+        .with_attempt(3);
+        assert_eq!(error.context().unwrap().attempt, 3);
+        assert_eq!(error.code(), "io");
+        assert_eq!(
+            error.to_string(),
+            "error requesting `v2/products/wow/versions` from us.version.battle.net:1119 over TCP \
+            (attempt 3): connection refused"
+        );
+        assert!(Error::DeadlineExceeded.context().is_none());
+    }
+
+    #[test]
+    fn test_check_known_product() {
+        let endpoint = Endpoint::Versions("wow_nope".to_owned());
+        assert!(matches!(
+            check_known_product(&endpoint, b""),
+            Err(Error::UnknownProduct(product)) if product == "wow_nope"
+        ));
+        assert!(matches!(
+            check_known_product(&endpoint, b"\r\n"),
+            Err(Error::UnknownProduct(_))
+        ));
+        assert!(check_known_product(&endpoint, b"Region!STRING:0\n## seqn = 1").is_ok());
+        assert!(check_known_product(&Endpoint::Summary, b"").is_ok());
+        assert_eq!(endpoint.product(), Some("wow_nope"));
+        assert_eq!(Endpoint::Summary.product(), None);
+    }
 }
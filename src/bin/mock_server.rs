@@ -0,0 +1,114 @@
+//! A stand-in version server for testing offline, answering the TCP protocol with the responses
+//! of a fixtures directory laid out as `wownow snapshot` writes it, or else with synthetic ones
+#![warn(clippy::pedantic)]
+#![warn(missing_docs)]
+
+use std::{path::PathBuf, process::ExitCode};
+use wownow::{
+    __bench::{synthetic_summary, synthetic_versions},
+    prelude::*,
+};
+
+/// The usage, printed for `--help` and bad arguments
+const USAGE: &str = "\
+Usage: wownow-mock-server [--listen ADDR] [FIXTURES]
+
+Answers version server requests with the files under FIXTURES, by endpoint path
+(FIXTURES/v2/summary, FIXTURES/v2/products/wow/versions, ...), or without it, with
+synthetic responses for products product_0 to product_2 in 5 regions.
+
+Options:
+  --listen ADDR  The address to listen at [default: 127.0.0.1:1119]
+  -h, --help     Print this help";
+
+/// The address listened at by default, the version server's port on localhost
+const DEFAULT_LISTEN: &str = "127.0.0.1:1119";
+
+/// The products answered for without fixtures
+const SYNTHETIC_PRODUCTS: usize = 3;
+
+/// The regions of each product answered for without fixtures
+const SYNTHETIC_REGIONS: usize = 5;
+
+/// The parsed arguments
+struct Args {
+    listen: String,
+    fixtures: Option<PathBuf>,
+}
+
+/// Parse the arguments, if not asked for help, or return the message to exit with.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args {
+        listen: DEFAULT_LISTEN.to_owned(),
+        fixtures: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--listen" => {
+                parsed.listen = args
+                    .next()
+                    .ok_or_else(|| format!("Error: --listen needs an address\n\n{USAGE}"))?;
+            }
+            _ if arg.starts_with('-') || parsed.fixtures.is_some() => {
+                return Err(format!("Error: unexpected argument `{arg}`\n\n{USAGE}"));
+            }
+            _ => parsed.fixtures = Some(arg.into()),
+        }
+    }
+    Ok(Some(parsed))
+}
+
+/// A server answering with synthetic responses
+fn synthetic() -> MockServer {
+    (0..SYNTHETIC_PRODUCTS).fold(
+        MockServer::new().with_response(&Endpoint::Summary, synthetic_summary(SYNTHETIC_PRODUCTS)),
+        |server, index| {
+            server.with_response(
+                &Endpoint::Versions(format!("product_{index}")),
+                synthetic_versions(SYNTHETIC_REGIONS),
+            )
+        },
+    )
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let server = match &args.fixtures {
+        Some(dir) => match MockServer::from_dir(dir) {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("Error: could not read fixtures `{}`: {e}", dir.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => synthetic(),
+    };
+    let paths = server.paths().join("\n  ");
+    let server = match server.listen(&args.listen).await {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Error: could not listen at `{}`: {e}", args.listen);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("Listening at {}, answering:\n  {paths}", server.addr());
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: could not wait for Ctrl-C: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Errors, and the key-value format of config files on the CDN
+use crate::response::base::Hex16;
+use std::fmt::Write;
+
+/// Errors that can occur when getting files from the CDN.
+///
+/// New variants may be added, so matches must have a wildcard arm. Each variant has a stable
+/// code, from [`Error::code`], for structured output.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The CDN has no hosts to download from
+    #[error("the CDN lists no hosts")]
+    NoHosts,
+
+    /// Downloading a file failed on every host. Holds the last host's error.
+    #[error("error downloading {url}: {source}")]
+    Download {
+        /// The URL of the file on the last host tried
+        url: String,
+
+        /// The HTTP error
+        source: reqwest::Error,
+    },
+
+    /// A file's MD5 didn't match the key it was downloaded by, so it was corrupted
+    #[error("{url} has an MD5 of {md5}, not the one its name gives")]
+    ChecksumMismatch {
+        /// The URL of the file on the last host tried
+        url: String,
+
+        /// The MD5 of the file, in hex
+        md5: String,
+    },
+
+    /// A config file was not valid UTF-8
+    #[error(transparent)]
+    Utf8(#[from] std::string::FromUtf8Error),
+
+    /// A line of a config file was neither a comment nor `key = value`
+    #[error("malformed line {0} of config: `{1}`")]
+    MalformedLine(usize, String),
+
+    /// A config file lacks a key it must have
+    #[error("config has no `{0}`")]
+    MissingKey(&'static str),
+
+    /// A value in a config file isn't a 16-byte hex key
+    #[error("`{0}` is not a 16-byte hex key")]
+    InvalidKey(String),
+}
+
+impl Error {
+    /// A stable code identifying the kind of error, which won't change between versions:
+    ///
+    /// | Variant            | Code                |
+    /// |--------------------|---------------------|
+    /// | `NoHosts`          | `no_hosts`          |
+    /// | `Download`         | `download`          |
+    /// | `ChecksumMismatch` | `checksum_mismatch` |
+    /// | `Utf8`             | `utf8`              |
+    /// | `MalformedLine`    | `malformed_line`    |
+    /// | `MissingKey`       | `missing_key`       |
+    /// | `InvalidKey`       | `invalid_key`       |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoHosts => "no_hosts",
+            Self::Download { .. } => "download",
+            Self::ChecksumMismatch { .. } => "checksum_mismatch",
+            Self::Utf8(_) => "utf8",
+            Self::MalformedLine(..) => "malformed_line",
+            Self::MissingKey(_) => "missing_key",
+            Self::InvalidKey(_) => "invalid_key",
+        }
+    }
+}
+
+/// A result type for getting files from the CDN
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Format a key as lowercase hex, as it appears in responses and URLs.
+pub(crate) fn to_hex(key: &Hex16) -> String {
+    let mut hex = String::with_capacity(key.len() * 2);
+    for byte in key {
+        // writing to a String can't fail
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+/// A config file: lines of `key = value`, where a value is a list separated by spaces, and `#`
+/// comments.
+#[derive(Debug)]
+pub(crate) struct ConfigFile<'input> {
+    entries: Vec<(&'input str, Vec<&'input str>)>,
+}
+
+impl<'input> ConfigFile<'input> {
+    /// The values of a key, if it's in the file
+    pub(crate) fn get(&self, key: &str) -> Option<&[&'input str]> {
+        self.entries
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    /// The values of a key that must be in the file
+    pub(crate) fn require(&self, key: &'static str) -> Result<&[&'input str]> {
+        self.get(key).ok_or(Error::MissingKey(key))
+    }
+
+    /// The single value of a key, if it's in the file and not empty, such as a name
+    pub(crate) fn value(&self, key: &str) -> Option<&'input str> {
+        self.get(key)?.first().copied()
+    }
+}
+
+impl<'input> TryFrom<&'input str> for ConfigFile<'input> {
+    type Error = Error;
+
+    fn try_from(input: &'input str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (index, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, values)) = line.split_once('=') else {
+                return Err(Error::MalformedLine(index + 1, line.to_owned()));
+            };
+            entries.push((key.trim(), values.split_whitespace().collect()));
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Check that a value is a 16-byte hex key, as keys in config files are.
+pub(crate) fn parse_key(value: &str) -> Result<String> {
+    if value.len() == 32 && value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        Ok(value.to_ascii_lowercase())
+    } else {
+        Err(Error::InvalidKey(value.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file() {
+        let config = ConfigFile::try_from(
+            "# Build Configuration\n\
+            \n\
+            root = 2c9e8b4e0f2b6b3a4e0b4f3d5a6c7e8f\n\
+            install-size = 23017 22484\n\
+            build-partial-priority = \n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.value("root"),
+            Some("2c9e8b4e0f2b6b3a4e0b4f3d5a6c7e8f")
+        );
+        assert_eq!(config.get("install-size"), Some(&["23017", "22484"][..]));
+        assert_eq!(config.value("build-partial-priority"), None);
+        assert!(matches!(
+            config.require("encoding"),
+            Err(Error::MissingKey("encoding"))
+        ));
+
+        assert!(matches!(
+            ConfigFile::try_from("# Build Configuration\nroot"),
+            Err(Error::MalformedLine(2, line)) if line == "root"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+//! Model for the build config, which names the files making up a build
+use crate::cdn::base::{parse_key, ConfigFile, Error, Result};
+use serde::Serialize;
+
+/// The keys of one of a build's files: its content key, and on newer builds, the encoding key
+/// under which it's stored on the CDN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileKeys {
+    /// The content key, the MD5 of the file's contents
+    pub content_key: String,
+
+    /// The encoding key, the MD5 of the file as stored, if the config gives it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_key: Option<String>,
+}
+
+impl FileKeys {
+    /// The keys of a file from the values of its key in the config
+    fn from_values(key: &'static str, values: &[&str]) -> Result<Self> {
+        let Some(content_key) = values.first() else {
+            return Err(Error::MissingKey(key));
+        };
+        Ok(Self {
+            content_key: parse_key(content_key)?,
+            encoding_key: values.get(1).map(|value| parse_key(value)).transpose()?,
+        })
+    }
+}
+
+/// The build config of a build, referenced by `build_config` in the versions response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildConfig {
+    /// The content key of the root file, which maps file paths and IDs to content keys
+    pub root: String,
+
+    /// The encoding file, which maps content keys to encoding keys
+    pub encoding: FileKeys,
+
+    /// The install manifest, of the files installed before the game is first launched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install: Option<FileKeys>,
+
+    /// The download manifest, of every file in the order they should be downloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<FileKeys>,
+
+    /// The size file, of the sizes of files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<FileKeys>,
+
+    /// The name of the build, such as `WOW-53584patch10.2.5_Retail`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_name: Option<String>,
+}
+
+impl TryFrom<&str> for BuildConfig {
+    type Error = Error;
+
+    fn try_from(input: &str) -> Result<Self> {
+        let config = ConfigFile::try_from(input)?;
+        let optional = |key: &'static str| {
+            config
+                .get(key)
+                .filter(|values| !values.is_empty())
+                .map(|values| FileKeys::from_values(key, values))
+                .transpose()
+        };
+
+        Ok(Self {
+            root: parse_key(
+                config
+                    .require("root")?
+                    .first()
+                    .ok_or(Error::MissingKey("root"))?,
+            )?,
+            encoding: FileKeys::from_values("encoding", config.require("encoding")?)?,
+            install: optional("install")?,
+            download: optional("download")?,
+            size: optional("size")?,
+            build_name: config.value("build-name").map(ToOwned::to_owned),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_config() {
+        let input = "# Build Configuration\n\
+            \n\
+            root = 2c9e8b4e0f2b6b3a4e0b4f3d5a6c7e8f\n\
+            install = 0f1e2d3c4b5a69788796a5b4c3d2e1f0 A1B2C3D4E5F60718293A4B5C6D7E8F90\n\
+            install-size = 23017 22484\n\
+            download = 1f1e2d3c4b5a69788796a5b4c3d2e1f0 b1b2c3d4e5f60718293a4b5c6d7e8f90\n\
+            encoding = 3f1e2d3c4b5a69788796a5b4c3d2e1f0 d1b2c3d4e5f60718293a4b5c6d7e8f90\n\
+            encoding-size = 54326798 54311240\n\
+            build-name = WOW-53584patch10.2.5_Retail\n\
+            build-uid = wow\n";
+
+        assert_eq!(
+            BuildConfig::try_from(input).unwrap(),
+            BuildConfig {
+                root: "2c9e8b4e0f2b6b3a4e0b4f3d5a6c7e8f".to_owned(),
+                encoding: FileKeys {
+                    content_key: "3f1e2d3c4b5a69788796a5b4c3d2e1f0".to_owned(),
+                    encoding_key: Some("d1b2c3d4e5f60718293a4b5c6d7e8f90".to_owned()),
+                },
+                install: Some(FileKeys {
+                    content_key: "0f1e2d3c4b5a69788796a5b4c3d2e1f0".to_owned(),
+                    encoding_key: Some("a1b2c3d4e5f60718293a4b5c6d7e8f90".to_owned()),
+                }),
+                download: Some(FileKeys {
+                    content_key: "1f1e2d3c4b5a69788796a5b4c3d2e1f0".to_owned(),
+                    encoding_key: Some("b1b2c3d4e5f60718293a4b5c6d7e8f90".to_owned()),
+                }),
+                size: None,
+                build_name: Some("WOW-53584patch10.2.5_Retail".to_owned()),
+            }
+        );
+
+        assert!(matches!(
+            BuildConfig::try_from("root = 2c9e8b4e0f2b6b3a4e0b4f3d5a6c7e8f"),
+            Err(Error::MissingKey("encoding"))
+        ));
+        assert!(matches!(
+            BuildConfig::try_from("root = 2c9e\nencoding = 3f1e2d3c4b5a69788796a5b4c3d2e1f0"),
+            Err(Error::InvalidKey(key)) if key == "2c9e"
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+//! Model for the CDN config, which lists the archives holding a build's files
+use crate::cdn::base::{parse_key, ConfigFile, Error, Result};
+use serde::Serialize;
+
+/// The CDN config of a build, referenced by `cdn_config` in the versions response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CdnConfig {
+    /// The keys of the archives, which bundle the files of builds on the CDN, in order
+    pub archives: Vec<String>,
+
+    /// The key of the archive group, an index combining those of all the archives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_group: Option<String>,
+
+    /// The key of the file index, of the files stored outside any archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_index: Option<String>,
+}
+
+impl TryFrom<&str> for CdnConfig {
+    type Error = Error;
+
+    fn try_from(input: &str) -> Result<Self> {
+        let config = ConfigFile::try_from(input)?;
+        let optional = |key| config.value(key).map(parse_key).transpose();
+
+        Ok(Self {
+            archives: config
+                .require("archives")?
+                .iter()
+                .map(|key| parse_key(key))
+                .collect::<Result<_>>()?,
+            archive_group: optional("archive-group")?,
+            file_index: optional("file-index")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cdn_config() {
+        let input = "# CDN Configuration\n\
+            \n\
+            archives = 0017a402f556fbece46c38dc431a2c9b 003b147730a109e3a480d32a54280955\n\
+            archives-index-size = 36124 145184\n\
+            archive-group = 69b5bd5f4a5a9a51c1f4cba5ae6aa82b\n\
+            patch-archives = 0021b6ccf3e8c1fc4ba2a1dde8c82cb1\n\
+            file-index = 2d4a8b5fb4d7d1f0a4d4c7b0e5c8e4d6\n\
+            file-index-size = 9276\n";
+
+        assert_eq!(
+            CdnConfig::try_from(input).unwrap(),
+            CdnConfig {
+                archives: vec![
+                    "0017a402f556fbece46c38dc431a2c9b".to_owned(),
+                    "003b147730a109e3a480d32a54280955".to_owned()
+                ],
+                archive_group: Some("69b5bd5f4a5a9a51c1f4cba5ae6aa82b".to_owned()),
+                file_index: Some("2d4a8b5fb4d7d1f0a4d4c7b0e5c8e4d6".to_owned()),
+            }
+        );
+        assert!(matches!(
+            CdnConfig::try_from("file-index = 2d4a8b5fb4d7d1f0a4d4c7b0e5c8e4d6"),
+            Err(Error::MissingKey("archives"))
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+//! Downloads of files from the CDN hosts of a product
+use crate::{
+    cdn::{
+        base::{to_hex, Error, Result},
+        build_config::BuildConfig,
+        cdn_config::CdnConfig,
+    },
+    response::{base::Hex16, cdns::Record as CdnsRecord},
+};
+use md5::{Digest, Md5};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// How long to wait to connect to a host before trying the next
+#[cfg(not(target_arch = "wasm32"))]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a host to answer a request, once connected, before trying the next
+#[cfg(not(target_arch = "wasm32"))]
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The CDN of a product in a region: the hosts serving the files of its builds, and the path of
+/// the product on them
+#[derive(Debug, Clone)]
+pub struct Cdn {
+    http: reqwest::Client,
+    hosts: Vec<String>,
+    path: String,
+}
+
+impl Cdn {
+    /// The CDN of a region's record in the cdns response. Hosts that don't connect in 10 seconds,
+    /// or answer in 30, are given up on for the next.
+    ///
+    /// # Panics
+    ///
+    /// Panics if TLS can't be initialized, as `reqwest::Client::new` does.
+    #[must_use]
+    pub fn new(record: &CdnsRecord) -> Self {
+        let http = reqwest::Client::builder();
+        // so a host that stalls is given up on for the next, rather than waited on forever
+        #[cfg(not(target_arch = "wasm32"))]
+        let http = http.connect_timeout(CONNECT_TIMEOUT).timeout(TIMEOUT);
+        Self {
+            http: http.build().expect("HTTP client builds"),
+            hosts: record.hosts.clone(),
+            path: record.path.clone(),
+        }
+    }
+
+    /// The hosts, in the order they're tried
+    #[must_use]
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// The URL of a config file on a host, such as
+    /// `http://level3.blizzard.com/tpr/wow/config/47/e9/47e9e06f8371afb141e22614a912acc8`
+    #[must_use]
+    pub fn config_url(&self, host: &str, key: &Hex16) -> String {
+        let key = to_hex(key);
+        format!(
+            "http://{host}/{}/config/{}/{}/{key}",
+            self.path,
+            &key[..2],
+            &key[2..4]
+        )
+    }
+
+    /// Download a config file, trying each host in turn until one has it. Files are named by the
+    /// MD5 of their contents, so one that doesn't match its key is corrupt, and the next host is
+    /// tried.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no host has the file intact, or it isn't UTF-8.
+    pub async fn get_config(&self, key: &Hex16) -> Result<String> {
+        let mut error = Error::NoHosts;
+        for host in &self.hosts {
+            let url = self.config_url(host, key);
+            match self.download(&url).await {
+                Ok(body) if Md5::digest(&body)[..] == key[..] => {
+                    return Ok(String::from_utf8(body)?)
+                }
+                Ok(body) => {
+                    error = Error::ChecksumMismatch {
+                        url,
+                        md5: to_hex(&Md5::digest(&body).into()),
+                    };
+                }
+                Err(source) => error = Error::Download { url, source },
+            }
+        }
+        Err(error)
+    }
+
+    /// Download and parse a build config, such as that of `build_config` in the versions
+    /// response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no host has the build config, or it cannot be parsed.
+    pub async fn get_build_config(&self, key: &Hex16) -> Result<BuildConfig> {
+        BuildConfig::try_from(self.get_config(key).await?.as_str())
+    }
+
+    /// Download and parse a CDN config, such as that of `cdn_config` in the versions response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no host has the CDN config, or it cannot be parsed.
+    pub async fn get_cdn_config(&self, key: &Hex16) -> Result<CdnConfig> {
+        CdnConfig::try_from(self.get_config(key).await?.as_str())
+    }
+
+    /// Whether a host has a config file, from a `HEAD` request for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, or the host answers with an error other than
+    /// `404 Not Found`.
+    pub async fn has_config(&self, host: &str, key: &Hex16) -> Result<bool> {
+        let url = self.config_url(host, key);
+        match self.http.head(&url).send().await {
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => Ok(false),
+            Ok(response) => match response.error_for_status() {
+                Ok(_) => Ok(true),
+                Err(source) => Err(Error::Download { url, source }),
+            },
+            Err(source) => Err(Error::Download { url, source }),
+        }
+    }
+
+    /// The first `length` bytes of a config file from a host, with a range request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host doesn't have the file.
+    pub async fn get_config_range(&self, host: &str, key: &Hex16, length: u64) -> Result<Vec<u8>> {
+        let url = self.config_url(host, key);
+        let range = async {
+            let response = self
+                .http
+                .get(&url)
+                .header(
+                    reqwest::header::RANGE,
+                    format!("bytes=0-{}", length.saturating_sub(1)),
+                )
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(response.bytes().await?.to_vec())
+        };
+        range
+            .await
+            .map_err(|source| Error::Download { url, source })
+    }
+
+    async fn download(&self, url: &str) -> reqwest::Result<Vec<u8>> {
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_url() {
+        let cdn = Cdn::new(&CdnsRecord {
+            name: "us".to_owned(),
+            path: "tpr/wow".to_owned(),
+            hosts: vec!["level3.blizzard.com".to_owned()],
+            servers: Vec::new(),
+            config_path: "tpr/configs/data".to_owned(),
+        });
+        assert_eq!(
+            cdn.config_url(
+                "level3.blizzard.com",
+                &[
+                    0x47, 0xe9, 0xe0, 0x6f, 0x83, 0x71, 0xaf, 0xb1, 0x41, 0xe2, 0x26, 0x14, 0xa9,
+                    0x12, 0xac, 0xc8
+                ]
+            ),
+            "http://level3.blizzard.com/tpr/wow/config/47/e9/47e9e06f8371afb141e22614a912acc8"
+        );
+    }
+
+    /// Serve `body` to every request on a local port, returning the host to reach it at
+    async fn serve(body: &'static [u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(body).await;
+            }
+        });
+        host
+    }
+
+    #[tokio::test]
+    async fn test_get_config_checksum() {
+        let body = b"# Build Configuration\n";
+        let key: Hex16 = Md5::digest(body).into();
+        let cdn = |hosts| {
+            Cdn::new(&CdnsRecord {
+                name: "us".to_owned(),
+                path: "tpr/wow".to_owned(),
+                hosts,
+                servers: Vec::new(),
+                config_path: "tpr/configs/data".to_owned(),
+            })
+        };
+
+        let corrupt = serve(b"# Build Configurati\n").await;
+        let error = cdn(vec![corrupt.clone()])
+            .get_config(&key)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "checksum_mismatch");
+
+        // a corrupt file on one host is fetched from the next
+        let intact = serve(body).await;
+        assert_eq!(
+            cdn(vec![corrupt, intact]).get_config(&key).await.unwrap(),
+            "# Build Configuration\n"
+        );
+    }
+}
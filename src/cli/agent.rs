@@ -0,0 +1,24 @@
+//! The versions of the Battle.net app's own products, for `--agent`
+use crate::cli::replay;
+use std::time::Duration;
+use wownow::prelude::*;
+
+/// Get the versions of the Battle.net app's products, giving up on each request after the
+/// timeout if there is one, and output them with all their columns, by product.
+pub(crate) async fn run(timeout: Option<Duration>, pretty_print: bool) -> crate::Result {
+    let client = match timeout {
+        Some(timeout) => replay::client().with_timeout(timeout),
+        None => replay::client(),
+    };
+    let versions = client
+        .get_agent_versions()
+        .await
+        .map_err(|e| format!("Error getting the Battle.net app's versions: {e}"))?;
+    if versions.is_empty() {
+        return Err(format!(
+            "Error: the server knows none of the Battle.net app's products ({})",
+            AGENT_PRODUCTS.join(", ")
+        ));
+    }
+    crate::to_json(&versions, pretty_print)
+}
@@ -0,0 +1,197 @@
+//! Alerts on conditions of products that persist across the daemon's polls, such as regions on
+//! different builds for longer than a rollout takes, so the usual minutes of divergence while a
+//! build rolls out don't raise one
+use crate::cli::config::Alert;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use wownow::prelude::*;
+
+/// A condition of a product to alert on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Condition {
+    /// Some regions are on an older build than the newest
+    Divergent,
+
+    /// Some region's background download is a newer build than its live version
+    BgdlAhead,
+}
+
+impl Condition {
+    /// The name of the condition, as written in the config file and stored in the history
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Divergent => "divergent",
+            Self::BgdlAhead => "bgdl_ahead",
+        }
+    }
+
+    /// The condition of a name, if it's one
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "divergent" => Some(Self::Divergent),
+            "bgdl_ahead" => Some(Self::BgdlAhead),
+            _ => None,
+        }
+    }
+
+    /// The regions of a product the condition holds in, sorted, so it holds of the product if
+    /// there are any. `None` if it can't be told, as for [`Condition::BgdlAhead`] of a product
+    /// without its background download set.
+    pub(crate) fn regions(self, product: &Product) -> Option<Vec<String>> {
+        let mut regions: Vec<String> = match self {
+            Self::Divergent => product
+                .regions_behind()
+                .map(|version| version.region().to_owned())
+                .collect(),
+            Self::BgdlAhead => {
+                product.bgdl()?;
+                product
+                    .prepatch_regions()
+                    .map(|(live, _)| live.region().to_owned())
+                    .collect()
+            }
+        };
+        regions.sort();
+        Some(regions)
+    }
+}
+
+/// How long a condition has held of a product, over consecutive checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConditionState {
+    /// When the condition was first found to hold
+    pub(crate) since: DateTime<Utc>,
+
+    /// The consecutive checks it's held in, including the first
+    pub(crate) checks: u32,
+
+    /// When it was last found to hold
+    pub(crate) last_checked: DateTime<Utc>,
+}
+
+impl ConditionState {
+    /// The state after another check at `now`, which the condition held in or not. `None` if it
+    /// doesn't hold.
+    pub(crate) fn advance(previous: Option<Self>, holds: bool, now: DateTime<Utc>) -> Option<Self> {
+        if !holds {
+            return None;
+        }
+        Some(match previous {
+            Some(previous) => Self {
+                since: previous.since,
+                checks: previous.checks.saturating_add(1),
+                last_checked: now,
+            },
+            None => Self {
+                since: now,
+                checks: 1,
+                last_checked: now,
+            },
+        })
+    }
+}
+
+/// A change in whether an alert's condition has persisted long enough to alert on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Transition {
+    /// The condition has now held for as long as the alert allows
+    Fired,
+
+    /// The condition no longer holds, after the alert fired
+    Resolved,
+}
+
+impl Alert {
+    /// Whether the alert is for a product
+    pub(crate) fn applies_to(&self, product: &str) -> bool {
+        self.products
+            .as_ref()
+            .is_none_or(|products| products.iter().any(|name| name == product))
+    }
+
+    /// Whether a state of the alert's condition has held for as long and as many checks as the
+    /// alert allows
+    fn is_met(&self, state: Option<&ConditionState>) -> bool {
+        state.is_some_and(|state| {
+            let held = (state.last_checked - state.since)
+                .to_std()
+                .unwrap_or_default();
+            state.checks >= self.checks && held >= self.persists.unwrap_or_default()
+        })
+    }
+
+    /// Whether the alert fires or resolves as its condition goes from one state to the next
+    pub(crate) fn transition(
+        &self,
+        previous: Option<&ConditionState>,
+        current: Option<&ConditionState>,
+    ) -> Option<Transition> {
+        match (self.is_met(previous), self.is_met(current)) {
+            (false, true) => Some(Transition::Fired),
+            (true, false) if current.is_none() => Some(Transition::Resolved),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use std::time::Duration;
+
+    fn alert(persists: Option<Duration>, checks: u32) -> Alert {
+        Alert {
+            condition: Condition::Divergent,
+            products: None,
+            persists,
+            checks,
+        }
+    }
+
+    #[test]
+    fn test_transition() {
+        let start = DateTime::parse_from_rfc3339("2024-03-19T15:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let at = |minutes| start + TimeDelta::minutes(minutes);
+        // checks every 10 minutes, holding at 0, 10, 20 and 30, then not at 40
+        let states: Vec<Option<ConditionState>> = [true, true, true, true, false]
+            .into_iter()
+            .zip(0..)
+            .scan(None, |state, (holds, check)| {
+                *state = ConditionState::advance(*state, holds, at(check * 10));
+                Some(*state)
+            })
+            .collect();
+        assert_eq!(states[3].unwrap().since, start);
+        assert_eq!(states[3].unwrap().checks, 4);
+        assert_eq!(states[4], None);
+
+        let transitions = |alert: &Alert| {
+            let mut previous = None;
+            let mut transitions = Vec::new();
+            for state in &states {
+                transitions.push(alert.transition(previous.as_ref(), state.as_ref()));
+                previous = *state;
+            }
+            transitions
+        };
+        let (fired, resolved) = (Some(Transition::Fired), Some(Transition::Resolved));
+        assert_eq!(
+            transitions(&alert(Some(Duration::from_mins(30)), 1)),
+            [None, None, None, fired, resolved]
+        );
+        assert_eq!(
+            transitions(&alert(None, 2)),
+            [None, fired, None, None, resolved]
+        );
+        assert_eq!(
+            transitions(&alert(None, 1)),
+            [fired, None, None, None, resolved]
+        );
+        // never met, so never fired, nor resolved
+        assert_eq!(transitions(&alert(None, 5)), [None; 5]);
+    }
+}
@@ -0,0 +1,69 @@
+//! The build config of a product's current build, from the CDN
+use crate::cli::{history::to_hex, replay};
+use clap::Args;
+use serde::Serialize;
+use wownow::prelude::*;
+
+/// Show the build config of a product's current build in a region
+#[derive(Args, Debug)]
+pub(crate) struct BuildConfigArgs {
+    /// The product whose build config to show
+    #[arg(long, default_value = "wow")]
+    product: String,
+
+    /// The region whose build to show, and whose CDN to download from
+    #[arg(long, default_value = "us")]
+    region: String,
+}
+
+/// The build config, with the build it's of
+#[derive(Serialize)]
+struct Output<'a> {
+    product: &'a str,
+    region: &'a str,
+    key: String,
+
+    #[serde(flatten)]
+    build_config: BuildConfig,
+}
+
+/// The record of a product's current build in a region, and the region's CDN, from which the
+/// build's files are downloaded
+pub(crate) async fn current_build(
+    product: &str,
+    region: &str,
+) -> Result<(VersionsRecord, Cdn), String> {
+    let client = replay::client();
+    let (versions, cdns) = tokio::join!(client.get_versions(product), client.get_cdns(product));
+    let versions = versions.map_err(|e| format!("Error getting `{product}` versions: {e}"))?;
+    let cdns = cdns.map_err(|e| format!("Error getting `{product}` CDNs: {e}"))?;
+
+    let record = versions
+        .records
+        .into_iter()
+        .find(|record| record.region == region)
+        .ok_or_else(|| format!("Error: `{product}` has no version in region `{region}`"))?;
+    let cdn = cdns
+        .region(region)
+        .ok_or_else(|| format!("Error: `{product}` has no CDN in region `{region}`"))?;
+    Ok((record, Cdn::new(cdn)))
+}
+
+pub(crate) async fn run(args: BuildConfigArgs, pretty_print: bool) -> crate::Result {
+    let BuildConfigArgs { product, region } = &args;
+    let (record, cdn) = current_build(product, region).await?;
+    let build_config = cdn
+        .get_build_config(&record.build_config)
+        .await
+        .map_err(|e| format!("Error getting `{product}` build config: {e}"))?;
+
+    crate::to_json(
+        &Output {
+            product,
+            region,
+            key: to_hex(&record.build_config),
+            build_config,
+        },
+        pretty_print,
+    )
+}
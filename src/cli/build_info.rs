@@ -0,0 +1,66 @@
+//! Where the binary came from, embedded by the build script, for `--version --verbose`
+use chrono::{DateTime, SecondsFormat};
+
+/// The commit the binary was built from, or `unknown` if it wasn't built from a git checkout
+const GIT_COMMIT: &str = env!("WOWNOW_GIT_COMMIT");
+
+/// The date of the source the binary was built from, in seconds since the Unix epoch:
+/// `SOURCE_DATE_EPOCH` if set, or else the commit's date, or empty if neither is known
+const SOURCE_DATE: &str = env!("WOWNOW_SOURCE_DATE");
+
+/// The target triple the binary was built for, such as `x86_64-unknown-linux-gnu`
+const TARGET: &str = env!("WOWNOW_TARGET");
+
+/// The enabled cargo features, separated by commas and sorted
+const FEATURES: &str = env!("WOWNOW_FEATURES");
+
+/// The version, as `--version` prints it, followed when verbose by the commit, source date,
+/// target and features, a line each, to paste into bug reports.
+pub(crate) fn version(verbose: bool) -> String {
+    let version = format!("wownow {}", env!("CARGO_PKG_VERSION"));
+    if !verbose {
+        return version;
+    }
+    let date = SOURCE_DATE
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map_or_else(
+            || "unknown".to_owned(),
+            |date| date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    let features = if FEATURES.is_empty() {
+        "none".to_owned()
+    } else {
+        FEATURES.replace(',', ", ")
+    };
+    format!(
+        "{version}\n\
+        commit: {GIT_COMMIT}\n\
+        date: {date}\n\
+        target: {TARGET}\n\
+        features: {features}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(
+            version(false),
+            format!("wownow {}", env!("CARGO_PKG_VERSION"))
+        );
+
+        let verbose = version(true);
+        let lines: Vec<_> = verbose.lines().collect();
+        assert_eq!(lines[0], version(false));
+        assert_eq!(lines[1], format!("commit: {GIT_COMMIT}"));
+        assert!(lines[2].starts_with("date: "));
+        assert_eq!(lines[3], format!("target: {TARGET}"));
+        // the binary needs these
+        assert!(lines[4].contains("tokio") && lines[4].contains("chrono"));
+    }
+}
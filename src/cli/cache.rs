@@ -0,0 +1,595 @@
+//! On-disk cache of raw responses, so that repeated invocations don't hit the network
+use crate::cli::capture::Capture;
+use chrono::{DateTime, Utc};
+use clap::Subcommand;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use wownow::prelude::*;
+
+/// A directory of raw responses, one file per endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+
+    /// How old an entry may be and still be used
+    max_age: Duration,
+
+    /// Whether to ignore existing entries, always fetching fresh responses (which are then cached)
+    refresh: bool,
+
+    /// Whether to never contact the server, using existing entries no matter how old
+    offline: bool,
+
+    /// Whether a response with an older seqn than the cached one is an error, rather than a
+    /// warning
+    strict_seqn: bool,
+}
+
+impl Cache {
+    /// A cache in the user's cache directory, or `None` if the platform doesn't have one.
+    pub(crate) fn new(
+        max_age: Duration,
+        refresh: bool,
+        offline: bool,
+        strict_seqn: bool,
+    ) -> Option<Self> {
+        Some(Self {
+            dir: default_dir()?,
+            max_age,
+            refresh,
+            offline,
+            strict_seqn,
+        })
+    }
+
+    /// Whether the cache never contacts the server
+    pub(crate) fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// When the cached response of an endpoint was retrieved from the server, if it's cached.
+    pub(crate) async fn retrieved_at(&self, endpoint: &Endpoint) -> Option<DateTime<Utc>> {
+        let modified = tokio::fs::metadata(self.entry_path(endpoint))
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        Some(modified.into())
+    }
+
+    fn entry_path(&self, endpoint: &Endpoint) -> PathBuf {
+        self.dir.join(encode_file_name(&endpoint.path()))
+    }
+
+    /// Read the cached response of an endpoint, and how old it is. Entries are read even when
+    /// refreshing, for their seqns.
+    async fn read(&self, endpoint: &Endpoint) -> Option<(Vec<u8>, Duration)> {
+        let path = self.entry_path(endpoint);
+        let modified = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        // an entry from the future (clock skew) has no age, so counts as fresh
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let raw = tokio::fs::read(&path).await.ok()?;
+        Some((raw, age))
+    }
+
+    /// Write the response of an endpoint to the cache. The write is atomic, so concurrent readers
+    /// never see a partial entry.
+    pub(crate) async fn write(&self, endpoint: &Endpoint, response: &[u8]) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.entry_path(endpoint);
+        let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&tmp_path, response).await?;
+        tokio::fs::rename(&tmp_path, &path).await
+    }
+}
+
+#[cfg(test)]
+impl Cache {
+    /// An empty cache in a temporary directory named for a test
+    pub(crate) fn temp(name: &str, max_age: Duration) -> Self {
+        let dir = std::env::temp_dir().join(format!("wownow-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self {
+            dir,
+            max_age,
+            refresh: false,
+            offline: false,
+            strict_seqn: false,
+        }
+    }
+
+    /// Remove the cache's directory
+    pub(crate) fn remove(&self) {
+        std::fs::remove_dir_all(&self.dir).unwrap();
+    }
+}
+
+/// The directory wownow caches responses in by default, e.g., `~/.cache/wownow` on Linux.
+pub(crate) fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("wownow"))
+}
+
+/// Decode a file name made by [`encode_file_name`] back into an endpoint path.
+fn decode_file_name(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut iter = name.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Encode an endpoint path as a file name. Chars that may be unsafe in file names are
+/// percent-encoded, so each endpoint has a distinct file name.
+pub(crate) fn encode_file_name(endpoint_path: &str) -> String {
+    let mut name = String::with_capacity(endpoint_path.len());
+    for byte in endpoint_path.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+            name.push(char::from(byte));
+        } else {
+            // writing to a String can't fail
+            let _ = write!(name, "%{byte:02X}");
+        }
+    }
+    name
+}
+
+/// A response that carries a sequence number
+trait Sequenced {
+    fn seqn(&self) -> u32;
+}
+
+impl Sequenced for SummaryResponse {
+    fn seqn(&self) -> u32 {
+        self.seqn
+    }
+}
+
+impl Sequenced for VersionsResponse {
+    fn seqn(&self) -> u32 {
+        self.seqn
+    }
+}
+
+impl Sequenced for CdnsResponse {
+    fn seqn(&self) -> u32 {
+        self.seqn
+    }
+}
+
+/// Parse a response, fetching its raw form from the cache if it's current there, or with the
+/// client otherwise. Responses from the server are cached once they parse successfully, and
+/// dumped by `capture` whether or not they parse. Invalid UTF-8 is replaced, with a warning.
+///
+/// If the server's current seqn of the endpoint is known (from the summary), a cached response is
+/// current if its seqn is at least that, no matter its age. Otherwise, it's current if it's
+/// younger than the cache's max age. An offline cache considers all responses current, and errors
+/// instead of contacting the server.
+async fn get_cached<T>(
+    client: &Client,
+    cache: Option<&Cache>,
+    capture: &Capture,
+    endpoint: &Endpoint,
+    current_seqn: Option<u32>,
+) -> ApiResult<T>
+where
+    T: for<'a> TryFrom<&'a str, Error = ResponseError> + Sequenced,
+{
+    let start = Instant::now();
+    // the seqn of the cached response, to check the server doesn't go back on
+    let mut cached_seqn = None;
+    if let Some(cache) = cache {
+        if let Some((raw, age)) = cache.read(endpoint).await {
+            // an entry that doesn't parse (e.g., from an older wownow) is treated as a miss
+            if let Ok(response) = parse_lossy_with_warning::<T>(endpoint, &raw) {
+                cached_seqn = Some(response.seqn());
+                let is_current = !cache.refresh
+                    && match current_seqn {
+                        _ if cache.offline => true,
+                        Some(current_seqn) => response.seqn() >= current_seqn,
+                        None => age <= cache.max_age,
+                    };
+                if is_current {
+                    capture.keep(endpoint, response.seqn(), &raw);
+                    capture.record_request(endpoint, true, start.elapsed());
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    if cache.is_some_and(|cache| cache.offline) {
+        return Err(ApiError::NotCached(endpoint.path()));
+    }
+
+    let raw = client.get_raw(endpoint).await?;
+    let parsed = parse_lossy_with_warning::<T>(endpoint, &raw);
+    capture
+        .dump(endpoint, parsed.as_ref().ok().map(Sequenced::seqn), &raw)
+        .await;
+    check_known_product(endpoint, &raw)?;
+    let response = parsed.map_err(|source| ApiError::Response {
+        context: client.transport().context(endpoint),
+        source,
+    })?;
+
+    let regressed = match (cache, cached_seqn) {
+        (Some(cache), Some(cached_seqn)) => {
+            check_seqn(cache, endpoint, cached_seqn, response.seqn())?
+        }
+        _ => false,
+    };
+    // keep the newer response cached, so a regression is noticed again until the server catches
+    // up
+    if let Some(cache) = cache.filter(|_| !regressed) {
+        if let Err(e) = cache.write(endpoint, &raw).await {
+            eprintln!("Warning: could not cache `{}`: {e}", endpoint.path());
+        }
+    }
+    capture.keep(endpoint, response.seqn(), &raw);
+    capture.record_request(endpoint, false, start.elapsed());
+
+    Ok(response)
+}
+
+/// Check that a response's seqn isn't older than that of the cached response, which would mean the
+/// server is a stale mirror or a change hasn't propagated to it. Returns whether it's older, after
+/// warning of it, or fails if the cache is strict about seqns.
+fn check_seqn(cache: &Cache, endpoint: &Endpoint, cached_seqn: u32, seqn: u32) -> ApiResult<bool> {
+    if seqn >= cached_seqn {
+        return Ok(false);
+    }
+    let error = ApiError::SeqnRegression {
+        path: endpoint.path(),
+        previous: cached_seqn,
+        seqn,
+    };
+    if cache.strict_seqn {
+        return Err(error);
+    }
+    eprintln!("Warning: {error}, so the server may be a stale mirror");
+    Ok(true)
+}
+
+/// Parse a response, replacing invalid UTF-8 with a warning rather than failing, so one bad byte
+/// doesn't fail the run.
+fn parse_lossy_with_warning<T>(endpoint: &Endpoint, raw: &[u8]) -> Result<T, ResponseError>
+where
+    T: for<'a> TryFrom<&'a str, Error = ResponseError>,
+{
+    let (response, replaced) = parse_lossy(raw)?;
+    if let Some(e) = replaced {
+        eprintln!(
+            "Warning: replaced invalid UTF-8 in `{}`: {e}",
+            endpoint.path()
+        );
+    }
+    Ok(response)
+}
+
+/// Get the summary, from the cache if possible.
+pub(crate) async fn get_summary(
+    client: &Client,
+    cache: Option<&Cache>,
+    capture: &Capture,
+) -> ApiResult<SummaryResponse> {
+    get_cached(client, cache, capture, &Endpoint::Summary, None).await
+}
+
+/// Get the versions of a product, from the cache if possible. `current_seqn` is the seqn the
+/// summary gives for the product's versions, if known.
+pub(crate) async fn get_versions(
+    client: &Client,
+    cache: Option<&Cache>,
+    capture: &Capture,
+    product: &str,
+    current_seqn: Option<u32>,
+) -> ApiResult<VersionsResponse> {
+    get_cached(
+        client,
+        cache,
+        capture,
+        &Endpoint::Versions(product.to_owned()),
+        current_seqn,
+    )
+    .await
+}
+
+/// Get the versions of a product being downloaded in the background, from the cache if possible.
+pub(crate) async fn get_bgdl(
+    client: &Client,
+    cache: Option<&Cache>,
+    capture: &Capture,
+    product: &str,
+) -> ApiResult<VersionsResponse> {
+    get_cached(
+        client,
+        cache,
+        capture,
+        &Endpoint::Bgdl(product.to_owned()),
+        None,
+    )
+    .await
+}
+
+/// Get the CDNs of a product, from the cache if possible.
+pub(crate) async fn get_cdns(
+    client: &Client,
+    cache: Option<&Cache>,
+    capture: &Capture,
+    product: &str,
+) -> ApiResult<CdnsResponse> {
+    get_cached(
+        client,
+        cache,
+        capture,
+        &Endpoint::Cdns(product.to_owned()),
+        None,
+    )
+    .await
+}
+
+/// Inspect or clear the response cache
+#[derive(Subcommand, Debug)]
+pub(crate) enum CacheCommand {
+    /// Print the path of the cache directory
+    Path,
+
+    /// List the cached responses, with their sequence numbers and ages
+    Ls,
+
+    /// Delete all cached responses
+    Clear,
+
+    /// Print the number, total size, and age range of the cached responses
+    Stats,
+}
+
+/// A cached response
+struct Entry {
+    endpoint: Endpoint,
+
+    /// The response's seqn, or `None` if it doesn't parse
+    seqn: Option<u32>,
+
+    age: Duration,
+
+    /// The size of the response in bytes
+    size: u64,
+}
+
+/// Read the entries of the cache at `dir`, sorted by endpoint path. Files that aren't entries,
+/// like those of in-progress writes, are skipped.
+async fn read_entries(dir: &Path) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+
+    let mut read_dir = match tokio::fs::read_dir(dir).await {
+        Ok(read_dir) => read_dir,
+        // a cache that hasn't been written to yet is empty
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+
+    while let Some(dir_entry) = read_dir.next_entry().await? {
+        let Some(endpoint) = dir_entry
+            .file_name()
+            .to_str()
+            .and_then(decode_file_name)
+            .and_then(|path| Endpoint::from_path(&path))
+        else {
+            continue;
+        };
+
+        let metadata = dir_entry.metadata().await?;
+        let age = SystemTime::now()
+            .duration_since(metadata.modified()?)
+            .unwrap_or_default();
+        let raw = tokio::fs::read(dir_entry.path()).await?;
+        let seqn = match endpoint {
+            Endpoint::Summary => SummaryResponse::try_from(raw.as_slice())
+                .ok()
+                .map(|response| response.seqn),
+            // bgdl responses have the same shape as versions responses
+            Endpoint::Versions(_) | Endpoint::Bgdl(_) => VersionsResponse::try_from(raw.as_slice())
+                .ok()
+                .map(|response| response.seqn),
+            Endpoint::Cdns(_) => CdnsResponse::try_from(raw.as_slice())
+                .ok()
+                .map(|response| response.seqn),
+        };
+
+        entries.push(Entry {
+            endpoint,
+            seqn,
+            age,
+            size: metadata.len(),
+        });
+    }
+
+    entries.sort_by_key(|entry| entry.endpoint.path());
+    Ok(entries)
+}
+
+/// Format an age to the second, e.g., `1h 2m 3s`.
+fn format_age(age: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(age.as_secs())).to_string()
+}
+
+/// Run a `cache` subcommand.
+pub(crate) async fn run(command: CacheCommand) -> crate::Result {
+    let dir = default_dir().ok_or("Error: no cache directory on this platform")?;
+
+    match command {
+        CacheCommand::Path => Ok(dir.display().to_string()),
+        CacheCommand::Ls => {
+            let entries = read_entries(&dir)
+                .await
+                .map_err(|e| format!("Error reading cache: {e}"))?;
+            let lines = entries
+                .iter()
+                .map(|entry| {
+                    let seqn = entry
+                        .seqn
+                        .map_or_else(|| "-".to_owned(), |seqn| seqn.to_string());
+                    format!(
+                        "{}\t{seqn}\t{}",
+                        entry.endpoint.path(),
+                        format_age(entry.age)
+                    )
+                })
+                .collect::<Vec<_>>();
+            Ok(lines.join("\n"))
+        }
+        CacheCommand::Clear => match tokio::fs::remove_dir_all(&dir).await {
+            Ok(()) => Ok(format!("Cleared {}", dir.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(format!("Cleared {}", dir.display()))
+            }
+            Err(e) => Err(format!("Error clearing cache: {e}")),
+        },
+        CacheCommand::Stats => {
+            let entries = read_entries(&dir)
+                .await
+                .map_err(|e| format!("Error reading cache: {e}"))?;
+            let size = entries.iter().map(|entry| entry.size).sum::<u64>();
+            let mut lines = vec![
+                format!("entries: {}", entries.len()),
+                format!("size: {size} bytes"),
+            ];
+            if let (Some(oldest), Some(newest)) = (
+                entries.iter().map(|entry| entry.age).max(),
+                entries.iter().map(|entry| entry.age).min(),
+            ) {
+                lines.push(format!("oldest: {}", format_age(oldest)));
+                lines.push(format!("newest: {}", format_age(newest)));
+            }
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_file_name() {
+        assert_eq!(encode_file_name("v2/summary"), "v2%2Fsummary");
+        assert_eq!(
+            encode_file_name("v2/products/../versions"),
+            "v2%2Fproducts%2F%2E%2E%2Fversions"
+        );
+        assert_eq!(
+            decode_file_name("v2%2Fproducts%2F%2E%2E%2Fversions").as_deref(),
+            Some("v2/products/../versions")
+        );
+        assert_eq!(decode_file_name("v2%2"), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_write() {
+        let cache = Cache::temp("read-write", Duration::from_mins(1));
+        let endpoint = Endpoint::Versions("wow".to_owned());
+
+        assert_eq!(cache.read(&endpoint).await, None);
+        cache.write(&endpoint, b"response").await.unwrap();
+        let (raw, age) = cache.read(&endpoint).await.unwrap();
+        assert_eq!(raw, b"response");
+        assert!(age < cache.max_age);
+
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_versions_current_seqn() {
+        // an expired cache, so that entries can only be current by seqn
+        let cache = Cache::temp("current-seqn", Duration::ZERO);
+        let client = Client::new();
+        let endpoint = Endpoint::Versions("wow".to_owned());
+        cache
+            .write(
+                &endpoint,
+                b"Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 2118468\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+            )
+            .await
+            .unwrap();
+
+        let versions = get_versions(
+            &client,
+            Some(&cache),
+            &Capture::default(),
+            "wow",
+            Some(2_118_468),
+        )
+        .await
+        .unwrap();
+        assert_eq!(versions.seqn, 2_118_468);
+
+        // a cached seqn that's behind is refetched, unless offline
+        let offline = Cache {
+            offline: true,
+            ..cache.clone()
+        };
+        let versions = get_versions(
+            &client,
+            Some(&offline),
+            &Capture::default(),
+            "wow",
+            Some(2_118_469),
+        )
+        .await
+        .unwrap();
+        assert_eq!(versions.seqn, 2_118_468);
+        assert!(offline.retrieved_at(&endpoint).await.is_some());
+        assert!(get_versions(
+            &client,
+            Some(&offline),
+            &Capture::default(),
+            "wow_classic",
+            None
+        )
+        .await
+        .is_err());
+
+        let entries = read_entries(&cache.dir).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].endpoint, endpoint);
+        assert_eq!(entries[0].seqn, Some(2_118_468));
+
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_seqn() {
+        let cache = Cache::temp("check-seqn", Duration::ZERO);
+        let endpoint = Endpoint::Versions("wow".to_owned());
+        assert!(!check_seqn(&cache, &endpoint, 10, 10).unwrap());
+        assert!(!check_seqn(&cache, &endpoint, 10, 11).unwrap());
+        assert!(check_seqn(&cache, &endpoint, 10, 9).unwrap());
+
+        let strict = Cache {
+            strict_seqn: true,
+            ..cache
+        };
+        assert!(matches!(
+            check_seqn(&strict, &endpoint, 10, 9),
+            Err(ApiError::SeqnRegression {
+                previous: 10,
+                seqn: 9,
+                ..
+            })
+        ));
+    }
+}
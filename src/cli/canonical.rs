@@ -0,0 +1,147 @@
+//! Canonical JSON output, the same bytes for the same versions however and whenever fetched
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Fields that depend on when or how wownow ran rather than on the versions, left out of
+/// canonical output
+const ENVIRONMENTAL_FIELDS: [&str; 3] = ["retrieval_datetime", "retrieved_at", "build_age"];
+
+/// Fields holding timestamps, formatted to the second in UTC in canonical output
+const TIMESTAMP_FIELDS: [&str; 1] = ["first_seen"];
+
+/// Serialize output as canonical JSON, pretty printed if called for: with keys and arrays sorted,
+/// timestamps formatted alike, and without fields that vary from run to run.
+pub(crate) fn to_canonical_json<T: Serialize + ?Sized>(
+    value: &T,
+    pretty_print: bool,
+) -> crate::Result {
+    let mut value =
+        serde_json::to_value(value).map_err(|e| format!("Error serializing JSON: {e}"))?;
+    canonicalize(&mut value);
+    // maps in values are sorted by key, so objects serialize with sorted keys
+    crate::to_json(&value, pretty_print)
+}
+
+/// Serialize output as canonical JSON like [`to_canonical_json`], but keeping its
+/// `retrieval_datetime` as the given time, such as that of `SOURCE_DATE_EPOCH`, so snapshots of
+/// the output are byte for byte the same and still have one.
+pub(crate) fn to_reproducible_json<T: Serialize + ?Sized>(
+    value: &T,
+    retrieval_datetime: DateTime<Utc>,
+    pretty_print: bool,
+) -> crate::Result {
+    let mut value =
+        serde_json::to_value(value).map_err(|e| format!("Error serializing JSON: {e}"))?;
+    canonicalize(&mut value);
+    if let Value::Object(object) = &mut value {
+        object.insert(
+            "retrieval_datetime".to_owned(),
+            Value::String(retrieval_datetime.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        );
+    }
+    crate::to_json(&value, pretty_print)
+}
+
+/// Parse a `SOURCE_DATE_EPOCH`, in seconds since the Unix epoch.
+pub(crate) fn parse_source_date_epoch(seconds: &str) -> Result<DateTime<Utc>, String> {
+    seconds
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .ok_or_else(|| format!("`{seconds}` should be a number of seconds since the Unix epoch"))
+}
+
+/// Make a JSON value canonical, in place.
+fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|key, _| !ENVIRONMENTAL_FIELDS.contains(&key.as_str()));
+            for (key, value) in object.iter_mut() {
+                if TIMESTAMP_FIELDS.contains(&key.as_str()) {
+                    format_timestamp(value);
+                }
+                canonicalize(value);
+            }
+        }
+        Value::Array(array) => {
+            array.iter_mut().for_each(canonicalize);
+            // the canonical text of canonical values orders them as well as anything
+            array.sort_by_cached_key(Value::to_string);
+        }
+        _ => {}
+    }
+}
+
+/// Format an RFC 3339 timestamp to the second in UTC, as `2024-03-14T17:56:25Z`.
+fn format_timestamp(value: &mut Value) {
+    if let Some(timestamp) = value
+        .as_str()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+    {
+        *value = Value::String(
+            timestamp
+                .to_utc()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_canonical_json() {
+        let output = |versions: Value| {
+            to_canonical_json(
+                &json!({
+                    "retrieval_datetime": "2024-03-14T17:56:25.593962700Z",
+                    "products": [{
+                        "name": "wow",
+                        "retrieved_at": "2024-03-14T17:50:00Z",
+                        "versions": versions,
+                    }],
+                }),
+                false,
+            )
+            .unwrap()
+        };
+
+        let canonical = output(json!([
+            {"region": "us", "build": "53584", "first_seen": "2024-03-14T10:56:25.5+01:00", "build_age": 60},
+            {"region": "eu", "build": "53584"},
+        ]));
+        assert_eq!(
+            canonical,
+            r#"{"products":[{"name":"wow","versions":[{"build":"53584","first_seen":"2024-03-14T09:56:25Z","region":"us"},{"build":"53584","region":"eu"}]}]}"#
+        );
+        assert_eq!(
+            output(json!([
+                {"build": "53584", "region": "eu"},
+                {"build_age": 120, "first_seen": "2024-03-14T09:56:25.5Z", "build": "53584", "region": "us"},
+            ])),
+            canonical
+        );
+    }
+
+    #[test]
+    fn test_to_reproducible_json() {
+        let epoch = parse_source_date_epoch("1710438985").unwrap();
+        assert_eq!(
+            to_reproducible_json(
+                &json!({
+                    "retrieval_datetime": "2024-03-14T17:56:25.593962700Z",
+                    "products": [{"name": "wow", "retrieved_at": "2024-03-14T17:50:00Z"}],
+                }),
+                epoch,
+                false,
+            )
+            .unwrap(),
+            r#"{"products":[{"name":"wow"}],"retrieval_datetime":"2024-03-14T17:56:25Z"}"#
+        );
+        assert!(parse_source_date_epoch("yesterday").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Keeping raw responses besides parsing them: writing them to a directory (`--dump-raw`), and
+//! embedding their text (`--include-raw`), hashes (`--audit`) or how they were got
+//! (`--with-meta`) in the output
+use crate::cli::{cache::encode_file_name, history::to_hex};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use wownow::prelude::*;
+
+/// Responses by endpoint, each with its seqn
+type Kept = HashMap<Endpoint, (u32, Vec<u8>)>;
+
+/// Where raw responses are kept, if anywhere. Clones share the responses they keep.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Capture {
+    /// The directory to write responses fetched from the server into
+    dump_dir: Option<PathBuf>,
+
+    /// Every response, fetched or cached, with its seqn, by endpoint, to embed in the output
+    kept: Option<Arc<Mutex<Kept>>>,
+
+    /// How every response was got, to describe in the output
+    requests: Option<Arc<Mutex<Vec<RequestMeta>>>>,
+}
+
+/// How a response was got, for `--with-meta`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct RequestMeta {
+    /// The endpoint's path, such as `v2/products/wow/versions`
+    endpoint: String,
+
+    /// Whether it was read from the local cache rather than fetched
+    cached: bool,
+
+    /// How many times the request was retried. Requests aren't retried yet, so this is always 0.
+    retries: u32,
+
+    /// How long reading or fetching it took
+    duration_ms: u128,
+}
+
+/// The hash of a response the output was made from, to verify the output against a capture
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct AuditEntry {
+    /// The endpoint's path, such as `v2/products/wow/versions`
+    endpoint: String,
+
+    seqn: u32,
+
+    /// The SHA-256 of the response, exactly as the server sent it, in hex
+    sha256: String,
+}
+
+impl Capture {
+    /// Write fetched responses into `dump_dir`, if given, keep every response if `keep`, and
+    /// record how each was got if `record_requests`.
+    pub(crate) fn new(dump_dir: Option<PathBuf>, keep: bool, record_requests: bool) -> Self {
+        Self {
+            dump_dir,
+            kept: keep.then(Arc::default),
+            requests: record_requests.then(Arc::default),
+        }
+    }
+
+    /// Record how an endpoint's response was got, if requests are recorded.
+    pub(crate) fn record_request(&self, endpoint: &Endpoint, cached: bool, duration: Duration) {
+        if let Some(requests) = &self.requests {
+            requests
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(RequestMeta {
+                    endpoint: endpoint.path(),
+                    cached,
+                    retries: 0,
+                    duration_ms: duration.as_millis(),
+                });
+        }
+    }
+
+    /// Keep an endpoint's response and its seqn, if responses are kept.
+    pub(crate) fn keep(&self, endpoint: &Endpoint, seqn: u32, raw: &[u8]) {
+        if let Some(kept) = &self.kept {
+            kept.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(endpoint.clone(), (seqn, raw.to_vec()));
+        }
+    }
+
+    /// The kept text of an endpoint's response, with invalid UTF-8 replaced
+    fn text(&self, endpoint: &Endpoint) -> Option<String> {
+        let kept = self
+            .kept
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (_, raw) = kept.get(endpoint)?;
+        Some(String::from_utf8_lossy(raw).into_owned())
+    }
+
+    /// The hash of every kept response, sorted by endpoint
+    fn audit(&self) -> Vec<AuditEntry> {
+        let Some(kept) = &self.kept else {
+            return Vec::new();
+        };
+        let mut entries = kept
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(endpoint, (seqn, raw))| AuditEntry {
+                endpoint: endpoint.path(),
+                seqn: *seqn,
+                sha256: to_hex(&Sha256::digest(raw)),
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        entries
+    }
+
+    /// Write a response fetched from the server into the dump directory, if there is one,
+    /// exactly as it arrived. `seqn` is `None` if it didn't parse.
+    pub(crate) async fn dump(&self, endpoint: &Endpoint, seqn: Option<u32>, raw: &[u8]) {
+        let Some(dir) = &self.dump_dir else {
+            return;
+        };
+        if let Err(e) = dump_raw(dir, endpoint, seqn, raw).await {
+            eprintln!(
+                "Warning: could not write `{}` to `{}`: {e}",
+                endpoint.path(),
+                dir.display()
+            );
+        }
+    }
+
+    /// Embed the hashes of the kept responses in the JSON of a fetch, as `audit`: each response's
+    /// endpoint, seqn and SHA-256.
+    pub(crate) fn embed_audit(&self, fetch: &mut Value) {
+        if let Value::Object(fetch) = fetch {
+            fetch.insert("audit".to_owned(), serde_json::json!(self.audit()));
+        }
+    }
+
+    /// Embed a description of how a fetch was made in its JSON, as `meta`: the server queried and
+    /// its protocol, wownow's version, how each response was got, sorted by endpoint, and how long
+    /// the whole run took.
+    pub(crate) fn embed_meta(&self, fetch: &mut Value, duration: Duration) {
+        let Value::Object(fetch) = fetch else {
+            return;
+        };
+        let mut requests = self.requests.as_ref().map_or_else(Vec::new, |requests| {
+            requests
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        });
+        requests.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        let server = RequestContext::new(&Endpoint::Summary);
+        fetch.insert(
+            "meta".to_owned(),
+            serde_json::json!({
+                "host": server.host,
+                "protocol": server.protocol.to_string(),
+                "wownow_version": env!("CARGO_PKG_VERSION"),
+                "duration_ms": duration.as_millis(),
+                "requests": requests,
+            }),
+        );
+    }
+
+    /// Embed the kept texts in the JSON of a fetch: the summary's as `raw_summary`, and each
+    /// product's versions and background download as `raw` in the product and its `bgdl`.
+    pub(crate) fn embed_texts(&self, fetch: &mut Value) {
+        let Value::Object(fetch) = fetch else {
+            return;
+        };
+        if let Some(text) = self.text(&Endpoint::Summary) {
+            fetch.insert("raw_summary".to_owned(), Value::String(text));
+        }
+        let Some(Value::Array(products)) = fetch.get_mut("products") else {
+            return;
+        };
+        for product in products.iter_mut().filter_map(Value::as_object_mut) {
+            let Some(name) = product.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            let name = name.to_owned();
+            if let Some(Value::Object(bgdl)) = product.get_mut("bgdl") {
+                if let Some(text) = self.text(&Endpoint::Bgdl(name.clone())) {
+                    bgdl.insert("raw".to_owned(), Value::String(text));
+                }
+            }
+            if let Some(text) = self.text(&Endpoint::Versions(name)) {
+                product.insert("raw".to_owned(), Value::String(text));
+            }
+        }
+    }
+}
+
+/// The name of a raw response written by `--dump-raw`: the endpoint's path, encoded as for the
+/// cache, its seqn, or `unparsed` if it didn't parse, and when it was fetched, such as
+/// `v2%2Fsummary.2119172.20240319T150200Z`.
+fn dump_file_name(endpoint: &Endpoint, seqn: Option<u32>, fetched_at: DateTime<Utc>) -> String {
+    let seqn = seqn.map_or_else(|| "unparsed".to_owned(), |seqn| seqn.to_string());
+    format!(
+        "{}.{seqn}.{}",
+        encode_file_name(&endpoint.path()),
+        fetched_at.format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+/// Write a raw response fetched from the server into `dir`.
+async fn dump_raw(
+    dir: &Path,
+    endpoint: &Endpoint,
+    seqn: Option<u32>,
+    raw: &[u8],
+) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(dir.join(dump_file_name(endpoint, seqn, Utc::now())), raw).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_dump_raw() {
+        let fetched_at = DateTime::parse_from_rfc3339("2024-03-19T15:02:00.131Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            dump_file_name(&Endpoint::Summary, Some(2_119_172), fetched_at),
+            "v2%2Fsummary.2119172.20240319T150200Z"
+        );
+        assert_eq!(
+            dump_file_name(&Endpoint::Versions("wow".to_owned()), None, fetched_at),
+            "v2%2Fproducts%2Fwow%2Fversions.unparsed.20240319T150200Z"
+        );
+
+        let dir = std::env::temp_dir().join(format!("wownow-test-dump-{}", std::process::id()));
+        dump_raw(&dir, &Endpoint::Summary, Some(1), b"response")
+            .await
+            .unwrap();
+        let entries = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(std::fs::read(&entries[0]).unwrap(), b"response");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_embed_texts() {
+        let capture = Capture::new(None, true, false);
+        capture.keep(&Endpoint::Summary, 1, b"summary");
+        capture.keep(&Endpoint::Versions("wow".to_owned()), 2, b"wow \xff");
+        capture.keep(&Endpoint::Bgdl("wow".to_owned()), 3, b"wow bgdl");
+
+        let mut fetch = json!({
+            "products": [
+                { "name": "wow", "versions": [], "bgdl": { "name": "wow" } },
+                { "name": "wowt", "versions": [] },
+            ],
+        });
+        capture.embed_texts(&mut fetch);
+        assert_eq!(
+            fetch,
+            json!({
+                "raw_summary": "summary",
+                "products": [
+                    {
+                        "name": "wow",
+                        "versions": [],
+                        "bgdl": { "name": "wow", "raw": "wow bgdl" },
+                        "raw": "wow \u{fffd}",
+                    },
+                    { "name": "wowt", "versions": [] },
+                ],
+            })
+        );
+
+        // without texts kept, nothing is embedded
+        let capture = Capture::default();
+        capture.keep(&Endpoint::Summary, 1, b"summary");
+        let mut fetch = json!({ "products": [] });
+        capture.embed_texts(&mut fetch);
+        assert_eq!(fetch, json!({ "products": [] }));
+    }
+
+    #[test]
+    fn test_embed_audit() {
+        let capture = Capture::new(None, true, false);
+        capture.keep(&Endpoint::Versions("wow".to_owned()), 2, b"");
+        capture.keep(&Endpoint::Summary, 1, b"abc");
+
+        let mut fetch = json!({ "products": [] });
+        capture.embed_audit(&mut fetch);
+        assert_eq!(
+            fetch,
+            json!({
+                "products": [],
+                "audit": [
+                    {
+                        "endpoint": "v2/products/wow/versions",
+                        "seqn": 2,
+                        "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    },
+                    {
+                        "endpoint": "v2/summary",
+                        "seqn": 1,
+                        "sha256": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_embed_meta() {
+        let capture = Capture::new(None, false, true);
+        capture.record_request(
+            &Endpoint::Versions("wow".to_owned()),
+            false,
+            Duration::from_millis(120),
+        );
+        capture.record_request(&Endpoint::Summary, true, Duration::from_millis(2));
+
+        let mut fetch = json!({ "products": [] });
+        capture.embed_meta(&mut fetch, Duration::from_millis(130));
+        assert_eq!(
+            fetch,
+            json!({
+                "products": [],
+                "meta": {
+                    "host": "us.version.battle.net:1119",
+                    "protocol": "TCP",
+                    "wownow_version": env!("CARGO_PKG_VERSION"),
+                    "duration_ms": 130,
+                    "requests": [
+                        {
+                            "endpoint": "v2/products/wow/versions",
+                            "cached": false,
+                            "retries": 0,
+                            "duration_ms": 120,
+                        },
+                        { "endpoint": "v2/summary", "cached": true, "retries": 0, "duration_ms": 2 },
+                    ],
+                },
+            })
+        );
+    }
+}
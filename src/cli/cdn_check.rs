@@ -0,0 +1,131 @@
+//! Checks of which CDN hosts have propagated a product's current build
+use crate::cli::replay;
+use clap::Args;
+use serde::Serialize;
+use tokio::task::JoinSet;
+use wownow::prelude::*;
+
+/// Check which CDN hosts of a product have its current build's config files
+#[derive(Args, Debug)]
+pub(crate) struct CdnCheckArgs {
+    /// The product whose CDN hosts to check
+    #[arg(long, default_value = "wow")]
+    product: String,
+
+    /// Only check the hosts of this region, such as `us`
+    #[arg(long)]
+    region: Option<String>,
+}
+
+/// Whether a host has the config files of a region's current build
+#[derive(Debug, PartialEq, Serialize)]
+struct HostStatus {
+    region: String,
+    host: String,
+    build_config: bool,
+    cdn_config: bool,
+
+    /// Whether the host has every config file, so it has propagated the build
+    propagated: bool,
+
+    /// Why the host couldn't be checked, if it couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl HostStatus {
+    fn new(
+        region: String,
+        host: String,
+        build_config: CdnResult<bool>,
+        cdn_config: CdnResult<bool>,
+    ) -> Self {
+        match (build_config, cdn_config) {
+            (Ok(build_config), Ok(cdn_config)) => Self {
+                region,
+                host,
+                build_config,
+                cdn_config,
+                propagated: build_config && cdn_config,
+                error: None,
+            },
+            (Err(e), _) | (_, Err(e)) => Self {
+                region,
+                host,
+                build_config: false,
+                cdn_config: false,
+                propagated: false,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+pub(crate) async fn run(args: CdnCheckArgs, pretty_print: bool) -> crate::Result {
+    let product = &args.product;
+    let client = replay::client();
+    let (versions, cdns) = tokio::join!(client.get_versions(product), client.get_cdns(product));
+    let versions = versions.map_err(|e| format!("Error getting `{product}` versions: {e}"))?;
+    let cdns = cdns.map_err(|e| format!("Error getting `{product}` CDNs: {e}"))?;
+
+    let mut set = JoinSet::new();
+    for record in &cdns.records {
+        if args
+            .region
+            .as_ref()
+            .is_some_and(|region| *region != record.name)
+        {
+            continue;
+        }
+        let Some(version) = versions.region(&record.name) else {
+            continue;
+        };
+        let cdn = Cdn::new(record);
+        for host in cdn.hosts() {
+            let (cdn, region, host) = (cdn.clone(), record.name.clone(), host.clone());
+            let (build_config, cdn_config) = (version.build_config, version.cdn_config);
+            set.spawn(async move {
+                let (build_config, cdn_config) = tokio::join!(
+                    cdn.has_config(&host, &build_config),
+                    cdn.has_config(&host, &cdn_config)
+                );
+                HostStatus::new(region, host, build_config, cdn_config)
+            });
+        }
+    }
+    if set.is_empty() {
+        return Err(format!("Error: `{product}` has no CDN hosts to check"));
+    }
+
+    let mut statuses = Vec::new();
+    while let Some(join_result) = set.join_next().await {
+        statuses.push(join_result.map_err(|e| format!("Error joining task: {e}"))?);
+    }
+    statuses.sort_by(|a, b| (&a.region, &a.host).cmp(&(&b.region, &b.host)));
+    crate::to_json(&statuses, pretty_print)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_status() {
+        let status = |build_config, cdn_config| {
+            HostStatus::new(
+                "us".to_owned(),
+                "level3.blizzard.com".to_owned(),
+                build_config,
+                cdn_config,
+            )
+        };
+
+        assert!(status(Ok(true), Ok(true)).propagated);
+        assert!(!status(Ok(true), Ok(false)).propagated);
+
+        let unreachable = status(Ok(true), Err(CdnError::NoHosts));
+        assert!(!unreachable.propagated);
+        assert!(!unreachable.build_config);
+        assert_eq!(unreachable.error.as_deref(), Some("the CDN lists no hosts"));
+    }
+}
@@ -0,0 +1,47 @@
+//! The CDN config of a product's current build, from the CDN
+use crate::cli::{build_config::current_build, history::to_hex};
+use clap::Args;
+use serde::Serialize;
+use wownow::prelude::*;
+
+/// Show the CDN config of a product's current build in a region
+#[derive(Args, Debug)]
+pub(crate) struct CdnConfigArgs {
+    /// The product whose CDN config to show
+    #[arg(long, default_value = "wow")]
+    product: String,
+
+    /// The region whose build to show, and whose CDN to download from
+    #[arg(long, default_value = "us")]
+    region: String,
+}
+
+/// The CDN config, with the build it's of
+#[derive(Serialize)]
+struct Output<'a> {
+    product: &'a str,
+    region: &'a str,
+    key: String,
+
+    #[serde(flatten)]
+    cdn_config: CdnConfig,
+}
+
+pub(crate) async fn run(args: CdnConfigArgs, pretty_print: bool) -> crate::Result {
+    let CdnConfigArgs { product, region } = &args;
+    let (record, cdn) = current_build(product, region).await?;
+    let cdn_config = cdn
+        .get_cdn_config(&record.cdn_config)
+        .await
+        .map_err(|e| format!("Error getting `{product}` CDN config: {e}"))?;
+
+    crate::to_json(
+        &Output {
+            product,
+            region,
+            key: to_hex(&record.cdn_config),
+            cdn_config,
+        },
+        pretty_print,
+    )
+}
@@ -0,0 +1,238 @@
+//! Ranking of the CDN hosts of a product by latency
+use crate::cli::replay;
+use clap::Args;
+use std::{fmt::Write, time::Duration};
+use tokio::{task::JoinSet, time::Instant};
+use wownow::prelude::*;
+
+/// Measure the latency of each CDN host of a product
+#[derive(Args, Debug)]
+pub(crate) struct CdnPingArgs {
+    /// The product whose CDN hosts to measure
+    #[arg(long, default_value = "wow")]
+    product: String,
+
+    /// Only measure the hosts of this region, such as `us`
+    #[arg(long)]
+    region: Option<String>,
+
+    /// How many requests to make of each host, of which the median latency is shown
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+
+    /// Also time a range request for this many bytes of the current build config, as a small
+    /// download
+    #[arg(long, value_name = "BYTES")]
+    range: Option<u64>,
+}
+
+/// The measurements of a host
+#[derive(Debug)]
+struct Measurement {
+    region: String,
+    host: String,
+
+    /// The median latency of `HEAD` requests, or why they failed
+    latency: Result<Duration, String>,
+
+    /// How long the range request took, if one was made
+    range: Option<Result<Duration, String>>,
+}
+
+/// Time `HEAD` requests for the current build config, and a range request of it if asked.
+async fn measure(
+    cdn: Cdn,
+    region: String,
+    host: String,
+    key: Hex16,
+    count: u32,
+    range: Option<u64>,
+) -> Measurement {
+    let latency = async {
+        let mut latencies = Vec::new();
+        for _ in 0..count {
+            let start = Instant::now();
+            // any answer, even that the file is missing, shows how quickly the host responds
+            cdn.has_config(&host, &key)
+                .await
+                .map_err(|e| e.to_string())?;
+            latencies.push(start.elapsed());
+        }
+        latencies.sort();
+        Ok(latencies[latencies.len() / 2])
+    }
+    .await;
+
+    let range = match range {
+        Some(length) if latency.is_ok() => {
+            let start = Instant::now();
+            Some(
+                cdn.get_config_range(&host, &key, length)
+                    .await
+                    .map(|_| start.elapsed())
+                    .map_err(|e| e.to_string()),
+            )
+        }
+        _ => None,
+    };
+    Measurement {
+        region,
+        host,
+        latency,
+        range,
+    }
+}
+
+/// Sort measurements by region, then fastest first, with unreachable hosts last.
+fn rank(measurements: &mut [Measurement]) {
+    measurements.sort_by(|a, b| {
+        a.region
+            .cmp(&b.region)
+            .then_with(|| match (&a.latency, &b.latency) {
+                (Ok(a), Ok(b)) => a.cmp(b),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            })
+    });
+}
+
+fn format_timing(timing: &Result<Duration, String>) -> String {
+    match timing {
+        Ok(duration) => format!("{}ms", duration.as_millis()),
+        Err(e) => format!("unreachable: {e}"),
+    }
+}
+
+/// Render ranked measurements as a table, numbering the hosts of each region.
+fn render(measurements: &[Measurement]) -> String {
+    let with_range = measurements.iter().any(|m| m.range.is_some());
+    let mut rows = vec![vec![
+        "REGION".to_owned(),
+        "RANK".to_owned(),
+        "HOST".to_owned(),
+        "LATENCY".to_owned(),
+    ]];
+    if with_range {
+        rows[0].push("RANGE".to_owned());
+    }
+    let mut rank = 0;
+    for (index, measurement) in measurements.iter().enumerate() {
+        if index == 0 || measurements[index - 1].region != measurement.region {
+            rank = 0;
+        }
+        rank += 1;
+        let mut row = vec![
+            measurement.region.clone(),
+            if measurement.latency.is_ok() {
+                rank.to_string()
+            } else {
+                "-".to_owned()
+            },
+            measurement.host.clone(),
+            format_timing(&measurement.latency),
+        ];
+        if with_range {
+            row.push(
+                measurement
+                    .range
+                    .as_ref()
+                    .map_or_else(String::new, format_timing),
+            );
+        }
+        rows.push(row);
+    }
+
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut table = String::new();
+    for row in &rows {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            // writing to a String can't fail
+            let _ = write!(line, "{cell:<width$}  ");
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table.trim_end().to_owned()
+}
+
+pub(crate) async fn run(args: CdnPingArgs) -> crate::Result {
+    let product = &args.product;
+    let client = replay::client();
+    let (versions, cdns) = tokio::join!(client.get_versions(product), client.get_cdns(product));
+    let versions = versions.map_err(|e| format!("Error getting `{product}` versions: {e}"))?;
+    let cdns = cdns.map_err(|e| format!("Error getting `{product}` CDNs: {e}"))?;
+
+    let mut set = JoinSet::new();
+    for record in &cdns.records {
+        if args
+            .region
+            .as_ref()
+            .is_some_and(|region| *region != record.name)
+        {
+            continue;
+        }
+        let Some(version) = versions.region(&record.name) else {
+            continue;
+        };
+        let cdn = Cdn::new(record);
+        for host in cdn.hosts() {
+            set.spawn(measure(
+                cdn.clone(),
+                record.name.clone(),
+                host.clone(),
+                version.build_config,
+                args.count,
+                args.range,
+            ));
+        }
+    }
+    if set.is_empty() {
+        return Err(format!("Error: `{product}` has no CDN hosts to measure"));
+    }
+
+    let mut measurements = Vec::new();
+    while let Some(join_result) = set.join_next().await {
+        measurements.push(join_result.map_err(|e| format!("Error joining task: {e}"))?);
+    }
+    rank(&mut measurements);
+    Ok(render(&measurements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let measurement = |region: &str, host: &str, latency: Result<u64, &str>| Measurement {
+            region: region.to_owned(),
+            host: host.to_owned(),
+            latency: latency
+                .map(Duration::from_millis)
+                .map_err(ToOwned::to_owned),
+            range: None,
+        };
+        let mut measurements = vec![
+            measurement("us", "slow.example.com", Ok(80)),
+            measurement("eu", "eu.example.com", Ok(30)),
+            measurement("us", "down.example.com", Err("timed out")),
+            measurement("us", "fast.example.com", Ok(20)),
+        ];
+        rank(&mut measurements);
+        assert_eq!(
+            render(&measurements),
+            "REGION  RANK  HOST              LATENCY\n\
+            eu      1     eu.example.com    30ms\n\
+            us      1     fast.example.com  20ms\n\
+            us      2     slow.example.com  80ms\n\
+            us      -     down.example.com  unreachable: timed out"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Cross-validation of products' versions against their CDNs, catching builds whose config files
+//! can't be found where the CDNs say, such as when a change hasn't propagated everywhere yet
+#[cfg(feature = "cdn")]
+use crate::cli::history::to_hex;
+use clap::ValueEnum;
+use serde::Serialize;
+use wownow::prelude::*;
+
+/// How thoroughly to check that the versions' config files can be found on the CDNs
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CdnValidation {
+    /// Check that the CDNs give hosts and a path for every region of the versions, and that its
+    /// configs have hashes, without contacting the CDNs
+    Structure,
+
+    /// Also check that every host has every config file, over HTTP
+    #[cfg(feature = "cdn")]
+    Http,
+}
+
+/// An inconsistency between a product's versions and its CDNs
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct CdnIssue {
+    product: String,
+
+    /// The region, unless the issue is with all of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+
+    /// What's wrong, such as `no_cdn`, `no_hosts`, `no_path`, `no_config_path`, `empty_hash`,
+    /// `missing_config`, `unreachable_host`, or `cdns_unavailable`
+    issue: &'static str,
+
+    detail: String,
+}
+
+impl CdnIssue {
+    fn new(product: &str, region: Option<&str>, issue: &'static str, detail: String) -> Self {
+        Self {
+            product: product.to_owned(),
+            region: region.map(ToOwned::to_owned),
+            issue,
+            detail,
+        }
+    }
+
+    /// The product and region, to sort issues by
+    pub(crate) fn key(&self) -> (&str, Option<&str>) {
+        (&self.product, self.region.as_deref())
+    }
+}
+
+/// Check that each region of a product's versions can be resolved from its CDNs: that the CDNs
+/// list the region, with hosts and a path to find config files under (and a path for the product
+/// config, if the region has one), and that its build and CDN configs have hashes to look up.
+fn check_structure(
+    product: &str,
+    versions: &VersionsResponse,
+    cdns: &CdnsResponse,
+) -> Vec<CdnIssue> {
+    let mut issues = Vec::new();
+    for version in &versions.records {
+        let region = version.region.as_str();
+        let mut issue = |issue, detail| {
+            issues.push(CdnIssue::new(product, Some(region), issue, detail));
+        };
+        for (name, hash) in [
+            ("build_config", &version.build_config),
+            ("cdn_config", &version.cdn_config),
+        ] {
+            if hash.iter().all(|byte| *byte == 0) {
+                issue("empty_hash", format!("the {name} is all zeroes"));
+            }
+        }
+        let Some(cdn) = cdns.records.iter().find(|cdn| cdn.name == region) else {
+            issue("no_cdn", format!("the CDNs have no region `{region}`"));
+            continue;
+        };
+        if cdn.hosts.is_empty() {
+            issue("no_hosts", "the CDN lists no hosts".to_owned());
+        }
+        if cdn.path.is_empty() {
+            issue(
+                "no_path",
+                "the CDN gives no path for config files".to_owned(),
+            );
+        }
+        if cdn.config_path.is_empty() && version.product_config.iter().any(|byte| *byte != 0) {
+            issue(
+                "no_config_path",
+                "the CDN gives no path for the product config".to_owned(),
+            );
+        }
+    }
+    issues
+}
+
+/// Check that every host of each region's CDN has the build and CDN configs of the region's
+/// current build. Regions whose structure is broken are left to [`check_structure`].
+#[cfg(feature = "cdn")]
+async fn check_hosts(
+    product: &str,
+    versions: &VersionsResponse,
+    cdns: &CdnsResponse,
+) -> Vec<CdnIssue> {
+    let mut checks = Vec::new();
+    for version in &versions.records {
+        let Some(record) = cdns
+            .records
+            .iter()
+            .find(|cdn| cdn.name == version.region && !cdn.path.is_empty())
+        else {
+            continue;
+        };
+        let cdn = Cdn::new(record);
+        for host in cdn.hosts() {
+            for (name, hash) in [
+                ("build_config", version.build_config),
+                ("cdn_config", version.cdn_config),
+            ] {
+                let (cdn, region, host) = (cdn.clone(), &version.region, host.clone());
+                checks.push(async move {
+                    let issue = match cdn.has_config(&host, &hash).await {
+                        Ok(true) => return None,
+                        Ok(false) => (
+                            "missing_config",
+                            format!("`{host}` has no {name} `{}`", to_hex(&hash)),
+                        ),
+                        Err(e) => ("unreachable_host", format!("`{host}`: {e}")),
+                    };
+                    Some(CdnIssue::new(product, Some(region), issue.0, issue.1))
+                });
+            }
+        }
+    }
+    futures_util::future::join_all(checks)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Check a product's versions against its CDNs, as thoroughly as asked, returning what's
+/// inconsistent. CDNs that couldn't be fetched are an issue themselves.
+#[cfg_attr(not(feature = "cdn"), allow(clippy::unused_async))]
+pub(crate) async fn validate(
+    product: &str,
+    versions: &VersionsResponse,
+    cdns: ApiResult<CdnsResponse>,
+    validation: CdnValidation,
+) -> Vec<CdnIssue> {
+    let cdns = match cdns {
+        Ok(cdns) => cdns,
+        Err(e) => {
+            return vec![CdnIssue::new(
+                product,
+                None,
+                "cdns_unavailable",
+                e.to_string(),
+            )]
+        }
+    };
+    #[cfg_attr(not(feature = "cdn"), allow(unused_mut))]
+    let mut issues = check_structure(product, versions, &cdns);
+    #[cfg(feature = "cdn")]
+    if validation == CdnValidation::Http {
+        issues.extend(check_hosts(product, versions, &cdns).await);
+    }
+    #[cfg(not(feature = "cdn"))]
+    let _ = validation;
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_structure() {
+        let versions = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 1\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f\n\
+            eu|47e9e06f8371afb141e22614a912acc8|00000000000000000000000000000000||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f\n\
+            kr|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let cdns = CdnsResponse::try_from(
+            "Name!STRING:0|Path!STRING:0|Hosts!STRING:0|Servers!STRING:0|ConfigPath!STRING:0\n\
+            ## seqn = 1\n\
+            us|tpr/wow|level3.blizzard.com us.cdn.blizzard.com|http://level3.blizzard.com/?maxhosts=4|tpr/configs/data\n\
+            eu|||http://eu.cdn.blizzard.com/?maxhosts=4|tpr/configs/data",
+        )
+        .unwrap();
+
+        let issues = check_structure("wow", &versions, &cdns);
+        let issues = issues
+            .iter()
+            .map(|issue| (issue.region.as_deref().unwrap(), issue.issue))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                ("eu", "empty_hash"),
+                ("eu", "no_hosts"),
+                ("eu", "no_path"),
+                ("kr", "no_cdn"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Describing new builds for people, shared by the notification sinks
+use crate::cli::game::product_display_name;
+use wownow::prelude::*;
+
+/// A change of a product to a new build in one or more regions, from the same previous build
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ChangeGroup<'a> {
+    pub(crate) product: &'a str,
+    pub(crate) previous_version: Option<&'a str>,
+    pub(crate) previous_build: Option<&'a str>,
+    pub(crate) version: &'a str,
+    pub(crate) build: &'a str,
+    pub(crate) regions: Vec<&'a str>,
+}
+
+/// Group changes that take a product from the same previous build to the same new build, so
+/// they can be announced once with a list of regions. Groups are in order of their first change.
+pub(crate) fn group(changes: &[BuildChange]) -> Vec<ChangeGroup<'_>> {
+    let mut groups: Vec<ChangeGroup<'_>> = Vec::new();
+    for change in changes {
+        let existing = groups.iter_mut().find(|group| {
+            group.product == change.product
+                && group.previous_build == change.previous_build.as_deref()
+                && group.build == change.build
+        });
+        match existing {
+            Some(group) => group.regions.push(&change.region),
+            None => groups.push(ChangeGroup {
+                product: &change.product,
+                previous_version: change.previous_version.as_deref(),
+                previous_build: change.previous_build.as_deref(),
+                version: &change.version,
+                build: &change.build,
+                regions: vec![&change.region],
+            }),
+        }
+    }
+    groups
+}
+
+/// A one-line description of groups of changes, such as `World of Warcraft 10.2.6.53840, World of
+/// Warcraft Classic 1.15.2.53801`
+pub(crate) fn headline(groups: &[ChangeGroup<'_>]) -> String {
+    groups
+        .iter()
+        .map(|group| {
+            format!(
+                "{} {}.{}",
+                product_display_name(group.product).unwrap_or(group.product),
+                group.version,
+                group.build
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let change = |region: &str, previous_build: Option<&str>| BuildChange {
+            product: "wow".to_owned(),
+            region: region.to_owned(),
+            previous_version: previous_build.map(|_| "10.2.5".to_owned()),
+            previous_build: previous_build.map(str::to_owned),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        };
+        let changes = vec![
+            change("us", Some("53584")),
+            change("eu", Some("53584")),
+            change("kr", None),
+        ];
+
+        let groups = group(&changes);
+        assert_eq!(
+            groups,
+            vec![
+                ChangeGroup {
+                    product: "wow",
+                    previous_version: Some("10.2.5"),
+                    previous_build: Some("53584"),
+                    version: "10.2.6",
+                    build: "53840",
+                    regions: vec!["us", "eu"],
+                },
+                ChangeGroup {
+                    product: "wow",
+                    previous_version: None,
+                    previous_build: None,
+                    version: "10.2.6",
+                    build: "53840",
+                    regions: vec!["kr"],
+                },
+            ]
+        );
+        assert_eq!(
+            headline(&groups),
+            "World of Warcraft 10.2.6.53840, World of Warcraft 10.2.6.53840"
+        );
+    }
+}
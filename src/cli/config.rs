@@ -0,0 +1,463 @@
+//! The configuration file, for settings too involved for flags
+use crate::cli::alert::Condition;
+use croner::Cron;
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use wownow::prelude::*;
+
+/// The configuration file, read from `--config` or the default location if it exists
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) notify: NotifyConfig,
+
+    #[serde(default)]
+    pub(crate) daemon: DaemonConfig,
+
+    /// Named sets of products, each written as a `[sets.NAME]` table, to select with `--set`
+    #[serde(default)]
+    pub(crate) sets: BTreeMap<String, ProductSet>,
+
+    #[serde(default)]
+    pub(crate) timeouts: Timeouts,
+}
+
+/// How long to wait for each request to the version server before giving up on it
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Timeouts {
+    /// The timeout of every product without one of its own, such as `10s`, or none to wait as
+    /// long as the server takes
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub(crate) default: Option<Duration>,
+
+    /// The timeouts of particular products, written as a `[timeouts.products]` table, such as
+    /// longer ones for products served from distant mirrors
+    #[serde(default, deserialize_with = "deserialize_durations")]
+    pub(crate) products: BTreeMap<String, Duration>,
+}
+
+impl Timeouts {
+    /// A client with these timeouts
+    pub(crate) fn apply(&self, client: Client) -> Client {
+        let client = match self.default {
+            Some(timeout) => client.with_timeout(timeout),
+            None => client,
+        };
+        self.products
+            .iter()
+            .fold(client, |client, (product, timeout)| {
+                client.with_product_timeout(product, *timeout)
+            })
+    }
+}
+
+/// Deserialize a duration written like `10s` or `1m 30s`.
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a table of durations written like `10s` or `1m 30s`.
+fn deserialize_durations<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Duration>, D::Error> {
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, duration)| {
+            let duration = humantime::parse_duration(&duration)
+                .map_err(|e| serde::de::Error::custom(format!("`{key}`: {e}")))?;
+            Ok((key, duration))
+        })
+        .collect()
+}
+
+/// A named set of products, such as every Classic product
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProductSet {
+    pub(crate) products: Vec<String>,
+}
+
+/// What `wownow daemon` polls, and when
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DaemonConfig {
+    /// The schedules, each written as a `[[daemon.schedule]]` table
+    #[serde(default, rename = "schedule")]
+    pub(crate) schedules: Vec<Schedule>,
+
+    /// The alerts, each written as a `[[daemon.alert]]` table
+    #[serde(default, rename = "alert")]
+    pub(crate) alerts: Vec<Alert>,
+}
+
+/// A set of products, polled whenever a cron expression matches
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Schedule {
+    /// A cron expression in local time, such as `0 * * * *` for hourly. A sixth field at the start
+    /// gives seconds.
+    pub(crate) cron: Cron,
+
+    /// The products to poll, the live products by default
+    #[serde(default = "default_schedule_products")]
+    pub(crate) products: Vec<String>,
+}
+
+/// A condition of products to alert on once it's persisted, rather than as soon as it holds, such
+/// as regions divergent for longer than a rollout takes
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Alert {
+    pub(crate) condition: Condition,
+
+    /// The products to alert on, every polled product by default
+    #[serde(default)]
+    pub(crate) products: Option<Vec<String>>,
+
+    /// How long the condition must hold, such as `30m`, written as `for`
+    #[serde(default, rename = "for", deserialize_with = "deserialize_duration")]
+    pub(crate) persists: Option<Duration>,
+
+    /// How many consecutive polls the condition must hold in
+    #[serde(default = "default_alert_checks")]
+    pub(crate) checks: u32,
+}
+
+fn default_alert_checks() -> u32 {
+    1
+}
+
+fn default_schedule_products() -> Vec<String> {
+    crate::LIVE_PRODUCTS.map(str::to_owned).to_vec()
+}
+
+/// Where to send notifications of new builds
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NotifyConfig {
+    pub(crate) discord: Option<DiscordConfig>,
+    pub(crate) slack: Option<SlackConfig>,
+    pub(crate) email: Option<EmailConfig>,
+    pub(crate) ntfy: Option<NtfyConfig>,
+    pub(crate) pushover: Option<PushoverConfig>,
+    #[cfg(feature = "object-store")]
+    pub(crate) object_store: Option<ObjectStoreConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DiscordConfig {
+    /// The Discord webhook URL, from the channel's integration settings
+    pub(crate) url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SlackConfig {
+    /// The Slack incoming webhook URL, from the Slack app's settings
+    pub(crate) url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EmailConfig {
+    /// The SMTP server's host name
+    pub(crate) host: String,
+
+    /// The SMTP server's port, if not the default for `security`
+    pub(crate) port: Option<u16>,
+
+    #[serde(default)]
+    pub(crate) security: SmtpSecurity,
+
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+
+    /// The sender, such as `wownow <wownow@example.com>`
+    pub(crate) from: String,
+
+    /// The recipients
+    pub(crate) to: Vec<String>,
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SmtpSecurity {
+    /// Upgrade a plain connection with `STARTTLS`, on port 587 by default
+    #[default]
+    Starttls,
+
+    /// Connect with TLS from the start, on port 465 by default
+    Tls,
+
+    /// Don't secure the connection at all, on port 25 by default. Only for local relays.
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NtfyConfig {
+    /// The ntfy server, `https://ntfy.sh` by default
+    #[serde(default = "default_ntfy_server")]
+    pub(crate) server: String,
+
+    /// The topic to publish to
+    pub(crate) topic: String,
+
+    /// An access token, for protected topics
+    pub(crate) token: Option<String>,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PushoverConfig {
+    /// The application's API token
+    pub(crate) token: String,
+
+    /// The user (or group) key to notify
+    pub(crate) user: String,
+}
+
+#[cfg(feature = "object-store")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ObjectStoreConfig {
+    /// The bucket and prefix to upload under, such as `s3://bucket/prefix` or
+    /// `gs://bucket/prefix`. Credentials are read from the environment.
+    pub(crate) url: String,
+}
+
+impl Config {
+    /// Load the configuration from `path`, or if `None`, from the default location. A missing
+    /// file at the default location is an empty configuration, but a missing file at a given path
+    /// is an error.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(format!("Error reading config `{}`: {e}", path.display())),
+        };
+        toml::from_str(&contents)
+            .map_err(|e| format!("Error parsing config `{}`: {e}", path.display()))
+    }
+}
+
+impl Config {
+    /// The products of the named sets, in order and without duplicates, failing on a name that
+    /// isn't defined.
+    pub(crate) fn set_products(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let mut products: Vec<String> = Vec::new();
+        for name in names {
+            let Some(set) = self.sets.get(name) else {
+                return Err(if self.sets.is_empty() {
+                    format!("Error: unknown product set `{name}`; define it in the config file as `[sets.{name}]`")
+                } else {
+                    let names = self.sets.keys().map(String::as_str).collect::<Vec<_>>();
+                    format!(
+                        "Error: unknown product set `{name}`, expected one of {}",
+                        names.join(", ")
+                    )
+                });
+            };
+            for product in &set.products {
+                if !products.contains(product) {
+                    products.push(product.clone());
+                }
+            }
+        }
+        Ok(products)
+    }
+}
+
+/// The default configuration path (e.g., `~/.config/wownow/config.toml` on Linux)
+fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("wownow").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            [notify.discord]
+            url = "https://discord.com/api/webhooks/1/abc"
+
+            [notify.slack]
+            url = "https://hooks.slack.com/services/T0/B0/abc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.notify.discord.unwrap().url,
+            "https://discord.com/api/webhooks/1/abc"
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [notify.email]
+            host = "smtp.example.com"
+            from = "wownow <wownow@example.com>"
+            to = ["ops@example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.notify.email.unwrap().security,
+            SmtpSecurity::Starttls
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [notify.ntfy]
+            topic = "wow-builds"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.notify.ntfy.unwrap().server, "https://ntfy.sh");
+
+        let config: Config = toml::from_str(
+            r#"
+            [[daemon.schedule]]
+            cron = "* * * * Tue"
+            products = ["wow", "wowt"]
+
+            [[daemon.schedule]]
+            cron = "0 * * * *"
+            "#,
+        )
+        .unwrap();
+        let schedules = config.daemon.schedules;
+        assert_eq!(schedules.len(), 2);
+        assert_eq!(schedules[0].products, ["wow", "wowt"]);
+        assert_eq!(schedules[1].products, crate::LIVE_PRODUCTS);
+        assert!(toml::from_str::<Config>("[[daemon.schedule]]\ncron = \"soon\"").is_err());
+
+        assert!(toml::from_str::<Config>("[notify.carrier_pigeon]").is_err());
+    }
+
+    #[test]
+    fn test_alerts() {
+        let config: Config = toml::from_str(
+            r#"
+            [[daemon.alert]]
+            condition = "divergent"
+            for = "30m"
+
+            [[daemon.alert]]
+            condition = "bgdl_ahead"
+            products = ["wow"]
+            checks = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.daemon.alerts,
+            [
+                Alert {
+                    condition: Condition::Divergent,
+                    products: None,
+                    persists: Some(Duration::from_mins(30)),
+                    checks: 1,
+                },
+                Alert {
+                    condition: Condition::BgdlAhead,
+                    products: Some(vec!["wow".to_owned()]),
+                    persists: None,
+                    checks: 3,
+                },
+            ]
+        );
+        assert!(toml::from_str::<Config>("[[daemon.alert]]\ncondition = \"stale\"").is_err());
+        assert!(toml::from_str::<Config>(
+            "[[daemon.alert]]\ncondition = \"divergent\"\nfor = \"soon\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_set_products() {
+        let config: Config = toml::from_str(
+            r#"
+            [sets.classic-all]
+            products = ["wow_classic", "wow_classic_era", "wow_classic_ptr"]
+
+            [sets.retail]
+            products = ["wow", "wowt", "wow_classic"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config
+                .set_products(&["retail".to_owned(), "classic-all".to_owned()])
+                .unwrap(),
+            [
+                "wow",
+                "wowt",
+                "wow_classic",
+                "wow_classic_era",
+                "wow_classic_ptr"
+            ]
+        );
+        assert_eq!(
+            config.set_products(&["classic".to_owned()]).unwrap_err(),
+            "Error: unknown product set `classic`, expected one of classic-all, retail"
+        );
+        assert!(Config::default()
+            .set_products(&["classic".to_owned()])
+            .unwrap_err()
+            .contains("[sets.classic]"));
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let config: Config = toml::from_str(
+            r#"
+            [timeouts]
+            default = "10s"
+
+            [timeouts.products]
+            wow_classic_era = "1m 30s"
+            "#,
+        )
+        .unwrap();
+        let timeouts = config.timeouts;
+        assert_eq!(timeouts.default, Some(Duration::from_secs(10)));
+        assert_eq!(
+            timeouts.products["wow_classic_era"],
+            Duration::from_secs(90)
+        );
+        assert_eq!(Timeouts::default().default, None);
+
+        assert!(toml::from_str::<Config>("[timeouts]\ndefault = \"soon\"").is_err());
+        let error = toml::from_str::<Config>("[timeouts.products]\nwow = \"soon\"").unwrap_err();
+        assert!(error.to_string().contains("`wow`"), "{error}");
+    }
+}
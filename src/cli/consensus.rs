@@ -0,0 +1,269 @@
+//! Comparisons of a product's versions as served by each regional version server
+use crate::cli::{history::to_hex, replay};
+use clap::Args;
+use serde::Serialize;
+use tokio::task::JoinSet;
+use wownow::prelude::*;
+
+/// Compare a product's versions across regional version servers
+#[derive(Args, Debug)]
+pub(crate) struct ConsensusArgs {
+    /// The product to compare
+    #[arg(long, default_value = "wow")]
+    product: String,
+
+    /// The regions whose servers to query, at `{region}.version.battle.net`. Can be given
+    /// multiple times.
+    #[arg(
+        long = "server",
+        value_name = "REGION",
+        default_values = ["us", "eu", "kr"]
+    )]
+    servers: Vec<String>,
+}
+
+/// What a server answered
+#[derive(Debug, PartialEq, Serialize)]
+struct ServerStatus {
+    server: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seqn: Option<u32>,
+
+    /// Why the server's versions couldn't be fetched, if they couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A server's record of a region
+#[derive(Debug, PartialEq, Serialize)]
+struct ServerRecord {
+    server: String,
+
+    /// The versions name, or none if the server has no record of the region
+    versions_name: Option<String>,
+
+    /// The build config, or none if the server has no record of the region
+    build_config: Option<String>,
+}
+
+/// A region whose record isn't the same on every server
+#[derive(Debug, PartialEq, Serialize)]
+struct RecordDifference {
+    region: String,
+    records: Vec<ServerRecord>,
+}
+
+/// How the servers that answered compare
+#[derive(Debug, PartialEq, Serialize)]
+struct Consensus {
+    product: String,
+
+    /// Whether every server answered with the same sequence number and records. When they don't,
+    /// a change is still propagating between servers.
+    agree: bool,
+
+    servers: Vec<ServerStatus>,
+
+    /// The regions whose records differ between servers
+    differences: Vec<RecordDifference>,
+
+    /// The regions not on the build most regions have, by the first server to answer. When the
+    /// servers agree, these are genuine differences between regions, such as a staged rollout.
+    divergent_regions: Vec<String>,
+}
+
+/// Compare the versions responses of servers, in the order the servers were given.
+fn compare(
+    product: &str,
+    responses: &[(String, std::result::Result<VersionsResponse, String>)],
+) -> Consensus {
+    let servers = responses
+        .iter()
+        .map(|(server, response)| match response {
+            Ok(response) => ServerStatus {
+                server: server.clone(),
+                seqn: Some(response.seqn),
+                error: None,
+            },
+            Err(e) => ServerStatus {
+                server: server.clone(),
+                seqn: None,
+                error: Some(e.clone()),
+            },
+        })
+        .collect::<Vec<_>>();
+    let answered = responses
+        .iter()
+        .filter_map(|(server, response)| Some((server, response.as_ref().ok()?)))
+        .collect::<Vec<_>>();
+
+    let mut regions = Vec::new();
+    for (_, response) in &answered {
+        for record in &response.records {
+            if !regions.contains(&&record.region) {
+                regions.push(&record.region);
+            }
+        }
+    }
+    regions.sort();
+
+    let differences = regions
+        .into_iter()
+        .filter_map(|region| {
+            let records = answered
+                .iter()
+                .map(|(server, response)| (*server, response.region(region)))
+                .collect::<Vec<_>>();
+            if records.windows(2).all(|pair| pair[0].1 == pair[1].1) {
+                return None;
+            }
+            Some(RecordDifference {
+                region: region.clone(),
+                records: records
+                    .into_iter()
+                    .map(|(server, record)| ServerRecord {
+                        server: server.clone(),
+                        versions_name: record.map(|record| record.versions_name.clone()),
+                        build_config: record.map(|record| to_hex(&record.build_config)),
+                    })
+                    .collect(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let seqns_agree = answered
+        .windows(2)
+        .all(|pair| pair[0].1.seqn == pair[1].1.seqn);
+    let divergent_regions = answered
+        .first()
+        .and_then(|(_, response)| Product::try_from((product, *response)).ok())
+        .map(|versions| versions.divergent_regions().to_vec())
+        .unwrap_or_default();
+
+    Consensus {
+        product: product.to_owned(),
+        agree: seqns_agree && differences.is_empty(),
+        servers,
+        differences,
+        divergent_regions,
+    }
+}
+
+pub(crate) async fn run(args: ConsensusArgs, pretty_print: bool) -> crate::Result {
+    let mut set = JoinSet::new();
+    for (index, server) in args.servers.iter().enumerate() {
+        let client = replay::region_client(server);
+        let product = args.product.clone();
+        set.spawn(async move {
+            let response = client
+                .get_versions(&product)
+                .await
+                .map_err(|e| e.to_string());
+            (index, response)
+        });
+    }
+
+    let mut responses = Vec::new();
+    while let Some(join_result) = set.join_next().await {
+        responses.push(join_result.map_err(|e| format!("Error joining task: {e}"))?);
+    }
+    responses.sort_by_key(|(index, _)| *index);
+    let responses = responses
+        .into_iter()
+        .map(|(index, response)| (args.servers[index].clone(), response))
+        .collect::<Vec<_>>();
+
+    if responses.iter().all(|(_, response)| response.is_err()) {
+        let errors = responses
+            .iter()
+            .filter_map(|(server, response)| {
+                Some(format!("{server}: {}", response.as_ref().err()?))
+            })
+            .collect::<Vec<_>>();
+        return Err(format!(
+            "Error getting `{}` versions from every server:\n{}",
+            args.product,
+            errors.join("\n")
+        ));
+    }
+    crate::to_json(&compare(&args.product, &responses), pretty_print)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TestVersions;
+
+    fn response(seqn: u32, records: &[(&str, &str)]) -> VersionsResponse {
+        records
+            .iter()
+            .fold(
+                TestVersions::new().seqn(seqn),
+                |versions, (region, build_config)| {
+                    versions.region_with_build_config(region, "10.2.5.53584", build_config)
+                },
+            )
+            .response()
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = "47e9e06f8371afb141e22614a912acc8";
+        let new = "0f1e2d3c4b5a69788796a5b4c3d2e1f0";
+
+        let agreed = compare(
+            "wow",
+            &[
+                (
+                    "us".to_owned(),
+                    Ok(response(1, &[("us", old), ("eu", old)])),
+                ),
+                (
+                    "eu".to_owned(),
+                    Ok(response(1, &[("us", old), ("eu", old)])),
+                ),
+                ("kr".to_owned(), Err("connection refused".to_owned())),
+            ],
+        );
+        assert!(agreed.agree);
+        assert!(agreed.differences.is_empty());
+        assert_eq!(
+            agreed.servers[2].error.as_deref(),
+            Some("connection refused")
+        );
+
+        let propagating = compare(
+            "wow",
+            &[
+                (
+                    "us".to_owned(),
+                    Ok(response(2, &[("us", new), ("eu", old)])),
+                ),
+                (
+                    "eu".to_owned(),
+                    Ok(response(1, &[("us", old), ("eu", old)])),
+                ),
+            ],
+        );
+        assert!(!propagating.agree);
+        assert_eq!(
+            propagating.differences,
+            [RecordDifference {
+                region: "us".to_owned(),
+                records: vec![
+                    ServerRecord {
+                        server: "us".to_owned(),
+                        versions_name: Some("10.2.5.53584".to_owned()),
+                        build_config: Some(new.to_owned()),
+                    },
+                    ServerRecord {
+                        server: "eu".to_owned(),
+                        versions_name: Some("10.2.5.53584".to_owned()),
+                        build_config: Some(old.to_owned()),
+                    },
+                ],
+            }]
+        );
+    }
+}
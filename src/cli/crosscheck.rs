@@ -0,0 +1,262 @@
+//! Comparisons of products' versions from the version server with those of a community API,
+//! for independent confirmation of a new build
+use crate::cli::{history::to_hex, replay};
+use clap::Args;
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use wownow::prelude::*;
+
+/// The builds API of wago.tools, which lists the builds of each product, newest first
+const DEFAULT_SOURCE_URL: &str = "https://wago.tools/api/builds";
+
+/// Compare products' versions with those of a community API
+#[derive(Args, Debug)]
+pub(crate) struct CrosscheckArgs {
+    /// The products to compare. Can be given multiple times.
+    #[arg(
+        long = "product",
+        value_name = "PRODUCT",
+        default_values = ["wow", "wow_classic", "wow_classic_era"]
+    )]
+    products: Vec<String>,
+
+    /// The URL of the community API: a JSON object of each product's builds, as a list newest
+    /// first or a single build, each with a `version` (e.g., `10.2.6.53840`) and optionally a
+    /// `build_config`.
+    #[arg(long, value_name = "URL", default_value = DEFAULT_SOURCE_URL)]
+    source_url: String,
+}
+
+/// A product's build as one source gives it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct SourceBuild {
+    /// The versions name, such as `10.2.6.53840`
+    version: String,
+
+    /// The build config, if the source gives it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_config: Option<String>,
+}
+
+/// How the sources compare on one product
+#[derive(Debug, PartialEq, Serialize)]
+struct ProductCheck {
+    product: String,
+
+    /// Whether both sources gave a build, and their versions and build configs match
+    agree: bool,
+
+    /// The build most regions have on the version server, if its versions were fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_server: Option<SourceBuild>,
+
+    /// The newest build in the community API, if it lists the product
+    #[serde(skip_serializing_if = "Option::is_none")]
+    community: Option<SourceBuild>,
+
+    /// The fields that differ between the sources, such as `version` or `build_config`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    differences: Vec<&'static str>,
+
+    /// Why a source has no build of the product
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+/// How the sources compare on every product
+#[derive(Debug, PartialEq, Serialize)]
+struct Crosscheck {
+    source_url: String,
+
+    /// Whether the sources agree on every product
+    agree: bool,
+
+    products: Vec<ProductCheck>,
+}
+
+/// The build most regions of a versions response have, leaving out those mid-rollout.
+fn majority_build(product: &str, response: &VersionsResponse) -> Result<SourceBuild, String> {
+    let versions = Product::try_from((product, response)).map_err(|e| e.to_string())?;
+    let divergent = versions.divergent_regions();
+    let record = response
+        .records
+        .iter()
+        .find(|record| !divergent.contains(&record.region))
+        .ok_or_else(|| format!("the version server has no regions of `{product}`"))?;
+    Ok(SourceBuild {
+        version: record.versions_name.clone(),
+        build_config: Some(to_hex(&record.build_config)),
+    })
+}
+
+/// The newest build of each product in the community API's response. Products whose builds
+/// lack a version are left out.
+fn community_builds(body: &Value) -> Result<HashMap<String, SourceBuild>, String> {
+    let Value::Object(products) = body else {
+        return Err("expected a JSON object of products".to_owned());
+    };
+    let build = |build: &Value| {
+        Some(SourceBuild {
+            version: build.get("version")?.as_str()?.to_owned(),
+            build_config: build
+                .get("build_config")
+                .and_then(Value::as_str)
+                .filter(|config| !config.is_empty())
+                .map(str::to_ascii_lowercase),
+        })
+    };
+    Ok(products
+        .iter()
+        .filter_map(|(product, builds)| {
+            let newest = match builds {
+                Value::Array(builds) => builds.first()?,
+                single => single,
+            };
+            Some((product.clone(), build(newest)?))
+        })
+        .collect())
+}
+
+/// Compare a product's builds from the two sources.
+fn check(
+    product: &str,
+    version_server: Result<SourceBuild, String>,
+    community: Option<&SourceBuild>,
+) -> ProductCheck {
+    let mut errors = Vec::new();
+    let version_server = version_server.map_err(|e| errors.push(e)).ok();
+    if community.is_none() {
+        errors.push(format!("the community API has no builds of `{product}`"));
+    }
+    let mut differences = Vec::new();
+    if let (Some(ours), Some(theirs)) = (&version_server, community) {
+        if ours.version != theirs.version {
+            differences.push("version");
+        }
+        if theirs
+            .build_config
+            .as_ref()
+            .is_some_and(|config| Some(config) != ours.build_config.as_ref())
+        {
+            differences.push("build_config");
+        }
+    }
+    ProductCheck {
+        product: product.to_owned(),
+        agree: errors.is_empty() && differences.is_empty(),
+        version_server,
+        community: community.cloned(),
+        differences,
+        errors,
+    }
+}
+
+/// Fetch the community API's builds.
+async fn get_community_builds(url: &str) -> Result<HashMap<String, SourceBuild>, String> {
+    let body = reqwest::get(url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("Error getting `{url}`: {e}"))?
+        .text()
+        .await
+        .map_err(|e| format!("Error reading `{url}`: {e}"))?;
+    let body: Value =
+        serde_json::from_str(&body).map_err(|e| format!("Error parsing `{url}`: {e}"))?;
+    community_builds(&body).map_err(|e| format!("Error parsing `{url}`: {e}"))
+}
+
+pub(crate) async fn run(args: CrosscheckArgs, pretty_print: bool) -> crate::Result {
+    let client = replay::client();
+    let (community, responses) = tokio::join!(
+        get_community_builds(&args.source_url),
+        join_all(
+            args.products
+                .iter()
+                .map(|product| client.get_versions(product))
+        )
+    );
+    let community = community?;
+
+    let products = args
+        .products
+        .iter()
+        .zip(responses)
+        .map(|(product, response)| {
+            let version_server = response
+                .map_err(|e| format!("Error getting `{product}` versions: {e}"))
+                .and_then(|response| majority_build(product, &response));
+            check(product, version_server, community.get(product))
+        })
+        .collect::<Vec<_>>();
+    crate::to_json(
+        &Crosscheck {
+            source_url: args.source_url,
+            agree: products.iter().all(|product| product.agree),
+            products,
+        },
+        pretty_print,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_community_builds() {
+        let builds = community_builds(&json!({
+            "wow": [
+                { "version": "10.2.6.53840", "build_config": "0F1E2D3C4B5A69788796A5B4C3D2E1F0" },
+                { "version": "10.2.5.53584", "build_config": "47e9e06f8371afb141e22614a912acc8" },
+            ],
+            "wow_classic": { "version": "4.4.0.53627" },
+            "wowt": [],
+        }))
+        .unwrap();
+        assert_eq!(builds.len(), 2);
+        assert_eq!(
+            builds["wow"],
+            SourceBuild {
+                version: "10.2.6.53840".to_owned(),
+                build_config: Some("0f1e2d3c4b5a69788796a5b4c3d2e1f0".to_owned()),
+            }
+        );
+        assert_eq!(builds["wow_classic"].build_config, None);
+        assert!(community_builds(&json!([])).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 1\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f\n\
+            eu|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f\n\
+            kr|0f1e2d3c4b5a69788796a5b4c3d2e1f0|74093d42ce367c7a67f2831dbf64088d||53840|10.2.6.53840|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let ours = majority_build("wow", &response).unwrap();
+        assert_eq!(ours.version, "10.2.5.53584");
+
+        let same = SourceBuild {
+            version: "10.2.5.53584".to_owned(),
+            build_config: None,
+        };
+        assert!(check("wow", Ok(ours.clone()), Some(&same)).agree);
+
+        let newer = SourceBuild {
+            version: "10.2.6.53840".to_owned(),
+            build_config: Some("0f1e2d3c4b5a69788796a5b4c3d2e1f0".to_owned()),
+        };
+        let checked = check("wow", Ok(ours.clone()), Some(&newer));
+        assert!(!checked.agree);
+        assert_eq!(checked.differences, ["version", "build_config"]);
+
+        let checked = check("wow", Ok(ours), None);
+        assert!(!checked.agree);
+        assert_eq!(checked.errors.len(), 1);
+    }
+}
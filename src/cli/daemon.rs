@@ -0,0 +1,712 @@
+//! A long-running process polling sets of products on cron schedules, recording history and
+//! publishing new builds
+use crate::cli::{
+    alert::{Condition, ConditionState, Transition},
+    config::{Alert, Config, Schedule},
+    history::{self, History},
+    replay,
+    shutdown::{self, ShutdownArgs},
+    sinks,
+    systemd::{Systemd, SystemdArgs},
+    telemetry,
+    webhook::WebhookArgs,
+};
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Instant,
+};
+use tokio::task::JoinSet;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use wownow::prelude::*;
+
+/// Poll products on the schedules in the config file, logging what happens as JSON lines
+#[derive(Args, Debug)]
+pub(crate) struct DaemonArgs {
+    /// Record the polled versions in a history database at this path, or in the default location
+    /// if no path is given.
+    #[arg(long, value_name = "PATH", num_args(0..=1))]
+    #[allow(clippy::option_option)]
+    history_db: Option<Option<PathBuf>>,
+
+    /// Compare the first poll to the versions last recorded in the history database, so builds
+    /// released while the daemon was stopped are logged and notified as new, and a restart
+    /// doesn't forget what it had seen.
+    #[arg(long, requires("history_db"))]
+    preload_history: bool,
+
+    /// How to log new builds: as a JSON line, as a line per build like
+    /// `wow us: 10.2.5.53584 → 10.2.6.53840`, colored on a terminal, or both. Other events are
+    /// always JSON lines.
+    #[arg(long, value_enum, default_value_t = ChangeFormat::Json)]
+    format: ChangeFormat,
+
+    /// Print only an event per new build to stdout, as a JSON line with its `type`, `product`,
+    /// `region`, `old` and `new` builds, and `timestamp`, for log shippers and `jq`. Everything
+    /// else is logged to stderr instead.
+    #[arg(long, conflicts_with("format"))]
+    events: bool,
+
+    /// Exit the first time new builds are found, once they've been logged and notified, with
+    /// status 0. Exits with status 1 if stopped by a signal before then. For running a command on
+    /// the next new build, like `wownow daemon --exit-on-change && ./do-something.sh`.
+    #[arg(long)]
+    exit_on_change: bool,
+
+    #[command(flatten)]
+    webhooks: WebhookArgs,
+
+    #[command(flatten)]
+    systemd: SystemdArgs,
+
+    #[command(flatten)]
+    shutdown: ShutdownArgs,
+
+    #[cfg(feature = "otlp")]
+    #[command(flatten)]
+    otlp: telemetry::OtlpArgs,
+}
+
+/// A format to log new builds in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ChangeFormat {
+    /// A `new_builds` JSON line, like every other event
+    Json,
+
+    /// A line per new build, for people to read
+    Text,
+
+    /// The JSON line, then a line per new build
+    Both,
+}
+
+/// A new build, as printed with `--events`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BuildEvent<'a> {
+    /// `new_build` for a region with a different build than before, or `new_region` for a region
+    /// the product wasn't in before
+    r#type: &'static str,
+
+    product: &'a str,
+    region: &'a str,
+
+    /// The previous build, such as `10.2.5.53584`, or null for a new region
+    old: Option<String>,
+
+    /// The new build, such as `10.2.6.53840`
+    new: String,
+
+    /// When the new build was fetched
+    timestamp: DateTime<Utc>,
+}
+
+impl<'a> BuildEvent<'a> {
+    fn new(change: &'a BuildChange, timestamp: DateTime<Utc>) -> Self {
+        let old = match (&change.previous_version, &change.previous_build) {
+            (Some(version), Some(build)) => Some(format!("{version}.{build}")),
+            _ => None,
+        };
+        Self {
+            r#type: if old.is_some() {
+                "new_build"
+            } else {
+                "new_region"
+            },
+            product: &change.product,
+            region: &change.region,
+            old,
+            new: format!("{}.{}", change.version, change.build),
+            timestamp,
+        }
+    }
+}
+
+/// SGR escape sequences, to color the lines of new builds
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Render a new build as a line like `wow us: 10.2.5.53584 → 10.2.6.53840`, with the product and
+/// region bold, the old build red and the new one green if `color`. A region the product wasn't
+/// in before shows `(new)` for the old build.
+fn render_change(change: &BuildChange, color: bool) -> String {
+    let previous = match (&change.previous_version, &change.previous_build) {
+        (Some(version), Some(build)) => format!("{version}.{build}"),
+        _ => "(new)".to_owned(),
+    };
+    let current = format!("{}.{}", change.version, change.build);
+    let (product, region) = (&change.product, &change.region);
+    if color {
+        format!("{BOLD}{product} {region}{RESET}: {RED}{previous}{RESET} → {GREEN}{current}{RESET}")
+    } else {
+        format!("{product} {region}: {previous} → {current}")
+    }
+}
+
+/// Whether to color the lines of new builds: when stdout is a terminal, unless `NO_COLOR` is set
+/// (<https://no-color.org>).
+fn use_color() -> bool {
+    std::io::stdout().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// The state shared by every schedule
+struct Daemon {
+    client: Client,
+
+    /// The latest versions of every product polled so far, to detect new builds against
+    known: Mutex<HashMap<String, Product>>,
+
+    history: Option<Mutex<History>>,
+    sinks: FanOut,
+    systemd: Systemd,
+
+    /// The notifications being sent
+    notifications: TaskTracker,
+
+    /// How to log new builds
+    format: ChangeFormat,
+
+    /// Whether to color the lines of new builds
+    color: bool,
+
+    /// Cancelled to stop every schedule, on a signal or, with `--exit-on-change`, new builds
+    stop: CancellationToken,
+
+    /// Whether to print an event per new build in place of the `new_builds` line
+    events: bool,
+
+    exit_on_change: bool,
+
+    /// Whether new builds have been found since the daemon started
+    changed: AtomicBool,
+
+    alerts: Vec<Alert>,
+
+    /// How long each alerted-on condition has held of each product, kept in the history database
+    /// too if there is one, so a restart doesn't alert again or start the wait over
+    conditions: Mutex<HashMap<(String, Condition), ConditionState>>,
+}
+
+/// Whether to log to stderr, leaving stdout to the events of new builds
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Log an event to stdout (or stderr, with `--events`) as a line of JSON, with the time it
+/// happened and its `fields`.
+fn log(event: &str, fields: Value) {
+    let mut line = json!({ "time": Utc::now(), "event": event });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    if LOG_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+impl Daemon {
+    /// Poll the versions of some products, then record them and publish any new builds.
+    async fn poll(&self, products: &[String]) {
+        let start = Instant::now();
+        let mut set = JoinSet::new();
+        for product in products {
+            let client = self.client.clone();
+            let product = product.clone();
+            let with_bgdl = self
+                .alerts
+                .iter()
+                .any(|alert| alert.condition == Condition::BgdlAhead && alert.applies_to(&product));
+            set.spawn(telemetry::in_current_span(async move {
+                let result =
+                    telemetry::traced("versions", Some(&product), client.get_versions(&product))
+                        .await;
+                let bgdl = if with_bgdl && result.is_ok() {
+                    Some(telemetry::traced("bgdl", Some(&product), client.get_bgdl(&product)).await)
+                } else {
+                    None
+                };
+                (product, result, bgdl)
+            }));
+        }
+
+        let mut fetch = VersionsFetch::new();
+        let mut responses = Vec::new();
+        while let Some(join_result) = set.join_next().await {
+            match join_result {
+                Ok((product, Ok(response), bgdl)) => {
+                    match Product::try_from((product.as_str(), &response)) {
+                        Ok(found) => fetch.add_product(with_bgdl(found, bgdl)),
+                        Err(e) => {
+                            log(
+                                "poll_failed",
+                                json!({ "product": product, "error": e.to_string() }),
+                            );
+                            continue;
+                        }
+                    }
+                    responses.push((product, response));
+                }
+                Ok((product, Err(e), _)) => {
+                    log(
+                        "poll_failed",
+                        json!({ "product": product, "error": e.to_string() }),
+                    );
+                }
+                Err(e) => log("poll_failed", json!({ "error": e.to_string() })),
+            }
+        }
+        self.systemd.status(&format!(
+            "Polled {} of {} products at {}",
+            responses.len(),
+            products.len(),
+            fetch.retrieval_datetime()
+        ));
+        log(
+            "polled",
+            json!({
+                "products": responses.iter().map(|(product, _)| product).collect::<Vec<_>>(),
+                "duration_ms": start.elapsed().as_millis(),
+            }),
+        );
+
+        self.record_history(&fetch, &responses);
+        self.check_alerts(&fetch);
+        if let Some(event) = self.detect(&fetch) {
+            self.log_changes(&event);
+            self.changed.store(true, Ordering::Relaxed);
+            if self.exit_on_change {
+                self.stop.cancel();
+            }
+            if !self.sinks.is_empty() {
+                let sinks = self.sinks.clone();
+                self.notifications.spawn(async move {
+                    for failure in sinks.publish(&event).await {
+                        log(
+                            "notification_failed",
+                            json!({ "sink": failure.sink, "error": failure.error.to_string() }),
+                        );
+                    }
+                });
+            }
+        }
+    }
+
+    /// Log new builds in the chosen format.
+    fn log_changes(&self, event: &ChangeEvent) {
+        if self.events {
+            for change in &event.changes {
+                println!("{}", json!(BuildEvent::new(change, event.detected_at)));
+            }
+            return;
+        }
+        if self.format != ChangeFormat::Text {
+            log("new_builds", json!({ "changes": event.changes }));
+        }
+        if self.format != ChangeFormat::Json {
+            for change in &event.changes {
+                println!("{}", render_change(change, self.color));
+            }
+        }
+    }
+
+    /// Record the responses of a poll in the history database, if there is one.
+    fn record_history(&self, fetch: &VersionsFetch, responses: &[(String, VersionsResponse)]) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let mut history = history.lock().unwrap_or_else(PoisonError::into_inner);
+        for (product, response) in responses {
+            if let Err(e) = history.record(fetch.retrieval_datetime(), product, response) {
+                log(
+                    "history_failed",
+                    json!({ "product": product, "error": e.to_string() }),
+                );
+            }
+        }
+    }
+
+    /// Check the conditions of the alerts on the products of a poll, logging an `alert` when one
+    /// has persisted long enough, and an `alert_resolved` when it stops holding after that.
+    fn check_alerts(&self, fetch: &VersionsFetch) {
+        let mut conditions = self
+            .conditions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let now = fetch.retrieval_datetime();
+        let mut checked = Vec::new();
+        for alert in &self.alerts {
+            for product in fetch.products() {
+                let key = (product.name().to_owned(), alert.condition);
+                if !alert.applies_to(product.name()) || checked.contains(&key) {
+                    continue;
+                }
+                let Some(regions) = alert.condition.regions(product) else {
+                    continue;
+                };
+                checked.push(key.clone());
+                let previous = conditions.get(&key).copied();
+                let current = ConditionState::advance(previous, !regions.is_empty(), now);
+                for alert in self
+                    .alerts
+                    .iter()
+                    .filter(|other| other.condition == alert.condition)
+                    .filter(|other| other.applies_to(product.name()))
+                {
+                    let fields = json!({
+                        "product": product.name(),
+                        "condition": alert.condition.as_str(),
+                        "since": previous.or(current).map(|state| state.since),
+                    });
+                    match alert.transition(previous.as_ref(), current.as_ref()) {
+                        Some(Transition::Fired) => {
+                            let mut fields = fields;
+                            fields["regions"] = json!(regions);
+                            fields["checks"] = json!(current.map(|state| state.checks));
+                            log("alert", fields);
+                        }
+                        Some(Transition::Resolved) => log("alert_resolved", fields),
+                        None => {}
+                    }
+                }
+                self.record_condition(&key, current.as_ref());
+                match current {
+                    Some(state) => conditions.insert(key, state),
+                    None => conditions.remove(&key),
+                };
+            }
+        }
+    }
+
+    /// Record how long a condition has held of a product in the history database, if there is
+    /// one.
+    fn record_condition(
+        &self,
+        (product, condition): &(String, Condition),
+        state: Option<&ConditionState>,
+    ) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = history.set_condition_state(product, *condition, state) {
+            log(
+                "history_failed",
+                json!({ "product": product, "error": e.to_string() }),
+            );
+        }
+    }
+
+    /// The new builds in a poll, compared to the last poll of each product by any schedule
+    fn detect(&self, fetch: &VersionsFetch) -> Option<ChangeEvent> {
+        let mut known = self.known.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut previous = VersionsFetch::new();
+        for product in fetch.products() {
+            if let Some(known_product) = known.get(product.name()) {
+                previous.add_product(known_product.clone());
+            }
+        }
+        for product in fetch.products() {
+            known.insert(product.name().to_owned(), product.clone());
+        }
+        ChangeEvent::detect(&previous, fetch)
+    }
+}
+
+/// A product with its background download set from a response, if one was fetched. A download
+/// that couldn't be fetched or read is logged and left unset.
+fn with_bgdl(mut product: Product, bgdl: Option<ApiResult<VersionsResponse>>) -> Product {
+    let name = product.name().to_owned();
+    let bgdl = match bgdl {
+        None => return product,
+        Some(Ok(response)) => {
+            Product::try_from((name.as_str(), &response)).map_err(|e| e.to_string())
+        }
+        Some(Err(e)) => Err(e.to_string()),
+    };
+    match bgdl {
+        Ok(bgdl) => product.set_bgdl(bgdl),
+        Err(e) => log(
+            "poll_failed",
+            json!({ "product": name, "endpoint": "bgdl", "error": e }),
+        ),
+    }
+    product
+}
+
+/// Poll a schedule's products whenever its cron expression matches, until `shutdown` is
+/// cancelled.
+async fn run_schedule(daemon: Arc<Daemon>, schedule: Schedule, shutdown: CancellationToken) {
+    loop {
+        let now = Local::now();
+        let next = match schedule.cron.find_next_occurrence(&now, false) {
+            Ok(next) => next,
+            Err(e) => {
+                log(
+                    "schedule_failed",
+                    json!({ "cron": schedule.cron.pattern.to_string(), "error": e.to_string() }),
+                );
+                return;
+            }
+        };
+        tokio::select! {
+            () = tokio::time::sleep((next - now).to_std().unwrap_or_default()) => {}
+            () = shutdown.cancelled() => return,
+        }
+        telemetry::in_span("poll", daemon.poll(&schedule.products)).await;
+    }
+}
+
+pub(crate) async fn run(args: DaemonArgs, config: Config) -> crate::Result {
+    let systemd = Systemd::new(&args.systemd)?;
+    #[cfg(feature = "otlp")]
+    let telemetry = telemetry::Telemetry::new(&args.otlp)?;
+    let schedules = config.daemon.schedules;
+    if schedules.is_empty() {
+        return Err(
+            "Error: no schedules to run; add a `[[daemon.schedule]]` to the config file".to_owned(),
+        );
+    }
+    let history_db = args.history_db.map(history::resolve_path).transpose()?;
+    let history = match history_db {
+        None => None,
+        Some(path) => Some(
+            History::open(&path)
+                .map_err(|e| format!("Error opening history database `{}`: {e}", path.display()))?,
+        ),
+    };
+    let conditions = match &history {
+        Some(history) if !config.daemon.alerts.is_empty() => history
+            .condition_states()
+            .map_err(|e| format!("Error reading history: {e}"))?,
+        _ => HashMap::new(),
+    };
+    let known = match &history {
+        Some(history) if args.preload_history => history
+            .latest_products()
+            .map_err(|e| format!("Error reading history: {e}"))?
+            .into_iter()
+            .map(|product| (product.name().to_owned(), product))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    LOG_TO_STDERR.store(args.events, Ordering::Relaxed);
+    let daemon = Arc::new(Daemon {
+        client: config.timeouts.apply(replay::client()),
+        known: Mutex::new(known),
+        history: history.map(Mutex::new),
+        sinks: sinks::build(&args.webhooks, config.notify)?,
+        systemd,
+        notifications: TaskTracker::new(),
+        format: args.format,
+        color: use_color(),
+        events: args.events,
+        stop: CancellationToken::new(),
+        exit_on_change: args.exit_on_change,
+        changed: AtomicBool::new(false),
+        alerts: config.daemon.alerts,
+        conditions: Mutex::new(conditions),
+    });
+
+    // poll everything once at the start, so the first scheduled poll has something to compare to
+    let mut products: Vec<String> = Vec::new();
+    for schedule in &schedules {
+        for product in &schedule.products {
+            if !products.contains(product) {
+                products.push(product.clone());
+            }
+        }
+    }
+    log(
+        "started",
+        json!({
+            "schedules": schedules
+                .iter()
+                .map(|schedule| json!({
+                    "cron": schedule.cron.pattern.to_string(),
+                    "products": schedule.products,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    );
+    telemetry::in_span("poll", daemon.poll(&products)).await;
+    systemd.ready(&format!("Running {} schedules", schedules.len()));
+
+    let token = daemon.stop.clone();
+    let mut set = JoinSet::new();
+    for schedule in schedules {
+        set.spawn(run_schedule(Arc::clone(&daemon), schedule, token.clone()));
+    }
+    let work = async {
+        while set.join_next().await.is_some() {}
+        // the polls in progress have finished, leaving the notifications they send
+        daemon.notifications.close();
+        daemon.notifications.wait().await;
+    };
+    let result = shutdown::run_until_signal(&args.shutdown, &token, systemd, work).await;
+    #[cfg(feature = "otlp")]
+    telemetry.shutdown();
+    result?;
+    if token.is_cancelled() {
+        let changed = daemon.changed.load(Ordering::Relaxed);
+        log("stopped", json!({ "changed": changed }));
+        if args.exit_on_change && !changed {
+            return Err("Error: stopped before any new builds were found".to_owned());
+        }
+        Ok(String::new())
+    } else {
+        Err("Error: every schedule stopped".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TestVersions;
+
+    fn fetch(product: &str, regions: &[(&str, &str)]) -> VersionsFetch {
+        TestVersions::new().regions(regions).fetch(product)
+    }
+
+    fn daemon(alerts: Vec<Alert>) -> Daemon {
+        Daemon {
+            client: Client::new(),
+            known: Mutex::new(HashMap::new()),
+            history: None,
+            sinks: FanOut::new(),
+            systemd: Systemd::default(),
+            notifications: TaskTracker::new(),
+            format: ChangeFormat::Json,
+            color: false,
+            events: false,
+            stop: CancellationToken::new(),
+            exit_on_change: false,
+            changed: AtomicBool::new(false),
+            alerts,
+            conditions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let daemon = daemon(Vec::new());
+
+        assert!(daemon
+            .detect(&fetch("wow", &[("us", "10.2.5.53584")]))
+            .is_none());
+        // a poll of another product, as by another schedule, doesn't disturb what's known of `wow`
+        assert!(daemon
+            .detect(&fetch("wow_classic", &[("us", "1.15.1.53623")]))
+            .is_none());
+        let event = daemon
+            .detect(&fetch("wow", &[("us", "10.2.6.53840")]))
+            .unwrap();
+        assert_eq!(event.changes.len(), 1);
+        assert_eq!(event.changes[0].previous_build.as_deref(), Some("53584"));
+        assert!(daemon
+            .detect(&fetch("wow", &[("us", "10.2.6.53840")]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_render_change() {
+        let mut change = BuildChange {
+            product: "wow".to_owned(),
+            region: "us".to_owned(),
+            previous_version: Some("10.2.5".to_owned()),
+            previous_build: Some("53584".to_owned()),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        };
+        assert_eq!(
+            render_change(&change, false),
+            "wow us: 10.2.5.53584 → 10.2.6.53840"
+        );
+        assert_eq!(
+            render_change(&change, true),
+            "\x1b[1mwow us\x1b[0m: \x1b[31m10.2.5.53584\x1b[0m → \x1b[32m10.2.6.53840\x1b[0m"
+        );
+
+        change.previous_version = None;
+        change.previous_build = None;
+        assert_eq!(
+            render_change(&change, false),
+            "wow us: (new) → 10.2.6.53840"
+        );
+    }
+
+    #[test]
+    fn test_build_event() {
+        let mut change = BuildChange {
+            product: "wow".to_owned(),
+            region: "us".to_owned(),
+            previous_version: Some("10.2.5".to_owned()),
+            previous_build: Some("53584".to_owned()),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        };
+        let timestamp = DateTime::parse_from_rfc3339("2024-03-19T15:02:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            json!(BuildEvent::new(&change, timestamp)).to_string(),
+            r#"{"new":"10.2.6.53840","old":"10.2.5.53584","product":"wow","region":"us","timestamp":"2024-03-19T15:02:00Z","type":"new_build"}"#
+        );
+
+        change.previous_version = None;
+        change.previous_build = None;
+        let event = BuildEvent::new(&change, timestamp);
+        assert_eq!(event.r#type, "new_region");
+        assert_eq!(event.old, None);
+    }
+
+    #[test]
+    fn test_check_alerts() {
+        let daemon = daemon(vec![
+            Alert {
+                condition: Condition::Divergent,
+                products: None,
+                persists: None,
+                checks: 2,
+            },
+            // can't be told without the background download, so is never checked
+            Alert {
+                condition: Condition::BgdlAhead,
+                products: None,
+                persists: None,
+                checks: 1,
+            },
+        ]);
+        let state = || {
+            daemon
+                .conditions
+                .lock()
+                .unwrap()
+                .get(&("wow".to_owned(), Condition::Divergent))
+                .copied()
+        };
+
+        let rolling_out = fetch("wow", &[("us", "10.2.5.53840"), ("eu", "10.2.5.53584")]);
+        daemon.check_alerts(&rolling_out);
+        assert_eq!(state().unwrap().checks, 1);
+        daemon.check_alerts(&rolling_out);
+        assert_eq!(state().unwrap().checks, 2);
+        assert_eq!(daemon.conditions.lock().unwrap().len(), 1);
+
+        daemon.check_alerts(&fetch(
+            "wow",
+            &[("us", "10.2.5.53840"), ("eu", "10.2.5.53840")],
+        ));
+        assert_eq!(state(), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! Discord notifications of new builds, as rich embeds sent to a channel webhook
+use crate::cli::{
+    changes::{self, ChangeGroup},
+    game::product_display_name,
+    webhook,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use wownow::prelude::*;
+
+/// The most embeds Discord accepts in one message
+const MAX_EMBEDS: usize = 10;
+
+/// The color of the embeds' left border, Blizzard blue
+const EMBED_COLOR: u32 = 0x00_AE_FF;
+
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    username: &'static str,
+    embeds: &'a [Embed],
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Embed {
+    title: String,
+    description: String,
+    color: u32,
+    fields: Vec<Field>,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Field {
+    name: &'static str,
+    value: String,
+    inline: bool,
+}
+
+/// An embed announcing a group of changes
+fn embed(group: &ChangeGroup<'_>, detected_at: DateTime<Utc>) -> Embed {
+    let new = format!("{}.{}", group.version, group.build);
+    let description = match (group.previous_version, group.previous_build) {
+        (Some(previous_version), Some(previous_build)) => {
+            format!("{previous_version}.{previous_build} → {new}")
+        }
+        _ => format!("new in these regions: {new}"),
+    };
+    Embed {
+        title: product_display_name(group.product)
+            .unwrap_or(group.product)
+            .to_owned(),
+        description,
+        color: EMBED_COLOR,
+        fields: vec![
+            Field {
+                name: "Product",
+                value: format!("`{}`", group.product),
+                inline: true,
+            },
+            Field {
+                name: "Regions",
+                value: group.regions.join(", "),
+                inline: true,
+            },
+        ],
+        timestamp: detected_at,
+    }
+}
+
+/// Sends new builds to a Discord webhook
+pub(crate) struct DiscordSink {
+    pub(crate) http: reqwest::Client,
+    pub(crate) url: String,
+}
+
+#[async_trait]
+impl ChangeSink for DiscordSink {
+    fn name(&self) -> &'static str {
+        "Discord"
+    }
+
+    /// Send the changes, in as many messages as it takes.
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        let embeds: Vec<_> = changes::group(&event.changes)
+            .iter()
+            .map(|group| embed(group, event.detected_at))
+            .collect();
+        for chunk in embeds.chunks(MAX_EMBEDS) {
+            let message = Message {
+                username: "wownow",
+                embeds: chunk,
+            };
+            // serializing plain strings and dates can't fail
+            let body = serde_json::to_vec(&message).expect("Discord message serializes");
+            webhook::deliver(&self.http, &self.url, body, None).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embed() {
+        let detected_at = Utc::now();
+        let group = ChangeGroup {
+            product: "wow_classic",
+            previous_version: Some("1.15.1"),
+            previous_build: Some("53495"),
+            version: "1.15.2",
+            build: "53801",
+            regions: vec!["us", "eu"],
+        };
+
+        let embed = embed(&group, detected_at);
+        assert_eq!(embed.title, "World of Warcraft Classic");
+        assert_eq!(embed.description, "1.15.1.53495 → 1.15.2.53801");
+        assert_eq!(embed.fields[1].value, "us, eu");
+        assert_eq!(embed.timestamp, detected_at);
+    }
+}
@@ -0,0 +1,151 @@
+//! Email notifications of new builds, as a plain-text digest sent over SMTP
+use crate::cli::{
+    changes,
+    config::{EmailConfig, SmtpSecurity},
+    game::product_display_name,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::fmt::Write;
+use wownow::prelude::*;
+
+/// Sends digests to a fixed set of recipients
+pub(crate) struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Mailer {
+    /// Create a mailer from its configuration, checking the addresses are valid.
+    pub(crate) fn new(config: &EmailConfig) -> Result<Self, String> {
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| format!("invalid email `from` address `{}`: {e}", config.from))?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| {
+                to.parse()
+                    .map_err(|e| format!("invalid email `to` address `{to}`: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if to.is_empty() {
+            return Err("email notifications need at least one `to` address".to_owned());
+        }
+
+        let mut builder = match config.security {
+            SmtpSecurity::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+            }
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host),
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &config.host,
+            )),
+        }
+        .map_err(|e| format!("Error setting up SMTP for `{}`: {e}", config.host))?;
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+        })
+    }
+}
+
+#[async_trait]
+impl ChangeSink for Mailer {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    /// Email a digest of the changes to every recipient.
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        let (subject, body) = digest(event.detected_at, &event.changes);
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| format!("Error building email: {e}"))?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| format!("Error sending email: {e}"))?;
+        Ok(())
+    }
+}
+
+/// The subject and plain-text body of a digest of changes
+fn digest(detected_at: DateTime<Utc>, changes: &[BuildChange]) -> (String, String) {
+    let groups = changes::group(changes);
+    let subject = format!("New builds: {}", changes::headline(&groups));
+
+    let mut body = format!(
+        "wownow detected these new builds at {}:\n",
+        detected_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    for group in &groups {
+        let name = product_display_name(group.product).unwrap_or(group.product);
+        // writing to a String can't fail
+        let _ = write!(
+            body,
+            "\n{name} ({})\n  {}.{}",
+            group.product, group.version, group.build
+        );
+        if let (Some(previous_version), Some(previous_build)) =
+            (group.previous_version, group.previous_build)
+        {
+            let _ = write!(body, " (was {previous_version}.{previous_build})");
+        }
+        let _ = writeln!(body, "\n  regions: {}", group.regions.join(", "));
+    }
+    (subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let detected_at = DateTime::parse_from_rfc3339("2024-03-14T17:56:25Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let changes = vec![BuildChange {
+            product: "wow".to_owned(),
+            region: "us".to_owned(),
+            previous_version: Some("10.2.5".to_owned()),
+            previous_build: Some("53584".to_owned()),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        }];
+
+        let (subject, body) = digest(detected_at, &changes);
+        assert_eq!(subject, "New builds: World of Warcraft 10.2.6.53840");
+        assert_eq!(
+            body,
+            "wownow detected these new builds at 2024-03-14T17:56:25Z:\n\
+            \n\
+            World of Warcraft (wow)\n  \
+            10.2.6.53840 (was 10.2.5.53584)\n  \
+            regions: us\n"
+        );
+    }
+}
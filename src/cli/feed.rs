@@ -0,0 +1,100 @@
+//! An Atom feed of new builds, from the history database
+use crate::cli::history::BuildSighting;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write;
+
+/// How many builds the feed lists
+pub(crate) const FEED_LENGTH: usize = 50;
+
+/// Render an Atom feed with an entry for each build, which should be newest first. The feed is
+/// updated as of the newest build, or `now` if there are none.
+pub(crate) fn render(builds: &[BuildSighting], now: DateTime<Utc>) -> String {
+    let updated = builds.first().map_or(now, |build| build.first_seen);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str("  <id>urn:wownow:builds</id>\n");
+    out.push_str("  <title>New World of Warcraft builds</title>\n");
+    let _ = writeln!(out, "  <updated>{}</updated>", format_datetime(updated));
+    out.push_str("  <author><name>wownow</name></author>\n");
+
+    for build in builds {
+        let title = format!(
+            "{} {}.{} ({})",
+            build.product,
+            build.version,
+            build.build,
+            build.regions.join(", ")
+        );
+        let summary = format!(
+            "Build {} of {} (version {}) was first seen at {} in the {} regions.",
+            build.build,
+            build.product,
+            build.version,
+            format_datetime(build.first_seen),
+            build.regions.join(", ")
+        );
+        out.push_str("  <entry>\n");
+        let _ = writeln!(
+            out,
+            "    <id>urn:wownow:build:{}:{}.{}</id>",
+            escape(&build.product),
+            escape(&build.version),
+            escape(&build.build)
+        );
+        let _ = writeln!(out, "    <title>{}</title>", escape(&title));
+        let _ = writeln!(
+            out,
+            "    <updated>{}</updated>",
+            format_datetime(build.first_seen)
+        );
+        let _ = writeln!(out, "    <summary>{}</summary>", escape(&summary));
+        out.push_str("  </entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+fn format_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escape text for XML content.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let first_seen = DateTime::parse_from_rfc3339("2024-03-14T17:56:25Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let builds = vec![BuildSighting {
+            product: "wow".to_owned(),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+            first_seen,
+            regions: vec!["eu".to_owned(), "us".to_owned()],
+        }];
+
+        let feed = render(&builds, Utc::now());
+        assert!(feed.contains("<updated>2024-03-14T17:56:25Z</updated>\n  <author>"));
+        assert!(feed.contains("<id>urn:wownow:build:wow:10.2.6.53840</id>"));
+        assert!(feed.contains("<title>wow 10.2.6.53840 (eu, us)</title>"));
+        assert_eq!(feed.matches("<entry>").count(), 1);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
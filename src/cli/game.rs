@@ -0,0 +1,124 @@
+//! Blizzard games, identified by the prefixes of their product codes
+use clap::ValueEnum;
+use wownow::prelude::*;
+
+/// A Blizzard game. The summary lists the products of every game, each of whose product codes
+/// starts with one of the game's prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[allow(clippy::doc_markdown)] // these docs are game names, shown in the help text
+pub(crate) enum Game {
+    /// World of Warcraft
+    Wow,
+
+    /// Diablo III
+    D3,
+
+    /// Diablo IV
+    D4,
+
+    /// Overwatch
+    Ow,
+
+    /// Hearthstone
+    Hs,
+
+    /// Heroes of the Storm
+    Hots,
+
+    /// StarCraft
+    Sc1,
+
+    /// StarCraft II
+    Sc2,
+
+    /// Warcraft III
+    Wc3,
+}
+
+impl Game {
+    /// The prefixes of this game's product codes
+    pub(crate) fn product_prefixes(self) -> &'static [&'static str] {
+        match self {
+            Game::Wow => &["wow"],
+            Game::D3 => &["d3"],
+            Game::D4 => &["fenris"],
+            Game::Ow => &["pro"],
+            Game::Hs => &["hsb", "wtcg"],
+            Game::Hots => &["hero"],
+            Game::Sc1 => &["s1"],
+            Game::Sc2 => &["s2"],
+            Game::Wc3 => &["w3"],
+        }
+    }
+}
+
+/// The human-readable name of a product, such as "World of Warcraft Classic" for `wow_classic`,
+/// if it's one wownow knows of
+pub(crate) fn product_display_name(product: &str) -> Option<&'static str> {
+    Some(match product {
+        "wow" => "World of Warcraft",
+        "wowt" => "World of Warcraft PTR",
+        "wowxptr" => "World of Warcraft Experimental PTR",
+        "wow_beta" => "World of Warcraft Beta",
+        "wow_classic" => "World of Warcraft Classic",
+        "wow_classic_ptr" => "World of Warcraft Classic PTR",
+        "wow_classic_beta" => "World of Warcraft Classic Beta",
+        "wow_classic_era" => "World of Warcraft Classic Era",
+        "wow_classic_era_ptr" => "World of Warcraft Classic Era PTR",
+        _ => return None,
+    })
+}
+
+/// Which games' products to consider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum GameFilter {
+    /// Products of every game, even those not known to wownow
+    All,
+
+    /// Products of any of these games
+    Only(Vec<Game>),
+}
+
+impl GameFilter {
+    /// Narrow a product filter to these games' products.
+    pub(crate) fn apply(&self, filter: ProductFilter) -> ProductFilter {
+        match self {
+            GameFilter::All => filter,
+            GameFilter::Only(games) => filter.with_prefixes(
+                games
+                    .iter()
+                    .flat_map(|game| game.product_prefixes().iter().copied()),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_filter() {
+        let wow_only = GameFilter::Only(vec![Game::Wow]).apply(ProductFilter::new());
+        assert!(wow_only.matches("wow"));
+        assert!(wow_only.matches("wow_classic_era"));
+        assert!(!wow_only.matches("d3"));
+
+        let blizzard_classics =
+            GameFilter::Only(vec![Game::Sc1, Game::Wc3]).apply(ProductFilter::new());
+        assert!(blizzard_classics.matches("s1"));
+        assert!(blizzard_classics.matches("w3t"));
+        assert!(!blizzard_classics.matches("s2"));
+
+        assert!(GameFilter::All.apply(ProductFilter::new()).matches("viper"));
+    }
+
+    #[test]
+    fn test_product_display_name() {
+        assert_eq!(
+            product_display_name("wow_classic"),
+            Some("World of Warcraft Classic")
+        );
+        assert_eq!(product_display_name("viper"), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! Getting a single value, such as the version of `wow` in `us`, for shell scripts
+use crate::cli::{
+    cache::{self, Cache},
+    capture::Capture,
+    history::to_hex,
+};
+use std::{fmt::Display, str::FromStr};
+use wownow::prelude::*;
+
+/// A field of a region's record in the versions response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Version,
+    Build,
+    VersionsName,
+    BuildId,
+    BuildConfig,
+    CdnConfig,
+    KeyRing,
+    ProductConfig,
+}
+
+/// The fields by the names they're given in paths
+const FIELDS: [(&str, Field); 8] = [
+    ("version", Field::Version),
+    ("build", Field::Build),
+    ("versions_name", Field::VersionsName),
+    ("build_id", Field::BuildId),
+    ("build_config", Field::BuildConfig),
+    ("cdn_config", Field::CdnConfig),
+    ("key_ring", Field::KeyRing),
+    ("product_config", Field::ProductConfig),
+];
+
+impl Field {
+    /// The field's value in a record, as printed. A record without a key ring has an empty one.
+    fn of(self, record: &VersionsRecord) -> Result<String, String> {
+        let version = || Version::try_from(record).map_err(|e| format!("Error: {e}"));
+        Ok(match self {
+            Self::Version => version()?.version().to_owned(),
+            Self::Build => version()?.build().to_owned(),
+            Self::VersionsName => record.versions_name.clone(),
+            Self::BuildId => record.build_id.to_string(),
+            Self::BuildConfig => to_hex(&record.build_config),
+            Self::CdnConfig => to_hex(&record.cdn_config),
+            Self::KeyRing => record.key_ring.map(|key| to_hex(&key)).unwrap_or_default(),
+            Self::ProductConfig => to_hex(&record.product_config),
+        })
+    }
+}
+
+/// A value to get, named by a path of a product, region and field, such as `wow.us.version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GetPath {
+    pub(crate) product: String,
+    region: String,
+    field: Field,
+}
+
+impl FromStr for GetPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [product, region, field] = s.split('.').collect::<Vec<_>>()[..] else {
+            return Err(format!(
+                "`{s}` should be a product, region and field, such as `wow.us.version`"
+            ));
+        };
+        let Some(&(_, field)) = FIELDS.iter().find(|(name, _)| *name == field) else {
+            let names = FIELDS.map(|(name, _)| name);
+            return Err(format!(
+                "unknown field `{field}`, expected one of {}",
+                names.join(", ")
+            ));
+        };
+        Ok(Self {
+            product: product.to_owned(),
+            region: region.to_owned(),
+            field,
+        })
+    }
+}
+
+impl Display for GetPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = FIELDS
+            .iter()
+            .find(|(_, field)| *field == self.field)
+            .expect("every field is named");
+        write!(f, "{}.{}.{name}", self.product, self.region)
+    }
+}
+
+/// Get one value from a product's versions, bare and without the summary, so it's quick to
+/// substitute into a shell command.
+pub(crate) async fn run(
+    client: &Client,
+    path: &GetPath,
+    cache: Option<&Cache>,
+    capture: &Capture,
+) -> crate::Result {
+    let response = cache::get_versions(client, cache, capture, &path.product, None)
+        .await
+        .map_err(|e| format!("Error getting `{}` versions: {e}", path.product))?;
+    let record = response.region(&path.region).ok_or_else(|| {
+        format!(
+            "Error getting `{path}`: `{}` has no version in region `{}`",
+            path.product, path.region
+        )
+    })?;
+    path.field.of(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_path() {
+        let path: GetPath = "wow_classic.eu.build_config".parse().unwrap();
+        assert_eq!(
+            path,
+            GetPath {
+                product: "wow_classic".to_owned(),
+                region: "eu".to_owned(),
+                field: Field::BuildConfig,
+            }
+        );
+        assert_eq!(path.to_string(), "wow_classic.eu.build_config");
+        assert!("wow.us".parse::<GetPath>().is_err());
+        assert!("wow.us.version.extra".parse::<GetPath>().is_err());
+        assert!("wow.us.colour"
+            .parse::<GetPath>()
+            .unwrap_err()
+            .starts_with("unknown field `colour`"));
+
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 1\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let record = response.region("us").unwrap();
+        for (field, value) in [
+            (Field::Version, "10.2.5"),
+            (Field::Build, "53584"),
+            (Field::BuildId, "53584"),
+            (Field::BuildConfig, "47e9e06f8371afb141e22614a912acc8"),
+            (Field::KeyRing, ""),
+        ] {
+            assert_eq!(field.of(record).unwrap(), value);
+        }
+    }
+}
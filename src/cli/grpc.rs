@@ -0,0 +1,191 @@
+//! A gRPC service for the `serve` subcommand, serving the same data as the HTTP API
+use crate::cli::serve::{with_product, ApiFailure, SharedState};
+use axum::http::StatusCode;
+use chrono::Utc;
+use proto::wownow_server::{Wownow, WownowServer};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status};
+use wownow::prelude::*;
+
+/// The types generated from `proto/wownow.proto`
+#[allow(clippy::pedantic, missing_docs)]
+pub(crate) mod proto {
+    tonic::include_proto!("wownow.v1");
+}
+
+/// How many fetches a slow watcher may fall behind by before its stream waits
+const WATCH_BUFFER: usize = 4;
+
+impl From<ApiFailure> for Status {
+    fn from(failure: ApiFailure) -> Self {
+        match failure.0 {
+            StatusCode::NOT_FOUND => Status::not_found(failure.1),
+            _ => Status::unavailable(failure.1),
+        }
+    }
+}
+
+fn to_proto_product(product: &Product) -> proto::Product {
+    proto::Product {
+        name: product.name().to_owned(),
+        access: match product.access() {
+            Access::Public => "public",
+            Access::Encrypted => "encrypted",
+        }
+        .to_owned(),
+        versions: product
+            .versions()
+            .iter()
+            .map(|version| proto::Version {
+                region: version.region().to_owned(),
+                version: version.version().to_owned(),
+                build: version.build().to_owned(),
+            })
+            .collect(),
+    }
+}
+
+/// Get the versions of `products`, or if empty, of the live products from the latest refresh.
+async fn versions_fetch(
+    state: &SharedState,
+    products: &[String],
+) -> Result<proto::VersionsFetch, Status> {
+    if products.is_empty() {
+        let latest = state.latest.read().await;
+        let latest = latest
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("versions have not been fetched yet"))?;
+        return Ok(proto::VersionsFetch {
+            retrieval_datetime: latest.fetch.retrieval_datetime().to_rfc3339(),
+            summary_seqn: latest.fetch.summary_seqn(),
+            products: latest
+                .fetch
+                .products()
+                .iter()
+                .map(to_proto_product)
+                .collect(),
+        });
+    }
+
+    let retrieval_datetime = Utc::now().to_rfc3339();
+    let mut found = Vec::with_capacity(products.len());
+    for product in products {
+        found.push(with_product(state, product, to_proto_product).await?);
+    }
+    Ok(proto::VersionsFetch {
+        retrieval_datetime,
+        products: found,
+        summary_seqn: None,
+    })
+}
+
+struct Service {
+    state: SharedState,
+}
+
+#[tonic::async_trait]
+impl Wownow for Service {
+    async fn get_versions(
+        &self,
+        request: Request<proto::GetVersionsRequest>,
+    ) -> Result<Response<proto::VersionsFetch>, Status> {
+        let fetch = versions_fetch(&self.state, &request.into_inner().products).await?;
+        Ok(Response::new(fetch))
+    }
+
+    type WatchVersionsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::VersionsFetch, Status>> + Send>>;
+
+    async fn watch_versions(
+        &self,
+        request: Request<proto::GetVersionsRequest>,
+    ) -> Result<Response<Self::WatchVersionsStream>, Status> {
+        let products = request.into_inner().products;
+        let state = Arc::clone(&self.state);
+        let mut updates = state.updates.subscribe();
+        // send the current versions straight away, if there are any
+        updates.mark_changed();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut last_sent = None;
+            while updates.changed().await.is_ok() {
+                if state.latest.read().await.is_none() {
+                    continue;
+                }
+                let result = versions_fetch(&state, &products).await;
+                if let Ok(fetch) = &result {
+                    // only send when a version has changed, not on every refresh
+                    if last_sent.as_ref() == Some(&fetch.products) {
+                        continue;
+                    }
+                    last_sent = Some(fetch.products.clone());
+                }
+                let failed = result.is_err();
+                if tx.send(result).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Start serving the gRPC service on `addr` in the background until `shutdown` is cancelled,
+/// failing if the address can't be listened on.
+pub(crate) async fn spawn(
+    state: SharedState,
+    addr: SocketAddr,
+    shutdown: CancellationToken,
+) -> Result<(), String> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Error listening on {addr}: {e}"))?;
+    eprintln!("Serving gRPC on {addr}");
+
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(WownowServer::new(Service { state }))
+            .serve_with_incoming_shutdown(
+                tokio_stream::wrappers::TcpListenerStream::new(listener),
+                shutdown.cancelled_owned(),
+            )
+            .await;
+        if let Err(e) = result {
+            eprintln!("Error serving gRPC: {e}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_proto_product() {
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118468\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let product = Product::try_from(("wow", &response)).unwrap();
+
+        assert_eq!(
+            to_proto_product(&product),
+            proto::Product {
+                name: "wow".to_owned(),
+                access: "public".to_owned(),
+                versions: vec![proto::Version {
+                    region: "us".to_owned(),
+                    version: "10.2.5".to_owned(),
+                    build: "53584".to_owned(),
+                }],
+            }
+        );
+    }
+}
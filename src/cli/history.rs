@@ -0,0 +1,807 @@
+//! A `SQLite` store of every version observed, so wownow can answer questions about the past
+use crate::cli::{
+    alert::{Condition, ConditionState},
+    timezone::{self, ZoneArgs},
+};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use wownow::prelude::*;
+
+/// The schema version, stored in the database's `user_version`. Bump this and add a migration to
+/// [`History::migrate`] when changing the schema.
+const SCHEMA_VERSION: u32 = 2;
+
+/// A history database
+pub(crate) struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Open the history database at `path`, creating it (and its parent directories) if needed.
+    pub(crate) fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.parent() {
+            // if this fails, opening will fail with a clearer error
+            let _ = std::fs::create_dir_all(parent);
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(conn: Connection) -> rusqlite::Result<Self> {
+        let history = Self { conn };
+        history.migrate()?;
+        Ok(history)
+    }
+
+    fn migrate(&self) -> rusqlite::Result<()> {
+        let version: u32 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < 1 {
+            self.conn.execute_batch(
+                "CREATE TABLE observations (
+                    id INTEGER PRIMARY KEY,
+                    observed_at TEXT NOT NULL,
+                    product TEXT NOT NULL,
+                    region TEXT NOT NULL,
+                    version TEXT NOT NULL,
+                    build TEXT NOT NULL,
+                    seqn INTEGER NOT NULL,
+                    build_config TEXT,
+                    cdn_config TEXT,
+                    product_config TEXT
+                );
+                CREATE INDEX observations_product_region
+                    ON observations (product, region, observed_at);",
+            )?;
+        }
+        if version < 2 {
+            self.conn.execute_batch(
+                "CREATE TABLE conditions (
+                    product TEXT NOT NULL,
+                    condition TEXT NOT NULL,
+                    since TEXT NOT NULL,
+                    checks INTEGER NOT NULL,
+                    last_checked TEXT NOT NULL,
+                    PRIMARY KEY (product, condition)
+                );",
+            )?;
+        }
+        self.conn
+            .pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// Record every regional version of a product's versions response, as observed at
+    /// `observed_at`. Records whose versions name can't be parsed are skipped.
+    pub(crate) fn record(
+        &mut self,
+        observed_at: DateTime<Utc>,
+        product: &str,
+        response: &VersionsResponse,
+    ) -> rusqlite::Result<()> {
+        let observed_at = format_timestamp(observed_at);
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO observations (
+                    observed_at, product, region, version, build, seqn,
+                    build_config, cdn_config, product_config
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for record in &response.records {
+                let Ok(version) = Version::try_from(record) else {
+                    continue;
+                };
+                insert.execute(params![
+                    observed_at,
+                    product,
+                    version.region(),
+                    version.version(),
+                    version.build(),
+                    response.seqn,
+                    to_hex(&record.build_config),
+                    to_hex(&record.cdn_config),
+                    to_hex(&record.product_config),
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// The versions observed of each product in each region, with when each was first and last
+    /// seen, ordered by product, region and first seen.
+    pub(crate) fn query(&self, filter: &Filter) -> rusqlite::Result<Vec<VersionSpan>> {
+        let mut select = self.conn.prepare(
+            "SELECT product, region, version, build, MIN(observed_at), MAX(observed_at)
+            FROM observations
+            WHERE (?1 IS NULL OR product = ?1) AND (?2 IS NULL OR region = ?2)
+            GROUP BY product, region, version, build
+            HAVING (?3 IS NULL OR MAX(observed_at) >= ?3)
+            ORDER BY product, region, MIN(observed_at)",
+        )?;
+        let rows = select.query_map(
+            params![
+                filter.product,
+                filter.region,
+                filter.since.map(format_timestamp)
+            ],
+            |row| {
+                Ok(VersionSpan {
+                    product: row.get(0)?,
+                    region: row.get(1)?,
+                    version: row.get(2)?,
+                    build: row.get(3)?,
+                    first_seen: parse_timestamp(&row.get::<_, String>(4)?)?,
+                    last_seen: parse_timestamp(&row.get::<_, String>(5)?)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// The most recently first observed builds of every product, newest first, with the regions
+    /// each was observed in.
+    pub(crate) fn recent_builds(&self, limit: usize) -> rusqlite::Result<Vec<BuildSighting>> {
+        let mut select = self.conn.prepare(
+            "SELECT product, version, build, MIN(observed_at), GROUP_CONCAT(DISTINCT region)
+            FROM observations
+            GROUP BY product, version, build
+            ORDER BY MIN(observed_at) DESC, product
+            LIMIT ?1",
+        )?;
+        let rows = select.query_map(params![i64::try_from(limit).unwrap_or(i64::MAX)], |row| {
+            let mut regions: Vec<String> = row
+                .get::<_, String>(4)?
+                .split(',')
+                .map(str::to_owned)
+                .collect();
+            regions.sort();
+            Ok(BuildSighting {
+                product: row.get(0)?,
+                version: row.get(1)?,
+                build: row.get(2)?,
+                first_seen: parse_timestamp(&row.get::<_, String>(3)?)?,
+                regions,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// When a build of a product was first observed in a region, if it ever was.
+    pub(crate) fn first_seen(
+        &self,
+        product: &str,
+        region: &str,
+        build: &str,
+    ) -> rusqlite::Result<Option<DateTime<Utc>>> {
+        self.conn
+            .query_row(
+                "SELECT MIN(observed_at) FROM observations
+                WHERE product = ?1 AND region = ?2 AND build = ?3",
+                params![product, region, build],
+                |row| row.get::<_, Option<String>>(0),
+            )?
+            .map(|timestamp| parse_timestamp(&timestamp))
+            .transpose()
+    }
+
+    /// Annotate each of a product's versions with when its build was first observed, and so its
+    /// age as of `now`. Versions whose builds were never observed are left as they are.
+    pub(crate) fn annotate_ages(
+        &self,
+        product: &mut Product,
+        now: DateTime<Utc>,
+    ) -> rusqlite::Result<()> {
+        let name = product.name().to_owned();
+        for version in product.versions_mut() {
+            if let Some(first_seen) = self.first_seen(&name, version.region(), version.build())? {
+                version.set_first_seen(first_seen, now);
+            }
+        }
+        Ok(())
+    }
+
+    /// The versions of every product as last observed, to compare the next fetch to after a
+    /// restart. They're rebuilt from the observations, so have no key rings, and build IDs from
+    /// their builds.
+    pub(crate) fn latest_products(&self) -> rusqlite::Result<Vec<Product>> {
+        let mut select = self.conn.prepare(
+            "SELECT product, region, version, build, seqn, build_config, cdn_config, product_config
+            FROM observations AS o
+            WHERE observed_at = (SELECT MAX(observed_at) FROM observations WHERE product = o.product)
+            ORDER BY product, id",
+        )?;
+        let mut rows = select.query([])?;
+        let hex = |hex: Option<String>| {
+            hex.and_then(|hex| decode_hex16(&hex).ok())
+                .unwrap_or_default()
+        };
+        let mut responses: Vec<(String, VersionsResponse)> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let product: String = row.get(0)?;
+            let (version, build): (String, String) = (row.get(2)?, row.get(3)?);
+            let record = VersionsRecord {
+                region: row.get(1)?,
+                build_config: hex(row.get(5)?),
+                cdn_config: hex(row.get(6)?),
+                key_ring: None,
+                build_id: build.parse().unwrap_or_default(),
+                versions_name: format!("{version}.{build}"),
+                product_config: hex(row.get(7)?),
+            };
+            match responses.last_mut() {
+                Some((name, response)) if *name == product => response.records.push(record),
+                _ => responses.push((
+                    product,
+                    VersionsResponse {
+                        seqn: row.get(4)?,
+                        metadata: BTreeMap::new(),
+                        records: vec![record],
+                    },
+                )),
+            }
+        }
+        Ok(responses
+            .iter()
+            .filter_map(|(name, response)| Product::try_from((name.as_str(), response)).ok())
+            .collect())
+    }
+
+    /// How long each condition the daemon alerts on has held of each product it holds of, as last
+    /// checked. Conditions this version doesn't know are skipped.
+    pub(crate) fn condition_states(
+        &self,
+    ) -> rusqlite::Result<HashMap<(String, Condition), ConditionState>> {
+        let mut select = self
+            .conn
+            .prepare("SELECT product, condition, since, checks, last_checked FROM conditions")?;
+        let mut rows = select.query([])?;
+        let mut states = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let Some(condition) = Condition::from_name(&row.get::<_, String>(1)?) else {
+                continue;
+            };
+            let state = ConditionState {
+                since: parse_timestamp(&row.get::<_, String>(2)?)?,
+                checks: row.get(3)?,
+                last_checked: parse_timestamp(&row.get::<_, String>(4)?)?,
+            };
+            states.insert((row.get(0)?, condition), state);
+        }
+        Ok(states)
+    }
+
+    /// Record how long a condition has held of a product, or that it doesn't hold.
+    pub(crate) fn set_condition_state(
+        &self,
+        product: &str,
+        condition: Condition,
+        state: Option<&ConditionState>,
+    ) -> rusqlite::Result<()> {
+        match state {
+            Some(state) => self.conn.execute(
+                "INSERT OR REPLACE INTO conditions (product, condition, since, checks, last_checked)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    product,
+                    condition.as_str(),
+                    format_timestamp(state.since),
+                    state.checks,
+                    format_timestamp(state.last_checked),
+                ],
+            ),
+            None => self.conn.execute(
+                "DELETE FROM conditions WHERE product = ?1 AND condition = ?2",
+                params![product, condition.as_str()],
+            ),
+        }?;
+        Ok(())
+    }
+
+    /// Write every observation, oldest first, to `out` in the given format. Returns the number of
+    /// observations written.
+    fn export(&self, format: ExportFormat, out: impl Write) -> Result<usize, String> {
+        let mut select = self
+            .conn
+            .prepare(
+                "SELECT observed_at, product, region, version, build, seqn,
+                    build_config, cdn_config, product_config
+                FROM observations
+                ORDER BY observed_at, id",
+            )
+            .map_err(|e| format!("Error querying history: {e}"))?;
+        let observations = select
+            .query_map([], |row| {
+                Ok(Observation {
+                    observed_at: parse_timestamp(&row.get::<_, String>(0)?)?,
+                    product: row.get(1)?,
+                    region: row.get(2)?,
+                    version: row.get(3)?,
+                    build: row.get(4)?,
+                    seqn: row.get(5)?,
+                    build_config: row.get(6)?,
+                    cdn_config: row.get(7)?,
+                    product_config: row.get(8)?,
+                })
+            })
+            .map_err(|e| format!("Error querying history: {e}"))?;
+
+        let mut count = 0;
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                for observation in observations {
+                    let observation =
+                        observation.map_err(|e| format!("Error querying history: {e}"))?;
+                    writer
+                        .serialize(observation)
+                        .map_err(|e| format!("Error writing CSV: {e}"))?;
+                    count += 1;
+                }
+                writer
+                    .flush()
+                    .map_err(|e| format!("Error writing CSV: {e}"))?;
+            }
+            ExportFormat::Jsonl => {
+                let mut out = out;
+                for observation in observations {
+                    let observation =
+                        observation.map_err(|e| format!("Error querying history: {e}"))?;
+                    serde_json::to_writer(&mut out, &observation)
+                        .map_err(|e| format!("Error writing JSON: {e}"))?;
+                    out.write_all(b"\n")
+                        .map_err(|e| format!("Error writing JSON: {e}"))?;
+                    count += 1;
+                }
+                out.flush()
+                    .map_err(|e| format!("Error writing JSON: {e}"))?;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// A single observation of a version, as recorded
+#[derive(Debug, Serialize)]
+struct Observation {
+    observed_at: DateTime<Utc>,
+    product: String,
+    region: String,
+    version: String,
+    build: String,
+    seqn: u32,
+    build_config: Option<String>,
+    cdn_config: Option<String>,
+    product_config: Option<String>,
+}
+
+/// Which observations to consider in a query
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    pub(crate) product: Option<String>,
+    pub(crate) region: Option<String>,
+
+    /// Only consider versions last seen at or after this time
+    pub(crate) since: Option<DateTime<Utc>>,
+}
+
+/// A version of a product in a region, and the span of time it was observed over
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct VersionSpan {
+    pub(crate) product: String,
+    pub(crate) region: String,
+    pub(crate) version: String,
+    pub(crate) build: String,
+    pub(crate) first_seen: DateTime<Utc>,
+    pub(crate) last_seen: DateTime<Utc>,
+}
+
+/// A build of a product, and when it was first observed in any region
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct BuildSighting {
+    pub(crate) product: String,
+    pub(crate) version: String,
+    pub(crate) build: String,
+    pub(crate) first_seen: DateTime<Utc>,
+
+    /// The regions the build was ever observed in, sorted
+    pub(crate) regions: Vec<String>,
+}
+
+/// Query the history of observed versions
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands(true))]
+pub(crate) struct HistoryArgs {
+    #[command(subcommand)]
+    command: Option<HistoryCommand>,
+
+    /// Read the history database at this path, instead of the default location.
+    #[arg(long, global(true), value_name = "PATH")]
+    history_db: Option<PathBuf>,
+
+    /// Only show versions of this product.
+    #[arg(long)]
+    product: Option<String>,
+
+    /// Only show versions in this region.
+    #[arg(long)]
+    region: Option<String>,
+
+    /// Only show versions last seen at or after this date (e.g., `2024-01-01`, as UTC midnight) or
+    /// time (e.g., `2024-01-01T12:00:00Z`).
+    #[arg(long, value_name = "DATE", value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    #[command(flatten)]
+    zone: ZoneArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Dump every recorded observation, including sequence numbers and config hashes
+    Export(ExportArgs),
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// The format to export in.
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    format: ExportFormat,
+
+    /// Write to this file instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+/// A format to export observations in
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// Comma-separated values, with a header row
+    Csv,
+
+    /// One JSON object per line
+    Jsonl,
+}
+
+/// Parse a date or RFC 3339 time.
+fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| format!("`{since}` should be a date like 2024-01-01 or an RFC 3339 time"))
+}
+
+/// The history database at the given path, or the default one, failing if there's no default
+/// location on this platform.
+pub(crate) fn resolve_path(path: Option<PathBuf>) -> Result<PathBuf, String> {
+    path.or_else(default_path)
+        .ok_or_else(|| "Error: no default history database location on this platform".to_owned())
+}
+
+/// Open the history database at the given path, or the default one.
+fn open_existing(path: Option<PathBuf>) -> Result<History, String> {
+    let path = resolve_path(path)?;
+    if !path.exists() {
+        return Err(format!(
+            "Error: no history database at `{}`. Record one with `--history-db`.",
+            path.display()
+        ));
+    }
+    History::open(&path)
+        .map_err(|e| format!("Error opening history database `{}`: {e}", path.display()))
+}
+
+/// Run the `history` subcommand.
+pub(crate) fn run(args: HistoryArgs, pretty_print: bool) -> crate::Result {
+    let history = open_existing(args.history_db)?;
+
+    if let Some(HistoryCommand::Export(export_args)) = args.command {
+        match export_args.output {
+            Some(path) => {
+                let file = File::create(&path)
+                    .map_err(|e| format!("Error creating `{}`: {e}", path.display()))?;
+                history.export(export_args.format, BufWriter::new(file))?;
+            }
+            None => {
+                history.export(export_args.format, std::io::stdout().lock())?;
+            }
+        }
+        // everything has been written already
+        return Ok(String::new());
+    }
+
+    let filter = Filter {
+        product: args.product,
+        region: args.region,
+        since: args.since,
+    };
+    let spans = history
+        .query(&filter)
+        .map_err(|e| format!("Error querying history: {e}"))?;
+
+    let mut spans =
+        serde_json::to_value(spans).map_err(|e| format!("Error serializing JSON: {e}"))?;
+    timezone::localize(&mut spans, args.zone.zone());
+    crate::to_json(&spans, pretty_print)
+}
+
+/// The default path of the history database, e.g., `~/.local/share/wownow/history.sqlite` on
+/// Linux.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("wownow").join("history.sqlite"))
+}
+
+/// Format a timestamp for storage. Timestamps are stored as fixed-width RFC 3339 strings in UTC,
+/// so that they sort lexically.
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Parse a timestamp from storage.
+fn parse_timestamp(timestamp: &str) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+        })
+}
+
+/// Format bytes as lowercase hex, as they appear in responses.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // writing to a String can't fail
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions_response(seqn: u32, versions_name: &str) -> VersionsResponse {
+        VersionsResponse::try_from(format!(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = {seqn}\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|{versions_name}|53020d32e1a25648c8e1eafd5771935f\n\
+            eu|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|{versions_name}|53020d32e1a25648c8e1eafd5771935f",
+        ).as_str())
+        .unwrap()
+    }
+
+    fn in_memory() -> History {
+        History::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        parse_since(time).unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let mut history = in_memory();
+        let response = versions_response(2_118_468, "10.2.5.53584");
+
+        history.record(Utc::now(), "wow", &response).unwrap();
+
+        let rows = history
+            .conn
+            .prepare("SELECT region, version, build, seqn, build_config FROM observations ORDER BY region")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u32>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "eu".to_owned(),
+                    "10.2.5".to_owned(),
+                    "53584".to_owned(),
+                    2_118_468,
+                    "47e9e06f8371afb141e22614a912acc8".to_owned()
+                ),
+                (
+                    "us".to_owned(),
+                    "10.2.5".to_owned(),
+                    "53584".to_owned(),
+                    2_118_468,
+                    "47e9e06f8371afb141e22614a912acc8".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let mut history = in_memory();
+        let old = versions_response(1, "10.2.5.53584");
+        let new = versions_response(2, "10.2.6.53840");
+        history
+            .record(at("2024-01-01T00:00:00Z"), "wow", &old)
+            .unwrap();
+        history
+            .record(at("2024-01-02T00:00:00Z"), "wow", &old)
+            .unwrap();
+        history
+            .record(at("2024-01-03T00:00:00Z"), "wow", &new)
+            .unwrap();
+        history
+            .record(at("2024-01-03T00:00:00Z"), "wow_classic", &old)
+            .unwrap();
+
+        let filter = Filter {
+            product: Some("wow".to_owned()),
+            region: Some("us".to_owned()),
+            since: None,
+        };
+        assert_eq!(
+            history.query(&filter).unwrap(),
+            vec![
+                VersionSpan {
+                    product: "wow".to_owned(),
+                    region: "us".to_owned(),
+                    version: "10.2.5".to_owned(),
+                    build: "53584".to_owned(),
+                    first_seen: at("2024-01-01"),
+                    last_seen: at("2024-01-02"),
+                },
+                VersionSpan {
+                    product: "wow".to_owned(),
+                    region: "us".to_owned(),
+                    version: "10.2.6".to_owned(),
+                    build: "53840".to_owned(),
+                    first_seen: at("2024-01-03"),
+                    last_seen: at("2024-01-03"),
+                },
+            ]
+        );
+
+        let since = Filter {
+            since: Some(at("2024-01-03")),
+            ..filter
+        };
+        assert_eq!(history.query(&since).unwrap().len(), 1);
+
+        assert_eq!(history.query(&Filter::default()).unwrap().len(), 6);
+
+        assert_eq!(
+            history.first_seen("wow", "us", "53840").unwrap(),
+            Some(at("2024-01-03"))
+        );
+        assert_eq!(history.first_seen("wow", "us", "1").unwrap(), None);
+
+        assert_eq!(
+            history.recent_builds(2).unwrap(),
+            vec![
+                BuildSighting {
+                    product: "wow".to_owned(),
+                    version: "10.2.6".to_owned(),
+                    build: "53840".to_owned(),
+                    first_seen: at("2024-01-03"),
+                    regions: vec!["eu".to_owned(), "us".to_owned()],
+                },
+                BuildSighting {
+                    product: "wow_classic".to_owned(),
+                    version: "10.2.5".to_owned(),
+                    build: "53584".to_owned(),
+                    first_seen: at("2024-01-03"),
+                    regions: vec!["eu".to_owned(), "us".to_owned()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let mut history = in_memory();
+        let response = versions_response(2_118_468, "10.2.5.53584");
+        history.record(at("2024-01-01"), "wow", &response).unwrap();
+
+        let mut csv = Vec::new();
+        assert_eq!(history.export(ExportFormat::Csv, &mut csv).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "observed_at,product,region,version,build,seqn,build_config,cdn_config,product_config\n\
+            2024-01-01T00:00:00Z,wow,us,10.2.5,53584,2118468,47e9e06f8371afb141e22614a912acc8,74093d42ce367c7a67f2831dbf64088d,53020d32e1a25648c8e1eafd5771935f\n\
+            2024-01-01T00:00:00Z,wow,eu,10.2.5,53584,2118468,47e9e06f8371afb141e22614a912acc8,74093d42ce367c7a67f2831dbf64088d,53020d32e1a25648c8e1eafd5771935f\n"
+        );
+
+        let mut jsonl = Vec::new();
+        assert_eq!(history.export(ExportFormat::Jsonl, &mut jsonl).unwrap(), 2);
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let first = jsonl.lines().next().unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(first).unwrap()["seqn"],
+            2_118_468
+        );
+    }
+
+    #[test]
+    fn test_latest_products() {
+        let mut history = in_memory();
+        history
+            .record(
+                at("2024-01-01T00:00:00Z"),
+                "wow",
+                &versions_response(1, "10.2.5.53584"),
+            )
+            .unwrap();
+        history
+            .record(
+                at("2024-01-02T00:00:00Z"),
+                "wow",
+                &versions_response(2, "10.2.6.53840"),
+            )
+            .unwrap();
+        history
+            .record(
+                at("2024-01-01T00:00:00Z"),
+                "wowt",
+                &versions_response(3, "10.2.7.53900"),
+            )
+            .unwrap();
+
+        let products = history.latest_products().unwrap();
+        let latest = products
+            .iter()
+            .map(|product| {
+                let version = &product.versions()[0];
+                (product.name(), version.region(), version.build())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(latest, [("wow", "us", "53840"), ("wowt", "us", "53900")]);
+        assert_eq!(products[0].versions().len(), 2);
+    }
+
+    #[test]
+    fn test_condition_states() {
+        let history = in_memory();
+        let state = ConditionState {
+            since: at("2024-03-19T15:00:00Z"),
+            checks: 3,
+            last_checked: at("2024-03-19T15:20:00Z"),
+        };
+        history
+            .set_condition_state("wow", Condition::Divergent, Some(&state))
+            .unwrap();
+        history
+            .set_condition_state("wow", Condition::BgdlAhead, Some(&state))
+            .unwrap();
+        let advanced = ConditionState::advance(Some(state), true, at("2024-03-19T15:30:00Z"));
+        history
+            .set_condition_state("wow", Condition::Divergent, advanced.as_ref())
+            .unwrap();
+        history
+            .set_condition_state("wow", Condition::BgdlAhead, None)
+            .unwrap();
+
+        let states = history.condition_states().unwrap();
+        assert_eq!(states.len(), 1);
+        let divergent = states[&("wow".to_owned(), Condition::Divergent)];
+        assert_eq!(divergent.since, state.since);
+        assert_eq!(divergent.checks, 4);
+        assert_eq!(divergent.last_checked, at("2024-03-19T15:30:00Z"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Prometheus metrics for the `serve` subcommand, in the text exposition format
+use chrono::{DateTime, Utc};
+use std::{fmt::Write, sync::Mutex, time::Duration};
+use wownow::prelude::*;
+
+/// The upper bounds of the fetch duration histogram's buckets, in seconds
+const FETCH_DURATION_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Metrics about the server's fetches from the version server
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The count of fetches that took at most each bucket's upper bound, non-cumulative
+    bucket_counts: [u64; FETCH_DURATION_BUCKETS.len()],
+    fetch_count: u64,
+    fetch_duration_sum: f64,
+    error_count: u64,
+    last_success: Option<DateTime<Utc>>,
+}
+
+impl Metrics {
+    /// Record a fetch of the summary and versions, which took `duration` and finished at `at`.
+    pub(crate) fn record_fetch(&self, duration: Duration, succeeded: bool, at: DateTime<Utc>) {
+        // a poisoned lock only means another thread panicked mid-update, so the counts are still
+        // usable
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = FETCH_DURATION_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+        {
+            inner.bucket_counts[bucket] += 1;
+        }
+        inner.fetch_count += 1;
+        inner.fetch_duration_sum += seconds;
+        if succeeded {
+            inner.last_success = Some(at);
+        } else {
+            inner.error_count += 1;
+        }
+    }
+
+    /// Render the metrics, along with the versions of the latest fetch, if any.
+    pub(crate) fn render(&self, fetch: Option<&VersionsFetch>) -> String {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut out = String::new();
+
+        if let Some(fetch) = fetch {
+            out.push_str("# HELP wow_build_id The build number of a product in a region.\n");
+            out.push_str("# TYPE wow_build_id gauge\n");
+            for product in fetch.products() {
+                for version in product.versions() {
+                    // builds are numeric in practice, but aren't guaranteed to be
+                    if let Ok(build) = version.build().parse::<u64>() {
+                        let _ = writeln!(
+                            out,
+                            "wow_build_id{{product=\"{}\",region=\"{}\"}} {build}",
+                            escape_label(product.name()),
+                            escape_label(version.region()),
+                        );
+                    }
+                }
+            }
+
+            out.push_str(
+                "# HELP wow_version_info The version of a product in a region, as labels.\n",
+            );
+            out.push_str("# TYPE wow_version_info gauge\n");
+            for product in fetch.products() {
+                for version in product.versions() {
+                    let _ = writeln!(
+                        out,
+                        "wow_version_info{{product=\"{}\",region=\"{}\",version=\"{}\",build=\"{}\"}} 1",
+                        escape_label(product.name()),
+                        escape_label(version.region()),
+                        escape_label(version.version()),
+                        escape_label(version.build()),
+                    );
+                }
+            }
+        }
+
+        if let Some(last_success) = inner.last_success {
+            out.push_str(
+                "# HELP wownow_last_fetch_timestamp_seconds When the last successful fetch finished.\n",
+            );
+            out.push_str("# TYPE wownow_last_fetch_timestamp_seconds gauge\n");
+            let _ = writeln!(
+                out,
+                "wownow_last_fetch_timestamp_seconds {}",
+                last_success.timestamp()
+            );
+        }
+
+        out.push_str(
+            "# HELP wownow_fetch_duration_seconds How long fetching the summary and versions took.\n",
+        );
+        out.push_str("# TYPE wownow_fetch_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (bound, count) in FETCH_DURATION_BUCKETS.iter().zip(inner.bucket_counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "wownow_fetch_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "wownow_fetch_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            inner.fetch_count
+        );
+        let _ = writeln!(
+            out,
+            "wownow_fetch_duration_seconds_sum {}",
+            inner.fetch_duration_sum
+        );
+        let _ = writeln!(
+            out,
+            "wownow_fetch_duration_seconds_count {}",
+            inner.fetch_count
+        );
+
+        out.push_str("# HELP wownow_fetch_errors_total How many fetches failed.\n");
+        out.push_str("# TYPE wownow_fetch_errors_total counter\n");
+        let _ = writeln!(out, "wownow_fetch_errors_total {}", inner.error_count);
+
+        out
+    }
+}
+
+/// Escape a label value for the text exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        let at = DateTime::parse_from_rfc3339("2024-03-14T17:56:25Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        metrics.record_fetch(Duration::from_millis(300), true, at);
+        metrics.record_fetch(Duration::from_mins(2), false, at);
+
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118018\n\
+            us|6d5d4b8bd7ac7d1e2a3e8b5c4d3e2f1a|7c4d3e2f1a6d5d4b8bd7ac7d1e2a3e8b||53913|10.2.5.53913|a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6",
+        )
+        .unwrap();
+        let mut fetch = VersionsFetch::new();
+        fetch.add_product(Product::try_from(("wow", &response)).unwrap());
+
+        let rendered = metrics.render(Some(&fetch));
+        assert!(rendered.contains("wow_build_id{product=\"wow\",region=\"us\"} 53913\n"));
+        assert!(rendered.contains(
+            "wow_version_info{product=\"wow\",region=\"us\",version=\"10.2.5\",build=\"53913\"} 1\n"
+        ));
+        assert!(rendered.contains("wownow_last_fetch_timestamp_seconds 1710438985\n"));
+        assert!(rendered.contains("wownow_fetch_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(rendered.contains("wownow_fetch_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(rendered.contains("wownow_fetch_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("wownow_fetch_duration_seconds_count 2\n"));
+        assert!(rendered.contains("wownow_fetch_errors_total 1\n"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -0,0 +1,123 @@
+//! Early warnings of patches, from background downloads newer than the live builds
+use crate::cli::replay;
+use clap::Args;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use wownow::prelude::*;
+
+/// Show the regions whose background download is ahead of the live build
+#[derive(Args, Debug)]
+pub(crate) struct PrepatchArgs {
+    /// The products to check. Can be given multiple times. If not given, checks the live World of
+    /// Warcraft products.
+    #[arg(long = "product", value_name = "PRODUCT")]
+    products: Vec<String>,
+}
+
+/// A region of a product downloading a newer build in the background than its live build
+#[derive(Debug, PartialEq, Serialize)]
+struct Prepatch {
+    product: String,
+    region: String,
+    live_version: String,
+    live_build: String,
+    bgdl_version: String,
+    bgdl_build: String,
+}
+
+/// The regions of a product, with its background download set, that are ahead of the live build
+fn prepatches(product: &Product) -> Vec<Prepatch> {
+    product
+        .prepatch_regions()
+        .map(|(live, bgdl)| Prepatch {
+            product: product.name().to_owned(),
+            region: live.region().to_owned(),
+            live_version: live.version().to_owned(),
+            live_build: live.build().to_owned(),
+            bgdl_version: bgdl.version().to_owned(),
+            bgdl_build: bgdl.build().to_owned(),
+        })
+        .collect()
+}
+
+/// Get a product's versions and background download, as a product with its background download
+/// set.
+async fn get_product(client: &Client, name: &str) -> std::result::Result<Product, String> {
+    let (versions, bgdl) = tokio::join!(client.get_versions(name), client.get_bgdl(name));
+    let versions = versions.map_err(|e| format!("Error getting `{name}` versions: {e}"))?;
+    let bgdl = bgdl.map_err(|e| format!("Error getting `{name}` background download: {e}"))?;
+    let mut product = Product::try_from((name, &versions))
+        .map_err(|e| format!("Error reading `{name}` versions: {e}"))?;
+    product.set_bgdl(
+        Product::try_from((name, &bgdl))
+            .map_err(|e| format!("Error reading `{name}` background download: {e}"))?,
+    );
+    Ok(product)
+}
+
+pub(crate) async fn run(args: PrepatchArgs, pretty_print: bool) -> crate::Result {
+    let client = replay::client();
+    let products: Vec<Arc<str>> = if args.products.is_empty() {
+        let summary = client
+            .get_summary()
+            .await
+            .map_err(|e| format!("Error getting summary: {e}"))?;
+        ProductFilter::live()
+            .select(&summary)
+            .into_iter()
+            .map(|(product, _)| product)
+            .collect()
+    } else {
+        args.products.into_iter().map(Arc::from).collect()
+    };
+
+    let mut set = JoinSet::new();
+    for product in products {
+        let client = client.clone();
+        set.spawn(async move { get_product(&client, &product).await });
+    }
+
+    let mut found = Vec::new();
+    while let Some(join_result) = set.join_next().await {
+        let product = join_result.map_err(|e| format!("Error joining task: {e}"))??;
+        found.extend(prepatches(&product));
+    }
+    found.sort_by(|a, b| (&a.product, &a.region).cmp(&(&b.product, &b.region)));
+
+    crate::to_json(&found, pretty_print)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepatches() {
+        let product = |build: &str| {
+            let psv = format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 1\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||{build}|10.2.5.{build}|53020d32e1a25648c8e1eafd5771935f"
+            );
+            Product::try_from(("wow", &VersionsResponse::try_from(psv.as_str()).unwrap())).unwrap()
+        };
+
+        let mut live = product("53584");
+        live.set_bgdl(product("53584"));
+        assert!(prepatches(&live).is_empty());
+
+        live.set_bgdl(product("53840"));
+        assert_eq!(
+            prepatches(&live),
+            [Prepatch {
+                product: "wow".to_owned(),
+                region: "us".to_owned(),
+                live_version: "10.2.5".to_owned(),
+                live_build: "53584".to_owned(),
+                bgdl_version: "10.2.5".to_owned(),
+                bgdl_build: "53840".to_owned(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Push notifications of new builds, through ntfy and Pushover
+use crate::cli::{
+    changes,
+    config::{NtfyConfig, PushoverConfig},
+    game::product_display_name,
+    webhook,
+};
+use wownow::prelude::*;
+
+/// The Pushover API endpoint for sending messages
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+/// The title and message of a push notification of changes
+fn notification(changes: &[BuildChange]) -> (String, String) {
+    let groups = changes::group(changes);
+    let title = if groups.len() == 1 {
+        "New World of Warcraft build".to_owned()
+    } else {
+        format!("{} new World of Warcraft builds", groups.len())
+    };
+    let message = groups
+        .iter()
+        .map(|group| {
+            format!(
+                "{} {}.{} ({})",
+                product_display_name(group.product).unwrap_or(group.product),
+                group.version,
+                group.build,
+                group.regions.join(", ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (title, message)
+}
+
+/// Publishes new builds to an ntfy topic
+pub(crate) struct NtfySink {
+    pub(crate) http: reqwest::Client,
+    pub(crate) config: NtfyConfig,
+}
+
+#[async_trait]
+impl ChangeSink for NtfySink {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        let (title, message) = notification(&event.changes);
+        let url = format!(
+            "{}/{}",
+            self.config.server.trim_end_matches('/'),
+            self.config.topic
+        );
+        webhook::send_with_retry(|| {
+            let request = self
+                .http
+                .post(&url)
+                .header("Title", &title)
+                .header("Tags", "video_game")
+                .body(message.clone());
+            match &self.config.token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Sends new builds to a Pushover user
+pub(crate) struct PushoverSink {
+    pub(crate) http: reqwest::Client,
+    pub(crate) config: PushoverConfig,
+}
+
+#[async_trait]
+impl ChangeSink for PushoverSink {
+    fn name(&self) -> &'static str {
+        "Pushover"
+    }
+
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        let (title, message) = notification(&event.changes);
+        webhook::send_with_retry(|| {
+            self.http.post(PUSHOVER_URL).form(&[
+                ("token", self.config.token.as_str()),
+                ("user", self.config.user.as_str()),
+                ("title", title.as_str()),
+                ("message", message.as_str()),
+            ])
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification() {
+        let change = |product: &str, region: &str| BuildChange {
+            product: product.to_owned(),
+            region: region.to_owned(),
+            previous_version: None,
+            previous_build: None,
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        };
+
+        assert_eq!(
+            notification(&[change("wow", "us"), change("wow", "eu")]),
+            (
+                "New World of Warcraft build".to_owned(),
+                "World of Warcraft 10.2.6.53840 (us, eu)".to_owned()
+            )
+        );
+        assert_eq!(
+            notification(&[change("wow", "us"), change("wowt", "us")]).0,
+            "2 new World of Warcraft builds"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! A client for a running `wownow serve`, over TCP or a Unix socket
+use crate::cli::serve::ListenAddr;
+use clap::Args;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Query a running `wownow serve`
+#[derive(Args, Debug)]
+pub(crate) struct QueryArgs {
+    /// The path to request (e.g., `/v1/versions/wow`).
+    #[arg(default_value = "/v1/versions")]
+    path: String,
+
+    /// The address of the server: a TCP address, or on Unix, `unix:` followed by the path of a
+    /// socket (e.g., `unix:/run/wownow.sock`).
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    connect: ListenAddr,
+}
+
+/// A response from the server
+#[derive(Debug, PartialEq)]
+struct HttpResponse {
+    status: u16,
+    is_json: bool,
+    body: Vec<u8>,
+}
+
+/// Parse a complete HTTP/1.1 response, as read from a connection the server has closed.
+fn parse_response(raw: &[u8]) -> Result<HttpResponse, String> {
+    let header_end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or("response has no end of headers")?;
+    let head = std::str::from_utf8(&raw[..header_end])
+        .map_err(|e| format!("response headers are not UTF-8: {e}"))?;
+    let body = &raw[header_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| format!("unparseable status line `{status_line}`"))?;
+
+    let mut is_json = false;
+    let mut is_chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_ascii_lowercase();
+        if name.eq_ignore_ascii_case("content-type") {
+            is_json = value.starts_with("application/json");
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            is_chunked = value == "chunked";
+        }
+    }
+
+    let body = if is_chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(HttpResponse {
+        status,
+        is_json,
+        body,
+    })
+}
+
+/// Decode a body sent with chunked transfer encoding.
+fn decode_chunked(mut raw: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or("truncated chunk size")?;
+        let size_line = std::str::from_utf8(&raw[..line_end]).unwrap_or_default();
+        // chunk extensions follow a `;`, and are ignored
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|e| format!("unparseable chunk size `{size_hex}`: {e}"))?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = raw.get(..size).ok_or("truncated chunk")?;
+        body.extend_from_slice(chunk);
+        raw = raw.get(size + 2..).ok_or("truncated chunk")?;
+    }
+}
+
+/// Make a GET request over a connection, reading the response until the server closes it.
+async fn get<S>(mut stream: S, path: &str) -> Result<HttpResponse, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| format!("Error sending request: {e}"))?;
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .await
+        .map_err(|e| format!("Error reading response: {e}"))?;
+    parse_response(&raw)
+}
+
+/// Run the `query` subcommand, returning the server's response, pretty printed if called for
+/// and it's JSON.
+pub(crate) async fn run(args: QueryArgs, pretty_print: bool) -> crate::Result {
+    if !args.path.starts_with('/') {
+        return Err(format!("path `{}` must start with `/`", args.path));
+    }
+
+    let connect_error = |e| format!("Error connecting to {}: {e}", args.connect);
+    let response = match &args.connect {
+        ListenAddr::Tcp(addr) => {
+            let stream = tokio::net::TcpStream::connect(addr)
+                .await
+                .map_err(connect_error)?;
+            get(stream, &args.path).await?
+        }
+        #[cfg(unix)]
+        ListenAddr::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(path)
+                .await
+                .map_err(connect_error)?;
+            get(stream, &args.path).await?
+        }
+        #[cfg(not(unix))]
+        ListenAddr::Unix(_) => unreachable!("Unix addresses are rejected when parsing"),
+    };
+
+    if !response.is_json {
+        let body = String::from_utf8_lossy(&response.body)
+            .trim_end()
+            .to_owned();
+        return if (200..300).contains(&response.status) {
+            Ok(body)
+        } else {
+            Err(format!("Error from server ({}): {body}", response.status))
+        };
+    }
+
+    let value: serde_json::Value = serde_json::from_slice(&response.body)
+        .map_err(|e| format!("Error parsing response: {e}"))?;
+    if (200..300).contains(&response.status) {
+        crate::to_json(&value, pretty_print)
+    } else {
+        let message = value
+            .get("error")
+            .and_then(serde_json::Value::as_str)
+            .map_or_else(|| value.to_string(), str::to_owned);
+        Err(format!(
+            "Error from server ({}): {message}",
+            response.status
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let response = parse_response(
+            b"HTTP/1.1 404 Not Found\r\n\
+            content-type: application/json\r\n\
+            content-length: 33\r\n\
+            \r\n\
+            {\"error\":\"unknown product `wo`\"}",
+        )
+        .unwrap();
+        assert_eq!(
+            response,
+            HttpResponse {
+                status: 404,
+                is_json: true,
+                body: b"{\"error\":\"unknown product `wo`\"}".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_chunked_response() {
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\n\
+            content-type: text/plain\r\n\
+            transfer-encoding: chunked\r\n\
+            \r\n\
+            5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.status, 200);
+        assert!(!response.is_json);
+        assert_eq!(response.body, b"hello, world");
+    }
+}
@@ -0,0 +1,278 @@
+//! Per-client rate limiting of the HTTP API, with a token bucket for each IPv4 address or IPv6
+//! /64 network
+use crate::cli::serve::ApiFailure;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Args;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How many clients to track at most. Past this, those whose buckets have refilled are forgotten,
+/// then the longest idle, down to half as many, so pruning is rare however many clients there are.
+const MAX_CLIENTS: usize = 10_000;
+
+/// The requests per minute each client IP address may make by default
+const DEFAULT_RATE_LIMIT: u32 = 120;
+
+/// How many requests for products the server doesn't refresh may be made upstream at once by
+/// default
+const DEFAULT_MAX_UPSTREAM_FETCHES: usize = 4;
+
+/// Flags for rate limiting
+#[derive(Args, Debug)]
+pub(crate) struct RateLimitArgs {
+    /// Limit each client IPv4 address or IPv6 /64 to this many requests per minute, answering any
+    /// more with `429 Too Many Requests`, or 0 for no limit. Clients of a Unix socket are not
+    /// limited.
+    #[arg(long, value_name = "REQUESTS", default_value_t = DEFAULT_RATE_LIMIT)]
+    rate_limit: u32,
+
+    /// How many requests a client may make at once before `--rate-limit` applies. Defaults to
+    /// the per-minute limit.
+    #[arg(long, value_name = "REQUESTS")]
+    rate_limit_burst: Option<u32>,
+
+    /// How many requests for products the server doesn't refresh (and hasn't cached) may be made
+    /// to the version server at once, across every client, or 0 for no limit. Requests over the
+    /// limit wait their turn.
+    #[arg(long, value_name = "REQUESTS", default_value_t = DEFAULT_MAX_UPSTREAM_FETCHES)]
+    max_upstream_fetches: usize,
+}
+
+impl RateLimitArgs {
+    /// How many on-demand requests may be made upstream at once, if limited
+    pub(crate) fn max_upstream_fetches(&self) -> Option<usize> {
+        (self.max_upstream_fetches > 0).then_some(self.max_upstream_fetches)
+    }
+}
+
+/// A client's tokens, each allowing one request
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets for each client IP address, refilled at a steady rate up to a burst size
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// Tokens added per second
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// A rate limiter from the flags, or `None` if there's no limit.
+    pub(crate) fn new(args: &RateLimitArgs) -> Result<Option<Self>, String> {
+        let per_minute = args.rate_limit;
+        if per_minute == 0 {
+            return Ok(None);
+        }
+        let burst = args.rate_limit_burst.unwrap_or(per_minute);
+        if burst == 0 {
+            return Err("Error: --rate-limit-burst must be at least 1".to_owned());
+        }
+        Ok(Some(Self {
+            rate: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst),
+            buckets: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Take a token from a client's bucket at `now`, or if it's empty, return how long until it
+    /// has one.
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= MAX_CLIENTS {
+            self.prune(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(client_key(ip)).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refill(*bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Forget the clients whose buckets have refilled, then the longest idle until at most half of
+    /// `MAX_CLIENTS` are left.
+    fn prune(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| self.refill(*bucket, now) < self.burst);
+        let excess = buckets.len().saturating_sub(MAX_CLIENTS / 2);
+        if excess == 0 {
+            return;
+        }
+        let mut updated = buckets
+            .values()
+            .map(|bucket| bucket.updated)
+            .collect::<Vec<_>>();
+        let (_, &mut newest_evicted, _) = updated.select_nth_unstable(excess - 1);
+        buckets.retain(|_, bucket| bucket.updated > newest_evicted);
+    }
+
+    /// The tokens in a bucket at `now`
+    fn refill(&self, bucket: Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+/// The key of a client's bucket: its IPv4 address, or its IPv6 /64 network, which is what a
+/// single host is usually given, so rotating addresses within it doesn't escape the limit.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => IpAddr::V4(ip),
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !(u128::MAX >> 64))),
+    }
+}
+
+/// Middleware answering clients over their limit with `429 Too Many Requests` and a
+/// `Retry-After` header. Requests without a client address (e.g., over a Unix socket) pass.
+pub(crate) async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = ip {
+        if let Err(retry_after) = limiter.check(ip, Instant::now()) {
+            let mut response = ApiFailure(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests".to_owned(),
+            )
+            .into_response();
+            // round up, so clients don't retry a moment too soon
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+            return response;
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limiter = RateLimiter::new(&RateLimitArgs {
+            rate_limit: 60,
+            rate_limit_burst: Some(2),
+            max_upstream_fetches: DEFAULT_MAX_UPSTREAM_FETCHES,
+        })
+        .unwrap()
+        .unwrap();
+        let start = Instant::now();
+        let client = IpAddr::from([192, 0, 2, 1]);
+
+        assert_eq!(limiter.check(client, start), Ok(()));
+        assert_eq!(limiter.check(client, start), Ok(()));
+        assert_eq!(limiter.check(client, start), Err(Duration::from_secs(1)));
+        // other clients have their own buckets
+        assert_eq!(limiter.check(IpAddr::from([192, 0, 2, 2]), start), Ok(()));
+        // a token is added every second
+        assert_eq!(
+            limiter.check(client, start + Duration::from_secs(1)),
+            Ok(())
+        );
+
+        let disabled = RateLimitArgs {
+            rate_limit: 0,
+            rate_limit_burst: None,
+            max_upstream_fetches: 0,
+        };
+        assert!(RateLimiter::new(&disabled).unwrap().is_none());
+        assert_eq!(disabled.max_upstream_fetches(), None);
+        assert!(RateLimiter::new(&RateLimitArgs {
+            rate_limit: 60,
+            rate_limit_burst: Some(0),
+            max_upstream_fetches: 1,
+        })
+        .is_err());
+
+        // limited by default
+        let args = <RateLimitArgs as clap::FromArgMatches>::from_arg_matches(
+            &<RateLimitArgs as clap::Args>::augment_args(clap::Command::new("serve"))
+                .get_matches_from(["serve"]),
+        )
+        .unwrap();
+        assert!(RateLimiter::new(&args).unwrap().is_some());
+        assert_eq!(
+            args.max_upstream_fetches(),
+            Some(DEFAULT_MAX_UPSTREAM_FETCHES)
+        );
+    }
+
+    #[test]
+    fn test_client_key() {
+        let limiter = RateLimiter::new(&RateLimitArgs {
+            rate_limit: 60,
+            rate_limit_burst: Some(1),
+            max_upstream_fetches: 0,
+        })
+        .unwrap()
+        .unwrap();
+        let start = Instant::now();
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        // addresses in one /64 share a bucket
+        assert_eq!(limiter.check(ip("2001:db8:1:2::1"), start), Ok(()));
+        assert!(limiter.check(ip("2001:db8:1:2:ffff::9"), start).is_err());
+        assert_eq!(limiter.check(ip("2001:db8:1:3::1"), start), Ok(()));
+        // an IPv4-mapped address is its IPv4 address
+        assert_eq!(limiter.check(ip("192.0.2.1"), start), Ok(()));
+        assert!(limiter.check(ip("::ffff:192.0.2.1"), start).is_err());
+    }
+
+    #[test]
+    fn test_prune() {
+        let limiter = RateLimiter::new(&RateLimitArgs {
+            rate_limit: 60,
+            rate_limit_burst: Some(2),
+            max_upstream_fetches: 0,
+        })
+        .unwrap()
+        .unwrap();
+        let start = Instant::now();
+        let clients = u32::try_from(MAX_CLIENTS).unwrap();
+        // clients whose buckets never refill, each checking a moment after the last
+        for client in 0..clients {
+            let now = start + Duration::from_micros(client.into());
+            limiter
+                .check(IpAddr::from(client.to_be_bytes()), now)
+                .unwrap();
+        }
+        let len = || limiter.buckets.lock().unwrap().len();
+        assert_eq!(len(), MAX_CLIENTS);
+
+        // the next client evicts the longest idle half
+        let now = start + Duration::from_micros(clients.into());
+        limiter.check(IpAddr::from([255; 4]), now).unwrap();
+        assert_eq!(len(), MAX_CLIENTS / 2 + 1);
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.contains_key(&IpAddr::from(0u32.to_be_bytes())));
+        assert!(buckets.contains_key(&IpAddr::from((clients - 1).to_be_bytes())));
+    }
+}
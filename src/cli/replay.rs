@@ -0,0 +1,194 @@
+//! Recording every exchange with the version servers into a directory (`--record`), and
+//! answering every request from such a directory without the network (`--replay`), for
+//! deterministic tests of the whole CLI and reproducing bugs from users' recordings
+use crate::cli::cache::encode_file_name;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use wownow::prelude::*;
+
+/// How requests to the version servers are answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// By the servers, recording their responses into a directory
+    Record(PathBuf),
+
+    /// From the responses recorded in a directory
+    Replay(PathBuf),
+}
+
+/// How this run answers requests, if not by the servers alone
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// Answer every request of this run by the mode given, if any. Only the first call has an effect.
+pub(crate) fn init(record: Option<PathBuf>, replay: Option<PathBuf>) {
+    if let Some(mode) = record.map(Mode::Record).or(replay.map(Mode::Replay)) {
+        let _ = MODE.set(mode);
+    }
+}
+
+/// Whether requests are being recorded or replayed, in which case responses shouldn't be read
+/// from the cache, which would go around them
+pub(crate) fn is_active() -> bool {
+    MODE.get().is_some()
+}
+
+/// The file a response of a host is recorded in: one directory per host, with the endpoint's
+/// path encoded as a file name as in the cache.
+fn recording_path(dir: &Path, host: &str, endpoint: &Endpoint) -> PathBuf {
+    dir.join(encode_file_name(host))
+        .join(encode_file_name(&endpoint.path()))
+}
+
+/// Write a response into the file it's recorded in, replacing any earlier recording.
+async fn record(path: &Path, raw: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, raw).await
+}
+
+/// A transport recording every response of another into a directory
+#[derive(Debug)]
+pub(crate) struct RecordingTransport<T> {
+    inner: T,
+    dir: PathBuf,
+}
+
+#[async_trait]
+impl<T: Transport> Transport for RecordingTransport<T> {
+    async fn get(&self, endpoint: &Endpoint) -> ApiResult<Vec<u8>> {
+        let raw = self.inner.get(endpoint).await?;
+        let path = recording_path(&self.dir, &self.context(endpoint).host, endpoint);
+        if let Err(e) = record(&path, &raw).await {
+            eprintln!("Warning: could not record `{}`: {e}", path.display());
+        }
+        Ok(raw)
+    }
+
+    fn context(&self, endpoint: &Endpoint) -> RequestContext {
+        self.inner.context(endpoint)
+    }
+}
+
+/// A transport answering a host's requests from the responses recorded in a directory, without
+/// the network
+#[derive(Debug)]
+pub(crate) struct ReplayTransport {
+    dir: PathBuf,
+    host: String,
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn get(&self, endpoint: &Endpoint) -> ApiResult<Vec<u8>> {
+        let path = recording_path(&self.dir, &self.host, endpoint);
+        tokio::fs::read(&path).await.map_err(|e| ApiError::Io {
+            context: self.context(endpoint),
+            source: if e.kind() == io::ErrorKind::NotFound {
+                io::Error::new(
+                    e.kind(),
+                    format!("no recording at `{}` to replay", path.display()),
+                )
+            } else {
+                e
+            },
+        })
+    }
+
+    fn context(&self, endpoint: &Endpoint) -> RequestContext {
+        RequestContext {
+            host: self.host.clone(),
+            ..RequestContext::new(endpoint)
+        }
+    }
+}
+
+/// A client of a server, recording or replaying if this run does.
+fn client_of(transport: TcpTransport) -> Client {
+    match MODE.get() {
+        None => Client::with_transport(transport),
+        Some(Mode::Record(dir)) => Client::with_transport(RecordingTransport {
+            inner: transport,
+            dir: dir.clone(),
+        }),
+        Some(Mode::Replay(dir)) => Client::with_transport(ReplayTransport {
+            dir: dir.clone(),
+            host: transport.host().to_owned(),
+        }),
+    }
+}
+
+/// A client of the default version server, recording or replaying if this run does.
+pub(crate) fn client() -> Client {
+    client_of(TcpTransport::default())
+}
+
+/// A client of a region's version server, such as `eu`, recording or replaying if this run does.
+pub(crate) fn region_client(region: &str) -> Client {
+    client_of(TcpTransport::region(region))
+}
+
+/// Get the raw response of an endpoint from the default version server, recording or replaying
+/// if this run does.
+pub(crate) async fn get_raw(endpoint: &Endpoint) -> ApiResult<Vec<u8>> {
+    let transport = TcpTransport::default();
+    match MODE.get() {
+        None => transport.get(endpoint).await,
+        Some(Mode::Record(dir)) => {
+            RecordingTransport {
+                inner: transport,
+                dir: dir.clone(),
+            }
+            .get(endpoint)
+            .await
+        }
+        Some(Mode::Replay(dir)) => {
+            ReplayTransport {
+                dir: dir.clone(),
+                host: transport.host().to_owned(),
+            }
+            .get(endpoint)
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("wownow-test-replay-{}", std::process::id()));
+        let (recorded, rerecorded) = (dir.join("recorded"), dir.join("rerecorded"));
+        let endpoint = Endpoint::Versions("wow".to_owned());
+        let host = "us.version.battle.net:1119";
+        let path = recording_path(&recorded, host, &endpoint);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"versions").unwrap();
+
+        let replay = |dir: &Path| ReplayTransport {
+            dir: dir.to_owned(),
+            host: host.to_owned(),
+        };
+        // recording a replay records the same responses again
+        let recording = RecordingTransport {
+            inner: replay(&recorded),
+            dir: rerecorded.clone(),
+        };
+        assert_eq!(recording.get(&endpoint).await.unwrap(), b"versions");
+        assert_eq!(
+            replay(&rerecorded).get(&endpoint).await.unwrap(),
+            b"versions"
+        );
+
+        let error = replay(&recorded).get(&Endpoint::Summary).await.unwrap_err();
+        assert_eq!(error.context().unwrap().host, host);
+        assert!(error.to_string().contains("no recording at"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
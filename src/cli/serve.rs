@@ -0,0 +1,983 @@
+//! An HTTP server exposing the versions as a JSON API
+use crate::cli::{
+    config::Config,
+    feed,
+    history::{self, History},
+    metrics::Metrics,
+    rate_limit::{self, RateLimitArgs, RateLimiter},
+    replay,
+    shutdown::{self, ShutdownArgs},
+    sinks,
+    systemd::{Systemd, SystemdArgs},
+    telemetry,
+    webhook::WebhookArgs,
+    ws,
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use clap::Args;
+use futures_util::Stream;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        watch, RwLock, Semaphore,
+    },
+    task::JoinSet,
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use wownow::prelude::*;
+
+/// Serve the versions of the live products (and on request, any product) as a JSON API
+#[derive(Args, Debug)]
+pub(crate) struct ServeArgs {
+    /// The address to listen on: a TCP address, or on Unix, `unix:` followed by the path of a
+    /// socket (e.g., `unix:/run/wownow.sock`).
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: ListenAddr,
+
+    /// How often to refresh the summary and the versions of the live products. Versions of other
+    /// products are cached for this long.
+    #[arg(long, value_name = "DURATION", default_value = "1m", value_parser = humantime::parse_duration)]
+    refresh_interval: Duration,
+
+    /// Record the refreshed versions in a history database at this path, or in the default
+    /// location if no path is given, and serve a feed of new builds from it at `/feed.atom`.
+    #[arg(long, value_name = "PATH", num_args(0..=1))]
+    #[allow(clippy::option_option)]
+    history_db: Option<Option<PathBuf>>,
+
+    /// Compare the first refresh to the versions last recorded in the history database, so builds
+    /// released while the server was stopped are notified as new.
+    #[arg(long, requires("history_db"))]
+    preload_history: bool,
+
+    #[command(flatten)]
+    webhooks: WebhookArgs,
+
+    /// Also serve a gRPC service on this TCP address.
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    grpc_listen: Option<SocketAddr>,
+
+    #[command(flatten)]
+    systemd: SystemdArgs,
+
+    #[command(flatten)]
+    shutdown: ShutdownArgs,
+
+    #[command(flatten)]
+    rate_limit: RateLimitArgs,
+
+    #[cfg(feature = "otlp")]
+    #[command(flatten)]
+    otlp: telemetry::OtlpArgs,
+}
+
+/// An address the server can listen on, and clients can connect to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if !cfg!(unix) {
+                return Err("Unix sockets are only supported on Unix".to_owned());
+            }
+            if path.is_empty() {
+                return Err("missing socket path after `unix:`".to_owned());
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        s.parse()
+            .map(Self::Tcp)
+            .map_err(|e| format!("invalid address `{s}`: {e}"))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "http://{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// The most recently refreshed data
+pub(crate) struct Latest {
+    pub(crate) fetch: VersionsFetch,
+
+    /// The bodies of `/v1/summary`, `/v1/versions` and `/v1/versions/{product}`, rendered once
+    /// per refresh
+    summary_body: Rendered,
+    versions_body: Rendered,
+    product_bodies: HashMap<Arc<str>, Rendered>,
+
+    /// The products the summary lists, the only ones requests are passed on for
+    products: HashSet<Arc<str>>,
+}
+
+impl Latest {
+    fn new(
+        summary: &SummaryResponse,
+        fetch: VersionsFetch,
+        responses: &[(Arc<str>, VersionsResponse)],
+    ) -> Self {
+        let product_bodies = responses
+            .iter()
+            .filter_map(|(name, response)| {
+                let product = fetch.product(name)?;
+                // the summary's seqn too, for the access it gives the product
+                let seqns = [summary.seqn, response.seqn];
+                Some((Arc::clone(name), Rendered::json(product, &seqns)))
+            })
+            .collect();
+        // seqns only increase, so the greatest changes whenever any of the products do
+        let versions_seqn = responses
+            .iter()
+            .map(|(_, response)| response.seqn)
+            .max()
+            .unwrap_or_default();
+        Self {
+            summary_body: Rendered::json(summary, &[summary.seqn]),
+            versions_body: Rendered::json(&fetch, &[summary.seqn, versions_seqn]),
+            product_bodies,
+            products: summary
+                .records
+                .iter()
+                .map(|record| Arc::clone(&record.product))
+                .collect(),
+            fetch,
+        }
+    }
+}
+
+/// A JSON response body, and an entity tag made from the seqns of the upstream responses it's made
+/// from, so clients can revalidate it with `If-None-Match`
+#[derive(Debug, Clone)]
+struct Rendered {
+    etag: String,
+    body: Bytes,
+}
+
+impl Rendered {
+    fn json(value: &impl Serialize, seqns: &[u32]) -> Self {
+        let seqns = seqns.iter().map(u32::to_string).collect::<Vec<_>>();
+        Self {
+            etag: format!("\"seqn-{}\"", seqns.join("-")),
+            // serializing plain strings and dates can't fail
+            body: Bytes::from(serde_json::to_vec(value).expect("response serializes")),
+        }
+    }
+
+    /// The response to a request with `headers`: `304 Not Modified` if the request's
+    /// `If-None-Match` matches, or else the body
+    fn respond(&self, headers: &HeaderMap) -> Response {
+        let not_modified = headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        if not_modified {
+            return (
+                StatusCode::NOT_MODIFIED,
+                [(header::ETAG, self.etag.clone())],
+            )
+                .into_response();
+        }
+        (
+            [
+                (header::CONTENT_TYPE, "application/json".to_owned()),
+                (header::ETAG, self.etag.clone()),
+            ],
+            self.body.clone(),
+        )
+            .into_response()
+    }
+}
+
+pub(crate) struct AppState {
+    pub(crate) client: Client,
+    pub(crate) latest: RwLock<Option<Latest>>,
+    pub(crate) metrics: Metrics,
+
+    /// Notified after each successful refresh
+    pub(crate) updates: watch::Sender<()>,
+
+    /// Sent the new builds found by each refresh, for `/v1/events` and `/v1/ws`
+    pub(crate) changes: broadcast::Sender<Change>,
+
+    /// The last [`CHANGES_BUFFER`] new builds sent, for WebSocket clients to resume from
+    pub(crate) recent_changes: std::sync::Mutex<VecDeque<Change>>,
+
+    /// Cancelled when the server is shutting down, to end the streams of events
+    pub(crate) shutdown: CancellationToken,
+
+    /// Where refreshed versions are recorded, if anywhere
+    history: Option<std::sync::Mutex<History>>,
+
+    /// Permits for requests made upstream on demand, for products the server doesn't refresh, if
+    /// they're limited
+    upstream: Option<Semaphore>,
+}
+
+impl AppState {
+    fn new(client: Client, history: Option<History>, max_upstream_fetches: Option<usize>) -> Self {
+        Self {
+            client,
+            latest: RwLock::new(None),
+            metrics: Metrics::default(),
+            updates: watch::Sender::new(()),
+            changes: broadcast::Sender::new(CHANGES_BUFFER),
+            recent_changes: std::sync::Mutex::new(VecDeque::with_capacity(CHANGES_BUFFER)),
+            shutdown: CancellationToken::new(),
+            history: history.map(std::sync::Mutex::new),
+            upstream: max_upstream_fetches.map(Semaphore::new),
+        }
+    }
+
+    /// Make a request upstream on demand, once there's a permit for it if they're limited.
+    async fn on_demand<T>(&self, request: impl std::future::Future<Output = T>) -> T {
+        // the semaphore is never closed
+        let _permit = match &self.upstream {
+            Some(upstream) => upstream.acquire().await.ok(),
+            None => None,
+        };
+        request.await
+    }
+
+    /// Send new builds to the streams of events, numbered after the last sent.
+    fn publish(&self, event: Arc<ChangeEvent>) {
+        let mut recent = self
+            .recent_changes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let id = recent.back().map_or(1, |(id, _)| id + 1);
+        if recent.len() == CHANGES_BUFFER {
+            recent.pop_front();
+        }
+        recent.push_back((id, Arc::clone(&event)));
+        // there may be no streams to send to
+        let _ = self.changes.send((id, event));
+    }
+
+    /// Record the responses of a refresh in the history database, if there is one.
+    fn record_history(&self, latest: &Latest, responses: &[(Arc<str>, VersionsResponse)]) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let mut history = history
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for (product, response) in responses {
+            if let Err(e) = history.record(latest.fetch.retrieval_datetime(), product, response) {
+                eprintln!("Error recording `{product}` history: {e}");
+            }
+        }
+    }
+}
+
+pub(crate) type SharedState = Arc<AppState>;
+
+/// New builds found by a refresh, numbered from 1 in the order they were found since the server
+/// started
+pub(crate) type Change = (u64, Arc<ChangeEvent>);
+
+/// How many refreshes' new builds a stream of events can fall behind by before missing some, and
+/// a WebSocket client can resume from
+pub(crate) const CHANGES_BUFFER: usize = 16;
+
+/// An error response, with a JSON body of the form `{"error": "..."}`
+pub(crate) struct ApiFailure(pub(crate) StatusCode, pub(crate) String);
+
+impl IntoResponse for ApiFailure {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn not_ready() -> ApiFailure {
+    ApiFailure(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "versions have not been fetched yet".to_owned(),
+    )
+}
+
+/// Whether a product could be one: only lowercase letters, digits and underscores, so a request
+/// for it can't smuggle other lines into the request made upstream.
+fn is_product_code(product: &str) -> bool {
+    !product.is_empty()
+        && product
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_')
+}
+
+/// Check that a product asked for is in the latest summary (or before the first refresh, could
+/// be a product), failing with `404 Not Found` before any request is made upstream for it.
+async fn check_product(state: &AppState, product: &str) -> Result<(), ApiFailure> {
+    let unknown = || {
+        ApiFailure(
+            StatusCode::NOT_FOUND,
+            format!("unknown product `{product}`"),
+        )
+    };
+    if !is_product_code(product) {
+        return Err(unknown());
+    }
+    match &*state.latest.read().await {
+        Some(latest) if !latest.products.contains(product) => Err(unknown()),
+        _ => Ok(()),
+    }
+}
+
+/// Fetch the summary and the versions of the live products, returning them along with the raw
+/// versions responses.
+async fn fetch_latest(
+    client: &Client,
+) -> Result<(Latest, Vec<(Arc<str>, VersionsResponse)>), String> {
+    let summary = telemetry::traced("summary", None, client.get_summary())
+        .await
+        .map_err(|e| format!("Error getting summary: {e}"))?;
+
+    let mut set = JoinSet::new();
+    for record in &summary.records {
+        if crate::LIVE_PRODUCTS.contains(&&*record.product) && record.flags.is_empty() {
+            let client = client.clone();
+            let product = Arc::clone(&record.product);
+            set.spawn(telemetry::in_current_span(async move {
+                let result =
+                    telemetry::traced("versions", Some(&product), client.get_versions(&product))
+                        .await;
+                (product, result)
+            }));
+        }
+    }
+
+    let mut fetch = VersionsFetch::new();
+    let mut responses = Vec::new();
+    while let Some(join_result) = set.join_next().await {
+        let (product, result) = join_result.map_err(|e| format!("Error joining task: {e}"))?;
+        let response = result.map_err(|e| format!("Error getting `{product}` versions: {e}"))?;
+        fetch.add_product(
+            Product::try_from((Arc::clone(&product), &response))
+                .map_err(|e| format!("Error reading `{product}` versions: {e}"))?,
+        );
+        responses.push((product, response));
+    }
+    fetch.apply_summary(&summary);
+    fetch.sort_products();
+
+    Ok((Latest::new(&summary, fetch, &responses), responses))
+}
+
+/// Publish new builds to the sinks, if there are any, in the background so slow sinks don't delay
+/// refreshes.
+fn notify(sinks: &FanOut, notifications: &TaskTracker, event: Arc<ChangeEvent>) {
+    if sinks.is_empty() {
+        return;
+    }
+    let sinks = sinks.clone();
+    notifications.spawn(async move {
+        for failure in sinks.publish(&event).await {
+            eprintln!(
+                "Error sending {} notification: {}",
+                failure.sink, failure.error
+            );
+        }
+    });
+}
+
+/// Refresh the latest data, notifying of new builds since the previous refresh (or `baseline`,
+/// if there hasn't been one) in tasks tracked by `notifications`. A failed refresh is logged, and
+/// the previous data is kept.
+async fn refresh(
+    state: &AppState,
+    sinks: &FanOut,
+    systemd: Systemd,
+    notifications: &TaskTracker,
+    baseline: Option<&VersionsFetch>,
+) {
+    let start = Instant::now();
+    let result = telemetry::in_span("refresh", fetch_latest(&state.client)).await;
+    state
+        .metrics
+        .record_fetch(start.elapsed(), result.is_ok(), Utc::now());
+    match result {
+        Ok((latest, responses)) => {
+            state.record_history(&latest, &responses);
+            let event = state
+                .latest
+                .read()
+                .await
+                .as_ref()
+                .map(|previous| &previous.fetch)
+                .or(baseline)
+                .and_then(|previous| ChangeEvent::detect(previous, &latest.fetch))
+                .map(Arc::new);
+            systemd.status(&format!(
+                "Refreshed {} products at {}",
+                latest.fetch.products().len(),
+                latest.fetch.retrieval_datetime()
+            ));
+            *state.latest.write().await = Some(latest);
+            state.updates.send_replace(());
+            // after the latest versions are updated, so a snapshot is never older than the new
+            // builds sent before it
+            if let Some(event) = event {
+                state.publish(Arc::clone(&event));
+                notify(sinks, notifications, event);
+            }
+        }
+        Err(e) => {
+            systemd.status(&e);
+            eprintln!("{e}");
+        }
+    }
+}
+
+/// Refresh the latest data every `interval`, after the first refresh made at startup, until
+/// `shutdown` is cancelled.
+async fn refresh_periodically(
+    state: SharedState,
+    interval: Duration,
+    sinks: FanOut,
+    systemd: Systemd,
+    shutdown: CancellationToken,
+    notifications: TaskTracker,
+) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // the first tick is immediate, and the startup refresh was just made
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            () = shutdown.cancelled() => return,
+        }
+        refresh(&state, &sinks, systemd, &notifications, None).await;
+    }
+}
+
+async fn get_summary(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiFailure> {
+    let latest = state.latest.read().await;
+    let latest = latest.as_ref().ok_or_else(not_ready)?;
+    Ok(latest.summary_body.respond(&headers))
+}
+
+async fn get_versions(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiFailure> {
+    let latest = state.latest.read().await;
+    let latest = latest.as_ref().ok_or_else(not_ready)?;
+    Ok(latest.versions_body.respond(&headers))
+}
+
+/// Look up a product, from the latest refresh if it's there, or else from the server, and map it
+/// with `f`.
+#[cfg(feature = "grpc")]
+pub(crate) async fn with_product<T>(
+    state: &AppState,
+    product: &str,
+    f: impl FnOnce(&Product) -> T,
+) -> Result<T, ApiFailure> {
+    {
+        let latest = state.latest.read().await;
+        if let Some(found) = latest
+            .as_ref()
+            .and_then(|latest| latest.fetch.product(product))
+        {
+            return Ok(f(found));
+        }
+    }
+
+    check_product(state, product).await?;
+    let response = fetch_versions(state, product).await?;
+    Ok(f(&product_of(product, &response)?))
+}
+
+/// A product made from its versions response
+fn product_of(product: &str, response: &VersionsResponse) -> Result<Product, ApiFailure> {
+    Product::try_from((product, response)).map_err(|e| {
+        ApiFailure(
+            StatusCode::BAD_GATEWAY,
+            format!("Error reading `{product}` versions: {e}"),
+        )
+    })
+}
+
+/// Get a product's versions from the server, or the client's cache.
+async fn fetch_versions(state: &AppState, product: &str) -> Result<VersionsResponse, ApiFailure> {
+    match telemetry::traced(
+        "versions",
+        Some(product),
+        state.on_demand(state.client.get_versions(product)),
+    )
+    .await
+    {
+        Ok(response) => Ok(response),
+        Err(e @ ApiError::UnknownProduct(_)) => {
+            Err(ApiFailure(StatusCode::NOT_FOUND, e.to_string()))
+        }
+        Err(e) => Err(ApiFailure(
+            StatusCode::BAD_GATEWAY,
+            format!("Error getting `{product}` versions: {e}"),
+        )),
+    }
+}
+
+async fn get_product_versions(
+    State(state): State<SharedState>,
+    Path(product): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiFailure> {
+    {
+        let latest = state.latest.read().await;
+        if let Some(rendered) = latest
+            .as_ref()
+            .and_then(|latest| latest.product_bodies.get(product.as_str()))
+        {
+            return Ok(rendered.respond(&headers));
+        }
+    }
+
+    check_product(&state, &product).await?;
+    let response = fetch_versions(&state, &product).await?;
+    Ok(Rendered::json(&product_of(&product, &response)?, &[response.seqn]).respond(&headers))
+}
+
+/// Pass an endpoint's raw response through from the server, or the client's cache, so the
+/// library's `HttpTransport` can use this server as its proxy. Any origin may read it, for
+/// dashboards in browsers.
+async fn get_raw_response(
+    State(state): State<SharedState>,
+    Path(path): Path<String>,
+) -> Result<Response, ApiFailure> {
+    let path = format!("v2/{path}");
+    let endpoint = Endpoint::from_path(&path)
+        .ok_or_else(|| ApiFailure(StatusCode::NOT_FOUND, format!("unknown endpoint `{path}`")))?;
+    if let Some(product) = endpoint.product() {
+        check_product(&state, product).await?;
+    }
+    let refreshed = match &endpoint {
+        Endpoint::Summary => true,
+        Endpoint::Versions(product) => state
+            .latest
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|latest| latest.product_bodies.contains_key(product.as_str())),
+        _ => false,
+    };
+    let request = state.client.get_raw(&endpoint);
+    let raw = if refreshed {
+        telemetry::traced("raw", None, request).await
+    } else {
+        telemetry::traced("raw", None, state.on_demand(request)).await
+    }
+    .map_err(|e| {
+        ApiFailure(
+            StatusCode::BAD_GATEWAY,
+            format!("Error getting `{path}`: {e}"),
+        )
+    })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+        ],
+        raw.to_vec(),
+    )
+        .into_response())
+}
+
+/// Stream the new builds found by each refresh as server-sent events, each a `new_builds` event
+/// with its number as its ID and the builds as JSON, until the server shuts down. A stream too far
+/// behind skips the builds it missed.
+async fn get_events(
+    State(state): State<SharedState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let changes = state.changes.subscribe();
+    let stream = futures_util::stream::unfold(changes, move |mut changes| {
+        let shutdown = state.shutdown.clone();
+        async move {
+            loop {
+                let received = tokio::select! {
+                    received = changes.recv() => received,
+                    () = shutdown.cancelled() => return None,
+                };
+                match received {
+                    Ok((id, event)) => {
+                        let event = Event::default()
+                            .event("new_builds")
+                            .id(id.to_string())
+                            .json_data(&*event);
+                        return Some((event, changes));
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn get_metrics(State(state): State<SharedState>) -> Response {
+    let latest = state.latest.read().await;
+    let body = state
+        .metrics
+        .render(latest.as_ref().map(|latest| &latest.fetch));
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    )
+        .into_response()
+}
+
+async fn get_feed(State(state): State<SharedState>) -> Result<Response, ApiFailure> {
+    let Some(history) = &state.history else {
+        return Err(ApiFailure(
+            StatusCode::NOT_FOUND,
+            "the feed needs a history database, given with `--history-db`".to_owned(),
+        ));
+    };
+    let builds = history
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .recent_builds(feed::FEED_LENGTH)
+        .map_err(|e| {
+            ApiFailure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error reading history: {e}"),
+            )
+        })?;
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/atom+xml")],
+        feed::render(&builds, Utc::now()),
+    )
+        .into_response())
+}
+
+fn router(state: SharedState, limiter: Option<RateLimiter>) -> Router {
+    let router = Router::new()
+        .route("/v1/summary", get(get_summary))
+        .route("/v1/versions", get(get_versions))
+        .route("/v1/versions/{product}", get(get_product_versions))
+        .route("/v1/events", get(get_events))
+        .route("/v1/ws", get(ws::get_ws))
+        .route("/v2/{*path}", get(get_raw_response))
+        .route("/metrics", get(get_metrics))
+        .route("/feed.atom", get(get_feed))
+        .with_state(state);
+    match limiter {
+        Some(limiter) => router.layer(middleware::from_fn_with_state(
+            Arc::new(limiter),
+            rate_limit::limit,
+        )),
+        None => router,
+    }
+}
+
+/// Run the `serve` subcommand, until the server fails.
+pub(crate) async fn run(args: ServeArgs, config: Config) -> crate::Result {
+    let systemd = Systemd::new(&args.systemd)?;
+    #[cfg(feature = "otlp")]
+    let telemetry = telemetry::Telemetry::new(&args.otlp)?;
+    let history_db = args.history_db.map(history::resolve_path).transpose()?;
+    let history = match history_db {
+        None => None,
+        Some(path) => Some(
+            History::open(&path)
+                .map_err(|e| format!("Error opening history database `{}`: {e}", path.display()))?,
+        ),
+    };
+    let baseline = match &history {
+        Some(history) if args.preload_history => Some(preload(history)?),
+        _ => None,
+    };
+    let state = Arc::new(AppState::new(
+        config
+            .timeouts
+            .apply(replay::client().with_memory_cache(args.refresh_interval)),
+        history,
+        args.rate_limit.max_upstream_fetches(),
+    ));
+
+    let token = state.shutdown.clone();
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_listen {
+        crate::cli::grpc::spawn(Arc::clone(&state), addr, token.clone()).await?;
+    }
+
+    let sinks = sinks::build(&args.webhooks, config.notify)?;
+    let notifications = TaskTracker::new();
+    // fetch everything before starting, so the server is ready to answer once it's listening
+    refresh(&state, &sinks, systemd, &notifications, baseline.as_ref()).await;
+    let refresh = tokio::spawn(refresh_periodically(
+        Arc::clone(&state),
+        args.refresh_interval,
+        sinks,
+        systemd,
+        token.clone(),
+        notifications.clone(),
+    ));
+
+    let app = router(state, RateLimiter::new(&args.rate_limit)?);
+    let server = async {
+        match &args.listen {
+            ListenAddr::Tcp(addr) => {
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .map_err(|e| format!("Error listening on {}: {e}", args.listen))?;
+                eprintln!("Listening on {}", args.listen);
+                systemd.ready(&format!("Listening on {}", args.listen));
+                // with each connection's address, so clients can be rate limited by IP address
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(token.clone().cancelled_owned())
+                .await
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = tokio::net::UnixListener::bind(path)
+                    .map_err(|e| format!("Error listening on {}: {e}", args.listen))?;
+                eprintln!("Listening on {}", args.listen);
+                systemd.ready(&format!("Listening on {}", args.listen));
+                let result = axum::serve(listener, app)
+                    .with_graceful_shutdown(token.clone().cancelled_owned())
+                    .await;
+                let _ = std::fs::remove_file(path);
+                result
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => unreachable!("Unix addresses are rejected when parsing"),
+        }
+        .map_err(|e| format!("Error serving: {e}"))?;
+
+        // the server has finished its requests, leaving the refresh in progress and the
+        // notifications it sends
+        let _ = refresh.await;
+        notifications.close();
+        notifications.wait().await;
+        Ok(String::new())
+    };
+    let result = shutdown::run_until_signal(&args.shutdown, &token, systemd, server)
+        .await
+        .and_then(std::convert::identity);
+    #[cfg(feature = "otlp")]
+    telemetry.shutdown();
+    result
+}
+
+/// The versions last recorded in the history database, to compare the first refresh to
+fn preload(history: &History) -> Result<VersionsFetch, String> {
+    let mut fetch = VersionsFetch::new();
+    for product in history
+        .latest_products()
+        .map_err(|e| format!("Error reading history: {e}"))?
+    {
+        fetch.add_product(product);
+    }
+    Ok(fetch)
+}
+
+/// Remove a socket left behind by a previous server, which would otherwise prevent binding. Any
+/// other kind of file at the path is left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)
+            .map_err(|e| format!("Error removing stale socket `{}`: {e}", path.display())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TestVersions;
+
+    #[test]
+    fn test_listen_addr() {
+        assert_eq!(
+            "0.0.0.0:8080".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], 8080)))
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:/run/wownow.sock".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Unix(PathBuf::from("/run/wownow.sock"))
+        );
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("localhost".parse::<ListenAddr>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_not_ready() {
+        let state = Arc::new(AppState::new(Client::new(), None, None));
+
+        let response = get_versions(State(state), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_raw_response() {
+        let state = Arc::new(AppState::new(Client::new(), None, None));
+
+        let response = get_raw_response(State(state), Path("products/wow/nowhere".to_owned()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_check_product() {
+        let state = Arc::new(AppState::new(Client::new(), None, None));
+        // a smuggled line is refused before any request, even before the first refresh
+        for product in ["wow\r\nv2/summary", "WoW", "wow/../summary", ""] {
+            let error = check_product(&state, product).await.unwrap_err();
+            assert_eq!(error.0, StatusCode::NOT_FOUND);
+        }
+        assert!(check_product(&state, "wow_classic").await.is_ok());
+
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 2119172\n\
+            wow|2118018|\n\
+            wowt|2118019|",
+        )
+        .unwrap();
+        *state.latest.write().await = Some(Latest::new(&summary, VersionsFetch::new(), &[]));
+        assert!(check_product(&state, "wowt").await.is_ok());
+        assert_eq!(
+            check_product(&state, "wow_classic").await.unwrap_err().0,
+            StatusCode::NOT_FOUND
+        );
+        let response = get_product_versions(
+            State(Arc::clone(&state)),
+            Path("wow_classic".to_owned()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = get_raw_response(
+            State(state),
+            Path("products/wow\r\nv2/summary/versions".to_owned()),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_rendered() {
+        let rendered = Rendered::json(&json!({ "build": "53840" }), &[2_118_468]);
+        assert_eq!(rendered.etag, "\"seqn-2118468\"");
+        // a body of several responses changes with any of them
+        assert_eq!(
+            Rendered::json(&json!({}), &[2_119_172, 2_118_468]).etag,
+            "\"seqn-2119172-2118468\""
+        );
+
+        let response = rendered.respond(&HeaderMap::new());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"seqn-2118468\"");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            "\"seqn-1\", W/\"seqn-2118468\"".parse().unwrap(),
+        );
+        assert_eq!(
+            rendered.respond(&headers).status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        headers.insert(header::IF_NONE_MATCH, "\"seqn-1\"".parse().unwrap());
+        assert_eq!(rendered.respond(&headers).status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        use futures_util::StreamExt;
+
+        let fetch = |versions_name| TestVersions::new().region("us", versions_name).fetch("wow");
+        let state = Arc::new(AppState::new(Client::new(), None, None));
+        let mut body = get_events(State(Arc::clone(&state)))
+            .await
+            .into_response()
+            .into_body()
+            .into_data_stream();
+
+        let event = ChangeEvent::detect(&fetch("10.2.5.53584"), &fetch("10.2.6.53840")).unwrap();
+        state.publish(Arc::new(event));
+        let chunk = body.next().await.unwrap().unwrap();
+        let chunk = std::str::from_utf8(&chunk).unwrap();
+        assert!(chunk.starts_with("event: new_builds\nid: 1\ndata: {"));
+        assert!(chunk.contains(r#""previous_build":"53584""#));
+
+        // the stream ends when the server shuts down
+        state.shutdown.cancel();
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_on_demand() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let state = AppState::new(Client::new(), None, Some(2));
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let request = || async {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        };
+        futures_util::future::join_all((0..6).map(|_| state.on_demand(request()))).await;
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+}
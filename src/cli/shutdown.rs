@@ -0,0 +1,66 @@
+//! Graceful shutdown on SIGINT or SIGTERM, letting in-flight work finish first
+use crate::cli::systemd::Systemd;
+use clap::Args;
+use std::{future::Future, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// Flags for shutting down
+#[derive(Args, Debug)]
+pub(crate) struct ShutdownArgs {
+    /// On SIGINT or SIGTERM, how long to let in-flight requests, history writes and notifications
+    /// finish before exiting anyway.
+    #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = humantime::parse_duration)]
+    drain_timeout: Duration,
+}
+
+/// Wait for SIGINT (Ctrl-C), or on Unix, SIGTERM.
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                eprintln!("Error listening for SIGTERM: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Run `work` to completion, unless a signal arrives first. Then `token` is cancelled so `work`
+/// can wind down, and it's given the drain timeout to finish.
+pub(crate) async fn run_until_signal<T>(
+    args: &ShutdownArgs,
+    token: &CancellationToken,
+    systemd: Systemd,
+    work: impl Future<Output = T>,
+) -> Result<T, String> {
+    tokio::pin!(work);
+    tokio::select! {
+        output = &mut work => return Ok(output),
+        () = signal() => {}
+    }
+
+    eprintln!(
+        "Shutting down, giving in-flight work up to {} to finish",
+        humantime::format_duration(args.drain_timeout)
+    );
+    systemd.stopping();
+    token.cancel();
+    tokio::time::timeout(args.drain_timeout, work)
+        .await
+        .map_err(|_| {
+            format!(
+                "Error: in-flight work didn't finish within {}",
+                humantime::format_duration(args.drain_timeout)
+            )
+        })
+}
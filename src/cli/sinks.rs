@@ -0,0 +1,56 @@
+//! The sinks new builds are published to, from the flags and the config file
+use crate::cli::{
+    config::NotifyConfig,
+    discord::DiscordSink,
+    email::Mailer,
+    push::{NtfySink, PushoverSink},
+    slack::SlackSink,
+    webhook::WebhookArgs,
+};
+use wownow::prelude::*;
+
+/// The sinks to publish new builds to: the webhooks given on the command line, and the
+/// notifications configured in the config file
+pub(crate) fn build(webhooks: &WebhookArgs, notify: NotifyConfig) -> Result<FanOut, String> {
+    let http = reqwest::Client::new();
+    let mut sinks = FanOut::new();
+    for webhook in &webhooks.webhooks {
+        sinks.add(
+            webhook
+                .clone()
+                .into_sink(http.clone(), webhooks.webhook_secret.clone()),
+        );
+    }
+    if let Some(discord) = notify.discord {
+        sinks.add(DiscordSink {
+            http: http.clone(),
+            url: discord.url,
+        });
+    }
+    if let Some(slack) = notify.slack {
+        sinks.add(SlackSink {
+            http: http.clone(),
+            url: slack.url,
+        });
+    }
+    if let Some(email) = &notify.email {
+        sinks.add(Mailer::new(email)?);
+    }
+    if let Some(ntfy) = notify.ntfy {
+        sinks.add(NtfySink {
+            http: http.clone(),
+            config: ntfy,
+        });
+    }
+    if let Some(pushover) = notify.pushover {
+        sinks.add(PushoverSink {
+            http,
+            config: pushover,
+        });
+    }
+    #[cfg(feature = "object-store")]
+    if let Some(object_store) = &notify.object_store {
+        sinks.add(crate::cli::storage::ObjectStoreSink::new(object_store)?);
+    }
+    Ok(sinks)
+}
@@ -0,0 +1,111 @@
+//! Slack notifications of new builds, as Block Kit messages sent to an incoming webhook
+use crate::cli::{
+    changes::{self, ChangeGroup},
+    game::product_display_name,
+    webhook,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use wownow::prelude::*;
+
+/// A section block announcing a group of changes
+fn section(group: &ChangeGroup<'_>) -> Value {
+    let name = product_display_name(group.product).unwrap_or(group.product);
+    let new = format!("{}.{}", group.version, group.build);
+    let change = match (group.previous_version, group.previous_build) {
+        (Some(previous_version), Some(previous_build)) => {
+            format!("`{previous_version}.{previous_build}` → `{new}`")
+        }
+        _ => format!("new: `{new}`"),
+    };
+    json!({
+        "type": "section",
+        "text": {
+            "type": "mrkdwn",
+            "text": format!("*{name}* (`{}`)\n{change}", group.product),
+        },
+        "fields": [
+            { "type": "mrkdwn", "text": format!("*Regions*\n{}", group.regions.join(", ")) },
+        ],
+    })
+}
+
+/// The Block Kit message announcing the changes, with plain text for notifications
+fn message(detected_at: DateTime<Utc>, changes: &[BuildChange]) -> Value {
+    let groups = changes::group(changes);
+
+    let mut blocks = vec![json!({
+        "type": "header",
+        "text": { "type": "plain_text", "text": "New World of Warcraft builds" },
+    })];
+    blocks.extend(groups.iter().map(section));
+    blocks.push(json!({
+        "type": "context",
+        "elements": [
+            {
+                "type": "mrkdwn",
+                "text": format!(
+                    "Detected <!date^{}^{{date_short_pretty}} at {{time}}|{}>",
+                    detected_at.timestamp(),
+                    detected_at.to_rfc3339()
+                ),
+            },
+        ],
+    }));
+
+    json!({
+        "text": format!("New builds: {}", changes::headline(&groups)),
+        "blocks": blocks,
+    })
+}
+
+/// Sends new builds to a Slack incoming webhook
+pub(crate) struct SlackSink {
+    pub(crate) http: reqwest::Client,
+    pub(crate) url: String,
+}
+
+#[async_trait]
+impl ChangeSink for SlackSink {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        // serializing JSON values can't fail
+        let body = serde_json::to_vec(&message(event.detected_at, &event.changes))
+            .expect("Slack message serializes");
+        webhook::deliver(&self.http, &self.url, body, None).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let changes = vec![BuildChange {
+            product: "wow".to_owned(),
+            region: "us".to_owned(),
+            previous_version: Some("10.2.5".to_owned()),
+            previous_build: Some("53584".to_owned()),
+            version: "10.2.6".to_owned(),
+            build: "53840".to_owned(),
+        }];
+
+        let message = message(Utc::now(), &changes);
+        assert_eq!(
+            message["text"],
+            "New builds: World of Warcraft 10.2.6.53840"
+        );
+        let blocks = message["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[1]["text"]["text"],
+            "*World of Warcraft* (`wow`)\n`10.2.5.53584` → `10.2.6.53840`"
+        );
+        assert_eq!(blocks[1]["fields"][0]["text"], "*Regions*\nus");
+    }
+}
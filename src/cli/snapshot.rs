@@ -0,0 +1,247 @@
+//! Byte-exact archives of every endpoint of the version server
+use crate::cli::replay;
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::Serialize;
+use std::{path::PathBuf, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinSet};
+use wownow::prelude::*;
+
+/// How many endpoints to fetch at once
+const CONCURRENCY: usize = 16;
+
+/// The name of the index file in each snapshot directory
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// Save the raw responses of the summary and every product's versions, CDNs and background
+/// downloads
+#[derive(Args, Debug)]
+pub(crate) struct SnapshotArgs {
+    /// The directory to save snapshots in. Each snapshot is saved in its own timestamped
+    /// subdirectory.
+    #[arg(long, value_name = "PATH")]
+    dir: PathBuf,
+}
+
+/// The index of a snapshot, describing the responses saved in it
+#[derive(Debug, Serialize)]
+struct Index {
+    retrieval_datetime: DateTime<Utc>,
+
+    /// The seqn of the summary
+    seqn: u32,
+
+    /// The responses saved, sorted by path
+    entries: Vec<IndexEntry>,
+
+    /// The endpoints that could not be fetched, sorted by path
+    errors: Vec<IndexError>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexEntry {
+    /// The endpoint's path, which is also the path of its response relative to the snapshot
+    endpoint: String,
+
+    /// The seqn the summary gives for the endpoint
+    seqn: u32,
+
+    /// The size of the response in bytes
+    size: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexError {
+    endpoint: String,
+    error: String,
+}
+
+/// The endpoint of a summary record, if it's one wownow knows of
+fn endpoint_of(record: &SummaryRecord) -> Option<Endpoint> {
+    let product = record.product.to_string();
+    match record.flags.as_str() {
+        "" => Some(Endpoint::Versions(product)),
+        "cdn" => Some(Endpoint::Cdns(product)),
+        "bgdl" => Some(Endpoint::Bgdl(product)),
+        _ => None,
+    }
+}
+
+/// Whether a product name is safe to use as a path component, so a strange summary can't write
+/// outside of the snapshot directory.
+fn is_safe_product(product: &str) -> bool {
+    !product.is_empty()
+        && product
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+}
+
+/// Run the `snapshot` subcommand, returning the path of the new snapshot.
+pub(crate) async fn run(args: SnapshotArgs) -> crate::Result {
+    let retrieval_datetime = Utc::now();
+    let snapshot_dir = create_snapshot_dir(&args.dir, retrieval_datetime).await?;
+
+    let raw_summary = replay::get_raw(&Endpoint::Summary)
+        .await
+        .map_err(|e| format!("Error getting summary: {e}"))?;
+    let summary = SummaryResponse::try_from(raw_summary.as_slice())
+        .map_err(|e| format!("Error getting summary: {e}"))?;
+    write_response(&snapshot_dir, &Endpoint::Summary, &raw_summary).await?;
+
+    let mut entries = vec![IndexEntry {
+        endpoint: Endpoint::Summary.path(),
+        seqn: summary.seqn,
+        size: raw_summary.len(),
+    }];
+    let mut errors = Vec::new();
+
+    let semaphore = Arc::new(Semaphore::new(CONCURRENCY));
+    let mut set = JoinSet::new();
+    for record in &summary.records {
+        let Some(endpoint) = endpoint_of(record) else {
+            continue;
+        };
+        if !is_safe_product(&record.product) {
+            errors.push(IndexError {
+                endpoint: endpoint.path(),
+                error: "product name is not safe to use as a path".to_owned(),
+            });
+            continue;
+        }
+        let seqn = record.seqn;
+        let semaphore = Arc::clone(&semaphore);
+        set.spawn(async move {
+            // the semaphore is never closed, so acquiring can't fail
+            let _permit = semaphore.acquire_owned().await;
+            let result = replay::get_raw(&endpoint).await;
+            (endpoint, seqn, result)
+        });
+    }
+
+    while let Some(join_result) = set.join_next().await {
+        let (endpoint, seqn, result) =
+            join_result.map_err(|e| format!("Error joining task: {e}"))?;
+        match result {
+            Ok(raw) => {
+                write_response(&snapshot_dir, &endpoint, &raw).await?;
+                entries.push(IndexEntry {
+                    endpoint: endpoint.path(),
+                    seqn,
+                    size: raw.len(),
+                });
+            }
+            Err(e) => errors.push(IndexError {
+                endpoint: endpoint.path(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    entries.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    errors.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    let index = Index {
+        retrieval_datetime,
+        seqn: summary.seqn,
+        entries,
+        errors,
+    };
+    let index_json = crate::to_json(&index, true)?;
+    let index_path = snapshot_dir.join(INDEX_FILE_NAME);
+    tokio::fs::write(&index_path, index_json)
+        .await
+        .map_err(|e| format!("Error writing `{}`: {e}", index_path.display()))?;
+
+    Ok(snapshot_dir.display().to_string())
+}
+
+/// Write a raw response into the snapshot directory, at its endpoint's path.
+/// Create the directory of a snapshot taken at a time, in the snapshots directory. Fails if there
+/// is already one, such as from another snapshot in the same second, rather than merging them.
+async fn create_snapshot_dir(dir: &std::path::Path, at: DateTime<Utc>) -> Result<PathBuf, String> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Error creating `{}`: {e}", dir.display()))?;
+    let snapshot_dir = dir.join(at.format("%Y%m%dT%H%M%SZ").to_string());
+    match tokio::fs::create_dir(&snapshot_dir).await {
+        Ok(()) => Ok(snapshot_dir),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(format!(
+            "Error: a snapshot already exists at `{}`",
+            snapshot_dir.display()
+        )),
+        Err(e) => Err(format!("Error creating `{}`: {e}", snapshot_dir.display())),
+    }
+}
+
+async fn write_response(
+    snapshot_dir: &std::path::Path,
+    endpoint: &Endpoint,
+    raw: &[u8],
+) -> Result<(), String> {
+    let path = snapshot_dir.join(endpoint.path());
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Error creating `{}`: {e}", parent.display()))?;
+    }
+    tokio::fs::write(&path, raw)
+        .await
+        .map_err(|e| format!("Error writing `{}`: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_of() {
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 2119172\n\
+            wow|2118018|\n\
+            wow|1476930|cdn\n\
+            wow|2110722|bgdl\n\
+            wow|2110723|mystery",
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary.records.iter().map(endpoint_of).collect::<Vec<_>>(),
+            vec![
+                Some(Endpoint::Versions("wow".to_owned())),
+                Some(Endpoint::Cdns("wow".to_owned())),
+                Some(Endpoint::Bgdl("wow".to_owned())),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_safe_product() {
+        assert!(is_safe_product("wow_classic_era"));
+        assert!(!is_safe_product(".."));
+        assert!(!is_safe_product("wow/../.."));
+        assert!(!is_safe_product(""));
+    }
+
+    #[tokio::test]
+    async fn test_create_snapshot_dir() {
+        let dir = std::env::temp_dir().join(format!("wownow-test-snapshot-{}", std::process::id()));
+        let at = DateTime::parse_from_rfc3339("2024-03-19T15:02:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let snapshot_dir = create_snapshot_dir(&dir, at).await.unwrap();
+        assert_eq!(snapshot_dir, dir.join("20240319T150200Z"));
+
+        // another snapshot in the same second doesn't merge into it
+        let later = at + chrono::TimeDelta::milliseconds(500);
+        assert_eq!(
+            create_snapshot_dir(&dir, later).await.unwrap_err(),
+            format!(
+                "Error: a snapshot already exists at `{}`",
+                snapshot_dir.display()
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
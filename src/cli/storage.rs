@@ -0,0 +1,113 @@
+//! Uploads of the products with new builds to object storage, such as S3 or GCS
+use crate::cli::config::ObjectStoreConfig;
+use object_store::{
+    aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, path::Path, ObjectStore, PutPayload,
+};
+use std::sync::Arc;
+use wownow::prelude::*;
+
+/// Uploads each product with new builds to `{prefix}/{date}/{product}.json` in a bucket. A later
+/// upload on the same day replaces the earlier one, so each day keeps the last versions seen.
+pub(crate) struct ObjectStoreSink {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl ObjectStoreSink {
+    /// Create a sink from its configuration, reading the credentials for the bucket's service
+    /// from the environment (e.g., `AWS_ACCESS_KEY_ID` or `GOOGLE_SERVICE_ACCOUNT`).
+    pub(crate) fn new(config: &ObjectStoreConfig) -> Result<Self, String> {
+        let url = reqwest::Url::parse(&config.url)
+            .map_err(|e| format!("invalid object store URL `{}`: {e}", config.url))?;
+        let store: Arc<dyn ObjectStore> = match url.scheme() {
+            "s3" => Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_url(url.as_str())
+                    .build()
+                    .map_err(|e| format!("Error setting up S3 for `{url}`: {e}"))?,
+            ),
+            "gs" => Arc::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_url(url.as_str())
+                    .build()
+                    .map_err(|e| format!("Error setting up GCS for `{url}`: {e}"))?,
+            ),
+            scheme => {
+                return Err(format!(
+                    "unsupported object store URL scheme `{scheme}`, expected `s3` or `gs`"
+                ))
+            }
+        };
+        Ok(Self {
+            store,
+            prefix: Path::from(url.path()),
+        })
+    }
+
+    /// The path of a product's upload
+    fn path(&self, event: &ChangeEvent, product: &Product) -> Path {
+        self.prefix
+            .child(event.detected_at.format("%Y-%m-%d").to_string())
+            .child(format!("{}.json", product.name()))
+    }
+}
+
+#[async_trait]
+impl ChangeSink for ObjectStoreSink {
+    fn name(&self) -> &'static str {
+        "object storage"
+    }
+
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+        for product in &event.products {
+            // serializing plain strings and dates can't fail
+            let body = serde_json::to_vec_pretty(product).expect("product serializes");
+            self.store
+                .put(&self.path(&event, product), PutPayload::from(body))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 1\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53840|10.2.6.53840|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let store = Arc::new(InMemory::new());
+        let sink = ObjectStoreSink {
+            store: Arc::clone(&store) as Arc<dyn ObjectStore>,
+            prefix: Path::from("versions"),
+        };
+        let event = ChangeEvent {
+            detected_at: DateTime::parse_from_rfc3339("2024-03-14T17:56:25Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            changes: Vec::new(),
+            products: vec![Product::try_from(("wow", &response)).unwrap()],
+        };
+
+        sink.publish(event).await.unwrap();
+
+        let uploaded = store
+            .get(&Path::from("versions/2024-03-14/wow.json"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let uploaded: serde_json::Value = serde_json::from_slice(&uploaded).unwrap();
+        assert_eq!(uploaded["name"], "wow");
+        assert_eq!(uploaded["versions"][0]["build"], "53840");
+    }
+}
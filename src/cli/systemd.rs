@@ -0,0 +1,76 @@
+//! Supervision by systemd, through the `sd_notify` protocol
+use clap::Args;
+
+/// Flags for running as a systemd service
+#[derive(Args, Debug)]
+pub(crate) struct SystemdArgs {
+    /// Tell systemd when startup has finished and what wownow is doing, and feed its watchdog if
+    /// the unit sets `WatchdogSec=`. For units with `Type=notify`.
+    #[arg(long)]
+    systemd: bool,
+}
+
+/// Notifies systemd of the service's state, or does nothing if not asked to
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Systemd {
+    enabled: bool,
+}
+
+impl Systemd {
+    pub(crate) fn new(args: &SystemdArgs) -> Result<Self, String> {
+        if args.systemd && !cfg!(unix) {
+            return Err("Error: `--systemd` is only supported on Unix".to_owned());
+        }
+        Ok(Self {
+            enabled: args.systemd,
+        })
+    }
+
+    /// Tell systemd startup has finished, and start feeding its watchdog if it has one.
+    pub(crate) fn ready(self, status: &str) {
+        #[cfg(unix)]
+        if self.enabled {
+            notify(&[
+                sd_notify::NotifyState::Ready,
+                sd_notify::NotifyState::Status(status),
+            ]);
+            if let Some(timeout) = sd_notify::watchdog_enabled() {
+                // feed it twice per timeout, as systemd recommends, so one late wakeup isn't a miss
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(timeout / 2);
+                    loop {
+                        interval.tick().await;
+                        notify(&[sd_notify::NotifyState::Watchdog]);
+                    }
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+    }
+
+    /// Tell systemd the service is shutting down.
+    pub(crate) fn stopping(self) {
+        #[cfg(unix)]
+        if self.enabled {
+            notify(&[sd_notify::NotifyState::Stopping]);
+        }
+    }
+
+    /// Tell systemd what the service is doing, shown by `systemctl status`.
+    pub(crate) fn status(self, status: &str) {
+        #[cfg(unix)]
+        if self.enabled {
+            notify(&[sd_notify::NotifyState::Status(status)]);
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+    }
+}
+
+#[cfg(unix)]
+fn notify(state: &[sd_notify::NotifyState<'_>]) {
+    if let Err(e) = sd_notify::notify(state) {
+        eprintln!("Error notifying systemd: {e}");
+    }
+}
@@ -0,0 +1,168 @@
+//! Export of traces and metrics over OTLP, with the `otlp` feature. Without it, spans and
+//! measurements are no-ops.
+use std::{fmt::Display, future::Future};
+
+#[cfg(feature = "otlp")]
+use opentelemetry::{
+    global,
+    trace::{FutureExt, Span, SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::{metrics::SdkMeterProvider, trace::SdkTracerProvider, Resource};
+
+/// The name traces and metrics are reported under
+#[cfg(feature = "otlp")]
+const SERVICE_NAME: &str = "wownow";
+
+/// Flags for exporting telemetry
+#[cfg(feature = "otlp")]
+#[derive(clap::Args, Debug)]
+pub(crate) struct OtlpArgs {
+    /// Export traces of fetches and metrics of their latency to an OpenTelemetry collector at
+    /// this base URL over OTLP/HTTP, such as `http://localhost:4318`.
+    #[arg(long, value_name = "URL", env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+}
+
+/// The installed exporters, flushed when shut down
+#[cfg(feature = "otlp")]
+pub(crate) struct Telemetry {
+    providers: Option<(SdkTracerProvider, SdkMeterProvider)>,
+}
+
+#[cfg(feature = "otlp")]
+impl Telemetry {
+    /// Install exporters to the collector given by the flags, if any.
+    pub(crate) fn new(args: &OtlpArgs) -> Result<Self, String> {
+        use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+
+        let Some(endpoint) = &args.otlp_endpoint else {
+            return Ok(Self { providers: None });
+        };
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{endpoint}/v1/traces"))
+            .build()
+            .map_err(|e| format!("Error creating OTLP trace exporter: {e}"))?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_resource(resource.clone())
+            .build();
+
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{endpoint}/v1/metrics"))
+            .build()
+            .map_err(|e| format!("Error creating OTLP metric exporter: {e}"))?;
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(metrics)
+            .with_resource(resource)
+            .build();
+
+        global::set_tracer_provider(tracer_provider.clone());
+        global::set_meter_provider(meter_provider.clone());
+        Ok(Self {
+            providers: Some((tracer_provider, meter_provider)),
+        })
+    }
+
+    /// Export what's left and stop the exporters.
+    pub(crate) fn shutdown(self) {
+        if let Some((tracer_provider, meter_provider)) = self.providers {
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("Error exporting traces: {e}");
+            }
+            if let Err(e) = meter_provider.shutdown() {
+                eprintln!("Error exporting metrics: {e}");
+            }
+        }
+    }
+}
+
+/// Run `work` in a new span, a child of the current one, so the fetches it makes are its
+/// children.
+pub(crate) fn in_span<T>(
+    name: &'static str,
+    work: impl Future<Output = T>,
+) -> impl Future<Output = T> {
+    #[cfg(feature = "otlp")]
+    {
+        let span = global::tracer(SERVICE_NAME).start(name);
+        let cx = Context::current_with_span(span);
+        async move {
+            let output = work.with_context(cx.clone()).await;
+            cx.span().end();
+            output
+        }
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = name;
+        work
+    }
+}
+
+/// Run `work`, such as a spawned task, in the current span, which it would otherwise lose.
+pub(crate) fn in_current_span<T>(work: impl Future<Output = T>) -> impl Future<Output = T> {
+    #[cfg(feature = "otlp")]
+    {
+        work.with_current_context()
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        work
+    }
+}
+
+/// Make a call to the version server, recording it as a span and its duration in the
+/// `wownow.fetch.duration` histogram, labeled by endpoint (e.g., `versions`) and outcome.
+pub(crate) async fn traced<T, E: Display>(
+    endpoint: &'static str,
+    product: Option<&str>,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    #[cfg(feature = "otlp")]
+    {
+        let tracer = global::tracer(SERVICE_NAME);
+        let mut attributes = vec![KeyValue::new("wownow.endpoint", endpoint)];
+        if let Some(product) = product {
+            attributes.push(KeyValue::new("wownow.product", product.to_owned()));
+        }
+        let mut span = tracer
+            .span_builder(format!("GET {endpoint}"))
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .start(&tracer);
+
+        let start = std::time::Instant::now();
+        let result = call.await;
+        let duration = start.elapsed();
+
+        if let Err(e) = &result {
+            span.set_status(Status::error(e.to_string()));
+        }
+        span.end();
+        global::meter(SERVICE_NAME)
+            .f64_histogram("wownow.fetch.duration")
+            .with_unit("s")
+            .with_description("How long calls to the version server took")
+            .build()
+            .record(
+                duration.as_secs_f64(),
+                &[
+                    KeyValue::new("endpoint", endpoint),
+                    KeyValue::new("outcome", if result.is_ok() { "success" } else { "error" }),
+                ],
+            );
+        result
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = (endpoint, product);
+        call.await
+    }
+}
@@ -0,0 +1,120 @@
+//! Showing times in a time zone other than UTC, for comparing them with announcements like "the
+//! patch goes live at 3pm PDT" without converting by hand
+use chrono::{DateTime, Local, SecondsFormat};
+use chrono_tz::Tz;
+use clap::Args;
+use serde_json::Value;
+
+/// Fields holding timestamps, shown in the chosen time zone
+const TIMESTAMP_FIELDS: [&str; 4] = [
+    "retrieval_datetime",
+    "retrieved_at",
+    "first_seen",
+    "last_seen",
+];
+
+/// The time zone to show times in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Zone {
+    #[default]
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ZoneArgs {
+    /// Show times in UTC, such as `2024-03-19T15:02:00Z`. This is the default.
+    #[arg(long, conflicts_with_all(["local_time", "timezone"]))]
+    utc: bool,
+
+    /// Show times in the local time zone, with its offset from UTC, such as
+    /// `2024-03-19T08:02:00-07:00`.
+    #[arg(long, conflicts_with("timezone"))]
+    local_time: bool,
+
+    /// Show times in this time zone of the IANA database, such as `America/Los_Angeles`, with its
+    /// offset from UTC.
+    #[arg(long, value_name = "ZONE")]
+    timezone: Option<Tz>,
+}
+
+impl ZoneArgs {
+    pub(crate) fn zone(&self) -> Zone {
+        match (self.local_time, self.timezone) {
+            (_, Some(tz)) => Zone::Named(tz),
+            (true, None) => Zone::Local,
+            (false, None) => Zone::Utc,
+        }
+    }
+}
+
+/// Show the timestamps of a JSON value in a time zone, in place, keeping their precision.
+/// Timestamps are already in UTC, so are left as they are for [`Zone::Utc`].
+pub(crate) fn localize(value: &mut Value, zone: Zone) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if TIMESTAMP_FIELDS.contains(&key.as_str()) {
+                    localize_timestamp(value, zone);
+                } else {
+                    localize(value, zone);
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(|value| localize(value, zone)),
+        _ => {}
+    }
+}
+
+/// Show an RFC 3339 timestamp in a time zone.
+fn localize_timestamp(value: &mut Value, zone: Zone) {
+    let Some(timestamp) = value
+        .as_str()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+    else {
+        return;
+    };
+    let localized = match zone {
+        Zone::Utc => return,
+        Zone::Local => timestamp
+            .with_timezone(&Local)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        Zone::Named(tz) => timestamp
+            .with_timezone(&tz)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+    };
+    *value = Value::String(localized);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_localize() {
+        let original = json!({
+            "retrieval_datetime": "2024-03-19T15:02:00.131Z",
+            "products": [{
+                "versions": [{ "first_seen": "2024-01-10T18:00:00Z", "build": "53840" }],
+            }],
+        });
+
+        let mut value = original.clone();
+        localize(&mut value, Zone::Utc);
+        assert_eq!(value, original);
+
+        localize(&mut value, Zone::Named(chrono_tz::America::Los_Angeles));
+        assert_eq!(
+            value,
+            json!({
+                // daylight saving time
+                "retrieval_datetime": "2024-03-19T08:02:00.131-07:00",
+                "products": [{
+                    "versions": [{ "first_seen": "2024-01-10T10:00:00-08:00", "build": "53840" }],
+                }],
+            })
+        );
+    }
+}
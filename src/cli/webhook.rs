@@ -0,0 +1,236 @@
+//! Webhooks, sent the new builds as JSON when a refresh detects them
+use clap::Args;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{fmt::Write, str::FromStr, time::Duration};
+use wownow::prelude::*;
+
+/// How many times to try delivering to a webhook before giving up
+const ATTEMPTS: u32 = 3;
+
+/// How long to wait before the first retry. Each later retry waits twice as long as the last.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The header carrying the HMAC-SHA256 signature of the body, when a secret is given
+const SIGNATURE_HEADER: &str = "X-Wownow-Signature";
+
+/// Where to POST new builds
+#[derive(Args, Debug)]
+pub(crate) struct WebhookArgs {
+    /// POST new builds as JSON to this URL when they're detected. Prefix the URL with a
+    /// comma-separated list of products and `=` (e.g., `wow,wow_classic=https://example.com/hook`)
+    /// to only send builds of those products. May be given multiple times.
+    #[arg(long = "webhook", value_name = "[PRODUCTS=]URL")]
+    pub(crate) webhooks: Vec<Webhook>,
+
+    /// Sign webhook bodies with HMAC-SHA256 using this secret, sending the signature in the
+    /// `X-Wownow-Signature` header as `sha256=<hex>`.
+    #[arg(
+        long,
+        value_name = "SECRET",
+        env = "WOWNOW_WEBHOOK_SECRET",
+        hide_env_values(true)
+    )]
+    pub(crate) webhook_secret: Option<String>,
+}
+
+/// A webhook, and the products it's interested in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Webhook {
+    url: String,
+
+    /// The products to send changes of, or if `None`, every product
+    products: Option<Vec<String>>,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    /// Parse a webhook from `URL`, or `PRODUCTS=URL` where `PRODUCTS` is a comma-separated list.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // a URL has a `:` after its scheme before any `=`, so a prefix without one is a product
+        // list
+        let (products, url) = match s.split_once('=') {
+            Some((products, url)) if !products.contains(':') => {
+                let products: Vec<String> = products
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|product| !product.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                if products.is_empty() {
+                    return Err(format!("no products before `=` in webhook `{s}`"));
+                }
+                (Some(products), url)
+            }
+            _ => (None, s),
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("webhook URL `{url}` is not an http or https URL"));
+        }
+        Ok(Self {
+            url: url.to_owned(),
+            products,
+        })
+    }
+}
+
+impl Webhook {
+    /// A sink sending to this webhook, signing bodies if there's a secret
+    pub(crate) fn into_sink(self, http: reqwest::Client, secret: Option<String>) -> WebhookSink {
+        // the URL may carry credentials in its path or query, so only its host is shown in errors
+        let host = reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        WebhookSink {
+            name: format!("webhook to {host}"),
+            http,
+            webhook: self,
+            secret,
+        }
+    }
+}
+
+/// Sends new builds to a webhook
+pub(crate) struct WebhookSink {
+    name: String,
+    http: reqwest::Client,
+    webhook: Webhook,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl ChangeSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Send the changes the webhook is interested in, if there are any.
+    async fn publish(&self, mut event: ChangeEvent) -> Result<(), SinkError> {
+        if let Some(products) = &self.webhook.products {
+            event
+                .changes
+                .retain(|change| products.contains(&change.product));
+        }
+        if event.changes.is_empty() {
+            return Ok(());
+        }
+        // serializing plain strings and dates can't fail
+        let body = serde_json::to_vec(&event).expect("change event serializes");
+        deliver(&self.http, &self.webhook.url, body, self.secret.as_deref()).await?;
+        Ok(())
+    }
+}
+
+/// Sign a body with HMAC-SHA256, as the value of the signature header.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let mut signature = "sha256=".to_owned();
+    for byte in mac.finalize().into_bytes() {
+        // writing to a String can't fail
+        let _ = write!(signature, "{byte:02x}");
+    }
+    signature
+}
+
+/// POST a JSON body to a webhook, retrying on connection failures and server errors. With a
+/// secret, the body is signed.
+pub(crate) async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    body: Vec<u8>,
+    secret: Option<&str>,
+) -> Result<(), String> {
+    let signature = secret.map(|secret| sign(secret, &body));
+    send_with_retry(|| {
+        let request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        match &signature {
+            Some(signature) => request.header(SIGNATURE_HEADER, signature),
+            None => request,
+        }
+    })
+    .await
+}
+
+/// Send the request made by `make_request`, making it again to retry on connection failures
+/// and server errors.
+pub(crate) async fn send_with_retry(
+    make_request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<(), String> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let error = match make_request().send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            // the webhook rejected the payload, which retrying won't change
+            Ok(response) if response.status().is_client_error() => {
+                return Err(format!("rejected with {}", response.status()));
+            }
+            Ok(response) => format!("failed with {}", response.status()),
+            // without the URL, whose path and query may hold the webhook's token
+            Err(e) => e.without_url().to_string(),
+        };
+        if attempt == ATTEMPTS {
+            return Err(format!("{error} after {ATTEMPTS} attempts"));
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_webhook() {
+        assert_eq!(
+            "https://example.com/hook?a=b".parse::<Webhook>().unwrap(),
+            Webhook {
+                url: "https://example.com/hook?a=b".to_owned(),
+                products: None,
+            }
+        );
+        assert_eq!(
+            "wow, wow_classic=https://example.com/hook"
+                .parse::<Webhook>()
+                .unwrap(),
+            Webhook {
+                url: "https://example.com/hook".to_owned(),
+                products: Some(vec!["wow".to_owned(), "wow_classic".to_owned()]),
+            }
+        );
+        assert!("=https://example.com/hook".parse::<Webhook>().is_err());
+        assert!("ftp://example.com".parse::<Webhook>().is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        // from RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_with_retry_hides_url() {
+        let client = reqwest::Client::new();
+        let error = send_with_retry(|| {
+            client.post("http://127.0.0.1:1/api/webhooks/123/secret-token?thread_id=456")
+        })
+        .await
+        .unwrap_err();
+        assert!(error.ends_with("after 3 attempts"), "{error}");
+        assert!(!error.contains("/api/webhooks"), "{error}");
+        assert!(!error.contains("secret-token"), "{error}");
+        assert!(!error.contains("thread_id"), "{error}");
+    }
+}
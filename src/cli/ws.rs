@@ -0,0 +1,172 @@
+//! New builds pushed over WebSocket at `/v1/ws`, after a snapshot of the versions, for clients
+//! that keep a connection open and resume where they left off when it drops
+use crate::cli::serve::{Change, SharedState};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::VecDeque, sync::PoisonError};
+use tokio::sync::broadcast::error::RecvError;
+
+/// The query of `/v1/ws`
+#[derive(Debug, Deserialize)]
+pub(crate) struct WsQuery {
+    /// The ID of the last new builds the client received, to be sent those after it in place of
+    /// a snapshot
+    resume: Option<u64>,
+}
+
+/// The changes after `resume` in the recent ones, if they're all there: none if `resume` is the
+/// last, and `None` if some have been dropped, or `resume` is from before the server restarted.
+fn replay<T>(recent: &VecDeque<(u64, T)>, resume: u64) -> Option<Vec<&(u64, T)>> {
+    let last = recent.back().map_or(0, |(id, _)| *id);
+    let first = recent.front().map_or(1, |(id, _)| *id);
+    (resume <= last && resume + 1 >= first)
+        .then(|| recent.iter().filter(|(id, _)| *id > resume).collect())
+}
+
+/// A message of new builds: a `new_builds` object with its ID, `detected_at` and `changes`
+fn change_message((id, event): &Change) -> Value {
+    let mut message = json!({ "type": "new_builds", "id": id });
+    if let (Some(message), Ok(Value::Object(event))) =
+        (message.as_object_mut(), serde_json::to_value(&**event))
+    {
+        message.extend(event);
+    }
+    message
+}
+
+/// A snapshot message: a `snapshot` object with the ID of the last new builds sent and the
+/// versions of the live products, in the same format as `/v1/versions`, or null if they haven't
+/// been fetched yet. Returns the ID too.
+async fn snapshot_message(state: &SharedState) -> (Value, u64) {
+    // holding the versions while reading the ID, as new builds are sent after the versions are
+    // updated, so the snapshot is never older than the ID says
+    let latest = state.latest.read().await;
+    let id = state
+        .recent_changes
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .back()
+        .map_or(0, |(id, _)| *id);
+    let versions = latest.as_ref().map(|latest| &latest.fetch);
+    (
+        json!({ "type": "snapshot", "id": id, "versions": versions }),
+        id,
+    )
+}
+
+/// Send a JSON message, returning whether the client is still there.
+async fn send(socket: &mut WebSocket, message: &Value) -> bool {
+    socket
+        .send(Message::Text(message.to_string().into()))
+        .await
+        .is_ok()
+}
+
+/// Push new builds to a client until it disconnects or the server shuts down: first those after
+/// its `resume` ID if it gave one and they're all still kept, or else a snapshot, then each as
+/// it's found. A client sending `{"type":"snapshot"}` is sent a snapshot again.
+async fn push_changes(state: SharedState, mut socket: WebSocket, resume: Option<u64>) {
+    // subscribing before reading the recent new builds, so none are missed in between
+    let mut changes = state.changes.subscribe();
+    let replayed = resume.and_then(|resume| {
+        let recent = state
+            .recent_changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let replayed = replay(&recent, resume)?;
+        let last_id = replayed.last().map_or(resume, |(id, _)| *id);
+        Some((replayed.into_iter().map(change_message).collect(), last_id))
+    });
+    let (opening, mut last_id) = if let Some(replayed) = replayed {
+        replayed
+    } else {
+        let (message, id) = snapshot_message(&state).await;
+        (vec![message], id)
+    };
+    for message in opening {
+        if !send(&mut socket, &message).await {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            received = changes.recv() => match received {
+                Ok(change) if change.0 > last_id => {
+                    last_id = change.0;
+                    if !send(&mut socket, &change_message(&change)).await {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // some new builds were missed, so the client needs a snapshot to catch up
+                Err(RecvError::Lagged(_)) => {
+                    let (message, id) = snapshot_message(&state).await;
+                    last_id = id;
+                    if !send(&mut socket, &message).await {
+                        return;
+                    }
+                }
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let wants_snapshot = serde_json::from_str::<Value>(&text)
+                        .is_ok_and(|message| message["type"] == "snapshot");
+                    if wants_snapshot {
+                        let (message, id) = snapshot_message(&state).await;
+                        last_id = last_id.max(id);
+                        if !send(&mut socket, &message).await {
+                            return;
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+            () = state.shutdown.cancelled() => {
+                let _ = socket.send(Message::Close(None)).await;
+                return;
+            }
+        }
+    }
+}
+
+pub(crate) async fn get_ws(
+    State(state): State<SharedState>,
+    Query(query): Query<WsQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| push_changes(state, socket, query.resume))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let ids = |replayed: Option<Vec<&(u64, ())>>| {
+            replayed.map(|replayed| replayed.iter().map(|(id, ())| *id).collect::<Vec<_>>())
+        };
+        let recent: VecDeque<(u64, ())> = (5..=8).map(|id| (id, ())).collect();
+        assert_eq!(ids(replay(&recent, 6)), Some(vec![7, 8]));
+        assert_eq!(ids(replay(&recent, 4)), Some(vec![5, 6, 7, 8]));
+        assert_eq!(ids(replay(&recent, 8)), Some(vec![]));
+        // new builds 4 and earlier have been dropped
+        assert_eq!(ids(replay(&recent, 3)), None);
+        // from before the server restarted
+        assert_eq!(ids(replay(&recent, 9)), None);
+
+        let recent = VecDeque::new();
+        assert_eq!(ids(replay(&recent, 0)), Some(vec![]));
+        assert_eq!(ids(replay(&recent, 1)), None);
+    }
+}
@@ -0,0 +1,905 @@
+//! A client for the version server, for embedding wownow in long-running programs
+#[cfg(all(feature = "tokio", feature = "chrono"))]
+use crate::subscribe::{Subscription, SubscriptionEvent};
+#[cfg(feature = "tcp")]
+use crate::transport::TcpTransport;
+use crate::{
+    api::{parse, Endpoint, Error, RequestContext, Result},
+    consistent::{self, ConsistentFetch},
+    filter::{ProductFilter, AGENT_PRODUCTS},
+    output::{Product, ProductCode, VersionsFetch},
+    pool::BufferPool,
+    response::{
+        base::{Error as ResponseError, Limits, Response as BaseResponse},
+        cdns::Response as CdnsResponse,
+        generic::Response as GenericResponse,
+        summary::Response as SummaryResponse,
+        versions::{Record as VersionsRecord, Response as VersionsResponse},
+    },
+    retry::{NoRetry, RetryPolicy},
+    transport::Transport,
+};
+use futures_util::{
+    future::{self, Either},
+    stream, Stream, StreamExt,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    io,
+    pin::pin,
+    sync::Arc,
+    time::Duration,
+};
+#[cfg(feature = "tokio")]
+use tokio::time::Instant;
+
+/// The most responses kept in a client's in-memory cache
+#[cfg(not(target_arch = "wasm32"))]
+const MEMORY_CACHE_CAPACITY: u64 = 1024;
+
+/// The most versions requests `Client::get_all_versions` and the like make at once
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
+/// How many times `Client::get_consistent_versions` fetches before giving up on a changing summary
+const MAX_CONSISTENT_ATTEMPTS: u32 = 3;
+
+/// The progress of [`Client::fetch_all`], reported as each product's versions arrive or fail
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Progress<'a> {
+    /// The product just finished, such as `wow`
+    pub product: &'a str,
+
+    /// Why the product failed, or `None` if its versions arrived
+    pub error: Option<&'a Error>,
+
+    /// How many products have finished, this one included, whether or not they failed
+    pub finished: usize,
+
+    /// How many products are being fetched
+    pub total: usize,
+}
+
+/// A client for the version server.
+///
+/// Clients are cheap to clone, and clones share their in-memory cache, so one client can be
+/// shared by many tasks (e.g., the request handlers of a web service).
+///
+/// Subscribing and setting deadlines need the `tokio` feature. On WebAssembly, clients also have
+/// no memory cache and can't stream.
+#[derive(Debug, Clone)]
+pub struct Client {
+    transport: Arc<dyn Transport>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) memory_cache: Option<moka::future::Cache<Endpoint, Arc<Vec<u8>>>>,
+    retry_policy: Arc<dyn RetryPolicy>,
+    buffers: Arc<BufferPool>,
+    limits: Limits,
+    lossy_utf8: bool,
+    timeout: Option<Duration>,
+    product_timeouts: Arc<HashMap<String, Duration>>,
+}
+
+#[cfg(feature = "tcp")]
+impl Default for Client {
+    fn default() -> Self {
+        Self::with_transport(TcpTransport::default())
+    }
+}
+
+impl Client {
+    /// Create a client with no cache, so every call makes a request to the server over TCP.
+    #[cfg(feature = "tcp")]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a client making requests with a transport, such as an `HttpTransport` to a proxy,
+    /// with no cache.
+    #[must_use]
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            #[cfg(not(target_arch = "wasm32"))]
+            memory_cache: None,
+            retry_policy: Arc::new(NoRetry),
+            buffers: Arc::default(),
+            limits: Limits::default(),
+            lossy_utf8: false,
+            timeout: None,
+            product_timeouts: Arc::default(),
+        }
+    }
+
+    /// Keep responses in memory for `ttl`, answering calls for the same endpoint from memory in
+    /// the meantime instead of making a request to the server.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_memory_cache(mut self, ttl: Duration) -> Self {
+        self.memory_cache = Some(
+            moka::future::Cache::builder()
+                .max_capacity(MEMORY_CACHE_CAPACITY)
+                .time_to_live(ttl)
+                .build(),
+        );
+        self
+    }
+
+    /// Retry failed requests as the policy decides, such as with `Exponential` backoff. By
+    /// default, requests aren't retried.
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(policy);
+        self
+    }
+
+    /// Give up on each attempt at a request after `timeout`, failing it with an `Error::Io` of
+    /// kind `TimedOut`, which retry policies like `Exponential` retry. By default, requests wait
+    /// as long as the server takes.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on each attempt at a request for a product's endpoints after `timeout`, instead of
+    /// the timeout of `with_timeout`, such as to wait longer for products known to be slow.
+    #[must_use]
+    pub fn with_product_timeout(mut self, product: impl Into<String>, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.product_timeouts).insert(product.into(), timeout);
+        self
+    }
+
+    /// How long to wait for an attempt at a request for an endpoint, if not forever
+    fn timeout_of(&self, endpoint: &Endpoint) -> Option<Duration> {
+        endpoint
+            .product()
+            .and_then(|product| self.product_timeouts.get(product))
+            .copied()
+            .or(self.timeout)
+    }
+
+    /// Reject responses larger than the limits with `ResponseError::TooLarge`, instead of the
+    /// defaults, which are far beyond the size of any real response. The TCP transport stops
+    /// reading a response once it's past the limit on bytes.
+    #[must_use]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Replace bytes of responses that aren't valid UTF-8 with `U+FFFD REPLACEMENT CHARACTER`
+    /// rather than failing to parse them, so one bad byte only spoils the field it's in. Each
+    /// response with replacements counts toward the `wownow_lossy_parses_total` metric.
+    #[must_use]
+    pub fn with_lossy_utf8(mut self) -> Self {
+        self.lossy_utf8 = true;
+        self
+    }
+
+    /// The transport the client makes its requests with, such as to name the host of a request
+    /// in an error with [`Transport::context`].
+    #[must_use]
+    pub fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    /// Get the raw, unparsed response of an endpoint.
+    ///
+    /// With a memory cache, concurrent calls for an endpoint that isn't cached share one request
+    /// to the server, and its response or error.
+    ///
+    /// Cancellation safe: if the call is dropped while waiting on the server, its connection is
+    /// closed and nothing is cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub async fn get_raw(&self, endpoint: &Endpoint) -> Result<Arc<Vec<u8>>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(memory_cache) = &self.memory_cache {
+            return memory_cache
+                .try_get_with_by_ref(endpoint, async {
+                    Ok(Arc::new(self.get_raw_with_retries(endpoint).await?))
+                })
+                .await
+                .map_err(Error::from_shared);
+        }
+        Ok(Arc::new(self.get_raw_with_retries(endpoint).await?))
+    }
+
+    /// Get the raw response of an endpoint from the server, retrying as the policy decides.
+    async fn get_raw_with_retries(&self, endpoint: &Endpoint) -> Result<Vec<u8>> {
+        let mut attempt = 1;
+        loop {
+            let buffer = self.buffers.take();
+            let request = (self.transport).get_limited(endpoint, buffer, self.limits.max_bytes());
+            let result = match self.timeout_of(endpoint) {
+                Some(timeout) => {
+                    within_timeout(request, timeout, || self.transport.context(endpoint)).await
+                }
+                None => request.await,
+            };
+            match result {
+                Ok(raw) => return Ok(raw),
+                Err(e) => match self.retry_policy.retry_after(attempt, &e) {
+                    // a timer of its own, so retries wait on any runtime
+                    Some(delay) => futures_timer::Delay::new(delay).await,
+                    None => return Err(e.with_attempt(attempt)),
+                },
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Get and parse the response of an endpoint, returning the buffer it was read into for reuse
+    /// unless the memory cache keeps it.
+    async fn get_parsed<T>(&self, endpoint: &Endpoint) -> Result<T>
+    where
+        T: for<'a> TryFrom<BaseResponse<'a>, Error = ResponseError>,
+    {
+        let raw = self.get_raw(endpoint).await?;
+        let parsed = parse(endpoint, &raw, self.limits, self.lossy_utf8, || {
+            self.transport.context(endpoint)
+        });
+        if let Ok(buffer) = Arc::try_unwrap(raw) {
+            self.buffers.put(buffer);
+        }
+        parsed
+    }
+
+    /// Get the summary of all products.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_summary(&self) -> Result<SummaryResponse> {
+        self.get_parsed(&Endpoint::Summary).await
+    }
+
+    /// Get the versions of a product.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_versions(&self, product: &str) -> Result<VersionsResponse> {
+        self.get_parsed(&Endpoint::Versions(product.to_owned()))
+            .await
+    }
+
+    /// Get the versions of a product as a `GenericResponse`, keeping every column's fields as text,
+    /// for products whose versions have columns other than those of `VersionsResponse`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_generic_versions(&self, product: &str) -> Result<GenericResponse> {
+        self.get_parsed(&Endpoint::Versions(product.to_owned()))
+            .await
+    }
+
+    /// Get the versions of the Battle.net app's own products, [`AGENT_PRODUCTS`], by product,
+    /// with all their columns. Products the server doesn't know of are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any other request fails or response cannot be parsed.
+    pub async fn get_agent_versions(&self) -> Result<BTreeMap<String, GenericResponse>> {
+        let responses = future::join_all(
+            AGENT_PRODUCTS
+                .map(|product| async move { (product, self.get_generic_versions(product).await) }),
+        )
+        .await;
+        let mut versions = BTreeMap::new();
+        for (product, response) in responses {
+            match response {
+                Ok(response) => {
+                    versions.insert(product.to_owned(), response);
+                }
+                Err(Error::UnknownProduct(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Get the versions record of a product in one region, such as `us`, or `None` if the product
+    /// isn't in that region.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_version(&self, product: &str, region: &str) -> Result<Option<VersionsRecord>> {
+        let response = self.get_versions(product).await?;
+        Ok(response
+            .records
+            .into_iter()
+            .find(|record| record.region == region))
+    }
+
+    /// Get the CDNs of a product, the hosts from which the files of its builds are downloaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_cdns(&self, product: &str) -> Result<CdnsResponse> {
+        self.get_parsed(&Endpoint::Cdns(product.to_owned())).await
+    }
+
+    /// Get the versions of a product being downloaded in the background ahead of a patch, which
+    /// are in the format of the versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response cannot be parsed.
+    pub async fn get_bgdl(&self, product: &str) -> Result<VersionsResponse> {
+        self.get_parsed(&Endpoint::Bgdl(product.to_owned())).await
+    }
+
+    /// Get the versions of a product, giving up with `Error::DeadlineExceeded` if they haven't
+    /// arrived by `deadline`. The request's connection is closed when it gives up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the response cannot be parsed, or the deadline
+    /// passes first.
+    #[cfg(feature = "tokio")]
+    pub async fn get_versions_with_deadline(
+        &self,
+        product: &str,
+        deadline: Instant,
+    ) -> Result<VersionsResponse> {
+        tokio::time::timeout_at(deadline, self.get_versions(product))
+            .await
+            .map_err(|_| Error::DeadlineExceeded)?
+    }
+
+    /// Get the versions of many products concurrently, a few at a time, yielding each product's
+    /// result as soon as it arrives, so they can be shown progressively. Results are in the order
+    /// they arrive, not the order of `products`.
+    ///
+    /// A product that fails doesn't end the stream: its error is yielded in its place, and the
+    /// other products carry on.
+    pub fn stream_versions<I>(
+        &self,
+        products: I,
+    ) -> impl Stream<Item = Result<(ProductCode, VersionsResponse)>> + '_
+    where
+        I: IntoIterator,
+        I::Item: Into<ProductCode>,
+    {
+        let products: Vec<ProductCode> = products.into_iter().map(Into::into).collect();
+        stream::iter(products)
+            .map(move |product| async move {
+                let response = self.get_versions(&product).await?;
+                Ok((product, response))
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES)
+    }
+
+    /// Get the versions of the products matching a filter, as the CLI does: the summary is
+    /// fetched to select the products (unless the filter names them outright), then their
+    /// versions are fetched a few at a time. Products are in the order of the summary or filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error if fetching the summary or any product's versions fails.
+    pub async fn get_all_versions(&self, filter: &ProductFilter) -> Result<VersionsFetch> {
+        let (products, summary) = self.select_products(filter).await?;
+        let mut fetch = assemble(self.get_many_versions(products).await?)?;
+        if let Some(summary) = &summary {
+            fetch.apply_summary(summary);
+        }
+        Ok(fetch)
+    }
+
+    /// Get the versions of the products matching a filter like `get_all_versions`, calling
+    /// `on_progress` as each product's versions arrive or fail, so a GUI or TUI can show the
+    /// progress of a large fetch. A product that fails is left out of the fetch rather than
+    /// failing the rest. Products are in the order of the summary or filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fetching the summary fails.
+    pub async fn fetch_all(
+        &self,
+        filter: &ProductFilter,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<VersionsFetch> {
+        let (products, summary) = self.select_products(filter).await?;
+        let total = products.len();
+        let mut fetched: Vec<Option<Product>> = vec![None; total];
+        let mut results = stream::iter(products.into_iter().enumerate())
+            .map(|(index, product)| async move {
+                let result = self
+                    .get_versions(&product)
+                    .await
+                    .and_then(|response| Ok(Product::try_from((Arc::clone(&product), &response))?));
+                (index, product, result)
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES);
+        let mut finished = 0;
+        while let Some((index, product, result)) = results.next().await {
+            finished += 1;
+            on_progress(Progress {
+                product: &product,
+                error: result.as_ref().err(),
+                finished,
+                total,
+            });
+            fetched[index] = result.ok();
+        }
+
+        let mut fetch = VersionsFetch::new();
+        for product in fetched.into_iter().flatten() {
+            fetch.add_product(product);
+        }
+        if let Some(summary) = &summary {
+            fetch.apply_summary(summary);
+        }
+        Ok(fetch)
+    }
+
+    /// The products matching a filter: those it names outright, or else those of the summary it
+    /// selects, with the summary.
+    async fn select_products(
+        &self,
+        filter: &ProductFilter,
+    ) -> Result<(Vec<Arc<str>>, Option<SummaryResponse>)> {
+        if filter.named_products().is_empty() {
+            let summary = self.get_summary().await?;
+            let products = filter
+                .select(&summary)
+                .into_iter()
+                .map(|(product, _)| product)
+                .collect();
+            Ok((products, Some(summary)))
+        } else {
+            let products = filter
+                .named_products()
+                .iter()
+                .map(|product| Arc::from(product.as_str()))
+                .collect();
+            Ok((products, None))
+        }
+    }
+
+    /// Get the versions of the products matching a filter like `get_all_versions`, but also
+    /// check they're consistent with one summary: after fetching the versions, fetch the summary
+    /// again, and start over if its seqn changed in the meantime or any versions were older than
+    /// it said. Retried versions and summaries bypass the memory cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any request fails, or if the summary changed during every attempt.
+    pub async fn get_consistent_versions(&self, filter: &ProductFilter) -> Result<ConsistentFetch> {
+        for _ in 0..MAX_CONSISTENT_ATTEMPTS {
+            let summary = self.get_summary().await?;
+            let products = filter
+                .select(&summary)
+                .into_iter()
+                .map(|(product, _)| product)
+                .collect();
+            let responses = self.get_many_versions(products).await?;
+
+            self.forget(&Endpoint::Summary).await;
+            let recheck = self.get_summary().await?;
+            if recheck.seqn == summary.seqn && consistent::is_current(&summary, &responses) {
+                let mut fetch = assemble(responses)?;
+                fetch.apply_summary(&summary);
+                return Ok(ConsistentFetch::new(summary.seqn, fetch));
+            }
+            for (product, _) in &responses {
+                self.forget(&Endpoint::Versions(product.to_string())).await;
+            }
+        }
+        Err(Error::Inconsistent(MAX_CONSISTENT_ATTEMPTS))
+    }
+
+    /// Get the versions of products a few at a time, in the order given.
+    async fn get_many_versions(
+        &self,
+        products: Vec<Arc<str>>,
+    ) -> Result<Vec<(Arc<str>, VersionsResponse)>> {
+        stream::iter(products)
+            .map(|product| async move {
+                let response = self.get_versions(&product).await?;
+                Ok((product, response))
+            })
+            .buffered(MAX_CONCURRENT_FETCHES)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Drop an endpoint's response from the memory cache, so the next call fetches it anew.
+    #[cfg_attr(target_arch = "wasm32", allow(clippy::unused_async))]
+    async fn forget(&self, endpoint: &Endpoint) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(memory_cache) = &self.memory_cache {
+            memory_cache.invalidate(endpoint).await;
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = endpoint;
+    }
+
+    /// Watch products for changes, polling every `interval` and yielding an event for each
+    /// change. If `products` is empty, every product in the summary is watched.
+    ///
+    /// Each poll fetches the summary, then only the versions of products whose seqn has changed
+    /// since they were last fetched. The first poll learns the products' starting state and
+    /// yields nothing. Errors are yielded as they happen, and polling carries on after them.
+    ///
+    /// Polls happen only while the stream is polled, so with a memory cache, `interval` should
+    /// be longer than its TTL.
+    #[cfg(all(feature = "tokio", feature = "chrono"))]
+    pub fn subscribe<I>(
+        &self,
+        products: I,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<SubscriptionEvent>> + Send + 'static
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let subscription = Subscription::new(
+            self.clone(),
+            products.into_iter().map(Into::into).collect(),
+            interval,
+        );
+        stream::unfold(subscription, |mut subscription| async move {
+            let event = subscription.next().await;
+            Some((event, subscription))
+        })
+    }
+}
+
+/// A fetch of the versions responses of products
+fn assemble(responses: Vec<(Arc<str>, VersionsResponse)>) -> Result<VersionsFetch> {
+    let mut fetch = VersionsFetch::new();
+    for (product, response) in responses {
+        fetch.add_product(Product::try_from((product, &response))?);
+    }
+    Ok(fetch)
+}
+
+/// Wait for a request, failing it as timed out if it hasn't finished after `timeout`, in which
+/// case it's dropped, so cancelled.
+async fn within_timeout<T>(
+    request: impl Future<Output = Result<T>>,
+    timeout: Duration,
+    context: impl FnOnce() -> RequestContext,
+) -> Result<T> {
+    // a timer of its own, so timeouts work on any runtime
+    match future::select(pin!(request), futures_timer::Delay::new(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(Error::Io {
+            context: context(),
+            source: io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no response after {timeout:?}"),
+            ),
+        }),
+    }
+}
+
+#[cfg(all(test, feature = "tcp"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_cache() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        memory_cache
+            .insert(
+                Endpoint::Summary,
+                Arc::new(
+                    b"Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+                    ## seqn = 2119172\n\
+                    wow|2118018|"
+                        .to_vec(),
+                ),
+            )
+            .await;
+
+        // served from memory, so no request is made
+        let summary = client.clone().get_summary().await.unwrap();
+        assert_eq!(summary.seqn, 2_119_172);
+    }
+
+    #[tokio::test]
+    async fn test_stream_versions() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        for (product, build) in [("wow", "53840"), ("wowt", "53877")] {
+            let raw = format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 1\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||{build}|10.2.6.{build}|53020d32e1a25648c8e1eafd5771935f"
+            );
+            memory_cache
+                .insert(
+                    Endpoint::Versions(product.to_owned()),
+                    Arc::new(raw.into_bytes()),
+                )
+                .await;
+        }
+
+        let mut results: Vec<_> = client
+            .stream_versions(["wow", "wowt"])
+            .map(|result| {
+                let (product, response) = result.unwrap();
+                (product, response.records[0].build_id)
+            })
+            .collect()
+            .await;
+        results.sort();
+        assert_eq!(
+            results,
+            [("wow".to_owned(), 53840), ("wowt".to_owned(), 53877)]
+        );
+    }
+
+    /// A transport answering every request with the same versions after a delay, keeping the most
+    /// requests it was answering at once
+    #[derive(Debug, Default)]
+    struct InFlight {
+        running: std::sync::atomic::AtomicUsize,
+        most: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for InFlight {
+        async fn get(&self, _endpoint: &Endpoint) -> Result<Vec<u8>> {
+            use std::sync::atomic::Ordering;
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(running, Ordering::SeqCst);
+            futures_timer::Delay::new(Duration::from_millis(5)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(crate::fixtures::synthetic_versions(1).into_bytes())
+        }
+
+        fn context(&self, endpoint: &Endpoint) -> RequestContext {
+            RequestContext::new(endpoint)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_versions_bounded() {
+        let most = Arc::default();
+        let client = Client::with_transport(InFlight {
+            most: Arc::clone(&most),
+            ..InFlight::default()
+        });
+        let products = (0..50).map(|index| format!("product_{index}"));
+        let results: Vec<_> = client.stream_versions(products).collect().await;
+        assert_eq!(results.len(), 50);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(
+            most.load(std::sync::atomic::Ordering::SeqCst),
+            MAX_CONCURRENT_FETCHES
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_all_versions() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        memory_cache
+            .insert(
+                Endpoint::Summary,
+                Arc::new(
+                    b"Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+                    ## seqn = 1\n\
+                    wow|1|\n\
+                    wowt|2|\n\
+                    d3|3|"
+                        .to_vec(),
+                ),
+            )
+            .await;
+        for (product, build) in [("wow", "53840"), ("wowt", "53877")] {
+            let raw = format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 1\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||{build}|10.2.6.{build}|53020d32e1a25648c8e1eafd5771935f"
+            );
+            memory_cache
+                .insert(
+                    Endpoint::Versions(product.to_owned()),
+                    Arc::new(raw.into_bytes()),
+                )
+                .await;
+        }
+
+        let fetch = client
+            .get_all_versions(&ProductFilter::new().with_prefixes(["wow"]))
+            .await
+            .unwrap();
+        let names: Vec<_> = fetch.products().iter().map(Product::name).collect();
+        assert_eq!(names, ["wow", "wowt"]);
+        assert_eq!(fetch.summary_seqn(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_get_version() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        memory_cache
+            .insert(
+                Endpoint::Versions("wow".to_owned()),
+                Arc::new(
+                    b"Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                    ## seqn = 1\n\
+                    us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53840|10.2.6.53840|53020d32e1a25648c8e1eafd5771935f\n\
+                    eu|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f"
+                        .to_vec(),
+                ),
+            )
+            .await;
+
+        let record = client.get_version("wow", "eu").await.unwrap().unwrap();
+        assert_eq!(record.versions_name, "10.2.5.53584");
+        assert!(client.get_version("wow", "kr").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        memory_cache
+            .insert(
+                Endpoint::Versions("wow".to_owned()),
+                Arc::new(
+                    b"Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                    ## seqn = 1\n\
+                    us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53840|10.2.6.53840|53020d32e1a25648c8e1eafd5771935f"
+                        .to_vec(),
+                ),
+            )
+            .await;
+        // a response that parses, but whose versions name has no build
+        memory_cache
+            .insert(
+                Endpoint::Versions("wowt".to_owned()),
+                Arc::new(
+                    b"Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                    ## seqn = 1\n\
+                    us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53877|unversioned|53020d32e1a25648c8e1eafd5771935f"
+                        .to_vec(),
+                ),
+            )
+            .await;
+
+        let mut reports = Vec::new();
+        let fetch = client
+            .fetch_all(&ProductFilter::products(["wowt", "wow"]), |progress| {
+                reports.push((
+                    progress.product.to_owned(),
+                    progress.error.map(Error::code),
+                    progress.finished,
+                    progress.total,
+                ));
+            })
+            .await
+            .unwrap();
+        reports.sort();
+        assert_eq!(
+            reports
+                .iter()
+                .map(|(product, error, _, total)| (product.as_str(), *error, *total))
+                .collect::<Vec<_>>(),
+            [("wow", None, 2), ("wowt", Some("output"), 2)]
+        );
+        let mut finished: Vec<_> = reports.iter().map(|report| report.2).collect();
+        finished.sort_unstable();
+        assert_eq!(finished, [1, 2]);
+        assert_eq!(fetch.products().len(), 1);
+        // the summary isn't consulted for named products
+        assert_eq!(fetch.summary_seqn(), None);
+        assert_eq!(fetch.products()[0].name(), "wow");
+    }
+
+    /// A transport answering every request with the same versions, after a delay
+    #[derive(Debug)]
+    struct Delayed(Duration);
+
+    #[async_trait::async_trait]
+    impl Transport for Delayed {
+        async fn get(&self, _endpoint: &Endpoint) -> Result<Vec<u8>> {
+            futures_timer::Delay::new(self.0).await;
+            Ok(crate::fixtures::synthetic_versions(1).into_bytes())
+        }
+
+        fn context(&self, endpoint: &Endpoint) -> RequestContext {
+            RequestContext::new(endpoint)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        let client = Client::with_transport(Delayed(Duration::from_millis(50)))
+            .with_timeout(Duration::from_millis(10))
+            .with_product_timeout("wow_classic", Duration::from_secs(10));
+
+        let error = client.get_versions("wow").await.unwrap_err();
+        let Error::Io { source, .. } = &error else {
+            panic!("{error}");
+        };
+        assert_eq!(source.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            client
+                .get_versions("wow_classic")
+                .await
+                .unwrap()
+                .records
+                .len(),
+            1
+        );
+        assert!(client
+            .clone()
+            .with_product_timeout("wow", Duration::from_secs(10))
+            .get_versions("wow")
+            .await
+            .is_ok());
+        // overriding a clone's timeouts leaves the original's be
+        assert!(client.get_versions("wow").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_agent_versions() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let memory_cache = client.memory_cache.as_ref().unwrap();
+        let agent = "Region!STRING:0|BuildId!DEC:4|VersionsName!String:0|Installer!STRING:0\n\
+            ## seqn = 3016451\n\
+            us|9255|2.47.0.9255|BattleNet-Setup.exe";
+        for (product, raw) in [("agent", agent), ("bnt", "")] {
+            memory_cache
+                .insert(
+                    Endpoint::Versions(product.to_owned()),
+                    Arc::new(raw.as_bytes().to_vec()),
+                )
+                .await;
+        }
+
+        // the server doesn't know `bnt`, so it's left out
+        let versions = client.get_agent_versions().await.unwrap();
+        assert_eq!(versions.keys().collect::<Vec<_>>(), ["agent"]);
+        let us = versions["agent"].region("us").unwrap();
+        assert_eq!(us.get("Installer"), Some("BattleNet-Setup.exe"));
+    }
+
+    /// A transport answering every request with the same versions after a delay, counting them
+    #[derive(Debug, Default)]
+    struct Counting(Arc<std::sync::atomic::AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl Transport for Counting {
+        async fn get(&self, _endpoint: &Endpoint) -> Result<Vec<u8>> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            futures_timer::Delay::new(Duration::from_millis(20)).await;
+            Ok(crate::fixtures::synthetic_versions(1).into_bytes())
+        }
+
+        fn context(&self, endpoint: &Endpoint) -> RequestContext {
+            RequestContext::new(endpoint)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_shares_requests() {
+        let requests = Arc::default();
+        let client = Client::with_transport(Counting(Arc::clone(&requests)))
+            .with_memory_cache(Duration::from_mins(1));
+
+        let calls = (0..10).map(|_| client.get_versions("wow"));
+        for result in futures_util::future::join_all(calls).await {
+            assert_eq!(result.unwrap().records.len(), 1);
+        }
+        assert_eq!(requests.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+}
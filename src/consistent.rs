@@ -0,0 +1,93 @@
+//! Fetches of versions checked to be consistent with one summary
+use crate::output::VersionsFetch;
+#[cfg(feature = "client")]
+use crate::response::{
+    summary::Response as SummaryResponse, versions::Response as VersionsResponse,
+};
+#[cfg(feature = "client")]
+use std::sync::Arc;
+
+/// The versions of products, all current as of the same summary seqn.
+///
+/// Made by `Client::get_consistent_versions`, which re-checks the summary after fetching the
+/// versions and starts over if it changed in the meantime, so the versions can safely be cached
+/// under the seqn.
+#[derive(Debug)]
+pub struct ConsistentFetch {
+    seqn: u32,
+    fetch: VersionsFetch,
+}
+
+impl ConsistentFetch {
+    #[cfg(feature = "client")]
+    pub(crate) fn new(seqn: u32, fetch: VersionsFetch) -> Self {
+        Self { seqn, fetch }
+    }
+
+    /// The seqn of the summary the versions are consistent with
+    #[must_use]
+    pub fn seqn(&self) -> u32 {
+        self.seqn
+    }
+
+    /// The versions
+    #[must_use]
+    pub fn fetch(&self) -> &VersionsFetch {
+        &self.fetch
+    }
+
+    /// The versions, discarding the seqn
+    #[must_use]
+    pub fn into_fetch(self) -> VersionsFetch {
+        self.fetch
+    }
+}
+
+/// Whether every versions response is at least as new as the summary says it is. Older ones were
+/// served from a cache that the summary has moved past.
+#[cfg(feature = "client")]
+pub(crate) fn is_current(
+    summary: &SummaryResponse,
+    responses: &[(Arc<str>, VersionsResponse)],
+) -> bool {
+    responses.iter().all(|(product, response)| {
+        summary
+            .versions_seqn(product)
+            .is_none_or(|seqn| response.seqn >= seqn)
+    })
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TestVersions;
+
+    fn versions(seqn: u32) -> VersionsResponse {
+        TestVersions::new()
+            .seqn(seqn)
+            .region("us", "10.2.6.53840")
+            .response()
+    }
+
+    #[test]
+    fn test_is_current() {
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 1\n\
+            wow|10|\n\
+            wowt|12|",
+        )
+        .unwrap();
+
+        assert!(is_current(
+            &summary,
+            &[("wow".into(), versions(10)), ("wowt".into(), versions(13))]
+        ));
+        assert!(!is_current(
+            &summary,
+            &[("wow".into(), versions(10)), ("wowt".into(), versions(11))]
+        ));
+        // products missing from the summary can't be checked
+        assert!(is_current(&summary, &[("sc2".into(), versions(1))]));
+    }
+}
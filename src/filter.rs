@@ -0,0 +1,147 @@
+//! Selection of products from the summary
+use crate::response::summary::Response as SummaryResponse;
+use std::sync::Arc;
+
+/// The products traditionally "live", or playable by most users
+pub const LIVE_PRODUCTS: [&str; 3] = ["wow", "wow_classic", "wow_classic_era"];
+
+/// The products of the Battle.net app itself, rather than of a game, which launcher developers
+/// track alongside the games. Their versions have columns of their own, so are read as a
+/// `GenericResponse`.
+pub const AGENT_PRODUCTS: [&str; 2] = ["agent", "bnt"];
+
+/// Which products to get the versions of: either products named outright, or those in the summary
+/// that match some criteria.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProductFilter {
+    /// Products named outright, in which case the summary isn't consulted
+    products: Vec<String>,
+
+    /// Prefixes of product codes to match, or `None` to match any
+    prefixes: Option<Vec<String>>,
+
+    live_only: bool,
+}
+
+impl ProductFilter {
+    /// A filter matching every product in the summary
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A filter matching the live products, `wow`, `wow_classic` and `wow_classic_era`, as the
+    /// CLI does by default
+    #[must_use]
+    pub fn live() -> Self {
+        Self::new().live_only()
+    }
+
+    /// A filter of exactly these products, without consulting the summary. Any other criteria are
+    /// ignored.
+    #[must_use]
+    pub fn products<I>(products: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut filter = Self::new();
+        for product in products {
+            let product = product.into();
+            if !filter.products.contains(&product) {
+                filter.products.push(product);
+            }
+        }
+        filter
+    }
+
+    /// Only match products whose codes start with one of these prefixes, such as `wow` or `d3`.
+    #[must_use]
+    pub fn with_prefixes<I>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only match the live products.
+    #[must_use]
+    pub fn live_only(mut self) -> Self {
+        self.live_only = true;
+        self
+    }
+
+    /// The products named outright, if any
+    #[must_use]
+    pub fn named_products(&self) -> &[String] {
+        &self.products
+    }
+
+    /// Whether a product of the summary matches the criteria
+    #[must_use]
+    pub fn matches(&self, product: &str) -> bool {
+        self.prefixes.as_ref().is_none_or(|prefixes| {
+            prefixes
+                .iter()
+                .any(|prefix| product.starts_with(prefix.as_str()))
+        }) && (!self.live_only || LIVE_PRODUCTS.contains(&product))
+    }
+
+    /// The matching products of a summary with the seqns of their versions, or the products named
+    /// outright with their seqns if they're in the summary.
+    #[must_use]
+    pub fn select(&self, summary: &SummaryResponse) -> Vec<(Arc<str>, Option<u32>)> {
+        if !self.products.is_empty() {
+            return self
+                .products
+                .iter()
+                .map(|product| (Arc::from(product.as_str()), summary.versions_seqn(product)))
+                .collect();
+        }
+        summary
+            .records
+            .iter()
+            // flags indicate other endpoints, like cdn or bgdl. we just want the versions.
+            .filter(|record| record.flags.is_empty() && self.matches(&record.product))
+            .map(|record| (Arc::clone(&record.product), Some(record.seqn)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 1\n\
+            wow|10|\n\
+            wow|11|cdn\n\
+            wowt|12|\n\
+            d3|13|",
+        )
+        .unwrap();
+        let names = |filter: ProductFilter| {
+            filter
+                .select(&summary)
+                .into_iter()
+                .map(|(product, _)| product.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(ProductFilter::new()), ["wow", "wowt", "d3"]);
+        assert_eq!(names(ProductFilter::live()), ["wow"]);
+        assert_eq!(
+            names(ProductFilter::new().with_prefixes(["wow"])),
+            ["wow", "wowt"]
+        );
+        assert_eq!(
+            ProductFilter::products(["d3", "sc2", "d3"]).select(&summary),
+            [("d3".into(), Some(13)), ("sc2".into(), None)]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Synthetic responses, larger than any the version server gives, for benchmarking the parsers
+use std::fmt::Write;
+
+/// A hex key that differs for each `seed`, as the configs of different builds do
+fn key(seed: usize) -> String {
+    // a multiplicative hash spreads consecutive seeds over every byte
+    let bits = (seed as u128 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835);
+    format!("{bits:032x}")
+}
+
+/// A summary response listing `products` products, each with a versions and a cdns record, and
+/// every tenth with a bgdl record too.
+#[must_use]
+pub fn synthetic_summary(products: usize) -> String {
+    let mut summary = "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n## seqn = 2119172\n".to_owned();
+    for index in 0..products {
+        let seqn = 2_000_000 + index;
+        // writing to a String can't fail
+        let _ = writeln!(summary, "product_{index}|{seqn}|");
+        let _ = writeln!(summary, "product_{index}|{seqn}|cdn");
+        if index % 10 == 0 {
+            let _ = writeln!(summary, "product_{index}|{seqn}|bgdl");
+        }
+    }
+    summary
+}
+
+/// A versions response with a record for each of `regions` regions. Most regions are on the
+/// same build, but every seventh is a build behind, as during a rollout, and every third has no
+/// key ring, as most real regions don't.
+#[must_use]
+pub fn synthetic_versions(regions: usize) -> String {
+    let mut versions = "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n## seqn = 2118468\n".to_owned();
+    for index in 0..regions {
+        let build_id = if index % 7 == 0 { 53_583 } else { 53_584 };
+        let key_ring = if index % 3 == 0 {
+            String::new()
+        } else {
+            key(index)
+        };
+        // writing to a String can't fail
+        let _ = writeln!(
+            versions,
+            "region_{index}|{}|{}|{key_ring}|{build_id}|10.2.5.{build_id}|{}",
+            key(build_id),
+            key(build_id + 1),
+            key(regions + index),
+        );
+    }
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{
+        summary::Response as SummaryResponse, versions::Response as VersionsResponse,
+    };
+
+    #[test]
+    fn test_synthetic_responses() {
+        let summary = SummaryResponse::try_from(synthetic_summary(20).as_str()).unwrap();
+        assert_eq!(summary.records.len(), 42);
+
+        let versions = VersionsResponse::try_from(synthetic_versions(21).as_str()).unwrap();
+        assert_eq!(versions.records.len(), 21);
+        assert_eq!(
+            versions
+                .records
+                .iter()
+                .filter(|record| record.build_id == 53_583)
+                .count(),
+            3
+        );
+        assert!(versions.records[0].key_ring.is_none());
+        assert!(versions.records[1].key_ring.is_some());
+        assert_ne!(versions.records[1].key_ring, versions.records[2].key_ring);
+    }
+}
@@ -0,0 +1,60 @@
+//! Metrics about calls to the version server, emitted through the `metrics` facade with the
+//! `metrics` feature, so they're recorded by whatever recorder the embedding program installs.
+//! Without the feature, these are no-ops.
+//!
+//! - `wownow_requests_total`: a counter of requests over TCP, labeled by `endpoint` and `outcome`
+//! - `wownow_request_duration_seconds`: a histogram of request durations, labeled by `endpoint`
+//! - `wownow_parse_errors_total`: a counter of unparseable responses, labeled by `endpoint`
+//! - `wownow_lossy_parses_total`: a counter of responses parsed with invalid UTF-8 replaced,
+//!   labeled by `endpoint`
+use crate::api::Endpoint;
+#[cfg(feature = "tcp")]
+use std::time::Duration;
+
+/// The kind of an endpoint, without the product, to keep the labels' cardinality low
+#[cfg(feature = "metrics")]
+fn endpoint_label(endpoint: &Endpoint) -> &'static str {
+    match endpoint {
+        Endpoint::Summary => "summary",
+        Endpoint::Versions(_) => "versions",
+        Endpoint::Cdns(_) => "cdns",
+        Endpoint::Bgdl(_) => "bgdl",
+    }
+}
+
+/// Record a request to an endpoint that took `duration`.
+#[cfg(feature = "tcp")]
+pub(crate) fn record_request(endpoint: &Endpoint, duration: Duration, succeeded: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let endpoint = endpoint_label(endpoint);
+        let outcome = if succeeded { "success" } else { "error" };
+        metrics::counter!("wownow_requests_total", "endpoint" => endpoint, "outcome" => outcome)
+            .increment(1);
+        metrics::histogram!("wownow_request_duration_seconds", "endpoint" => endpoint)
+            .record(duration);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (endpoint, duration, succeeded);
+}
+
+/// Record that an endpoint's response had invalid UTF-8, which was replaced.
+pub(crate) fn record_lossy_parse(endpoint: &Endpoint) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("wownow_lossy_parses_total", "endpoint" => endpoint_label(endpoint))
+        .increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = endpoint;
+}
+
+/// Record whether an endpoint's response parsed, passing the result through.
+pub(crate) fn record_parse<T, E>(endpoint: &Endpoint, result: Result<T, E>) -> Result<T, E> {
+    #[cfg(feature = "metrics")]
+    if result.is_err() {
+        metrics::counter!("wownow_parse_errors_total", "endpoint" => endpoint_label(endpoint))
+            .increment(1);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = endpoint;
+    result
+}
@@ -1,25 +1,116 @@
-/// Support for wownow
-#[warn(clippy::pedantic)]
-#[warn(missing_docs)]
-#[warn(clippy::cargo)]
-#[allow(clippy::multiple_crate_versions)]
+//! Support for wownow
+#![warn(clippy::pedantic)]
+#![warn(missing_docs)]
+#![warn(clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
 
 pub(crate) mod response {
     pub(crate) mod base;
+    pub(crate) mod cdns;
+    pub(crate) mod generic;
     pub(crate) mod summary;
     pub(crate) mod versions;
 }
 pub(crate) mod api;
+#[cfg(feature = "cdn")]
+pub(crate) mod cdn {
+    pub(crate) mod base;
+    pub(crate) mod build_config;
+    pub(crate) mod cdn_config;
+    pub(crate) mod fetch;
+}
+#[cfg(feature = "client")]
+pub(crate) mod client;
+pub(crate) mod consistent;
+pub(crate) mod filter;
+pub(crate) mod fixtures;
+#[cfg(feature = "client")]
+pub(crate) mod instrument;
+#[cfg(feature = "test-server")]
+pub(crate) mod mock_server;
+#[cfg(feature = "chrono")]
+pub(crate) mod notify;
 pub(crate) mod output;
+#[cfg(feature = "client")]
+pub(crate) mod pool;
+#[cfg(feature = "client")]
+pub(crate) mod retry;
+#[cfg(all(feature = "tokio", feature = "chrono"))]
+pub(crate) mod subscribe;
+pub(crate) mod suggest;
+// not every set of features has tests that use each part of it
+#[cfg(test)]
+#[allow(dead_code)]
+pub(crate) mod test_fixtures {
+    use crate::prelude::*;
+    include!("test_fixtures.rs");
+}
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub(crate) mod timestamp;
+#[cfg(feature = "client")]
+pub(crate) mod transport;
 
+/// Convenient re-exports of the public API
 pub mod prelude {
+    #[cfg(feature = "cdn")]
+    pub use crate::cdn::{
+        base::{Error as CdnError, Result as CdnResult},
+        build_config::{BuildConfig, FileKeys},
+        cdn_config::CdnConfig,
+        fetch::Cdn,
+    };
+    #[cfg(feature = "test-server")]
+    pub use crate::mock_server::{Fault, MockServer, RunningMockServer};
+    #[cfg(feature = "chrono")]
+    pub use crate::notify::{BuildChange, ChangeEvent, ChangeSink, SinkError, SinkFailure};
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub use crate::timestamp::Timestamp;
+    #[cfg(feature = "http")]
+    pub use crate::transport::HttpTransport;
     pub use crate::{
-        api::{get_summary, get_versions, Error as ApiError, Result as ApiResult},
-        output::{Error as OutputError, Product, Result as OutputResult, Version, VersionsFetch},
+        api::{
+            check_known_product, Endpoint, Error as ApiError, Protocol, RequestContext,
+            Result as ApiResult,
+        },
+        consistent::ConsistentFetch,
+        filter::{ProductFilter, AGENT_PRODUCTS, LIVE_PRODUCTS},
+        output::{
+            Access, Error as OutputError, Product, ProductCategory, ProductCode,
+            Result as OutputResult, Version, VersionsFetch,
+        },
         response::{
-            base::{Dec4, Error as ResponseError, Hex16, String0},
+            base::{
+                decode_hex16, parse_lossy, Dec4, Error as ResponseError, Hex16,
+                Limits as ResponseLimits, String0, DEFAULT_MAX_BYTES, DEFAULT_MAX_COLUMNS,
+                DEFAULT_MAX_RECORDS,
+            },
+            cdns::{Record as CdnsRecord, Response as CdnsResponse},
+            generic::{Record as GenericRecord, Response as GenericResponse},
             summary::{Record as SummaryRecord, Response as SummaryResponse},
-            versions::{Record as VersionsRecord, Response as VersionsResponse},
+            versions::{
+                Columns as VersionsColumns, Record as VersionsRecord, Response as VersionsResponse,
+            },
         },
     };
+    #[cfg(feature = "tcp")]
+    pub use crate::{
+        api::{get_raw, get_summary, get_versions},
+        transport::TcpTransport,
+    };
+    #[cfg(feature = "client")]
+    pub use crate::{
+        client::{Client, Progress},
+        retry::{Exponential, NoRetry, RetryPolicy},
+        transport::Transport,
+    };
+    #[cfg(all(feature = "tokio", feature = "chrono"))]
+    pub use crate::{notify::FanOut, subscribe::SubscriptionEvent};
+    pub use async_trait::async_trait;
+}
+
+/// Synthetic responses for the benchmarks and the mock server. Not part of the public API, so
+/// they may change in any release.
+#[doc(hidden)]
+pub mod __bench {
+    pub use crate::fixtures::{synthetic_summary, synthetic_versions};
 }
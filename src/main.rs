@@ -4,15 +4,146 @@
 #![warn(clippy::cargo)]
 #![allow(clippy::multiple_crate_versions)]
 
-use clap::Parser;
-use serde_json::{to_string, to_string_pretty};
-use std::process::ExitCode;
-use tokio::task::JoinSet;
-use wownow::prelude::*;
+mod cli {
+    pub(crate) mod agent;
+    pub(crate) mod alert;
+    #[cfg(feature = "cdn")]
+    pub(crate) mod build_config;
+    pub(crate) mod build_info;
+    pub(crate) mod cache;
+    pub(crate) mod canonical;
+    pub(crate) mod capture;
+    #[cfg(feature = "cdn")]
+    pub(crate) mod cdn_check;
+    #[cfg(feature = "cdn")]
+    pub(crate) mod cdn_config;
+    #[cfg(feature = "cdn")]
+    pub(crate) mod cdn_ping;
+    pub(crate) mod cdn_validation;
+    pub(crate) mod changes;
+    pub(crate) mod config;
+    pub(crate) mod consensus;
+    pub(crate) mod crosscheck;
+    pub(crate) mod daemon;
+    pub(crate) mod discord;
+    pub(crate) mod email;
+    pub(crate) mod feed;
+    pub(crate) mod game;
+    pub(crate) mod get;
+    #[cfg(feature = "grpc")]
+    pub(crate) mod grpc;
+    pub(crate) mod history;
+    pub(crate) mod metrics;
+    pub(crate) mod prepatch;
+    pub(crate) mod push;
+    pub(crate) mod query;
+    pub(crate) mod rate_limit;
+    pub(crate) mod replay;
+    pub(crate) mod serve;
+    pub(crate) mod shutdown;
+    pub(crate) mod sinks;
+    pub(crate) mod slack;
+    pub(crate) mod snapshot;
+    #[cfg(feature = "object-store")]
+    pub(crate) mod storage;
+    pub(crate) mod systemd;
+    pub(crate) mod telemetry;
+    pub(crate) mod timezone;
+    pub(crate) mod webhook;
+    pub(crate) mod ws;
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod test_fixtures {
+    use wownow::prelude::*;
+    include!("test_fixtures.rs");
+}
+
+use clap::{Parser, Subcommand};
+use cli::{
+    agent, build_info,
+    cache::{self, Cache, CacheCommand},
+    canonical,
+    capture::Capture,
+    cdn_validation::{self, CdnIssue, CdnValidation},
+    config::{Config, Timeouts},
+    consensus::{self, ConsensusArgs},
+    crosscheck::{self, CrosscheckArgs},
+    daemon::{self, DaemonArgs},
+    game::{Game, GameFilter},
+    get::{self, GetPath},
+    history::{self, History, HistoryArgs},
+    prepatch::{self, PrepatchArgs},
+    query::{self, QueryArgs},
+    replay,
+    serve::{self, ServeArgs},
+    snapshot::{self, SnapshotArgs},
+    timezone::{self, Zone, ZoneArgs},
+};
+#[cfg(feature = "cdn")]
+use cli::{
+    build_config::{self, BuildConfigArgs},
+    cdn_check::{self, CdnCheckArgs},
+    cdn_config::{self, CdnConfigArgs},
+    cdn_ping::{self, CdnPingArgs},
+};
+use futures_util::future::try_join_all;
+use serde::Serialize;
+use serde_json::{json, to_string, to_string_pretty, Value};
+use std::{
+    future::Future,
+    io::Read,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{task::JoinSet, time::Instant};
+use wownow::{
+    __bench::{synthetic_summary, synthetic_versions},
+    prelude::*,
+};
 
+#[allow(clippy::struct_excessive_bools)]
 struct RunConfig {
-    live_only: bool,
+    /// The products to select from the summary when none are requested
+    filter: ProductFilter,
     pretty_print: bool,
+    products: Vec<String>,
+    products_from: Option<PathBuf>,
+    validate: bool,
+    cache: Option<Cache>,
+    #[allow(clippy::option_option)]
+    history_db: Option<Option<PathBuf>>,
+    with_age: bool,
+    with_bgdl: bool,
+    validate_cdn: Option<CdnValidation>,
+    only_divergent: bool,
+    fail_on_empty: bool,
+    /// The regional version servers to fetch from and merge, or none for just the default server
+    servers: Vec<String>,
+    /// How long the whole run may take, if bounded
+    deadline: Option<Duration>,
+    /// How long each product's requests may take, if bounded
+    timeouts: Timeouts,
+    best_effort: bool,
+    /// Whether to output products in the order they arrived rather than by name
+    unsorted: bool,
+    canonical: bool,
+    /// The time zone to show times in
+    zone: Zone,
+    /// The `retrieval_datetime` to output reproducibly, if asked to
+    reproducible: Option<chrono::DateTime<chrono::Utc>>,
+    /// The single value to print instead of products, if asked for
+    get: Option<GetPath>,
+    /// Where to keep raw responses, for `--dump-raw`, `--include-raw` and `--audit`
+    capture: Capture,
+    include_raw: bool,
+    audit: bool,
+    with_meta: bool,
+    /// When the run started, for the duration in `--with-meta`
+    started: Instant,
 }
 
 fn resolve_switched_arg(yes: bool, no: bool, default: bool) -> bool {
@@ -24,11 +155,77 @@ fn resolve_switched_arg(yes: bool, no: bool, default: bool) -> bool {
     }
 }
 
+impl RunConfig {
+    /// A client of the default version server with the run's timeouts, recording or replaying if
+    /// this run does.
+    fn client(&self) -> Client {
+        self.timeouts.apply(replay::client())
+    }
+}
+
 impl From<Args> for RunConfig {
     fn from(args: Args) -> Self {
+        // the live products are all WoW products, so only default to them when looking at WoW
+        let live_only = resolve_switched_arg(
+            args.live_only,
+            args.no_live_only,
+            !args.all_games && args.games.is_empty(),
+        );
+        // `--no-live-only` alone returns every product in the summary, as it did before games
+        // could be chosen
+        let games = if args.all_games || (args.games.is_empty() && !live_only) {
+            GameFilter::All
+        } else if args.games.is_empty() {
+            GameFilter::Only(vec![Game::Wow])
+        } else {
+            GameFilter::Only(args.games)
+        };
+        let filter = games.apply(ProductFilter::new());
         RunConfig {
-            live_only: resolve_switched_arg(args.live_only, args.no_live_only, true),
+            filter: if live_only {
+                filter.live_only()
+            } else {
+                filter
+            },
             pretty_print: resolve_switched_arg(args.pretty, args.no_pretty, true),
+            products: args.products,
+            products_from: args.products_from,
+            validate: args.validate,
+            // the cache would answer requests before they could be recorded or replayed
+            cache: if args.no_cache || replay::is_active() {
+                None
+            } else {
+                Cache::new(args.max_age, args.refresh, args.offline, args.strict_seqn)
+            },
+            history_db: args.history_db,
+            with_age: args.with_age,
+            with_bgdl: args.with_bgdl,
+            validate_cdn: args.validate_cdn,
+            only_divergent: args.only_divergent,
+            fail_on_empty: args.fail_on_empty,
+            servers: args.servers,
+            deadline: args.deadline,
+            timeouts: Timeouts {
+                default: args.timeout,
+                ..Timeouts::default()
+            },
+            best_effort: args.best_effort,
+            unsorted: args.unsorted,
+            canonical: args.canonical,
+            zone: args.zone.zone(),
+            reproducible: args
+                .reproducible
+                .then(|| args.source_date_epoch.unwrap_or_default()),
+            get: args.get,
+            capture: Capture::new(
+                args.dump_raw,
+                args.include_raw || args.audit,
+                args.with_meta,
+            ),
+            include_raw: args.include_raw,
+            audit: args.audit,
+            with_meta: args.with_meta,
+            started: Instant::now(),
         }
     }
 }
@@ -37,93 +234,1205 @@ impl From<Args> for RunConfig {
 // https://github.com/clap-rs/clap/discussions/5177
 /// Get the current versions of World of Warcraft
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, disable_version_flag(true))]
 #[allow(clippy::struct_excessive_bools)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Only return products that are traditionally "live", or playable by most users. This is the
     /// products named `wow`, `wow_classic`, and `wow_classic_era`.
     ///
-    /// Defaults to on. Turn off with `--no-live-only`.
+    /// Defaults to on, unless `--all-games` or `--game` is given. Turn off with `--no-live-only`.
     #[arg(long, overrides_with("no_live_only"))]
     live_only: bool,
     #[arg(long, overrides_with("live_only"), hide(true))]
     no_live_only: bool,
 
+    /// Return products of every Blizzard game in the summary, not just World of Warcraft.
+    #[arg(long, conflicts_with("games"))]
+    all_games: bool,
+
+    /// Return products of this Blizzard game instead of World of Warcraft, matched by product code
+    /// prefix. May be given multiple times. Without it, `--no-live-only` returns the products of
+    /// every game, as `--all-games` does.
+    #[arg(long = "game", value_name = "GAME")]
+    games: Vec<Game>,
+
     /// Pretty print the JSON output.
     ///
     /// Defaults to on. Turn off with `--no-pretty`.
-    #[arg(long, overrides_with("no_pretty"))]
+    #[arg(long, global(true), overrides_with("no_pretty"))]
     pretty: bool,
-    #[arg(long, overrides_with("pretty"), hide(true))]
+    #[arg(long, global(true), overrides_with("pretty"), hide(true))]
     no_pretty: bool,
+
+    #[command(flatten)]
+    zone: ZoneArgs,
+
+    /// Read the configuration file at this path, instead of the default location (e.g.,
+    /// `~/.config/wownow/config.toml` on Linux).
+    #[arg(long, global(true), value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Record every response of the version servers into this directory, one directory per
+    /// server with a file per endpoint, for `--replay` to answer from later. Bypasses the cache.
+    #[arg(long, global(true), value_name = "DIR")]
+    record: Option<PathBuf>,
+
+    /// Answer every request to the version servers from the responses recorded into this
+    /// directory with `--record`, without the network, so a run can be repeated exactly.
+    /// Bypasses the cache.
+    #[arg(long, global(true), value_name = "DIR", conflicts_with("record"))]
+    replay: Option<PathBuf>,
+
+    /// Get the versions of this product, such as `wow` or `wow_classic`. May be given multiple
+    /// times.
+    ///
+    /// When any products are given (here, with `--products-from` or with `--set`), the summary is
+    /// not consulted and `--live-only`, `--all-games` and `--game` have no effect.
+    #[arg(long = "product", value_name = "PRODUCT")]
+    products: Vec<String>,
+
+    /// Read products from a file, one per line, or from stdin if `-`.
+    ///
+    /// Blank lines and lines starting with `#` are ignored. These products are added to those
+    /// given with `--product`. A list without any products is an error.
+    #[arg(long, value_name = "PATH")]
+    products_from: Option<PathBuf>,
+
+    /// Get the versions of the products of this set, defined in the configuration file as a
+    /// `[sets.NAME]` table with a list of `products`. May be given multiple times. These products
+    /// are added to those given with `--product`.
+    #[arg(long = "set", value_name = "NAME")]
+    sets: Vec<String>,
+
+    /// Get the versions of the Battle.net app's own products (`agent` and `bnt`) instead of any
+    /// game's, with every column of their responses as text, by product. Products the server
+    /// doesn't know of are left out.
+    #[arg(
+        long,
+        conflicts_with_all([
+            "products",
+            "products_from",
+            "sets",
+            "games",
+            "all_games",
+            "servers",
+            "with_bgdl",
+            "validate_cdn",
+            "get",
+        ])
+    )]
+    agent: bool,
+
+    /// Check that the products given with `--product` or `--products-from` exist in the summary
+    /// before getting their versions, failing early if any do not.
+    #[arg(long)]
+    validate: bool,
+
+    /// Use responses cached by earlier runs if they are younger than this, such as `30s` or `5m`.
+    #[arg(long, value_name = "DURATION", default_value = "1m", value_parser = humantime::parse_duration)]
+    max_age: Duration,
+
+    /// Don't use cached responses, but still cache the responses fetched by this run.
+    #[arg(long)]
+    refresh: bool,
+
+    /// Neither use nor store cached responses.
+    #[arg(long, conflicts_with_all(["max_age", "refresh"]))]
+    no_cache: bool,
+
+    /// Never contact the server, serving everything from the cache no matter how old. Products
+    /// include when their versions were retrieved.
+    // the conflicts with the global `--record` and `--replay` are declared here, where they're
+    // only checked of the top-level command, which alone has `--offline`
+    #[arg(long, conflicts_with_all(["no_cache", "refresh", "record", "replay"]))]
+    offline: bool,
+
+    /// Fail if the server answers with an older sequence number than the cached response has,
+    /// as a stale mirror would, instead of warning of it.
+    #[arg(long, conflicts_with_all(["no_cache", "offline"]))]
+    strict_seqn: bool,
+
+    /// Record the fetched versions in a history database at this path, or in the default location
+    /// (e.g., `~/.local/share/wownow/history.sqlite` on Linux) if no path is given.
+    #[arg(long, value_name = "PATH", num_args(0..=1))]
+    #[allow(clippy::option_option)]
+    history_db: Option<Option<PathBuf>>,
+
+    /// Include when each build was first seen, and its age in seconds, from the history database.
+    #[arg(long, requires("history_db"))]
+    with_age: bool,
+
+    /// Include the versions each product is downloading in the background, and flag products
+    /// whose background download is a newer build than the live one, as before a patch.
+    #[arg(long)]
+    with_bgdl: bool,
+
+    /// Check that each region's build and CDN configs can be found under the hosts and paths of
+    /// the product's CDNs, listing what can't in `cdn_issues`, to catch a build that hasn't
+    /// propagated properly. `structure` only checks the responses; `http` (with the `cdn` feature)
+    /// also asks each host for each config file.
+    #[arg(
+        long,
+        value_name = "HOW",
+        num_args(0..=1),
+        default_missing_value = "structure",
+        conflicts_with_all(["servers", "get"])
+    )]
+    validate_cdn: Option<CdnValidation>,
+
+    /// Only output products whose regions aren't all on the same build, as when a build is being
+    /// rolled out region by region.
+    #[arg(long)]
+    only_divergent: bool,
+
+    /// Fail with exit code 3 if no products, or no regions of them, are left to output after
+    /// filtering, so automations don't act on an empty result from a filter that's too tight or
+    /// a server having a bad moment.
+    #[arg(long, conflicts_with("get"))]
+    fail_on_empty: bool,
+
+    /// Fetch versions from the version servers of these regions (e.g., `us,eu,kr`) at once, and
+    /// merge their records, each annotated with the server it came from. Bypasses the cache.
+    #[arg(
+        long,
+        value_name = "REGIONS",
+        value_delimiter = ',',
+        conflicts_with_all(["offline", "history_db"])
+    )]
+    servers: Vec<String>,
+
+    /// Give up if the whole run, with its retries and every product, hasn't finished after this
+    /// long, such as `20s`. Unlike per-request timeouts, this bounds how long wownow runs.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    deadline: Option<Duration>,
+
+    /// Give up on each of a product's requests if it hasn't been answered after this long, such
+    /// as `10s`, overriding the `[timeouts]` default of the config file. Products with timeouts of
+    /// their own in the config file's `[timeouts.products]` keep them.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// When the `--deadline` passes, output the products whose versions arrived, listing the
+    /// rest as `unfinished`, instead of failing.
+    #[arg(long, requires("deadline"))]
+    best_effort: bool,
+
+    /// Output products in the order their versions arrived, instead of sorted by name.
+    #[arg(long)]
+    unsorted: bool,
+
+    /// Output canonical JSON, byte for byte the same for the same versions, to store in git and
+    /// diff: keys and arrays are sorted, timestamps are to the second in UTC, and fields that vary
+    /// from run to run (when the versions were retrieved, and build ages) are left out.
+    #[arg(long, conflicts_with_all(["unsorted", "local_time", "timezone"]))]
+    canonical: bool,
+
+    /// Output the same bytes for the same versions, for snapshot tests and build pipelines: as
+    /// with `--canonical`, but with `retrieval_datetime` set to `--source-date-epoch` (or the
+    /// Unix epoch if it's not given).
+    #[arg(long, conflicts_with_all(["unsorted", "local_time", "timezone"]))]
+    reproducible: bool,
+
+    /// The time to output as `retrieval_datetime` with `--reproducible`, in seconds since the
+    /// Unix epoch. Ignored without `--reproducible`.
+    #[arg(
+        long,
+        env = "SOURCE_DATE_EPOCH",
+        value_name = "SECONDS",
+        value_parser = canonical::parse_source_date_epoch
+    )]
+    source_date_epoch: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Write large synthetic summary and versions responses, named `summary` and `versions`, into
+    /// this directory for the benchmarks to parse, and exit. Point `WOWNOW_BENCH_FIXTURES` at the
+    /// directory to benchmark with them, or with responses saved by `snapshot`.
+    #[arg(long, value_name = "PATH", exclusive(true))]
+    bench_fixtures: Option<PathBuf>,
+
+    /// Print one value, bare, and exit: a product, region and field, such as `wow.us.version`.
+    /// The field is one of `version`, `build`, `versions_name`, `build_id`, `build_config`,
+    /// `cdn_config`, `key_ring` or `product_config`. Skips the summary, so it's quick to use in
+    /// scripts, like `VER=$(wownow --get wow.us.version)`.
+    #[arg(
+        long,
+        value_name = "PRODUCT.REGION.FIELD",
+        conflicts_with_all(["products", "products_from", "sets", "servers", "canonical"])
+    )]
+    get: Option<GetPath>,
+
+    /// Also write each response fetched from the server, exactly as it arrived, into this
+    /// directory, named for its endpoint, seqn and when it was fetched (e.g.,
+    /// `v2%2Fproducts%2Fwow%2Fversions.2118468.20240319T150200Z`). Responses that fail to parse
+    /// are written too, with `unparsed` for their seqn. Cached responses aren't fetched, so pass
+    /// `--refresh` to capture everything.
+    #[arg(long, value_name = "PATH", conflicts_with_all(["offline", "servers"]))]
+    dump_raw: Option<PathBuf>,
+
+    /// Embed the text of each response in the output, exactly as the server sent it: each
+    /// product's versions (and background download) as its `raw`, and the summary, if it was
+    /// consulted, as `raw_summary`. For auditing, or parsing the responses with other tools.
+    #[arg(long, conflicts_with_all(["servers", "get"]))]
+    include_raw: bool,
+
+    /// Add an `audit` to the output listing every response it was made from, with the response's
+    /// endpoint, seqn and SHA-256, so an archived output can be verified against captured
+    /// responses (such as those of `--dump-raw`).
+    #[arg(long, conflicts_with_all(["servers", "get"]))]
+    audit: bool,
+
+    /// Add a `meta` to the output describing how it was made: the server queried and its
+    /// protocol, wownow's version, each response's endpoint, whether it was cached, how many
+    /// times it was retried and how long it took, and how long the whole run took. So archived
+    /// outputs describe themselves.
+    #[arg(long, conflicts_with_all(["servers", "get", "canonical", "reproducible"]))]
+    with_meta: bool,
+
+    /// Print the version, and exit.
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// With `--version`, also print the git commit, build date, target and enabled features of
+    /// this build, for bug reports.
+    #[arg(long, requires("version"))]
+    verbose: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect or clear the response cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+
+    /// Show the versions recorded in the history database, with when each was first and last seen
+    History(HistoryArgs),
+
+    /// Save the raw responses of the summary and every product's versions, CDNs and background
+    /// downloads into a timestamped directory, with an index
+    Snapshot(SnapshotArgs),
+
+    /// Serve the versions as a JSON API over HTTP, refreshing them periodically
+    Serve(ServeArgs),
+
+    /// Query a running `wownow serve`
+    Query(QueryArgs),
+
+    /// Poll products on cron schedules from the config file, recording history and sending
+    /// notifications of new builds, and log what happens as JSON lines
+    Daemon(DaemonArgs),
+
+    /// Show the regions of products whose background download is a newer build than the live
+    /// one, an early warning of an imminent patch
+    Prepatch(PrepatchArgs),
+
+    /// Compare a product's versions as served by several regional version servers, to tell a
+    /// change still propagating between servers from a genuine difference between regions
+    Consensus(ConsensusArgs),
+
+    /// Compare products' versions with those of a community API, such as wago.tools, for
+    /// independent confirmation of a new build before acting on it
+    Crosscheck(CrosscheckArgs),
+
+    /// Download and show the build config of a product's current build in a region, which names
+    /// the root, encoding, install, download and size files of the build
+    #[cfg(feature = "cdn")]
+    BuildConfig(BuildConfigArgs),
+
+    /// Download and show the CDN config of a product's current build in a region, which lists
+    /// the archives holding the build's files, the archive group and the file index
+    #[cfg(feature = "cdn")]
+    CdnConfig(CdnConfigArgs),
+
+    /// Measure the latency of each CDN host of a product in each region, and rank them, to pick
+    /// mirrors for large downloads
+    #[cfg(feature = "cdn")]
+    CdnPing(CdnPingArgs),
+
+    /// Check which CDN hosts of a product have the config files of its current build in each
+    /// region, to see which have propagated a new build
+    #[cfg(feature = "cdn")]
+    CdnCheck(CdnCheckArgs),
 }
 
 type Result = std::result::Result<String, String>;
 
-const LIVE_PRODUCTS: [&str; 3] = ["wow", "wow_classic", "wow_classic_era"];
+/// The exit code of a run that `--fail-on-empty` failed, to tell it apart from other failures
+const EMPTY_EXIT_CODE: u8 = 3;
+
+/// Why the run failed, and the exit code to fail with
+#[derive(Debug)]
+struct Failure {
+    message: String,
+    code: ExitCode,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            code: ExitCode::FAILURE,
+        }
+    }
+}
 
-async fn run(config: RunConfig) -> Result {
-    let summary = get_summary()
-        .await
-        .map_err(|e| format!("Error getting summary: {e}"))?;
+/// How many products the summary written by `--bench-fixtures` lists
+const BENCH_FIXTURE_PRODUCTS: usize = 5_000;
 
-    let matching_products = summary
-        .records
+/// How many regions the versions response written by `--bench-fixtures` has
+const BENCH_FIXTURE_REGIONS: usize = 1_000;
+
+/// Write the synthetic responses of `--bench-fixtures` into a directory, returning its path.
+fn write_bench_fixtures(dir: &Path) -> Result {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Error creating `{}`: {e}", dir.display()))?;
+    for (name, fixture) in [
+        ("summary", synthetic_summary(BENCH_FIXTURE_PRODUCTS)),
+        ("versions", synthetic_versions(BENCH_FIXTURE_REGIONS)),
+    ] {
+        let path = dir.join(name);
+        std::fs::write(&path, fixture)
+            .map_err(|e| format!("Error writing `{}`: {e}", path.display()))?;
+    }
+    Ok(dir.display().to_string())
+}
+
+/// Parse a product list, one product per line. Blank lines and `#` comments are skipped, as are
+/// duplicates.
+fn parse_product_list(list: &str) -> Vec<String> {
+    let mut products = Vec::new();
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !products.iter().any(|product| product == line) {
+            products.push(line.to_owned());
+        }
+    }
+    products
+}
+
+/// Read a product list from the file at `path`, or from stdin if `path` is `-`.
+fn read_product_list(path: &Path) -> std::io::Result<Vec<String>> {
+    let list = if path == Path::new("-") {
+        let mut list = String::new();
+        std::io::stdin().read_to_string(&mut list)?;
+        list
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    Ok(parse_product_list(&list))
+}
+
+/// An error message for a product that is not in the summary, including suggestions of similarly
+/// named products. Returns `None` if the product is in the summary.
+fn unknown_product_message(product: &str, summary: &SummaryResponse) -> Option<String> {
+    if summary.contains_product(product) {
+        return None;
+    }
+
+    let suggestions = summary
+        .suggest_products(product)
         .into_iter()
-        .filter_map(|record| {
-            // Only return products are that live (if called for by user) and have no flags. flags
-            // indicate things like cdn or bgdl which we don't care about, we just want the normal
-            // one.
-            if (!config.live_only || LIVE_PRODUCTS.contains(&record.product.as_str()))
-                && record.flags.is_empty()
-            {
-                Some(record.product)
-            } else {
-                None
-            }
-        })
+        .map(|suggestion| format!("`{suggestion}`"))
         .collect::<Vec<_>>();
+    let msg = match suggestions.as_slice() {
+        [] => format!("unknown product `{product}`"),
+        [only] => format!("unknown product `{product}`, did you mean {only}?"),
+        [init @ .., last] => format!(
+            "unknown product `{product}`, did you mean {} or {last}?",
+            init.join(", ")
+        ),
+    };
+    Some(msg)
+}
+
+/// The products named by `--product` and `--products-from`, without duplicates. An empty
+/// `--products-from` list with no other products is an error, rather than falling back to every
+/// live product.
+fn requested_products(config: &RunConfig) -> std::result::Result<Vec<String>, String> {
+    let mut requested_products = config.products.clone();
+    if let Some(path) = &config.products_from {
+        let listed = read_product_list(path)
+            .map_err(|e| format!("Error reading products from `{}`: {e}", path.display()))?;
+        for product in listed {
+            if !requested_products.contains(&product) {
+                requested_products.push(product);
+            }
+        }
+        if requested_products.is_empty() {
+            return Err(format!("Error: no products listed in `{}`", path.display()));
+        }
+    }
+    Ok(requested_products)
+}
+
+/// A product to get the versions of, and the seqn of its versions if the summary was consulted.
+type SelectedProduct = (Arc<str>, Option<u32>);
+
+/// Select the products to get the versions of: the requested products if there are any, or
+/// otherwise those in the summary that match the config. Also returns the summary, if it was
+/// consulted.
+async fn select_products(
+    config: &RunConfig,
+    requested_products: Vec<String>,
+) -> std::result::Result<(Vec<SelectedProduct>, Option<SummaryResponse>), String> {
+    let selected = if requested_products.is_empty() {
+        let summary = cache::get_summary(&config.client(), config.cache.as_ref(), &config.capture)
+            .await
+            .map_err(|e| format!("Error getting summary: {e}"))?;
+
+        (config.filter.select(&summary), Some(summary))
+    } else if config.validate {
+        let summary = cache::get_summary(&config.client(), config.cache.as_ref(), &config.capture)
+            .await
+            .map_err(|e| format!("Error getting summary: {e}"))?;
+        let unknown = requested_products
+            .iter()
+            .filter_map(|product| unknown_product_message(product, &summary))
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(unknown.join("\n"));
+        }
+        let selected = requested_products
+            .into_iter()
+            .map(|product| {
+                let seqn = summary.versions_seqn(&product);
+                (Arc::from(product), seqn)
+            })
+            .collect();
+        (selected, Some(summary))
+    } else {
+        let selected = requested_products
+            .into_iter()
+            .map(|product| (Arc::from(product), None))
+            .collect();
+        (selected, None)
+    };
+    Ok(selected)
+}
+
+/// Get a product's versions from the version server of each region, in the order given.
+async fn get_versions_from_servers(
+    servers: &[String],
+    product: &str,
+    timeouts: &Timeouts,
+) -> ApiResult<Vec<(Option<String>, VersionsResponse)>> {
+    try_join_all(servers.iter().map(|server| async move {
+        let client = timeouts.apply(replay::region_client(server));
+        let response = client.get_versions(product).await?;
+        Ok((Some(server.clone()), response))
+    }))
+    .await
+}
+
+/// Make a product of its versions responses, each from a server if named, annotating each version
+/// with its server.
+fn merge_responses(
+    product_name: &Arc<str>,
+    responses: Vec<(Option<String>, VersionsResponse)>,
+) -> std::result::Result<Option<Product>, String> {
+    let mut merged: Option<Product> = None;
+    for (server, response) in responses {
+        let mut product = Product::try_from((Arc::clone(product_name), &response)).map_err(
+            |e| match &server {
+                Some(server) => {
+                    format!("Error reading `{product_name}` versions from `{server}`: {e}")
+                }
+                None => format!("Error reading `{product_name}` versions: {e}"),
+            },
+        )?;
+        if let Some(server) = server {
+            for version in product.versions_mut() {
+                version.set_server(server.clone());
+            }
+        }
+        match merged.as_mut() {
+            Some(merged) => merged.append_versions(product),
+            None => merged = Some(product),
+        }
+    }
+    Ok(merged)
+}
+
+/// The versions of a product, from each server fetched from, and its background download if
+/// asked for
+type ProductFetch = (
+    Arc<str>,
+    ApiResult<Vec<(Option<String>, VersionsResponse)>>,
+    Option<ApiResult<VersionsResponse>>,
+    Option<Vec<CdnIssue>>,
+);
 
+/// Spawn a task fetching each product's versions, and its background download if asked for, and
+/// checking them against its CDNs if asked to.
+fn spawn_fetches(
+    config: &RunConfig,
+    matching_products: Vec<SelectedProduct>,
+) -> JoinSet<ProductFetch> {
     let mut set = JoinSet::new();
-    for matching_product in matching_products {
+    for (matching_product, seqn) in matching_products {
+        let cache = config.cache.clone();
+        let capture = config.capture.clone();
+        let with_bgdl = config.with_bgdl;
+        let validate_cdn = config.validate_cdn;
+        let servers = config.servers.clone();
+        let timeouts = config.timeouts.clone();
+        let client = config.client();
         set.spawn(async move {
-            get_versions(&matching_product)
-                .await
-                .map(|resp| (resp, matching_product.clone()))
-                .map_err(|e| (e, matching_product))
+            let (versions, bgdl, cdns) = tokio::join!(
+                async {
+                    if servers.is_empty() {
+                        let cache = cache.as_ref();
+                        cache::get_versions(&client, cache, &capture, &matching_product, seqn)
+                            .await
+                            .map(|response| vec![(None, response)])
+                    } else {
+                        get_versions_from_servers(&servers, &matching_product, &timeouts).await
+                    }
+                },
+                async {
+                    if with_bgdl {
+                        let cache = cache.as_ref();
+                        Some(cache::get_bgdl(&client, cache, &capture, &matching_product).await)
+                    } else {
+                        None
+                    }
+                },
+                async {
+                    if validate_cdn.is_some() {
+                        let cache = cache.as_ref();
+                        Some(cache::get_cdns(&client, cache, &capture, &matching_product).await)
+                    } else {
+                        None
+                    }
+                }
+            );
+            let cdn_issues = match (validate_cdn, cdns, &versions) {
+                (Some(validation), Some(cdns), Ok(responses)) => {
+                    let (_, response) = &responses[0];
+                    Some(
+                        cdn_validation::validate(&matching_product, response, cdns, validation)
+                            .await,
+                    )
+                }
+                _ => None,
+            };
+            (matching_product, versions, bgdl, cdn_issues)
         });
     }
+    set
+}
+
+/// Run a future until it completes, or until the deadline passes if there is one, in which case
+/// there's no output.
+async fn before_deadline<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// An error message for a deadline that passed before some work was done.
+fn deadline_message(config: &RunConfig, unfinished: &str) -> String {
+    format!(
+        "Deadline of {} passed before {unfinished}",
+        humantime::format_duration(config.deadline.unwrap_or_default())
+    )
+}
+
+/// Give up on the products whose versions hadn't arrived by the deadline, listing them in the
+/// fetch if making the best of it, or failing otherwise.
+fn give_up(
+    config: &RunConfig,
+    mut unfinished: Vec<Arc<str>>,
+    fetch: &mut VersionsFetch,
+) -> std::result::Result<(), String> {
+    unfinished.sort();
+    if !config.best_effort {
+        let products = unfinished
+            .iter()
+            .map(|product| format!("`{product}`"))
+            .collect::<Vec<_>>();
+        return Err(deadline_message(
+            config,
+            &format!("the versions of {} arrived", products.join(", ")),
+        ));
+    }
+    for product in unfinished {
+        fetch.add_unfinished(product.to_string());
+    }
+    Ok(())
+}
+
+/// The error message for a product whose versions couldn't be fetched.
+async fn versions_error(
+    config: &RunConfig,
+    explicit_products: bool,
+    product_name: &str,
+    error: ApiError,
+) -> String {
+    let ApiError::UnknownProduct(_) = error else {
+        return format!("Error getting `{product_name}` versions: {error}");
+    };
+    // a product the user named may just be misspelled. check the summary for similarly named
+    // products to suggest.
+    if explicit_products && !config.validate {
+        if let Ok(summary) =
+            cache::get_summary(&config.client(), config.cache.as_ref(), &config.capture).await
+        {
+            if let Some(msg) = unknown_product_message(product_name, &summary) {
+                return msg;
+            }
+        }
+    }
+    error.to_string()
+}
+
+/// Open the history database of `--history-db`, if given.
+fn open_history(config: &RunConfig) -> std::result::Result<Option<History>, String> {
+    let Some(path) = config.history_db.clone() else {
+        return Ok(None);
+    };
+    let path = history::resolve_path(path)?;
+    History::open(&path)
+        .map(Some)
+        .map_err(|e| format!("Error opening history database `{}`: {e}", path.display()))
+}
+
+/// Get the value of `--get`, unless the deadline passes first.
+async fn run_get(
+    config: &RunConfig,
+    path: &GetPath,
+    deadline: Option<Instant>,
+) -> std::result::Result<String, Failure> {
+    let client = config.client();
+    let get = get::run(&client, path, config.cache.as_ref(), &config.capture);
+    let value = before_deadline(deadline, get).await.ok_or_else(|| {
+        deadline_message(
+            config,
+            &format!("the versions of `{}` arrived", path.product),
+        )
+    })??;
+    Ok(value)
+}
+
+async fn run(config: RunConfig) -> std::result::Result<String, Failure> {
+    let deadline = config.deadline.map(|deadline| Instant::now() + deadline);
+    if let Some(path) = &config.get {
+        return run_get(&config, path, deadline).await;
+    }
+    let mut history = open_history(&config)?;
+    let requested_products = requested_products(&config)?;
+    let explicit_products = !requested_products.is_empty();
+    let (matching_products, summary) =
+        before_deadline(deadline, select_products(&config, requested_products))
+            .await
+            .ok_or_else(|| deadline_message(&config, "the summary arrived"))??;
+
+    let mut unfinished = matching_products
+        .iter()
+        .map(|(product, _)| Arc::clone(product))
+        .collect::<Vec<_>>();
+    let mut set = spawn_fetches(&config, matching_products);
+
+    let observed_at = chrono::Utc::now();
+    let offline = config.cache.as_ref().is_some_and(Cache::is_offline);
 
     let mut fetch = VersionsFetch::new();
-    while let Some(join_result) = set.join_next().await {
-        let response_result = join_result.map_err(|e| format!("Error joining task: {e}"))?;
-        let (response, product_name) = response_result.map_err(|(error, product_name)| {
-            format!("Error getting `{product_name}` versions: {error}")
-        })?;
+    let mut cdn_issues = config.validate_cdn.map(|_| Vec::new());
+    if offline {
+        fetch.set_offline();
+    }
+    loop {
+        let Some(join_result) = before_deadline(deadline, set.join_next()).await else {
+            // the tasks still running are aborted when the set is dropped
+            give_up(&config, unfinished, &mut fetch)?;
+            break;
+        };
+        let Some(join_result) = join_result else {
+            break;
+        };
+        let (product_name, response_result, bgdl_result, issues) =
+            join_result.map_err(|e| format!("Error joining task: {e}"))?;
+        unfinished.retain(|product| *product != product_name);
+        cdn_issues
+            .iter_mut()
+            .zip(issues)
+            .for_each(|(all, issues)| all.extend(issues));
+        let responses = match response_result {
+            Ok(responses) => responses,
+            Err(error) => {
+                return Err(
+                    versions_error(&config, explicit_products, &product_name, error)
+                        .await
+                        .into(),
+                )
+            }
+        };
+
+        // offline, versions are old news, not observations made now
+        if let Some(history) = history.as_mut().filter(|_| !offline) {
+            for (_, response) in &responses {
+                history
+                    .record(observed_at, &product_name, response)
+                    .map_err(|e| format!("Error recording `{product_name}` history: {e}"))?;
+            }
+        }
+
+        let Some(mut product) = merge_responses(&product_name, responses)? else {
+            continue;
+        };
+        if let Some(cache) = config.cache.as_ref().filter(|_| offline) {
+            let endpoint = Endpoint::Versions(product_name.to_string());
+            if let Some(retrieved_at) = cache.retrieved_at(&endpoint).await {
+                product.set_retrieved_at(retrieved_at);
+            }
+        }
+        if let Some(history) = history.as_ref().filter(|_| config.with_age) {
+            history
+                .annotate_ages(&mut product, observed_at)
+                .map_err(|e| format!("Error reading `{product_name}` history: {e}"))?;
+        }
+        if let Some(bgdl_result) = bgdl_result {
+            add_bgdl(&mut product, bgdl_result)?;
+        }
 
-        fetch.add_product(Product::from_versions_response(&product_name, &response));
+        if config.only_divergent && product.is_consistent() {
+            continue;
+        }
+        fetch.add_product(product);
     }
 
-    let output = if config.pretty_print {
-        to_string_pretty(&fetch).map_err(|e| format!("Error serializing JSON: {e}"))?
+    if let Some(summary) = &summary {
+        fetch.apply_summary(summary);
+    }
+    // products arrive in whatever order their fetches finish
+    if !config.unsorted {
+        fetch.sort_products();
+    }
+    if let Some(cdn_issues) = &mut cdn_issues {
+        cdn_issues.sort_by(|a, b| a.key().cmp(&b.key()));
+    }
+    if config.fail_on_empty {
+        check_not_empty(&fetch)?;
+    }
+    Ok(output(&config, &fetch, cdn_issues.as_deref())?)
+}
+
+/// Add the versions a product is downloading in the background to it.
+fn add_bgdl(
+    product: &mut Product,
+    bgdl_result: ApiResult<VersionsResponse>,
+) -> std::result::Result<(), String> {
+    let product_name = product.name().to_owned();
+    let bgdl = bgdl_result
+        .map_err(|e| format!("Error getting `{product_name}` background download: {e}"))?;
+    product.set_bgdl(
+        Product::try_from((product_name.as_str(), &bgdl))
+            .map_err(|e| format!("Error reading `{product_name}` background download: {e}"))?,
+    );
+    Ok(())
+}
+
+/// Fail with [`EMPTY_EXIT_CODE`] if a fetch has no products, or none of its products have
+/// regions.
+fn check_not_empty(fetch: &VersionsFetch) -> std::result::Result<(), Failure> {
+    let message = if fetch.products().is_empty() {
+        "no products are left after filtering"
+    } else if fetch
+        .products()
+        .iter()
+        .all(|product| product.versions().is_empty())
+    {
+        "no regions are left after filtering"
     } else {
-        to_string(&fetch).map_err(|e| format!("Error serializing JSON: {e}"))?
+        return Ok(());
     };
+    Err(Failure {
+        message: format!("Error: {message}"),
+        code: ExitCode::from(EMPTY_EXIT_CODE),
+    })
+}
+
+/// Serialize a fetch as `run` outputs it: as canonical JSON if asked for, and with the responses
+/// it was made from, how it was made, or its inconsistencies with the CDNs, embedded if asked for.
+fn output(config: &RunConfig, fetch: &VersionsFetch, cdn_issues: Option<&[CdnIssue]>) -> Result {
+    if config.include_raw || config.audit || config.with_meta || cdn_issues.is_some() {
+        let mut output =
+            serde_json::to_value(fetch).map_err(|e| format!("Error serializing JSON: {e}"))?;
+        if let (Some(cdn_issues), Value::Object(output)) = (cdn_issues, &mut output) {
+            output.insert("cdn_issues".to_owned(), json!(cdn_issues));
+        }
+        if config.include_raw {
+            config.capture.embed_texts(&mut output);
+        }
+        if config.audit {
+            config.capture.embed_audit(&mut output);
+        }
+        if config.with_meta {
+            config
+                .capture
+                .embed_meta(&mut output, config.started.elapsed());
+        }
+        return serialize(config, &output);
+    }
+    serialize(config, fetch)
+}
 
-    Ok(output)
+/// Serialize output as JSON, canonical or reproducible if asked for.
+fn serialize<T: Serialize + ?Sized>(config: &RunConfig, value: &T) -> Result {
+    if let Some(retrieval_datetime) = config.reproducible {
+        return canonical::to_reproducible_json(value, retrieval_datetime, config.pretty_print);
+    }
+    if config.canonical {
+        return canonical::to_canonical_json(value, config.pretty_print);
+    }
+    if config.zone != Zone::Utc {
+        let mut value =
+            serde_json::to_value(value).map_err(|e| format!("Error serializing JSON: {e}"))?;
+        timezone::localize(&mut value, config.zone);
+        return to_json(&value, config.pretty_print);
+    }
+    to_json(value, config.pretty_print)
+}
+
+/// Serialize output as JSON, pretty printed if called for.
+fn to_json<T: Serialize + ?Sized>(value: &T, pretty_print: bool) -> Result {
+    let output = if pretty_print {
+        to_string_pretty(value)
+    } else {
+        to_string(value)
+    };
+    output.map_err(|e| format!("Error serializing JSON: {e}"))
+}
+
+/// Configure a run from the arguments and the configuration file, adding the products of the sets
+/// given with `--set` to those given with `--product`, and taking the file's timeouts, unless
+/// `--timeout` overrides its default.
+fn with_config(mut args: Args) -> std::result::Result<RunConfig, String> {
+    let config = Config::load(args.config.as_deref())?;
+    for product in config.set_products(&args.sets)? {
+        if !args.products.contains(&product) {
+            args.products.push(product);
+        }
+    }
+    let mut run_config = RunConfig::from(args);
+    run_config.timeouts = Timeouts {
+        default: run_config.timeouts.default.or(config.timeouts.default),
+        ..config.timeouts
+    };
+    Ok(run_config)
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let args = Args::parse();
-    match run(args.into()).await {
+    let mut args = Args::parse();
+    if args.version {
+        println!("{}", build_info::version(args.verbose));
+        return ExitCode::SUCCESS;
+    }
+    replay::init(args.record.clone(), args.replay.clone());
+    let pretty_print = resolve_switched_arg(args.pretty, args.no_pretty, true);
+    let result = match args.command.take() {
+        None if args.agent => agent::run(args.timeout, pretty_print).await,
+        None => match args.bench_fixtures.take() {
+            Some(dir) => write_bench_fixtures(&dir),
+            None => match with_config(args) {
+                Ok(config) => return finish(run(config).await),
+                Err(e) => Err(e),
+            },
+        },
+        Some(Command::Cache(command)) => cache::run(command).await,
+        Some(Command::History(history_args)) => history::run(history_args, pretty_print),
+        Some(Command::Snapshot(snapshot_args)) => snapshot::run(snapshot_args).await,
+        Some(Command::Serve(serve_args)) => match Config::load(args.config.as_deref()) {
+            Ok(config) => serve::run(serve_args, config).await,
+            Err(e) => Err(e),
+        },
+        Some(Command::Query(query_args)) => query::run(query_args, pretty_print).await,
+        Some(Command::Daemon(daemon_args)) => match Config::load(args.config.as_deref()) {
+            Ok(config) => daemon::run(daemon_args, config).await,
+            Err(e) => Err(e),
+        },
+        Some(Command::Prepatch(prepatch_args)) => prepatch::run(prepatch_args, pretty_print).await,
+        Some(Command::Consensus(consensus_args)) => {
+            consensus::run(consensus_args, pretty_print).await
+        }
+        Some(Command::Crosscheck(crosscheck_args)) => {
+            crosscheck::run(crosscheck_args, pretty_print).await
+        }
+        #[cfg(feature = "cdn")]
+        Some(Command::BuildConfig(build_config_args)) => {
+            build_config::run(build_config_args, pretty_print).await
+        }
+        #[cfg(feature = "cdn")]
+        Some(Command::CdnConfig(cdn_config_args)) => {
+            cdn_config::run(cdn_config_args, pretty_print).await
+        }
+        #[cfg(feature = "cdn")]
+        Some(Command::CdnPing(cdn_ping_args)) => cdn_ping::run(cdn_ping_args).await,
+        #[cfg(feature = "cdn")]
+        Some(Command::CdnCheck(cdn_check_args)) => {
+            cdn_check::run(cdn_check_args, pretty_print).await
+        }
+    };
+    finish(result.map_err(Failure::from))
+}
+
+/// Print the output of a command, or why it failed, returning the exit code.
+fn finish(result: std::result::Result<String, Failure>) -> ExitCode {
+    match result {
         Ok(msg) => {
-            println!("{msg}");
+            // some subcommands write their own output, leaving nothing more to print
+            if !msg.is_empty() {
+                println!("{msg}");
+            }
             ExitCode::SUCCESS
         }
-        Err(msg) => {
-            eprintln!("{msg}");
-            ExitCode::FAILURE
+        Err(failure) => {
+            eprintln!("{}", failure.message);
+            failure.code
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_product_list() {
+        let list = "wow\n\
+        # classic products\n\
+          wow_classic  \n\
+        \n\
+        wow\n\
+        wow_classic_era";
+
+        assert_eq!(
+            parse_product_list(list),
+            vec!["wow", "wow_classic", "wow_classic_era"]
+        );
+    }
+
+    #[test]
+    fn test_requested_products() {
+        let path =
+            std::env::temp_dir().join(format!("wownow-test-products-{}", std::process::id()));
+        let config = |args: &[&str]| {
+            let mut all = vec!["wownow", "--products-from", path.to_str().unwrap()];
+            all.extend(args);
+            RunConfig::from(Args::parse_from(all))
+        };
+
+        std::fs::write(&path, "wow\nwow_classic\n").unwrap();
+        assert_eq!(
+            requested_products(&config(&["--product", "wow"])).unwrap(),
+            ["wow", "wow_classic"]
+        );
+
+        // an empty list selects nothing, rather than the live products
+        std::fs::write(&path, "# nothing today\n").unwrap();
+        assert_eq!(
+            requested_products(&config(&[])).unwrap_err(),
+            format!("Error: no products listed in `{}`", path.display())
+        );
+        assert_eq!(
+            requested_products(&config(&["--product", "wow"])).unwrap(),
+            ["wow"]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_product_message() {
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 2119172\n\
+            wow|2118018|\n\
+            wow_classic|2110722|\n\
+            wow_classic_era|2110723|\n\
+            wow_classic_ptr|2110724|",
+        )
+        .unwrap();
+
+        assert_eq!(unknown_product_message("wow_classic", &summary), None);
+        assert_eq!(
+            unknown_product_message("wow_clasic", &summary).unwrap(),
+            "unknown product `wow_clasic`, did you mean `wow_classic`?"
+        );
+        assert_eq!(
+            unknown_product_message("wow_classic_pt", &summary).unwrap(),
+            "unknown product `wow_classic_pt`, did you mean `wow_classic_ptr`, `wow_classic` or \
+            `wow_classic_era`?"
+        );
+        assert_eq!(
+            unknown_product_message("hearthstone", &summary).unwrap(),
+            "unknown product `hearthstone`"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let cache = Cache::temp("validate", Duration::from_hours(1));
+        cache
+            .write(
+                &Endpoint::Summary,
+                b"Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+                ## seqn = 2119172\n\
+                wow|2118018|\n\
+                wow_classic|2110722|",
+            )
+            .await
+            .unwrap();
+        let config = |products: &[&str]| {
+            let mut args = vec!["wownow", "--validate"];
+            for product in products {
+                args.extend(["--product", product]);
+            }
+            RunConfig {
+                cache: Some(cache.clone()),
+                ..RunConfig::from(Args::parse_from(args))
+            }
+        };
+
+        let (selected, summary) = select_products(&config(&["wow"]), vec!["wow".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(selected, [(Arc::from("wow"), Some(2_118_018))]);
+        assert!(summary.is_some());
+
+        // an unknown product fails the run before any versions are fetched
+        let failure = run(config(&["wow", "wow_clasic"])).await.unwrap_err();
+        assert_eq!(
+            failure.message,
+            "unknown product `wow_clasic`, did you mean `wow_classic`?"
+        );
+        assert_eq!(failure.code, ExitCode::FAILURE);
+
+        cache.remove();
+    }
+
+    #[test]
+    fn test_game_defaults() {
+        let filter =
+            |args: &[&str]| RunConfig::from(Args::parse_from([&["wownow"], args].concat())).filter;
+        assert!(filter(&[]).matches("wow"));
+        assert!(!filter(&[]).matches("wowt"));
+        assert!(!filter(&[]).matches("d3"));
+        // every product, as before games could be chosen
+        assert!(filter(&["--no-live-only"]).matches("wowt"));
+        assert!(filter(&["--no-live-only"]).matches("d3"));
+        assert!(!filter(&["--no-live-only", "--game", "wow"]).matches("d3"));
+        assert!(filter(&["--game", "d3"]).matches("d3"));
+    }
+
+    #[test]
+    fn test_merge_responses() {
+        let response = |build: &str| {
+            VersionsResponse::try_from(format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 2118468\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||{build}|10.2.5.{build}|53020d32e1a25648c8e1eafd5771935f"
+            ).as_str())
+            .unwrap()
+        };
+
+        let product = merge_responses(
+            &"wow".into(),
+            vec![
+                (Some("us".to_owned()), response("53584")),
+                (Some("eu".to_owned()), response("53840")),
+            ],
+        )
+        .unwrap()
+        .unwrap();
+        let versions: Vec<_> = product
+            .versions()
+            .iter()
+            .map(|version| (version.server(), version.build()))
+            .collect();
+        assert_eq!(versions, [(Some("us"), "53584"), (Some("eu"), "53840")]);
+
+        let product = merge_responses(&"wow".into(), vec![(None, response("53584"))])
+            .unwrap()
+            .unwrap();
+        assert_eq!(product.versions()[0].server(), None);
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let config = RunConfig::from(Args::parse_from(["wownow", "--deadline", "20s"]));
+        assert_eq!(config.deadline, Some(Duration::from_secs(20)));
+        assert_eq!(before_deadline(None, async { 1 }).await, Some(1));
+        assert_eq!(
+            before_deadline(Some(Instant::now()), std::future::pending::<()>()).await,
+            None
+        );
+
+        let mut fetch = VersionsFetch::new();
+        assert_eq!(
+            give_up(&config, vec!["wowt".into(), "wow".into()], &mut fetch),
+            Err("Deadline of 20s passed before the versions of `wow`, `wowt` arrived".to_owned())
+        );
+
+        let config = RunConfig::from(Args::parse_from([
+            "wownow",
+            "--deadline",
+            "20s",
+            "--best-effort",
+        ]));
+        give_up(&config, vec!["wowt".into(), "wow".into()], &mut fetch).unwrap();
+        assert_eq!(fetch.unfinished(), ["wow", "wowt"]);
+        assert!(Args::try_parse_from(["wownow", "--best-effort"]).is_err());
+    }
+
+    #[test]
+    fn test_check_not_empty() {
+        let header = "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118468";
+        let empty = VersionsResponse::try_from(header).unwrap();
+        let full = VersionsResponse::try_from(
+            format!("{header}\nus|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f").as_str(),
+        )
+        .unwrap();
+
+        let mut fetch = VersionsFetch::new();
+        let failure = check_not_empty(&fetch).unwrap_err();
+        assert_eq!(
+            failure.message,
+            "Error: no products are left after filtering"
+        );
+        assert_eq!(failure.code, ExitCode::from(EMPTY_EXIT_CODE));
+
+        fetch.add_product(Product::try_from(("wowt", &empty)).unwrap());
+        let failure = check_not_empty(&fetch).unwrap_err();
+        assert_eq!(
+            failure.message,
+            "Error: no regions are left after filtering"
+        );
+
+        fetch.add_product(Product::try_from(("wow", &full)).unwrap());
+        check_not_empty(&fetch).unwrap();
+    }
+
+    #[test]
+    fn test_timeout() {
+        let config = RunConfig::from(Args::parse_from(["wownow", "--timeout", "10s"]));
+        assert_eq!(config.timeouts.default, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_args() {
+        <Args as clap::CommandFactory>::command().debug_assert();
+        for args in [
+            ["wownow", "--agent", "--product", "wow"],
+            ["wownow", "--offline", "--record", "dir"],
+            ["wownow", "--offline", "--replay", "dir"],
+        ] {
+            assert!(Args::try_parse_from(args).is_err(), "{args:?}");
         }
     }
 }
@@ -0,0 +1,493 @@
+//! A stand-in for the version server, answering its TCP protocol with fixed responses, or
+//! misbehaving as asked, for testing clients offline
+use crate::api::Endpoint;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task::JoinHandle,
+};
+
+/// The longest request line read, far longer than any endpoint's path
+const MAX_REQUEST_BYTES: usize = 4096;
+
+/// A way for a [`MockServer`] to misbehave in answering a request, as real servers and networks
+/// sometimes do
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Reset the connection without answering, failing the request with an I/O error
+    Reset,
+
+    /// Answer with only the first bytes of the response, then close the connection, as when it
+    /// drops midway
+    Truncate(usize),
+
+    /// Answer a chunk of bytes at a time, waiting between chunks, as a congested server does
+    Drip {
+        /// The bytes written at a time
+        chunk: usize,
+
+        /// How long to wait before each chunk after the first
+        interval: Duration,
+    },
+
+    /// Answer with these bytes instead of the response
+    Garbage(Vec<u8>),
+
+    /// Answer with the response's `## seqn` lowered by this much, as a server's cache that's
+    /// fallen behind would
+    SeqnRegression(u32),
+}
+
+impl Fault {
+    /// Answer over a connection with a response, misbehaving as this says.
+    async fn answer(&self, stream: &mut TcpStream, response: &[u8]) -> io::Result<()> {
+        match self {
+            // dropping the stream with the request unread resets the connection
+            Self::Reset => return Ok(()),
+            Self::Truncate(len) => {
+                stream
+                    .write_all(&response[..response.len().min(*len)])
+                    .await?;
+            }
+            Self::Drip { chunk, interval } => {
+                for (index, chunk) in response.chunks((*chunk).max(1)).enumerate() {
+                    if index > 0 {
+                        tokio::time::sleep(*interval).await;
+                    }
+                    stream.write_all(chunk).await?;
+                    stream.flush().await?;
+                }
+            }
+            Self::Garbage(garbage) => stream.write_all(garbage).await?,
+            Self::SeqnRegression(by) => stream.write_all(&regress_seqn(response, *by)).await?,
+        }
+        stream.shutdown().await
+    }
+}
+
+/// A response with its `## seqn = N` line lowered by `by`, and the rest as it was
+fn regress_seqn(response: &[u8], by: u32) -> Vec<u8> {
+    let mut regressed = Vec::with_capacity(response.len());
+    for line in response.split_inclusive(|byte| *byte == b'\n') {
+        let seqn = std::str::from_utf8(line).ok().and_then(|line| {
+            let (key, value) = line.strip_prefix("##")?.split_once('=')?;
+            if key.trim() == "seqn" {
+                value.trim().parse::<u32>().ok()
+            } else {
+                None
+            }
+        });
+        match seqn {
+            Some(seqn) => {
+                let line_end = &line[line.trim_ascii_end().len()..];
+                regressed.extend(format!("## seqn = {}", seqn.saturating_sub(by)).bytes());
+                regressed.extend(line_end);
+            }
+            None => regressed.extend(line),
+        }
+    }
+    regressed
+}
+
+/// A server speaking the version server's TCP protocol, answering each request with the response
+/// given for its endpoint, or with the empty response the real server gives unknown products.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use wownow::{__bench::synthetic_versions, prelude::*};
+///
+/// let server = MockServer::new()
+///     .with_response(&Endpoint::Versions("wow".to_owned()), synthetic_versions(3))
+///     .with_fault(&Endpoint::Versions("wow".to_owned()), Fault::Reset)
+///     .spawn()
+///     .await?;
+/// let client = Client::with_transport(TcpTransport::with_host(server.host()))
+///     .with_retry_policy(Exponential::default());
+/// // the first attempt is reset, and the retry answered
+/// let versions = client.get_versions("wow").await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockServer {
+    /// The responses by endpoint path, such as `v2/products/wow/versions`
+    responses: HashMap<String, Vec<u8>>,
+
+    /// The faults due for the next requests, by endpoint path
+    faults: HashMap<String, VecDeque<Fault>>,
+}
+
+impl MockServer {
+    /// A server with no responses, answering every request as for an unknown product
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer requests for an endpoint with a response, such as the PSV of a versions response.
+    #[must_use]
+    pub fn with_response(mut self, endpoint: &Endpoint, raw: impl Into<Vec<u8>>) -> Self {
+        self.responses.insert(endpoint.path(), raw.into());
+        self
+    }
+
+    /// Misbehave in answering the next request for an endpoint, after any faults already given
+    /// for it. Once its faults are used up, the endpoint is answered properly again, so a fault
+    /// given twice fails two requests, then lets a third through.
+    #[must_use]
+    pub fn with_fault(mut self, endpoint: &Endpoint, fault: Fault) -> Self {
+        self.faults
+            .entry(endpoint.path())
+            .or_default()
+            .push_back(fault);
+        self
+    }
+
+    /// A server answering with the files of a directory laid out by endpoint path, as
+    /// `wownow snapshot` writes them: `{dir}/v2/summary`, `{dir}/v2/products/wow/versions`, and
+    /// so on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read.
+    pub fn from_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut server = Self::new();
+        server.add_dir(dir.as_ref(), "")?;
+        Ok(server)
+    }
+
+    /// Add the files under a directory, as responses of the paths under `prefix`.
+    fn add_dir(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(ToOwned::to_owned) else {
+                continue;
+            };
+            let path = format!("{prefix}{name}");
+            if entry.file_type()?.is_dir() {
+                self.add_dir(&entry.path(), &format!("{path}/"))?;
+            } else {
+                self.responses.insert(path, std::fs::read(entry.path())?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The endpoint paths the server has responses for, sorted
+    #[must_use]
+    pub fn paths(&self) -> Vec<&str> {
+        let mut paths: Vec<_> = self.responses.keys().map(String::as_str).collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    /// Answer the connections of a listener until the future is dropped, each on a task of its
+    /// own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        serve(Arc::new(self.into_state()), listener).await
+    }
+
+    /// Listen at an address, such as `127.0.0.1:1119`, and answer connections in the background
+    /// until the returned handle is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address can't be listened at.
+    pub async fn listen(self, addr: impl ToSocketAddrs) -> io::Result<RunningMockServer> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(self.into_state());
+        Ok(RunningMockServer {
+            addr,
+            task: tokio::spawn(serve(Arc::clone(&state), listener)),
+            state,
+        })
+    }
+
+    /// Listen at a free port of localhost, as tests do, and answer connections in the background
+    /// until the returned handle is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no port can be listened at.
+    pub async fn spawn(self) -> io::Result<RunningMockServer> {
+        self.listen("127.0.0.1:0").await
+    }
+
+    /// The state of the server while it answers
+    fn into_state(self) -> State {
+        State {
+            responses: self.responses,
+            faults: Mutex::new(self.faults),
+            requests: Mutex::default(),
+        }
+    }
+}
+
+/// A [`MockServer`] while it answers, shared by the tasks of its connections
+#[derive(Debug)]
+struct State {
+    responses: HashMap<String, Vec<u8>>,
+    faults: Mutex<HashMap<String, VecDeque<Fault>>>,
+
+    /// How many requests have been answered, by endpoint path
+    requests: Mutex<HashMap<String, usize>>,
+}
+
+impl State {
+    /// Count a request for an endpoint path, returning the fault due for it, if any.
+    fn request(&self, path: &str) -> Option<Fault> {
+        *self
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(path.to_owned())
+            .or_default() += 1;
+        self.faults
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(path)?
+            .pop_front()
+    }
+}
+
+/// Answer the connections of a listener, each on a task of its own.
+async fn serve(state: Arc<State>, listener: TcpListener) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            // a client hanging up early is its own business
+            let _ = answer(stream, &state).await;
+        });
+    }
+}
+
+/// Peek at the request line of a connection without reading it, returning its path and length.
+async fn peek_request(stream: &TcpStream) -> io::Result<(String, usize)> {
+    let mut buffer = vec![0; MAX_REQUEST_BYTES];
+    loop {
+        let peeked = stream.peek(&mut buffer).await?;
+        let line_end = buffer[..peeked].iter().position(|byte| *byte == b'\n');
+        if line_end.is_some() || peeked == 0 || peeked == buffer.len() {
+            let line_len = line_end.map_or(peeked, |end| end + 1);
+            let path = String::from_utf8_lossy(&buffer[..line_len]);
+            return Ok((path.trim().to_owned(), line_len));
+        }
+        // the rest of the line is yet to arrive
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+/// Read a request from a connection and answer it, closing the connection after the response,
+/// as the version server does, unless a fault is due.
+async fn answer(mut stream: TcpStream, state: &State) -> io::Result<()> {
+    let (path, line_len) = peek_request(&stream).await?;
+    let fault = state.request(&path);
+    if fault != Some(Fault::Reset) {
+        stream.read_exact(&mut vec![0; line_len]).await?;
+    }
+    let response = state.responses.get(&path).map_or(&[][..], Vec::as_slice);
+    if let Some(fault) = fault {
+        return fault.answer(&mut stream, response).await;
+    }
+    stream.write_all(response).await?;
+    stream.shutdown().await
+}
+
+/// A [`MockServer`] answering connections in the background, until this is dropped
+#[derive(Debug)]
+pub struct RunningMockServer {
+    addr: SocketAddr,
+    task: JoinHandle<io::Result<()>>,
+    state: Arc<State>,
+}
+
+impl RunningMockServer {
+    /// The address listened at
+    #[must_use]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The host and port listened at, such as `127.0.0.1:41119`, for
+    /// [`TcpTransport::with_host`](crate::prelude::TcpTransport::with_host)
+    #[must_use]
+    pub fn host(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// How many requests for an endpoint have been answered, faults included, such as to check
+    /// how many times a client tried
+    #[must_use]
+    pub fn requests(&self, endpoint: &Endpoint) -> usize {
+        self.state
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&endpoint.path())
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for RunningMockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::Error,
+        client::Client,
+        filter::ProductFilter,
+        fixtures::{synthetic_summary, synthetic_versions},
+        retry::Exponential,
+        transport::TcpTransport,
+    };
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let server = MockServer::new()
+            .with_response(&Endpoint::Summary, synthetic_summary(2))
+            .with_response(
+                &Endpoint::Versions("product_0".to_owned()),
+                synthetic_versions(3),
+            )
+            .spawn()
+            .await
+            .unwrap();
+        let client = Client::with_transport(TcpTransport::with_host(server.host()));
+
+        let summary = client.get_summary().await.unwrap();
+        assert_eq!(summary.seqn, 2_119_172);
+        let versions = client.get_versions("product_0").await.unwrap();
+        assert_eq!(versions.records.len(), 3);
+        let error = client.get_versions("product_1").await.unwrap_err();
+        assert!(matches!(error, Error::UnknownProduct(product) if product == "product_1"));
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = std::env::temp_dir().join(format!("wownow-test-mock-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("v2/products/wow")).unwrap();
+        std::fs::write(dir.join("v2/summary"), "summary").unwrap();
+        std::fs::write(dir.join("v2/products/wow/versions"), "versions").unwrap();
+
+        let server = MockServer::from_dir(&dir).unwrap();
+        assert_eq!(server.paths(), ["v2/products/wow/versions", "v2/summary"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_faults() {
+        let [reset, truncated, garbled, slow] =
+            ["product_0", "product_1", "product_2", "product_3"]
+                .map(|product| Endpoint::Versions(product.to_owned()));
+        let drip = Fault::Drip {
+            chunk: 64,
+            interval: Duration::from_millis(20),
+        };
+        let mut garbage = synthetic_versions(2).into_bytes();
+        let region = garbage.windows(8).position(|bytes| bytes == b"region_1");
+        garbage[region.unwrap() + 7] = 0xff;
+        let mut server = MockServer::new();
+        for endpoint in [&reset, &truncated, &garbled, &slow] {
+            server = server.with_response(endpoint, synthetic_versions(3));
+        }
+        let server = server
+            .with_fault(&reset, Fault::Reset)
+            .with_fault(&reset, Fault::Reset)
+            .with_fault(&reset, Fault::Reset)
+            .with_fault(&truncated, Fault::Truncate(200))
+            .with_fault(&garbled, Fault::Garbage(garbage.clone()))
+            .with_fault(&garbled, Fault::Garbage(garbage))
+            .with_fault(&slow, drip.clone())
+            .with_fault(&slow, drip)
+            .spawn()
+            .await
+            .unwrap();
+        let client = || Client::with_transport(TcpTransport::with_host(server.host()));
+        let retrying = client().with_retry_policy(Exponential {
+            initial_delay: Duration::from_millis(1),
+            ..Exponential::default()
+        });
+
+        // resets are retried, until one gets through
+        let error = client().get_versions("product_0").await.unwrap_err();
+        assert!(matches!(error, Error::Io { .. }), "{error}");
+        let versions = retrying.get_versions("product_0").await.unwrap();
+        assert_eq!(versions.records.len(), 3);
+        assert_eq!(server.requests(&reset), 4);
+
+        // responses cut short or garbled aren't retried, but lossy parsing gets past bad bytes
+        let error = retrying.get_versions("product_1").await.unwrap_err();
+        assert!(matches!(error, Error::Response { .. }), "{error}");
+        assert!(retrying.get_versions("product_2").await.is_err());
+        assert_eq!(server.requests(&truncated) + server.requests(&garbled), 2);
+        let lossy = client().with_lossy_utf8().get_versions("product_2").await;
+        assert_eq!(lossy.unwrap().records.len(), 2);
+
+        // a slow response arrives whole, unless the deadline passes first
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(50);
+        let error = client()
+            .get_versions_with_deadline("product_3", deadline)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DeadlineExceeded), "{error}");
+        let versions = client().get_versions("product_3").await.unwrap();
+        assert_eq!(versions.records.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_seqn_regression() {
+        let summary =
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n## seqn = 1\nproduct_0|2118468|\n";
+        let versions = Endpoint::Versions("product_0".to_owned());
+        let server = MockServer::new()
+            .with_response(&Endpoint::Summary, summary)
+            .with_response(&versions, synthetic_versions(3))
+            .with_fault(&versions, Fault::SeqnRegression(1))
+            .spawn()
+            .await
+            .unwrap();
+        let client = Client::with_transport(TcpTransport::with_host(server.host()));
+
+        // versions older than the summary says are fetched again
+        let fetch = client
+            .get_consistent_versions(&ProductFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(fetch.seqn(), 1);
+        assert_eq!(server.requests(&versions), 2);
+    }
+
+    #[test]
+    fn test_regress_seqn() {
+        assert_eq!(
+            regress_seqn(b"A!STRING:0\r\n## seqn = 5\r\na\r\n", 2),
+            b"A!STRING:0\r\n## seqn = 3\r\na\r\n"
+        );
+        assert_eq!(
+            regress_seqn(b"A!STRING:0\n##seqn=1\n", 2),
+            b"A!STRING:0\n## seqn = 0\n"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Notifying sinks of new builds
+use crate::output::{Product, VersionsFetch};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use tokio::task::JoinSet;
+
+/// An error from a sink, which may be of any type
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A new build of a product in a region
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildChange {
+    /// The product, such as `wow`
+    pub product: String,
+
+    /// The region, such as `us`
+    pub region: String,
+
+    /// The version before the change, if the product was in the region before
+    pub previous_version: Option<String>,
+
+    /// The build before the change, if the product was in the region before
+    pub previous_build: Option<String>,
+
+    /// The new version, such as `10.2.6`
+    pub version: String,
+
+    /// The new build, such as `53840`
+    pub build: String,
+}
+
+/// The new builds detected between two fetches
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    /// When the new builds were fetched
+    pub detected_at: DateTime<Utc>,
+
+    /// The new builds, in the order of the products and regions of the newer fetch
+    pub changes: Vec<BuildChange>,
+
+    /// The products with new builds, with the versions of every region as of the newer fetch
+    #[serde(skip)]
+    pub products: Vec<Product>,
+}
+
+impl ChangeEvent {
+    /// The builds in `new` that differ from those in `old`, for products in both, if there are
+    /// any. Products only in `new` are not changes, as they weren't being watched before.
+    #[must_use]
+    pub fn detect(old: &VersionsFetch, new: &VersionsFetch) -> Option<Self> {
+        let mut changes = Vec::new();
+        let mut products = Vec::new();
+        for new_product in new.products() {
+            let Some(old_product) = old.product(new_product.name()) else {
+                continue;
+            };
+            let changes_before = changes.len();
+            for new_version in new_product.versions() {
+                let old_version = old_product
+                    .versions()
+                    .iter()
+                    .find(|old_version| old_version.region() == new_version.region());
+                if old_version.is_some_and(|old_version| old_version.build() == new_version.build())
+                {
+                    continue;
+                }
+                changes.push(BuildChange {
+                    product: new_product.name().to_owned(),
+                    region: new_version.region().to_owned(),
+                    previous_version: old_version
+                        .map(|old_version| old_version.version().to_owned()),
+                    previous_build: old_version.map(|old_version| old_version.build().to_owned()),
+                    version: new_version.version().to_owned(),
+                    build: new_version.build().to_owned(),
+                });
+            }
+            if changes.len() > changes_before {
+                products.push(new_product.clone());
+            }
+        }
+        (!changes.is_empty()).then(|| Self {
+            detected_at: new.retrieval_datetime(),
+            changes,
+            products,
+        })
+    }
+}
+
+/// Somewhere to publish new builds, such as a chat service or a webhook. Implement this to
+/// receive the events of a [`FanOut`] alongside the built-in sinks.
+#[async_trait::async_trait]
+pub trait ChangeSink: Send + Sync {
+    /// A short name for the sink, to identify it in errors
+    fn name(&self) -> &str;
+
+    /// Publish an event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event could not be published.
+    async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError>;
+}
+
+/// A sink that failed to publish an event
+#[derive(Debug)]
+pub struct SinkFailure {
+    /// The name of the sink
+    pub sink: String,
+
+    /// Why it failed
+    pub error: SinkError,
+}
+
+/// Many sinks, all published to at once. Needs the `tokio` feature.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Default)]
+pub struct FanOut {
+    sinks: Vec<Arc<dyn ChangeSink>>,
+}
+
+#[cfg(feature = "tokio")]
+impl FanOut {
+    /// Create a fan-out to no sinks
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sink
+    pub fn add(&mut self, sink: impl ChangeSink + 'static) {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    /// Whether there are no sinks, so publishing would do nothing
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Publish an event to every sink concurrently, waiting for them all to finish. Returns the
+    /// sinks that failed.
+    pub async fn publish(&self, event: &ChangeEvent) -> Vec<SinkFailure> {
+        let mut set = JoinSet::new();
+        for sink in &self.sinks {
+            let sink = Arc::clone(sink);
+            let event = event.clone();
+            set.spawn(async move {
+                let result = sink.publish(event).await;
+                (sink, result)
+            });
+        }
+
+        let mut failures = Vec::new();
+        while let Some(join_result) = set.join_next().await {
+            match join_result {
+                Ok((_, Ok(()))) => {}
+                Ok((sink, Err(error))) => failures.push(SinkFailure {
+                    sink: sink.name().to_owned(),
+                    error,
+                }),
+                Err(e) => failures.push(SinkFailure {
+                    sink: "unknown".to_owned(),
+                    error: Box::new(e),
+                }),
+            }
+        }
+        failures
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl std::fmt::Debug for FanOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.sinks.iter().map(|sink| sink.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TestVersions;
+    #[cfg(feature = "tokio")]
+    use std::sync::Mutex;
+
+    fn fetch(versions_names: &[(&str, &str)]) -> VersionsFetch {
+        TestVersions::new().regions(versions_names).fetch("wow")
+    }
+
+    #[test]
+    fn test_detect() {
+        let old = fetch(&[("us", "10.2.5.53584"), ("eu", "10.2.5.53584")]);
+        let new = fetch(&[
+            ("us", "10.2.6.53840"),
+            ("eu", "10.2.5.53584"),
+            ("kr", "10.2.6.53840"),
+        ]);
+
+        let event = ChangeEvent::detect(&old, &new).unwrap();
+        assert_eq!(
+            event.changes,
+            vec![
+                BuildChange {
+                    product: "wow".to_owned(),
+                    region: "us".to_owned(),
+                    previous_version: Some("10.2.5".to_owned()),
+                    previous_build: Some("53584".to_owned()),
+                    version: "10.2.6".to_owned(),
+                    build: "53840".to_owned(),
+                },
+                BuildChange {
+                    product: "wow".to_owned(),
+                    region: "kr".to_owned(),
+                    previous_version: None,
+                    previous_build: None,
+                    version: "10.2.6".to_owned(),
+                    build: "53840".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(event.products.len(), 1);
+        assert_eq!(event.products[0].versions().len(), 3);
+        assert!(ChangeEvent::detect(&new, &new).is_none());
+        assert!(ChangeEvent::detect(&VersionsFetch::new(), &new).is_none());
+    }
+
+    #[cfg(feature = "tokio")]
+    struct Recorder {
+        name: &'static str,
+        published: Arc<Mutex<Vec<ChangeEvent>>>,
+    }
+
+    #[cfg(feature = "tokio")]
+    #[async_trait::async_trait]
+    impl ChangeSink for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn publish(&self, event: ChangeEvent) -> Result<(), SinkError> {
+            if self.name == "broken" {
+                return Err("out of order".into());
+            }
+            self.published.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_fan_out() {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let mut fan_out = FanOut::new();
+        fan_out.add(Recorder {
+            name: "working",
+            published: Arc::clone(&published),
+        });
+        fan_out.add(Recorder {
+            name: "broken",
+            published: Arc::clone(&published),
+        });
+
+        let event = ChangeEvent {
+            detected_at: Utc::now(),
+            changes: Vec::new(),
+            products: Vec::new(),
+        };
+        let failures = fan_out.publish(&event).await;
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].detected_at, event.detected_at);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].sink, "broken");
+        assert_eq!(failures[0].error.to_string(), "out of order");
+    }
+}
@@ -1,66 +1,547 @@
-use crate::response::versions::{Record as VersionsRecord, Response as VersionsResponse};
+//! Output models, serialized as the JSON output of the CLI
+use crate::response::{
+    summary::Response as SummaryResponse,
+    versions::{Record as VersionsRecord, Response as VersionsResponse},
+};
+#[cfg(any(feature = "chrono", feature = "time"))]
+use crate::timestamp::{self, Timestamp};
 use serde::Serialize;
+use std::sync::Arc;
 
-/// Errors that can occur when parsing a response.
-#[derive(Debug, thiserror::Error)]
+/// Errors that can occur when building output.
+///
+/// New variants may be added, so matches must have a wildcard arm. Each variant has a stable
+/// code, from [`Error::code`], for structured output.
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
+    /// A versions name could not be split into a version and a build
     #[error("unparseable version field: {0}")]
     UnparseableVersion(String),
 }
 
+impl Error {
+    /// A stable code identifying the kind of error, which won't change between versions:
+    ///
+    /// | Variant              | Code                  |
+    /// |----------------------|-----------------------|
+    /// | `UnparseableVersion` | `unparseable_version` |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnparseableVersion(_) => "unparseable_version",
+        }
+    }
+}
+
+/// A result type for building output
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The versions of a set of products, fetched at a point in time
 #[derive(Debug, Serialize)]
 pub struct VersionsFetch {
-    retrieval_datetime: chrono::DateTime<chrono::Utc>,
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[serde(serialize_with = "timestamp::serialize")]
+    retrieval_datetime: Timestamp,
+
+    /// The seqn of the summary the products were selected from, if it was consulted, to key
+    /// caches on and compare the freshness of fetches without comparing every product
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary_seqn: Option<u32>,
+
+    /// Whether the versions were served from a local cache without contacting the server, in
+    /// which case they may be stale
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    offline: bool,
+
     products: Vec<Product>,
+
+    /// The products whose versions hadn't arrived when the fetch gave up on them, such as at a
+    /// deadline
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unfinished: Vec<String>,
 }
 
 impl VersionsFetch {
+    /// Create an empty fetch, timestamped now with the `chrono` or `time` feature
+    #[must_use]
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Add a product to the fetch
     pub fn add_product(&mut self, product: Product) {
         self.products.push(product);
     }
+
+    /// When the fetch was made
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[must_use]
+    pub fn retrieval_datetime(&self) -> Timestamp {
+        self.retrieval_datetime
+    }
+
+    /// Sort the products by name, so the same products are in the same order however they were
+    /// fetched (e.g., concurrently, in the order they arrived).
+    pub fn sort_products(&mut self) {
+        self.products.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// The products in the fetch
+    #[must_use]
+    pub fn products(&self) -> &[Product] {
+        &self.products
+    }
+
+    /// The product with the given name, if it's in the fetch
+    #[must_use]
+    pub fn product(&self, name: &str) -> Option<&Product> {
+        self.products.iter().find(|product| &*product.name == name)
+    }
+
+    /// The seqn of the summary the products were selected from, if it was consulted
+    #[must_use]
+    pub fn summary_seqn(&self) -> Option<u32> {
+        self.summary_seqn
+    }
+
+    /// Record the seqn of the summary the products were selected from
+    pub fn set_summary_seqn(&mut self, seqn: u32) {
+        self.summary_seqn = Some(seqn);
+    }
+
+    /// Record the summary the products were selected from: its seqn, and which products it flags
+    /// as special, whose configs are then taken to be encrypted.
+    pub fn apply_summary(&mut self, summary: &SummaryResponse) {
+        self.set_summary_seqn(summary.seqn);
+        for product in &mut self.products {
+            if summary.is_flagged(&product.name) {
+                product.access = Access::Encrypted;
+            }
+        }
+    }
+
+    /// Mark the fetch as served from a local cache without contacting the server
+    pub fn set_offline(&mut self) {
+        self.offline = true;
+    }
+
+    /// The products whose versions the fetch gave up on, so it's missing them
+    #[must_use]
+    pub fn unfinished(&self) -> &[String] {
+        &self.unfinished
+    }
+
+    /// Record that the fetch gave up on the versions of a product, so it's missing them
+    pub fn add_unfinished(&mut self, name: impl Into<String>) {
+        self.unfinished.push(name.into());
+    }
 }
 
+// only timestamped with chrono or time
+#[cfg_attr(
+    not(any(feature = "chrono", feature = "time")),
+    allow(clippy::derivable_impls)
+)]
 impl Default for VersionsFetch {
     fn default() -> Self {
         Self {
-            retrieval_datetime: chrono::Utc::now(),
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            retrieval_datetime: timestamp::now(),
+            summary_seqn: None,
+            offline: false,
             products: Vec::new(),
+            unfinished: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+/// A product's code, as the servers name it, such as `wow` or `wow_classic`
+pub type ProductCode = String;
+
+/// A product and its versions in each region
+#[derive(Debug, Clone, Serialize)]
 pub struct Product {
-    name: String,
+    name: Arc<str>,
+    access: Access,
+
+    /// When the versions were retrieved from the server, if earlier than the fetch (e.g., when
+    /// served from a local cache)
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "timestamp::serialize_option"
+    )]
+    retrieved_at: Option<Timestamp>,
+
     versions: Vec<Version>,
+
+    /// Whether every region has the same build, as it does except during a staged rollout
+    consistent: bool,
+
+    /// The regions not on the build most regions have, during a staged rollout
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    divergent_regions: Vec<String>,
+
+    /// The versions being downloaded in the background ahead of a patch, one per region, if they
+    /// were fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bgdl: Option<Vec<Version>>,
+
+    /// Whether some region's background download is a newer build than its live version, the
+    /// classic sign of an imminent patch
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prepatch: bool,
 }
 
-impl Product {
-    pub fn from_versions_response(name: &str, response: &VersionsResponse) -> Self {
+/// Whether a product's build and CDN configs can be read by the public
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// The configs are plain, and can be fetched from the CDN and read by anyone
+    Public,
+
+    /// The configs are encrypted with the key ring named in the versions response, or the product
+    /// is an internal or vendor one, or flagged as special by the summary, whose configs typically
+    /// are. Fetching their configs from the CDN is not useful without the key.
+    Encrypted,
+}
+
+/// The prefixes of the codes of internal and vendor products, such as `wowdev` and `wowv2`, whose
+/// configs are encrypted
+const INTERNAL_PRODUCT_PREFIXES: [&str; 2] = ["wowdev", "wowv"];
+
+impl Access {
+    /// Determine the access of a product from its versions response. A product is encrypted if any
+    /// of its records names a key ring.
+    #[must_use]
+    pub fn from_versions_response(response: &VersionsResponse) -> Self {
+        if response
+            .records
+            .iter()
+            .any(|record| record.key_ring.is_some())
+        {
+            Access::Encrypted
+        } else {
+            Access::Public
+        }
+    }
+
+    /// Determine the access of a product from its code and versions response. A product is
+    /// encrypted if any of its records names a key ring, or it's an internal or vendor product,
+    /// such as `wowdev`.
+    #[must_use]
+    pub fn of(product: &str, response: &VersionsResponse) -> Self {
+        if INTERNAL_PRODUCT_PREFIXES
+            .iter()
+            .any(|prefix| product.starts_with(prefix))
+        {
+            Access::Encrypted
+        } else {
+            Self::from_versions_response(response)
+        }
+    }
+}
+
+impl<N: Into<Arc<str>>> TryFrom<(N, &VersionsResponse)> for Product {
+    type Error = Error;
+
+    /// Create a product from its name (such as a `&str`, or an `Arc<str>` to share it) and
+    /// versions response, failing if any record's versions name cannot be split into a version and
+    /// a build.
+    fn try_from((name, response): (N, &VersionsResponse)) -> Result<Self> {
         let versions = response
             .records
             .iter()
             .map(Version::try_from)
-            .collect::<Result<_>>()
-            .unwrap();
-        Self {
-            name: name.to_owned(),
+            .collect::<Result<_>>()?;
+        let divergent_regions = divergent_regions(response);
+        let name = name.into();
+        Ok(Self {
+            access: Access::of(&name, response),
+            name,
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            retrieved_at: None,
             versions,
+            consistent: divergent_regions.is_empty(),
+            divergent_regions,
+            bgdl: None,
+            prepatch: false,
+        })
+    }
+}
+
+/// The regions of a versions response whose build config and build ID differ from those of most
+/// regions. When regions are evenly split, the newest build counts as most, so the regions it has
+/// yet to reach are the divergent ones.
+fn divergent_regions(response: &VersionsResponse) -> Vec<String> {
+    let build = |record: &VersionsRecord| (record.build_config, record.build_id);
+    let mut counts: Vec<(_, usize)> = Vec::new();
+    for record in &response.records {
+        match counts.iter_mut().find(|(key, _)| *key == build(record)) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((build(record), 1)),
         }
     }
+    let Some((majority, _)) = counts
+        .into_iter()
+        .max_by_key(|&((_, build_id), count)| (count, build_id))
+    else {
+        return Vec::new();
+    };
+
+    let mut regions: Vec<_> = response
+        .records
+        .iter()
+        .filter(|record| build(record) != majority)
+        .map(|record| record.region.clone())
+        .collect();
+    regions.sort();
+    regions
 }
 
-#[derive(Debug, Serialize)]
+impl Product {
+    /// Create a product from a versions response
+    ///
+    /// # Panics
+    ///
+    /// Panics if any record's versions name cannot be split into a version and a build.
+    #[deprecated(
+        note = "use `Product::try_from((name, response))`, which returns an error instead"
+    )]
+    #[must_use]
+    pub fn from_versions_response(name: &str, response: &VersionsResponse) -> Self {
+        Self::try_from((name, response)).unwrap()
+    }
+
+    /// The product name, such as `wow`
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the product's configs can be read by the public
+    #[must_use]
+    pub fn access(&self) -> Access {
+        self.access
+    }
+
+    /// Set when the versions were retrieved from the server, for versions retrieved earlier than
+    /// the fetch they're part of
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn set_retrieved_at(&mut self, retrieved_at: Timestamp) {
+        self.retrieved_at = Some(retrieved_at);
+    }
+
+    /// The versions of the product, one per region
+    #[must_use]
+    pub fn versions(&self) -> &[Version] {
+        &self.versions
+    }
+
+    /// The versions of the product, one per region, for annotating
+    pub fn versions_mut(&mut self) -> &mut [Version] {
+        &mut self.versions
+    }
+
+    /// Append the versions of another fetch of the product, such as from another server. Whether
+    /// the product is consistent, and its background download, are kept from this fetch.
+    pub fn append_versions(&mut self, other: Product) {
+        self.versions.extend(other.versions);
+    }
+
+    /// Whether every region has the same build config and build ID
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.consistent
+    }
+
+    /// The regions whose build differs from that of most regions, such as during a staged
+    /// rollout, sorted
+    #[must_use]
+    pub fn divergent_regions(&self) -> &[String] {
+        &self.divergent_regions
+    }
+
+    /// The version of the newest build in any region, or the first region's if several have it.
+    /// `None` if no region's build is a number.
+    #[must_use]
+    pub fn newest(&self) -> Option<&Version> {
+        self.versions
+            .iter()
+            .filter_map(|version| Some((version.build_number()?, version)))
+            .rev()
+            .max_by_key(|(build, _)| *build)
+            .map(|(_, version)| version)
+    }
+
+    /// The versions of the regions on an older build than the newest, which have yet to get it.
+    /// Unlike [`Product::divergent_regions`], which compares regions with most of them, a region
+    /// ahead of the rest isn't behind.
+    pub fn regions_behind(&self) -> impl Iterator<Item = &Version> {
+        let newest = self.newest().and_then(Version::build_number);
+        self.versions.iter().filter(move |version| {
+            matches!((version.build_number(), newest), (Some(build), Some(newest)) if build < newest)
+        })
+    }
+
+    /// Set the versions being downloaded in the background, from the product's bgdl endpoint
+    /// (which is in the format of the versions endpoint), and so whether a patch is imminent.
+    pub fn set_bgdl(&mut self, bgdl: Product) {
+        self.bgdl = Some(bgdl.versions);
+        let prepatch = self.prepatch_regions().next().is_some();
+        self.prepatch = prepatch;
+    }
+
+    /// The versions being downloaded in the background, one per region, if they were set
+    #[must_use]
+    pub fn bgdl(&self) -> Option<&[Version]> {
+        self.bgdl.as_deref()
+    }
+
+    /// Whether some region's background download is a newer build than its live version
+    #[must_use]
+    pub fn is_prepatch(&self) -> bool {
+        self.prepatch
+    }
+
+    /// The live and background-downloaded versions of each region whose background download is a
+    /// newer build
+    pub fn prepatch_regions(&self) -> impl Iterator<Item = (&Version, &Version)> {
+        self.bgdl.iter().flatten().filter_map(|bgdl| {
+            let live = self
+                .versions
+                .iter()
+                .find(|live| live.region == bgdl.region)?;
+            (bgdl.build_number()? > live.build_number()?).then_some((live, bgdl))
+        })
+    }
+}
+
+/// The line of the game a product is of, which decides how its versions are numbered in the
+/// `## Interface:` line of addons' TOC files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductCategory {
+    /// The current expansion, such as `wow`, `wowt` and `wow_beta`
+    Retail,
+
+    /// A classic expansion being progressed through, such as `wow_classic` and `wow_classic_ptr`
+    Classic,
+
+    /// The original game, as it was, such as `wow_classic_era` and `wow_classic_era_ptr`
+    ClassicEra,
+}
+
+impl ProductCategory {
+    /// The category of a product by its code, retail unless it's a classic product
+    #[must_use]
+    pub fn of(product: &str) -> Self {
+        if product.starts_with("wow_classic_era") {
+            Self::ClassicEra
+        } else if product.starts_with("wow_classic") {
+            Self::Classic
+        } else {
+            Self::Retail
+        }
+    }
+}
+
+/// The version and build of a product in a region
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::struct_field_names)]
 pub struct Version {
     region: String,
     version: String,
     build: String,
+
+    /// The version server this version was served by, when merging several servers' versions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+
+    /// When this build was first observed in this region, if known
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "timestamp::serialize_option"
+    )]
+    first_seen: Option<Timestamp>,
+
+    /// How long this build has been live in this region, in seconds, if known
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_age: Option<u64>,
+}
+
+impl Version {
+    /// The region, such as `us` or `eu`
+    #[must_use]
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// The version, such as `10.2.5`
+    #[must_use]
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The build, such as `53584`
+    #[must_use]
+    pub fn build(&self) -> &str {
+        &self.build
+    }
+
+    /// The version server this version was served by, if set
+    #[must_use]
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    /// Set the version server this version was served by, such as `eu`
+    pub fn set_server(&mut self, server: impl Into<String>) {
+        self.server = Some(server.into());
+    }
+
+    /// The interface number of the version, as addons give it in the `## Interface:` line of
+    /// their TOC files: the major, minor and patch versions as two digits each after the first,
+    /// such as `100205` for retail's `10.2.5` or `11502` for classic era's `1.15.2`. Retail
+    /// versions before Shadowlands (9.0) leave out the patch, as their `.5` patches kept the
+    /// interface of the `.0`, such as `80200` for `8.2.5`. `None` if the version isn't numbers.
+    #[must_use]
+    pub fn interface_number(&self, category: ProductCategory) -> Option<u32> {
+        let mut parts = self.version.split('.').map(str::parse::<u32>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().transpose().ok()?.unwrap_or(0);
+        let patch = parts.next().transpose().ok()?.unwrap_or(0);
+        if parts.next().is_some() || minor > 99 || patch > 99 {
+            return None;
+        }
+        let patch = match category {
+            ProductCategory::Retail if major < 9 => 0,
+            _ => patch,
+        };
+        major.checked_mul(10_000)?.checked_add(minor * 100 + patch)
+    }
+
+    /// The build as a number, for ordering builds
+    fn build_number(&self) -> Option<u32> {
+        self.build.parse().ok()
+    }
+
+    /// When this build was first observed in this region, if known
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[must_use]
+    pub fn first_seen(&self) -> Option<Timestamp> {
+        self.first_seen
+    }
+
+    /// Set when this build was first observed in this region, and so its age as of `now`.
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn set_first_seen(&mut self, first_seen: Timestamp, now: Timestamp) {
+        self.first_seen = Some(first_seen);
+        // a first sighting in the future (clock skew) is as young as can be
+        self.build_age = Some(timestamp::seconds_between(first_seen, now));
+    }
 }
 
 impl TryFrom<&VersionsRecord> for Version {
@@ -75,6 +556,252 @@ impl TryFrom<&VersionsRecord> for Version {
             region,
             version: version.to_owned(),
             build: build.to_owned(),
+            server: None,
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            first_seen: None,
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            build_age: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_versions;
+
+    #[test]
+    fn test_access() {
+        let public = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118468\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        assert_eq!(Access::from_versions_response(&public), Access::Public);
+
+        let encrypted = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118468\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d|3ca57fe7319a297346440e4d2a03a0cd|53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        assert_eq!(
+            Access::from_versions_response(&encrypted),
+            Access::Encrypted
+        );
+
+        // internal products are encrypted without a key ring
+        assert_eq!(Access::of("wow", &public), Access::Public);
+        assert_eq!(Access::of("wowdev", &public), Access::Encrypted);
+        assert_eq!(Access::of("wowv2", &public), Access::Encrypted);
+
+        // as are products the summary flags
+        let summary = SummaryResponse::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 2119172\n\
+            wow|2118018|\n\
+            wow|1476930|cdn\n\
+            wow|1476931|bgdl\n\
+            wowe1|2118019|\n\
+            wowe1|2118020|vendor",
+        )
+        .unwrap();
+        let mut fetch = VersionsFetch::new();
+        fetch.add_product(Product::try_from(("wow", &public)).unwrap());
+        fetch.add_product(Product::try_from(("wowe1", &public)).unwrap());
+        assert_eq!(fetch.product("wowe1").unwrap().access(), Access::Public);
+        fetch.apply_summary(&summary);
+        assert_eq!(fetch.summary_seqn(), Some(2_119_172));
+        assert_eq!(fetch.product("wow").unwrap().access(), Access::Public);
+        assert_eq!(fetch.product("wowe1").unwrap().access(), Access::Encrypted);
+    }
+
+    #[test]
+    fn test_try_from() {
+        let response = |versions_name: &str| {
+            VersionsResponse::try_from(format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 2118468\n\
+                us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|{versions_name}|53020d32e1a25648c8e1eafd5771935f"
+            ).as_str())
+            .unwrap()
+        };
+
+        let product = Product::try_from(("wow", &response("10.2.5.53584"))).unwrap();
+        assert_eq!(product.name(), "wow");
+        assert_eq!(product.versions()[0].build(), "53584");
+
+        let error = Product::try_from(("wow", &response("53584"))).unwrap_err();
+        assert!(matches!(error, Error::UnparseableVersion(name) if name == "53584"));
+    }
+
+    #[test]
+    fn test_divergent_regions() {
+        let product = |records: &str| {
+            let response = VersionsResponse::try_from(format!(
+                "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+                ## seqn = 2118468\n\
+                {records}"
+            ).as_str())
+            .unwrap();
+            Product::try_from(("wow", &response)).unwrap()
+        };
+        let old = "47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f";
+        let new = "0f1e2d3c4b5a69788796a5b4c3d2e1f0|74093d42ce367c7a67f2831dbf64088d||53840|10.2.6.53840|53020d32e1a25648c8e1eafd5771935f";
+
+        let consistent = product(&format!("us|{old}\neu|{old}\nkr|{old}"));
+        assert!(consistent.is_consistent());
+        assert!(consistent.divergent_regions().is_empty());
+
+        let rolling_out = product(&format!("us|{new}\neu|{old}\nkr|{old}"));
+        assert!(!rolling_out.is_consistent());
+        assert_eq!(rolling_out.divergent_regions(), ["us"]);
+
+        let split = product(&format!("us|{new}\neu|{old}\nkr|{new}\ntw|{old}"));
+        assert_eq!(split.divergent_regions(), ["eu", "tw"]);
+
+        let regions_behind = |product: &Product| {
+            product
+                .regions_behind()
+                .map(|version| version.region().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(consistent.newest().unwrap().region(), "us");
+        assert!(regions_behind(&consistent).is_empty());
+        // the region ahead is the divergent one, and the rest are behind it
+        assert_eq!(rolling_out.newest().unwrap().build(), "53840");
+        assert_eq!(regions_behind(&rolling_out), ["eu", "kr"]);
+        assert_eq!(split.newest().unwrap().region(), "us");
+        assert_eq!(regions_behind(&split), ["eu", "tw"]);
+    }
+
+    #[test]
+    fn test_set_bgdl() {
+        let product = |builds: &[(&str, &str)]| {
+            let records = builds.iter().map(|(region, build)| {
+                format!("{region}|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||{build}|10.2.5.{build}|53020d32e1a25648c8e1eafd5771935f")
+            });
+            let psv = ["Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16".to_owned(), "## seqn = 1".to_owned()]
+                .into_iter()
+                .chain(records)
+                .collect::<Vec<_>>()
+                .join("\n");
+            Product::try_from(("wow", &VersionsResponse::try_from(psv.as_str()).unwrap())).unwrap()
+        };
+
+        let mut live = product(&[("us", "53584"), ("eu", "53584")]);
+        live.set_bgdl(product(&[("us", "53584"), ("eu", "53584")]));
+        assert!(!live.is_prepatch());
+        assert_eq!(live.bgdl().unwrap().len(), 2);
+
+        live.set_bgdl(product(&[("us", "53584"), ("eu", "53840")]));
+        assert!(live.is_prepatch());
+        let regions: Vec<_> = live
+            .prepatch_regions()
+            .map(|(live, bgdl)| (live.region(), live.build(), bgdl.build()))
+            .collect();
+        assert_eq!(regions, [("eu", "53584", "53840")]);
+    }
+
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    #[test]
+    fn test_set_first_seen() {
+        #[cfg(feature = "chrono")]
+        use chrono::Duration;
+        #[cfg(not(feature = "chrono"))]
+        use time::Duration;
+
+        let response = VersionsResponse::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 2118468\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f",
+        )
+        .unwrap();
+        let mut version = Version::try_from(&response.records[0]).unwrap();
+        let now = timestamp::now();
+
+        version.set_first_seen(now - Duration::days(13), now);
+        assert_eq!(version.build_age, Some(13 * 24 * 60 * 60));
+
+        version.set_first_seen(now + Duration::seconds(1), now);
+        assert_eq!(version.build_age, Some(0));
+    }
+
+    #[test]
+    fn test_sort_products() {
+        let response = VersionsResponse::try_from(synthetic_versions(3).as_str()).unwrap();
+        let mut fetch = VersionsFetch::new();
+        for name in ["wowt", "wow_classic", "wow"] {
+            fetch.add_product(Product::try_from((name, &response)).unwrap());
+        }
+        fetch.sort_products();
+        let names: Vec<_> = fetch.products().iter().map(Product::name).collect();
+        assert_eq!(names, ["wow", "wow_classic", "wowt"]);
+    }
+
+    // interface numbers are written as in TOC files
+    #[allow(clippy::unreadable_literal)]
+    #[test]
+    fn test_interface_number() {
+        let version = |version: &str| Version {
+            region: "us".to_owned(),
+            version: version.to_owned(),
+            build: "1".to_owned(),
+            server: None,
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            first_seen: None,
+            #[cfg(any(feature = "chrono", feature = "time"))]
+            build_age: None,
+        };
+        for (product, versions) in [
+            (
+                "wow",
+                &[
+                    ("7.3.5", 70300),
+                    ("8.2.5", 80200),
+                    ("8.3.0", 80300),
+                    ("9.0.2", 90002),
+                    ("9.1.5", 90105),
+                    ("10.0.2", 100002),
+                    ("10.2.5", 100205),
+                    ("11.0.2", 110002),
+                ][..],
+            ),
+            (
+                "wow_classic",
+                &[("2.5.1", 20501), ("3.4.3", 30403), ("4.4.0", 40400)][..],
+            ),
+            (
+                "wow_classic_era",
+                &[("1.13.2", 11302), ("1.14.4", 11404), ("1.15.2", 11502)][..],
+            ),
+        ] {
+            let category = ProductCategory::of(product);
+            for &(name, interface) in versions {
+                assert_eq!(
+                    version(name).interface_number(category),
+                    Some(interface),
+                    "{product} {name}"
+                );
+            }
+        }
+        assert_eq!(
+            ProductCategory::of("wow_classic_era_ptr"),
+            ProductCategory::ClassicEra
+        );
+        assert_eq!(ProductCategory::of("wowt"), ProductCategory::Retail);
+        assert_eq!(
+            version("10.2").interface_number(ProductCategory::Retail),
+            Some(100200)
+        );
+        assert_eq!(
+            version("10.x.5").interface_number(ProductCategory::Retail),
+            None
+        );
+        assert_eq!(
+            version("1.100.0").interface_number(ProductCategory::Retail),
+            None
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! A pool of buffers that responses are read into, reused from request to request
+use std::sync::{Mutex, PoisonError};
+
+/// The most buffers kept for reuse, about as many as requests a client makes at once
+const MAX_BUFFERS: usize = 16;
+
+/// Buffers that grew larger than this aren't kept, so one unusually large response doesn't stay
+/// allocated for good
+const MAX_BUFFER_CAPACITY: usize = 1 << 20;
+
+/// Buffers for reading responses into. Taking a buffer reuses a returned one if there is one, so a
+/// client fetching over and over (e.g., a daemon polling every few seconds) doesn't allocate
+/// afresh for each response.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    /// An empty buffer, reused if one has been returned
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.buffers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .unwrap_or_default()
+    }
+
+    /// Return a buffer for reuse, unless the pool is full or the buffer is too large to keep.
+    pub(crate) fn put(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_BUFFER_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        if buffers.len() < MAX_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::default();
+        assert_eq!(pool.take().capacity(), 0);
+
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(b"Product!STRING:0");
+        let capacity = buffer.capacity();
+        pool.put(buffer);
+        let reused = pool.take();
+        assert!(reused.is_empty());
+        assert_eq!(reused.capacity(), capacity);
+
+        pool.put(Vec::with_capacity(MAX_BUFFER_CAPACITY + 1));
+        assert_eq!(pool.take().capacity(), 0);
+
+        for _ in 0..=MAX_BUFFERS {
+            pool.put(Vec::with_capacity(8));
+        }
+        assert_eq!(pool.buffers.lock().unwrap().len(), MAX_BUFFERS);
+    }
+}
@@ -1,7 +1,11 @@
-use std::fmt::Display;
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display, ops::Range, str::Utf8Error};
 
 /// Errors that can occur when parsing a response.
-#[derive(Debug, thiserror::Error)]
+///
+/// New variants may be added, so matches must have a wildcard arm. Each variant has a stable
+/// code, from [`Error::code`], for structured output.
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// An unknown type name was encountered.
     #[error("unknown type name `{0}`")]
@@ -50,10 +54,142 @@ pub enum Error {
     /// An error occurred interpreting bytes as UTF-8
     #[error(transparent)]
     Utf8(#[from] std::str::Utf8Error),
+
+    /// A "HEX:16" field should be 32 hex digits
+    #[error("`{0}` is not 32 hex digits")]
+    InvalidHex(String),
+
+    /// A response should be within the limits on its size
+    #[error("response has more {what} than the limit of {limit}")]
+    TooLarge {
+        /// What there's too much of: `bytes`, `records` or `columns`
+        what: &'static str,
+
+        /// The most there may be
+        limit: usize,
+    },
+}
+
+impl Error {
+    /// A stable code identifying the kind of error, which won't change between versions:
+    ///
+    /// | Variant                  | Code                       |
+    /// |--------------------------|----------------------------|
+    /// | `UnknownTypeName`        | `unknown_type_name`        |
+    /// | `ExpectedColon`          | `expected_colon`           |
+    /// | `ExpectedBang`           | `expected_bang`            |
+    /// | `MultipleSeqn`           | `multiple_seqn`            |
+    /// | `MismatchedRecordLength` | `mismatched_record_length` |
+    /// | `ExpectedHeaderLine`     | `expected_header_line`     |
+    /// | `ExpectedSeqnLine`       | `expected_seqn_line`       |
+    /// | `ExpectedField`          | `expected_field`           |
+    /// | `EmptyField`             | `empty_field`              |
+    /// | `UnexpectedType`         | `unexpected_type`          |
+    /// | `UnparseableInt`         | `unparseable_int`          |
+    /// | `Utf8`                   | `utf8`                     |
+    /// | `InvalidHex`             | `invalid_hex`              |
+    /// | `TooLarge`               | `too_large`                |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownTypeName(_) => "unknown_type_name",
+            Self::ExpectedColon(_) => "expected_colon",
+            Self::ExpectedBang(_) => "expected_bang",
+            Self::MultipleSeqn => "multiple_seqn",
+            Self::MismatchedRecordLength(..) => "mismatched_record_length",
+            Self::ExpectedHeaderLine => "expected_header_line",
+            Self::ExpectedSeqnLine => "expected_seqn_line",
+            Self::ExpectedField(_) => "expected_field",
+            Self::EmptyField(_) => "empty_field",
+            Self::UnexpectedType(..) => "unexpected_type",
+            Self::UnparseableInt(_) => "unparseable_int",
+            Self::Utf8(_) => "utf8",
+            Self::InvalidHex(_) => "invalid_hex",
+            Self::TooLarge { .. } => "too_large",
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The most bytes a response may have by default, far more than the largest real response
+pub const DEFAULT_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// The most records a response may have by default
+pub const DEFAULT_MAX_RECORDS: usize = 100_000;
+
+/// The most columns a response may have by default
+pub const DEFAULT_MAX_COLUMNS: usize = 64;
+
+/// Limits on the size of a response, beyond which it's rejected with `Error::TooLarge` rather
+/// than read and parsed, so a misbehaving server can't exhaust memory. The defaults are far
+/// beyond the size of any real response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most bytes
+    bytes: usize,
+
+    /// The most records
+    records: usize,
+
+    /// The most columns
+    columns: usize,
+}
+
+impl Limits {
+    /// Limit how many bytes a response may have.
+    #[must_use]
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.bytes = max_bytes;
+        self
+    }
+
+    /// Limit how many records a response may have.
+    #[must_use]
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.records = max_records;
+        self
+    }
+
+    /// Limit how many columns a response may have.
+    #[must_use]
+    pub fn with_max_columns(mut self, max_columns: usize) -> Self {
+        self.columns = max_columns;
+        self
+    }
+
+    /// The most bytes a response may have
+    #[must_use]
+    pub fn max_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Check that a response of `length` bytes isn't too large.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::TooLarge` if it has more bytes than the limit.
+    pub fn check_bytes(&self, length: usize) -> Result<()> {
+        if length > self.bytes {
+            return Err(Error::TooLarge {
+                what: "bytes",
+                limit: self.bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            bytes: DEFAULT_MAX_BYTES,
+            records: DEFAULT_MAX_RECORDS,
+            columns: DEFAULT_MAX_COLUMNS,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(super) enum TypeName {
     /// A type for string data. E.g,. "STRING:0". Strings always seem to have a dummy length of 0,
@@ -103,7 +239,7 @@ pub(super) struct Type {
     length: usize,
 }
 
-impl<'input> TryFrom<&'input str> for Type {
+impl TryFrom<&str> for Type {
     type Error = Error;
 
     fn try_from(type_: &str) -> Result<Self> {
@@ -154,49 +290,166 @@ impl<'input> TryFrom<&'input str> for Header<'input> {
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(super) struct Response<'input> {
+pub(crate) struct Response<'input> {
     /// A monotonically increasing identifier. Used by caches to determine if they need to update.
     pub(super) seqn: u32,
 
+    /// The `## key = value` comment lines other than seqn, in the order they appear
+    metadata: Vec<(&'input str, &'input str)>,
+
     headers: Vec<Header<'input>>,
 
-    records: Vec<Vec<&'input str>>,
+    input: &'input str,
+
+    /// The byte ranges in `input` of every record's cells, record after record, so a response
+    /// takes one allocation for its cells however many records it has
+    cells: Vec<Range<usize>>,
 }
 
 impl<'input> Response<'input> {
     pub(super) fn iter_records(&self) -> impl Iterator<Item = Record<'_, 'input>> {
-        self.records.iter().map(move |values| Record {
-            headers: &self.headers,
-            values,
-        })
+        // a header line always has a header, but guard against chunks of none, which panic
+        self.cells
+            .chunks(self.headers.len().max(1))
+            .map(move |cells| Record {
+                headers: &self.headers,
+                input: self.input,
+                cells,
+            })
+    }
+
+    /// The names of the columns, in the order of the header line
+    pub(super) fn column_names(&self) -> impl Iterator<Item = &'input str> + '_ {
+        self.headers.iter().map(|header| header.name)
+    }
+
+    /// The `## key = value` comment lines other than seqn, by key. A key given more than once
+    /// keeps its last value.
+    pub(super) fn metadata(&self) -> BTreeMap<String, String> {
+        self.metadata
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
     }
 }
 
-impl<'input> TryFrom<&'input str> for Response<'input> {
-    type Error = Error;
+/// The text of a comment line after its `##`, such as ` seqn = 2119172`, or `None` if the line
+/// isn't a comment.
+fn comment(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("##")
+}
 
-    fn try_from(input: &'input str) -> Result<Self> {
+/// The byte ranges of the lines of `input`, without their line endings, as `str::lines` splits
+/// it. Newlines are found with memchr, which scans many bytes at a time.
+fn line_ranges(input: &[u8]) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    memchr::memchr_iter(b'\n', input)
+        .map(Some)
+        .chain(std::iter::once(None))
+        .filter_map(move |newline| {
+            let end = newline.unwrap_or(input.len());
+            let line = start..end;
+            start = end + 1;
+            // a final newline ends the last line rather than starting another
+            if newline.is_none() && line.is_empty() {
+                return None;
+            }
+            match input[line.clone()].last() {
+                Some(b'\r') => Some(line.start..line.end - 1),
+                _ => Some(line),
+            }
+        })
+}
+
+/// The byte ranges in `input` of the `|`-separated cells of the line at `line`.
+fn cell_ranges(input: &[u8], line: Range<usize>) -> impl Iterator<Item = Range<usize>> + '_ {
+    let Range {
+        start: line_start,
+        end: line_end,
+    } = line;
+    let mut start = line_start;
+    memchr::memchr_iter(b'|', &input[line_start..line_end])
+        .map(move |separator| line_start + separator)
+        .chain(std::iter::once(line_end))
+        .map(move |end| {
+            let cell = start..end;
+            start = end + 1;
+            cell
+        })
+}
+
+impl<'input> Response<'input> {
+    /// Parse a response, rejecting it if it's larger than the limits.
+    pub(crate) fn parse_with_limits(input: &'input [u8], limits: Limits) -> Result<Self> {
+        limits.check_bytes(input.len())?;
+        let input = std::str::from_utf8(input)?;
+        Self::parse_str_with_limits(input, limits)
+    }
+
+    fn parse_str_with_limits(input: &'input str, limits: Limits) -> Result<Self> {
+        limits.check_bytes(input.len())?;
         let mut headers: Option<Vec<Header>> = None;
         let mut seqn: Option<u32> = None;
-        let mut records: Vec<Vec<&'input str>> = Vec::new();
-
-        for line in input.lines() {
-            if let Some(number_part) = line.strip_prefix("## seqn = ") {
-                if seqn.is_some() {
-                    return Err(Error::MultipleSeqn);
+        let mut metadata = Vec::new();
+        let mut cells: Vec<Range<usize>> = Vec::new();
+        let mut records = 0;
+
+        for line_range in line_ranges(input.as_bytes()) {
+            // ranges split at ASCII bytes, so they're on character boundaries
+            let line = &input[line_range.clone()];
+            if let Some(comment) = comment(line) {
+                // any whitespace is allowed around the key, `=` and value
+                let entry = comment
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim(), value.trim()));
+                match entry {
+                    Some(("seqn", number_part)) => {
+                        if seqn.is_some() {
+                            return Err(Error::MultipleSeqn);
+                        }
+                        seqn = Some(number_part.parse()?);
+                    }
+                    Some(entry) => metadata.push(entry),
+                    // a comment without a value, which says nothing we can use
+                    None => {}
+                }
+            } else if let Some(headers) = &headers {
+                records += 1;
+                if records > limits.records {
+                    return Err(Error::TooLarge {
+                        what: "records",
+                        limit: limits.records,
+                    });
+                }
+                let record_start = cells.len();
+                // one cell past the header is enough to know the record is too long, without
+                // collecting every cell of a line of nothing but separators
+                cells.extend(cell_ranges(input.as_bytes(), line_range).take(headers.len() + 1));
+                let length = cells.len() - record_start;
+                if length != headers.len() {
+                    // count the cells of a record that's too long without collecting them
+                    let length = if length > headers.len() {
+                        memchr::memchr_iter(b'|', line.as_bytes()).count() + 1
+                    } else {
+                        length
+                    };
+                    return Err(Error::MismatchedRecordLength(length, headers.len()));
                 }
-                seqn = Some(number_part.parse()?);
-            } else if headers.is_none() {
-                headers = Some(Header::parse_header_line(line)?);
             } else {
-                // unwrapping is safe because we've already parsed the header line
-                let headers_len = headers.as_ref().unwrap().len();
-
-                let record = line.split('|').collect::<Vec<_>>();
-                if record.len() != headers_len {
-                    return Err(Error::MismatchedRecordLength(record.len(), headers_len));
+                let parsed = Header::parse_header_line(line)?;
+                if parsed.len() > limits.columns {
+                    return Err(Error::TooLarge {
+                        what: "columns",
+                        limit: limits.columns,
+                    });
                 }
-                records.push(record);
+                // records are about as long as the header line, so this is roughly enough cells
+                // for every record, allocated once, though never more than the records allowed
+                cells.reserve(
+                    (parsed.len() * input.len() / line.len().max(1))
+                        .min(limits.records.saturating_mul(parsed.len())),
+                );
+                headers = Some(parsed);
             }
         }
 
@@ -205,24 +458,77 @@ impl<'input> TryFrom<&'input str> for Response<'input> {
 
         Ok(Self {
             seqn,
+            metadata,
             headers,
-            records,
+            input,
+            cells,
         })
     }
 }
 
+impl<'input> TryFrom<&'input str> for Response<'input> {
+    type Error = Error;
+
+    fn try_from(input: &'input str) -> Result<Self> {
+        Self::parse_str_with_limits(input, Limits::default())
+    }
+}
+
+/// Decode a response as UTF-8, replacing any invalid bytes with `U+FFFD REPLACEMENT CHARACTER` if
+/// lossy, along with the error of the first invalid bytes if there were any.
+fn decode(input: &[u8], lossy: bool) -> Result<(Cow<'_, str>, Option<Utf8Error>)> {
+    match std::str::from_utf8(input) {
+        Ok(input) => Ok((Cow::Borrowed(input), None)),
+        Err(error) if lossy => Ok((String::from_utf8_lossy(input), Some(error))),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Parse a response within the limits, replacing invalid UTF-8 if lossy, along with the error of
+/// the first invalid bytes if any were replaced.
+// only clients parse this way
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+pub(crate) fn parse_response<T>(
+    input: &[u8],
+    limits: Limits,
+    lossy: bool,
+) -> Result<(T, Option<Utf8Error>)>
+where
+    T: for<'a> TryFrom<Response<'a>, Error = Error>,
+{
+    limits.check_bytes(input.len())?;
+    let (input, replaced) = decode(input, lossy)?;
+    let response = Response::parse_str_with_limits(&input, limits)?;
+    Ok((T::try_from(response)?, replaced))
+}
+
+/// Parse a response such as a `VersionsResponse`, replacing any bytes that aren't valid UTF-8
+/// with `U+FFFD REPLACEMENT CHARACTER` rather than failing, so one bad byte in an otherwise fine
+/// response only spoils the field it's in. Along with the response is the error of the first
+/// invalid bytes, if any were replaced, to warn of.
+///
+/// # Errors
+///
+/// Returns an error if the response cannot be parsed.
+pub fn parse_lossy<T>(input: &[u8]) -> Result<(T, Option<Utf8Error>)>
+where
+    T: for<'a> TryFrom<&'a str, Error = Error>,
+{
+    let (input, replaced) = decode(input, true)?;
+    Ok((T::try_from(&input)?, replaced))
+}
+
 impl<'input> TryFrom<&'input [u8]> for Response<'input> {
     type Error = Error;
 
     fn try_from(input: &'input [u8]) -> Result<Self> {
-        let input = std::str::from_utf8(input)?;
-        Self::try_from(input)
+        Self::parse_with_limits(input, Limits::default())
     }
 }
 
 pub(super) struct Field<'resp, 'input> {
     type_: &'resp Type,
-    value: &'resp &'input str,
+    value: &'input str,
 }
 
 const STRING_TYPE: Type = Type {
@@ -244,6 +550,45 @@ pub type String0 = String;
 /// A field with type "HEX:16", a 16-byte value
 pub type Hex16 = [u8; 16];
 
+/// Decode the hex of a "HEX:16" field, such as `47e9e06f8371afb141e22614a912acc8`, into its
+/// bytes. With the `simd-hex` feature, this uses the SIMD instructions of the CPU, if it has any.
+///
+/// # Errors
+///
+/// Returns an error if the field isn't 32 hex digits.
+pub fn decode_hex16(hex: &str) -> Result<Hex16> {
+    #[cfg(feature = "simd-hex")]
+    {
+        faster_hex::hex_decode_array(hex.as_bytes()).map_err(|_| Error::InvalidHex(hex.to_owned()))
+    }
+    #[cfg(not(feature = "simd-hex"))]
+    {
+        let digits = hex.as_bytes();
+        if digits.len() != 32 {
+            return Err(Error::InvalidHex(hex.to_owned()));
+        }
+        let mut bytes = [0; 16];
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+            let (Some(high), Some(low)) = (hex_digit(pair[0]), hex_digit(pair[1])) else {
+                return Err(Error::InvalidHex(hex.to_owned()));
+            };
+            *byte = high << 4 | low;
+        }
+        Ok(bytes)
+    }
+}
+
+/// The value of a hex digit of either case
+#[cfg(not(feature = "simd-hex"))]
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
 /// A field with type "DEC:4", a u32
 pub type Dec4 = u32;
 
@@ -260,13 +605,7 @@ impl<'resp, 'input> TryFrom<&Field<'resp, 'input>> for Option<Hex16> {
         if field.value.is_empty() {
             Ok(None)
         } else {
-            let mut bytes = [0; 16];
-            let mut iter = field.value.as_bytes().chunks(2);
-            for (idx, chunk) in iter.by_ref().enumerate() {
-                bytes[idx] =
-                    u8::from_str_radix(unsafe { std::str::from_utf8_unchecked(chunk) }, 16)?;
-            }
-            Ok(Some(bytes))
+            decode_hex16(field.value).map(Some)
         }
     }
 }
@@ -294,23 +633,32 @@ impl<'resp, 'input> TryFrom<&Field<'resp, 'input>> for Dec4 {
     }
 }
 
-impl<'resp, 'input> TryFrom<&Field<'resp, 'input>> for String0 {
+impl<'resp, 'input> TryFrom<&Field<'resp, 'input>> for &'input str {
     type Error = Error;
 
     fn try_from(field: &Field<'resp, 'input>) -> Result<Self> {
         if field.type_ != &STRING_TYPE {
             return Err(Error::UnexpectedType(
                 field.type_.to_string(),
-                DEC4_TYPE.to_string(),
+                STRING_TYPE.to_string(),
             ));
         }
-        Ok((*field.value).to_string())
+        Ok(field.value)
+    }
+}
+
+impl<'resp, 'input> TryFrom<&Field<'resp, 'input>> for String0 {
+    type Error = Error;
+
+    fn try_from(field: &Field<'resp, 'input>) -> Result<Self> {
+        <&str>::try_from(field).map(ToOwned::to_owned)
     }
 }
 
 pub(super) struct Record<'resp, 'input> {
-    headers: &'resp Vec<Header<'input>>,
-    values: &'resp Vec<&'input str>,
+    headers: &'resp [Header<'input>],
+    input: &'input str,
+    cells: &'resp [Range<usize>],
 }
 
 impl<'resp, 'input> Record<'resp, 'input> {
@@ -319,11 +667,184 @@ impl<'resp, 'input> Record<'resp, 'input> {
             if header.name == name {
                 Some(Field {
                     type_: &header.type_,
-                    value: &self.values[idx],
+                    value: &self.input[self.cells[idx].clone()],
                 })
             } else {
                 None
             }
         })
     }
+
+    /// The name of each field's column, with the field, in the order of the header line
+    pub(super) fn fields(&self) -> impl Iterator<Item = (&'input str, &'input str)> + '_ {
+        self.headers
+            .iter()
+            .zip(self.cells)
+            .map(|(header, cell)| (header.name, &self.input[cell.clone()]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::versions::Response as VersionsResponse;
+
+    #[test]
+    fn test_response_cells() {
+        let response = Response::try_from(
+            "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+            ## seqn = 2119172\n\
+            wow|2118018|\n\
+            wow_classic|2110722|cdn",
+        )
+        .unwrap();
+        let products: Vec<_> = response
+            .iter_records()
+            .map(|record| {
+                let field = |name| record.get_field_by_header_name(name).unwrap().value;
+                (field("Product"), field("Seqn"), field("Flags"))
+            })
+            .collect();
+        assert_eq!(
+            products,
+            [("wow", "2118018", ""), ("wow_classic", "2110722", "cdn")]
+        );
+
+        assert!(matches!(
+            Response::try_from("Product!STRING:0|Seqn!DEC:4\r\n## seqn = 1\r\nwow|1|extra\r\n"),
+            Err(Error::MismatchedRecordLength(3, 2))
+        ));
+        let separators = format!(
+            "Product!STRING:0|Seqn!DEC:4\n## seqn = 1\n{}\n",
+            "|".repeat(99_999)
+        );
+        assert!(matches!(
+            Response::try_from(separators.as_str()),
+            Err(Error::MismatchedRecordLength(100_000, 2))
+        ));
+    }
+
+    #[test]
+    fn test_line_and_cell_ranges() {
+        for input in ["", "a", "a\n", "a\r\nb", "a\n\nb\n", "\n", "a|b\r\n|"] {
+            let lines: Vec<_> = line_ranges(input.as_bytes())
+                .map(|line| &input[line])
+                .collect();
+            assert_eq!(
+                lines,
+                input.lines().collect::<Vec<_>>(),
+                "lines of {input:?}"
+            );
+            for line in line_ranges(input.as_bytes()) {
+                let cells: Vec<_> = cell_ranges(input.as_bytes(), line.clone())
+                    .map(|cell| &input[cell])
+                    .collect();
+                assert_eq!(cells, input[line].split('|').collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_hex16() {
+        assert_eq!(
+            decode_hex16("47e9e06f8371afb141e22614a912acc8").unwrap(),
+            [
+                0x47, 0xe9, 0xe0, 0x6f, 0x83, 0x71, 0xaf, 0xb1, 0x41, 0xe2, 0x26, 0x14, 0xa9, 0x12,
+                0xac, 0xc8
+            ]
+        );
+        assert_eq!(
+            decode_hex16("47E9E06F8371AFB141E22614A912ACC8").unwrap(),
+            decode_hex16("47e9e06f8371afb141e22614a912acc8").unwrap()
+        );
+        for invalid in [
+            "47e9e06f8371afb141e22614a912aczz",
+            "47e9e06f8371afb141e22614a912acc",
+            "47e9e06f8371afb141e22614a912acc800",
+            "+7e9e06f8371afb141e22614a912acc8",
+        ] {
+            assert!(matches!(
+                decode_hex16(invalid),
+                Err(Error::InvalidHex(hex)) if hex == invalid
+            ));
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let input = "Product!STRING:0|Seqn!DEC:4\n## seqn = 1\nwow|1\nwowt|2\n";
+        assert!(Response::parse_with_limits(input.as_bytes(), Limits::default()).is_ok());
+        for (limits, too_many) in [
+            (Limits::default().with_max_bytes(input.len() - 1), "bytes"),
+            (Limits::default().with_max_records(1), "records"),
+            (Limits::default().with_max_columns(1), "columns"),
+        ] {
+            assert!(matches!(
+                Response::parse_with_limits(input.as_bytes(), limits),
+                Err(Error::TooLarge { what, .. }) if what == too_many
+            ));
+        }
+        assert_eq!(
+            Limits::default()
+                .with_max_records(2)
+                .check_bytes(DEFAULT_MAX_BYTES + 1)
+                .unwrap_err()
+                .code(),
+            "too_large"
+        );
+    }
+
+    #[test]
+    fn test_parse_lossy() {
+        let input = b"Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = 1\n\
+            us|47e9e06f8371afb141e22614a912acc8|74093d42ce367c7a67f2831dbf64088d||53584|10.2.5.\xff53584|53020d32e1a25648c8e1eafd5771935f";
+        assert!(matches!(
+            VersionsResponse::try_from(&input[..]),
+            Err(Error::Utf8(_))
+        ));
+
+        let (response, replaced) = parse_lossy::<VersionsResponse>(input).unwrap();
+        assert_eq!(response.records[0].versions_name, "10.2.5.\u{fffd}53584");
+        assert_eq!(
+            Some(replaced.unwrap().valid_up_to()),
+            input.iter().position(|&byte| byte == 0xff)
+        );
+
+        let (response, replaced) =
+            parse_response::<VersionsResponse>(input, Limits::default(), true).unwrap();
+        assert_eq!(response.records[0].build_id, 53_584);
+        assert!(replaced.is_some());
+        assert!(parse_response::<VersionsResponse>(input, Limits::default(), false).is_err());
+    }
+
+    #[test]
+    fn test_comment_lines() {
+        for input in [
+            "Product!STRING:0\n## seqn = 7\nwow",
+            "Product!STRING:0\n##seqn=7\nwow",
+            "Product!STRING:0\n  ##  seqn   =  7 \nwow",
+            "Product!STRING:0\n## seqn = 7\n## region-hint\nwow",
+        ] {
+            let response = Response::try_from(input).unwrap();
+            assert_eq!(response.seqn, 7);
+            assert_eq!(response.iter_records().count(), 1);
+            assert!(response.metadata().is_empty());
+        }
+
+        let response =
+            Response::try_from("## format = 2\nProduct!STRING:0\n## seqn = 7\n##ttl=60\nwow")
+                .unwrap();
+        assert_eq!(
+            response.metadata(),
+            BTreeMap::from([
+                ("format".to_owned(), "2".to_owned()),
+                ("ttl".to_owned(), "60".to_owned()),
+            ])
+        );
+        assert!(matches!(
+            Response::try_from("Product!STRING:0\n## seqn = 7\n##seqn=8\nwow"),
+            Err(Error::MultipleSeqn)
+        ));
+    }
 }
@@ -0,0 +1,155 @@
+//! Model for the cdns response
+use crate::response::base::{
+    Error, Record as BaseRecord, Response as BaseResponse, Result, String0,
+};
+use std::collections::BTreeMap;
+
+/// A record in the cdns response
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    /// The region
+    pub name: String0,
+
+    /// The path of the product's files on each host, such as `tpr/wow`
+    pub path: String0,
+
+    /// The hosts, such as `level3.blizzard.com`
+    pub hosts: Vec<String0>,
+
+    /// The hosts' URLs, with parameters, such as `http://level3.blizzard.com/?maxhosts=4`
+    pub servers: Vec<String0>,
+
+    /// The path of the product config on each host, such as `tpr/configs/data`
+    pub config_path: String0,
+}
+
+/// Split a field listing values separated by spaces.
+fn split_list(list: &str) -> Vec<String0> {
+    list.split_whitespace().map(ToOwned::to_owned).collect()
+}
+
+impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
+    type Error = Error;
+
+    fn try_from(record: BaseRecord<'_, 'input>) -> Result<Self> {
+        let name = (&record
+            .get_field_by_header_name("Name")
+            .ok_or(Error::ExpectedField("Name"))?)
+            .try_into()?;
+        let path = (&record
+            .get_field_by_header_name("Path")
+            .ok_or(Error::ExpectedField("Path"))?)
+            .try_into()?;
+        let hosts: String0 = (&record
+            .get_field_by_header_name("Hosts")
+            .ok_or(Error::ExpectedField("Hosts"))?)
+            .try_into()?;
+        let servers: String0 = (&record
+            .get_field_by_header_name("Servers")
+            .ok_or(Error::ExpectedField("Servers"))?)
+            .try_into()?;
+        let config_path = (&record
+            .get_field_by_header_name("ConfigPath")
+            .ok_or(Error::ExpectedField("ConfigPath"))?)
+            .try_into()?;
+
+        Ok(Self {
+            name,
+            path,
+            hosts: split_list(&hosts),
+            servers: split_list(&servers),
+            config_path,
+        })
+    }
+}
+
+/// The cdns response
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    /// The sequence number
+    pub seqn: u32,
+
+    /// The response's `## key = value` comment lines other than seqn, by key, which the server
+    /// may add
+    pub metadata: BTreeMap<String, String>,
+
+    /// The records
+    pub records: Vec<Record>,
+}
+
+impl Response {
+    /// The record of a region, such as `us`
+    #[must_use]
+    pub fn region(&self, region: &str) -> Option<&Record> {
+        self.records.iter().find(|record| record.name == region)
+    }
+}
+
+impl<'input> TryFrom<BaseResponse<'input>> for Response {
+    type Error = Error;
+
+    fn try_from(response: BaseResponse<'input>) -> Result<Self> {
+        Ok(Self {
+            seqn: response.seqn,
+            metadata: response.metadata(),
+            records: response
+                .iter_records()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl<'input> TryFrom<&'input str> for Response {
+    type Error = Error;
+
+    fn try_from(input: &'input str) -> Result<Self> {
+        let response = BaseResponse::try_from(input)?;
+        Response::try_from(response)
+    }
+}
+
+impl<'input> TryFrom<&'input [u8]> for Response {
+    type Error = Error;
+
+    fn try_from(input: &'input [u8]) -> Result<Self> {
+        let response = BaseResponse::try_from(input)?;
+        Response::try_from(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cdns_response() {
+        let input = "Name!STRING:0|Path!STRING:0|Hosts!STRING:0|Servers!STRING:0|ConfigPath!STRING:0\n\
+        ## seqn = 2241282\n\
+        us|tpr/wow|level3.blizzard.com us.cdn.blizzard.com|http://level3.blizzard.com/?maxhosts=4 http://us.cdn.blizzard.com/?maxhosts=4|tpr/configs/data";
+
+        let cdns_response = Response::try_from(input).unwrap();
+
+        assert_eq!(
+            cdns_response,
+            Response {
+                metadata: BTreeMap::new(),
+                seqn: 2_241_282,
+                records: vec![Record {
+                    name: "us".to_owned(),
+                    path: "tpr/wow".to_owned(),
+                    hosts: vec![
+                        "level3.blizzard.com".to_owned(),
+                        "us.cdn.blizzard.com".to_owned()
+                    ],
+                    servers: vec![
+                        "http://level3.blizzard.com/?maxhosts=4".to_owned(),
+                        "http://us.cdn.blizzard.com/?maxhosts=4".to_owned()
+                    ],
+                    config_path: "tpr/configs/data".to_owned(),
+                }]
+            }
+        );
+        assert!(cdns_response.region("eu").is_none());
+    }
+}
@@ -0,0 +1,118 @@
+//! Model for a response of any columns, such as the versions of products with columns of their
+//! own
+use crate::response::base::{Error, Record as BaseRecord, Response as BaseResponse, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A record of a generic response, with every field as it's written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Record {
+    /// The fields, by the name of their column, such as `Region`
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Record {
+    /// The field of a column, such as `Region`, if the response has the column
+    #[must_use]
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.fields.get(column).map(String::as_str)
+    }
+}
+
+impl<'input> From<BaseRecord<'_, 'input>> for Record {
+    fn from(record: BaseRecord<'_, 'input>) -> Self {
+        Self {
+            fields: record
+                .fields()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// A response of any columns, keeping every field as text rather than converting the columns of
+/// a known response, for responses with columns the other models don't have (such as those of
+/// the Battle.net app's products)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Response {
+    /// The sequence number
+    pub seqn: u32,
+
+    /// The response's `## key = value` comment lines other than seqn, by key, which the server
+    /// may add
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+
+    /// The names of the columns, in the order of the header line
+    pub columns: Vec<String>,
+
+    /// The records
+    pub records: Vec<Record>,
+}
+
+impl Response {
+    /// The record of a region, such as `us`, if the response has a `Region` column
+    #[must_use]
+    pub fn region(&self, region: &str) -> Option<&Record> {
+        self.records
+            .iter()
+            .find(|record| record.get("Region") == Some(region))
+    }
+}
+
+impl TryFrom<BaseResponse<'_>> for Response {
+    type Error = Error;
+
+    fn try_from(response: BaseResponse) -> Result<Self> {
+        Ok(Self {
+            seqn: response.seqn,
+            metadata: response.metadata(),
+            columns: response.column_names().map(ToOwned::to_owned).collect(),
+            records: response.iter_records().map(Record::from).collect(),
+        })
+    }
+}
+
+impl<'input> TryFrom<&'input str> for Response {
+    type Error = Error;
+
+    fn try_from(input: &'input str) -> Result<Self> {
+        let response = BaseResponse::try_from(input)?;
+        Response::try_from(response)
+    }
+}
+
+impl<'input> TryFrom<&'input [u8]> for Response {
+    type Error = Error;
+
+    fn try_from(input: &'input [u8]) -> Result<Self> {
+        let response = BaseResponse::try_from(input)?;
+        Response::try_from(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let response = Response::try_from(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16|Installer!STRING:0\n\
+            ## seqn = 3016451\n\
+            us|3ef7a4e4fa5c5d6ad2ba8ec4b6c5ac1c|d3e4a5b6c7d8e9f0a1b2c3d4e5f6a7b8||9255|2.47.0.9255||BattleNet-Setup.exe\n\
+            eu|3ef7a4e4fa5c5d6ad2ba8ec4b6c5ac1c|d3e4a5b6c7d8e9f0a1b2c3d4e5f6a7b8||9255|2.47.0.9255||",
+        )
+        .unwrap();
+        assert_eq!(response.seqn, 3_016_451);
+        assert_eq!(response.columns.len(), 8);
+        assert_eq!(response.columns[7], "Installer");
+        let us = response.region("us").unwrap();
+        assert_eq!(us.get("VersionsName"), Some("2.47.0.9255"));
+        assert_eq!(us.get("Installer"), Some("BattleNet-Setup.exe"));
+        assert_eq!(response.region("eu").unwrap().get("Installer"), Some(""));
+        assert_eq!(us.get("Nope"), None);
+        assert!(response.region("kr").is_none());
+    }
+}
@@ -1,13 +1,19 @@
 //! Model for the summary response
-use crate::response::base::{
-    Dec4, Error, Record as BaseRecord, Response as BaseResponse, Result, String0,
+use crate::{
+    response::base::{
+        Dec4, Error, Record as BaseRecord, Response as BaseResponse, Result, String0,
+    },
+    suggest::{suggestions, PRODUCT_ALIASES},
 };
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 /// A record in the summary response
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Record {
-    /// The product name
-    pub product: String0,
+    /// The product name, shared by the consecutive records of a product's endpoints
+    pub product: Arc<str>,
 
     /// The sequence number
     pub seqn: Dec4,
@@ -16,14 +22,19 @@ pub struct Record {
     pub flags: String0,
 }
 
-impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
-    type Error = Error;
-
-    fn try_from(record: BaseRecord<'_, 'input>) -> Result<Self> {
-        let product = (&record
+impl Record {
+    /// Convert a record, sharing the product name of the previous record if it's the same
+    /// product, as the records of a product's endpoints (e.g., `cdn` and `bgdl`) follow each
+    /// other.
+    fn from_base(record: &BaseRecord<'_, '_>, previous: Option<&Record>) -> Result<Self> {
+        let product: &str = (&record
             .get_field_by_header_name("Product")
             .ok_or(Error::ExpectedField("Product"))?)
             .try_into()?;
+        let product = match previous {
+            Some(previous) if *previous.product == *product => Arc::clone(&previous.product),
+            _ => Arc::from(product),
+        };
         let seqn = (&record
             .get_field_by_header_name("Seqn")
             .ok_or(Error::ExpectedField("Seqn"))?)
@@ -41,26 +52,87 @@ impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
     }
 }
 
+impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
+    type Error = Error;
+
+    fn try_from(record: BaseRecord<'_, 'input>) -> Result<Self> {
+        Record::from_base(&record, None)
+    }
+}
+
 /// The summary response
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Response {
     /// The sequence number
     pub seqn: u32,
 
+    /// The response's `## key = value` comment lines other than seqn, by key, which the server
+    /// may add
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+
     /// The records
     pub records: Vec<Record>,
 }
 
-impl<'input> TryFrom<BaseResponse<'input>> for Response {
+impl Response {
+    /// Whether any record is for the given product
+    #[must_use]
+    pub fn contains_product(&self, product: &str) -> bool {
+        self.records
+            .iter()
+            .any(|record| &*record.product == product)
+    }
+
+    /// The seqn of a product's versions, from the product's record with no flags. (Records with
+    /// flags are for other endpoints, like `cdn` or `bgdl`.)
+    #[must_use]
+    pub fn versions_seqn(&self, product: &str) -> Option<Dec4> {
+        self.records
+            .iter()
+            .find(|record| &*record.product == product && record.flags.is_empty())
+            .map(|record| record.seqn)
+    }
+
+    /// Whether the summary flags a product as special: whether any of its records has flags other
+    /// than `cdn` or `bgdl`, which only mark the records of its other endpoints.
+    #[must_use]
+    pub fn is_flagged(&self, product: &str) -> bool {
+        self.records.iter().any(|record| {
+            &*record.product == product && !matches!(record.flags.as_str(), "" | "cdn" | "bgdl")
+        })
+    }
+
+    /// Products in this summary whose names, or common aliases such as `classic` for
+    /// `wow_classic`, are close to the given one, closest first. Useful for suggesting a
+    /// correction when the given product does not exist.
+    #[must_use]
+    pub fn suggest_products(&self, product: &str) -> Vec<&str> {
+        let codes = self.records.iter().map(|record| &*record.product);
+        let aliases = PRODUCT_ALIASES.iter().filter_map(|&(alias, aliased)| {
+            let record = self
+                .records
+                .iter()
+                .find(|record| &*record.product == aliased)?;
+            Some((alias, &*record.product))
+        });
+        suggestions(product, codes.map(|code| (code, code)).chain(aliases))
+    }
+}
+
+impl TryFrom<BaseResponse<'_>> for Response {
     type Error = Error;
 
     fn try_from(response: BaseResponse) -> Result<Self> {
+        let mut records: Vec<Record> = Vec::new();
+        for record in response.iter_records() {
+            let record = Record::from_base(&record, records.last())?;
+            records.push(record);
+        }
         Ok(Self {
             seqn: response.seqn,
-            records: response
-                .iter_records()
-                .map(TryInto::try_into)
-                .collect::<Result<_>>()?,
+            metadata: response.metadata(),
+            records,
         })
     }
 }
@@ -101,30 +173,67 @@ mod tests {
         assert_eq!(
             summary_response,
             Response {
+                metadata: BTreeMap::new(),
                 seqn: 2_119_172,
                 records: vec![
                     Record {
-                        product: "agent".to_owned(),
+                        product: "agent".into(),
                         seqn: 1_476_930,
                         flags: "cdn".to_owned()
                     },
                     Record {
-                        product: "agent".to_owned(),
+                        product: "agent".into(),
                         seqn: 2_118_018,
                         flags: String::new()
                     },
                     Record {
-                        product: "agent_beta".to_owned(),
+                        product: "agent_beta".into(),
                         seqn: 1_476_931,
                         flags: "cdn".to_owned()
                     },
                     Record {
-                        product: "agent_beta".to_owned(),
+                        product: "agent_beta".into(),
                         seqn: 2_110_722,
                         flags: String::new()
                     }
                 ]
             }
         );
+        let records = &summary_response.records;
+        assert!(Arc::ptr_eq(&records[0].product, &records[1].product));
+        assert!(!Arc::ptr_eq(&records[1].product, &records[2].product));
+    }
+
+    #[test]
+    fn test_suggest_products() {
+        let input = "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n\
+        ## seqn = 2119172\n\
+        wow|2118018|\n\
+        wow|1476930|cdn\n\
+        wow_classic|2110722|\n\
+        wow_classic_era|2110723|";
+
+        let summary_response = Response::try_from(input).unwrap();
+
+        assert!(summary_response.contains_product("wow_classic"));
+        assert!(!summary_response.is_flagged("wow"));
+        assert!(!summary_response.contains_product("wow_clasic"));
+        assert_eq!(summary_response.versions_seqn("wow"), Some(2_118_018));
+        assert_eq!(summary_response.versions_seqn("wow_clasic"), None);
+        assert_eq!(
+            summary_response.suggest_products("wow_clasic"),
+            vec!["wow_classic"]
+        );
+        assert_eq!(summary_response.suggest_products("wo"), vec!["wow"]);
+        // aliases of products in the summary, but not of those missing from it
+        assert_eq!(
+            summary_response.suggest_products("classic"),
+            vec!["wow_classic"]
+        );
+        assert_eq!(
+            summary_response.suggest_products("era"),
+            vec!["wow_classic_era"]
+        );
+        assert!(summary_response.suggest_products("ptr").is_empty());
     }
 }
@@ -1,7 +1,8 @@
 //! Model for the versions response
 use crate::response::base::{
-    Dec4, Error, Hex16, Record as BaseRecord, Response as BaseResponse, Result, String0,
+    Dec4, Error, Field, Hex16, Record as BaseRecord, Response as BaseResponse, Result, String0,
 };
+use std::{collections::BTreeMap, ops::BitOr};
 
 /// A record in the versions response
 #[derive(Debug, PartialEq)]
@@ -29,38 +30,80 @@ pub struct Record {
     pub product_config: Hex16,
 }
 
-impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
-    type Error = Error;
+/// A set of the columns of the versions response, to convert only some fields of each record.
+/// Combine columns with `|`, such as `Columns::REGION | Columns::VERSIONS_NAME`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Columns(u8);
 
-    fn try_from(record: BaseRecord<'_, 'input>) -> Result<Self> {
-        let region = (&record
-            .get_field_by_header_name("Region")
-            .ok_or(Error::ExpectedField("Region"))?)
-            .try_into()?;
-        let build_config = (&record
-            .get_field_by_header_name("BuildConfig")
-            .ok_or(Error::ExpectedField("BuildConfig"))?)
-            .try_into()?;
-        let cdn_config = (&record
-            .get_field_by_header_name("CDNConfig")
-            .ok_or(Error::ExpectedField("CDNConfig"))?)
-            .try_into()?;
-        let key_ring = (&record
-            .get_field_by_header_name("KeyRing")
-            .ok_or(Error::ExpectedField("KeyRing"))?)
-            .try_into()?;
-        let build_id = (&record
-            .get_field_by_header_name("BuildId")
-            .ok_or(Error::ExpectedField("BuildId"))?)
-            .try_into()?;
-        let versions_name = (&record
-            .get_field_by_header_name("VersionsName")
-            .ok_or(Error::ExpectedField("VersionsName"))?)
-            .try_into()?;
-        let product_config = (&record
-            .get_field_by_header_name("ProductConfig")
-            .ok_or(Error::ExpectedField("ProductConfig"))?)
-            .try_into()?;
+impl Columns {
+    /// The `Region` column, of [`Record::region`]
+    pub const REGION: Self = Self(1);
+
+    /// The `BuildConfig` column, of [`Record::build_config`]
+    pub const BUILD_CONFIG: Self = Self(1 << 1);
+
+    /// The `CDNConfig` column, of [`Record::cdn_config`]
+    pub const CDN_CONFIG: Self = Self(1 << 2);
+
+    /// The `KeyRing` column, of [`Record::key_ring`]
+    pub const KEY_RING: Self = Self(1 << 3);
+
+    /// The `BuildId` column, of [`Record::build_id`]
+    pub const BUILD_ID: Self = Self(1 << 4);
+
+    /// The `VersionsName` column, of [`Record::versions_name`]
+    pub const VERSIONS_NAME: Self = Self(1 << 5);
+
+    /// The `ProductConfig` column, of [`Record::product_config`]
+    pub const PRODUCT_CONFIG: Self = Self(1 << 6);
+
+    /// Every column
+    pub const ALL: Self = Self((1 << 7) - 1);
+
+    /// Whether every column of `other` is in this set
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Columns {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Convert a column's field of a record if it's among `columns`, or leave it empty (the type's
+/// default) without looking for it.
+fn column<'resp, 'input, T>(
+    record: &BaseRecord<'resp, 'input>,
+    columns: Columns,
+    column: Columns,
+    name: &'static str,
+) -> Result<T>
+where
+    T: Default + for<'field> TryFrom<&'field Field<'resp, 'input>, Error = Error>,
+{
+    if !columns.contains(column) {
+        return Ok(T::default());
+    }
+    (&record
+        .get_field_by_header_name(name)
+        .ok_or(Error::ExpectedField(name))?)
+        .try_into()
+}
+
+impl Record {
+    fn from_columns(record: &BaseRecord<'_, '_>, columns: Columns) -> Result<Self> {
+        let region = column(record, columns, Columns::REGION, "Region")?;
+        let build_config = column(record, columns, Columns::BUILD_CONFIG, "BuildConfig")?;
+        let cdn_config = column(record, columns, Columns::CDN_CONFIG, "CDNConfig")?;
+        let key_ring = column(record, columns, Columns::KEY_RING, "KeyRing")?;
+        let build_id = column(record, columns, Columns::BUILD_ID, "BuildId")?;
+        let versions_name = column(record, columns, Columns::VERSIONS_NAME, "VersionsName")?;
+        let product_config = column(record, columns, Columns::PRODUCT_CONFIG, "ProductConfig")?;
 
         Ok(Self {
             region,
@@ -74,22 +117,64 @@ impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
     }
 }
 
+impl<'input> TryFrom<BaseRecord<'_, 'input>> for Record {
+    type Error = Error;
+
+    fn try_from(record: BaseRecord<'_, 'input>) -> Result<Self> {
+        Record::from_columns(&record, Columns::ALL)
+    }
+}
+
 /// The versions response
 #[derive(Debug, PartialEq)]
 pub struct Response {
     /// The sequence number
     pub seqn: u32,
 
+    /// The response's `## key = value` comment lines other than seqn, by key, which the server
+    /// may add
+    pub metadata: BTreeMap<String, String>,
+
     /// The records
     pub records: Vec<Record>,
 }
 
+impl Response {
+    /// The record of a region, such as `us`
+    #[must_use]
+    pub fn region(&self, region: &str) -> Option<&Record> {
+        self.records.iter().find(|record| record.region == region)
+    }
+
+    /// Parse a response, converting only the fields of `columns` and skipping the rest, which is
+    /// faster for bulk processing that doesn't need every field (such as the hex decoding of
+    /// configs). Fields of other columns are left empty: zeroed hashes, a build ID of 0, an empty
+    /// versions name and no key ring.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the response cannot be parsed, or lacks or has a malformed field of one
+    /// of `columns`.
+    pub fn parse_columns(input: &[u8], columns: Columns) -> Result<Self> {
+        let response = BaseResponse::try_from(input)?;
+        Ok(Self {
+            seqn: response.seqn,
+            metadata: response.metadata(),
+            records: response
+                .iter_records()
+                .map(|record| Record::from_columns(&record, columns))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
 impl<'input> TryFrom<BaseResponse<'input>> for Response {
     type Error = Error;
 
     fn try_from(response: BaseResponse<'input>) -> Result<Self> {
         Ok(Self {
             seqn: response.seqn,
+            metadata: response.metadata(),
             records: response
                 .iter_records()
                 .map(TryInto::try_into)
@@ -132,6 +217,7 @@ mod tests {
         assert_eq!(
             versions_response,
             Response {
+                metadata: BTreeMap::new(),
                 seqn: 2_118_468,
                 records: vec![
                     Record {
@@ -174,4 +260,33 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_parse_columns() {
+        let input = "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+        ## seqn = 2118468\n\
+        us|47e9e06f8371afb141e22614a912acc8|not hex||53584|10.2.5.53584|53020d32e1a25648c8e1eafd5771935f";
+
+        let response =
+            Response::parse_columns(input.as_bytes(), Columns::REGION | Columns::VERSIONS_NAME)
+                .unwrap();
+        assert_eq!(
+            response.records,
+            [Record {
+                region: "us".to_owned(),
+                build_config: [0; 16],
+                cdn_config: [0; 16],
+                key_ring: None,
+                build_id: 0,
+                versions_name: "10.2.5.53584".to_owned(),
+                product_config: [0; 16],
+            }]
+        );
+
+        assert!(Response::parse_columns(input.as_bytes(), Columns::ALL).is_err());
+        assert!(matches!(
+            Response::parse_columns(b"Region!STRING:0\n## seqn = 1\nus", Columns::BUILD_ID),
+            Err(Error::ExpectedField("BuildId"))
+        ));
+    }
 }
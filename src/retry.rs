@@ -0,0 +1,85 @@
+//! Policies for retrying failed requests to the version server
+use crate::api::Error;
+use std::{fmt::Debug, time::Duration};
+
+/// Decides whether and when a client retries a failed request.
+///
+/// Implement this to integrate other policies, such as a circuit breaker shared by many clients.
+pub trait RetryPolicy: Debug + Send + Sync {
+    /// How long to wait before retrying a request that has failed `attempt` times (starting at
+    /// 1) with `error`, or `None` to give up and return the error.
+    fn retry_after(&self, attempt: u32, error: &Error) -> Option<Duration>;
+}
+
+/// Never retry, returning the first error. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry_after(&self, _attempt: u32, _error: &Error) -> Option<Duration> {
+        None
+    }
+}
+
+/// Retry I/O errors, such as refused connections, waiting twice as long after each failure.
+/// Other errors, like unparseable responses, are returned at once.
+#[derive(Debug, Clone, Copy)]
+pub struct Exponential {
+    /// The most attempts to make in all, including the first
+    pub max_attempts: u32,
+
+    /// How long to wait after the first failure
+    pub initial_delay: Duration,
+
+    /// The longest to wait between attempts
+    pub max_delay: Duration,
+}
+
+impl Default for Exponential {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy for Exponential {
+    fn retry_after(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_attempts || !matches!(error, Error::Io { .. }) {
+            return None;
+        }
+        let factor = 2_u32.saturating_pow(attempt - 1);
+        Some(
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential() {
+        let policy = Exponential {
+            max_attempts: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(3),
+        };
+        let io = Error::Io {
+            context: crate::api::RequestContext::new(&crate::api::Endpoint::Summary),
+            source: std::io::ErrorKind::ConnectionRefused.into(),
+        };
+
+        assert_eq!(policy.retry_after(1, &io), Some(Duration::from_secs(1)));
+        assert_eq!(policy.retry_after(2, &io), Some(Duration::from_secs(2)));
+        assert_eq!(policy.retry_after(3, &io), Some(Duration::from_secs(3)));
+        assert_eq!(policy.retry_after(4, &io), None);
+        assert_eq!(policy.retry_after(1, &Error::DeadlineExceeded), None);
+        assert_eq!(NoRetry.retry_after(1, &io), None);
+    }
+}
@@ -0,0 +1,270 @@
+//! Subscriptions to changes of products, polled by a client
+use crate::{
+    api::Result,
+    client::Client,
+    notify::ChangeEvent,
+    output::{Product, VersionsFetch},
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Something that happened to the products of a subscription
+#[derive(Debug, Clone)]
+pub enum SubscriptionEvent {
+    /// Products have new builds in some regions
+    NewBuilds(ChangeEvent),
+
+    /// A product's regions were all on the same build, but now some are on another, such as when
+    /// a build is being rolled out region by region. Holds the product as it is now.
+    RegionDiverged(Product),
+
+    /// A product appeared in the summary
+    ProductAdded(String),
+
+    /// A product disappeared from the summary
+    ProductRemoved(String),
+}
+
+/// The state of a subscription between polls
+pub(crate) struct Subscription {
+    client: Client,
+
+    /// The products to watch, or if empty, every product in the summary
+    products: Vec<String>,
+
+    period: Duration,
+    interval: Option<Interval>,
+
+    /// The seqn of each product's versions when they were last fetched
+    seqns: HashMap<String, u32>,
+
+    /// The versions of each product when they were last fetched
+    known: HashMap<String, Product>,
+
+    /// Whether the first poll, which only learns the products' starting state, has happened
+    started: bool,
+
+    /// Events and errors of the last poll that haven't been yielded yet
+    pending: VecDeque<Result<SubscriptionEvent>>,
+}
+
+impl Subscription {
+    pub(crate) fn new(client: Client, products: Vec<String>, period: Duration) -> Self {
+        Self {
+            client,
+            products,
+            period,
+            interval: None,
+            seqns: HashMap::new(),
+            known: HashMap::new(),
+            started: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Wait for the next event or error, polling as often as it takes.
+    pub(crate) async fn next(&mut self) -> Result<SubscriptionEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+            let period = self.period;
+            let interval = self.interval.get_or_insert_with(|| {
+                let mut interval = tokio::time::interval(period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            });
+            interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    /// Fetch the summary, then the versions of the watched products whose seqns have changed,
+    /// queueing the events and errors.
+    async fn poll(&mut self) {
+        let summary = match self.client.get_summary().await {
+            Ok(summary) => summary,
+            Err(e) => {
+                self.pending.push_back(Err(e));
+                return;
+            }
+        };
+        let present: HashMap<String, u32> = if self.products.is_empty() {
+            summary
+                .records
+                .iter()
+                .filter(|record| record.flags.is_empty())
+                .map(|record| (record.product.to_string(), record.seqn))
+                .collect()
+        } else {
+            self.products
+                .iter()
+                .filter_map(|product| {
+                    let seqn = summary.versions_seqn(product)?;
+                    Some((product.clone(), seqn))
+                })
+                .collect()
+        };
+
+        let removed: Vec<String> = self
+            .seqns
+            .keys()
+            .filter(|product| !present.contains_key(*product))
+            .cloned()
+            .collect();
+        for product in removed {
+            self.seqns.remove(&product);
+            self.known.remove(&product);
+            self.pending
+                .push_back(Ok(SubscriptionEvent::ProductRemoved(product)));
+        }
+
+        let mut old = VersionsFetch::new();
+        let mut new = VersionsFetch::new();
+        let mut sorted: Vec<_> = present.into_iter().collect();
+        sorted.sort();
+        for (product, seqn) in sorted {
+            let previous_seqn = self.seqns.get(&product).copied();
+            if previous_seqn == Some(seqn) {
+                continue;
+            }
+            let response = match self.client.get_versions(&product).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // keep the old seqn, so the product is fetched again next time
+                    self.pending.push_back(Err(e));
+                    continue;
+                }
+            };
+            let fetched = match Product::try_from((product.as_str(), &response)) {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    self.pending.push_back(Err(e.into()));
+                    continue;
+                }
+            };
+            self.seqns.insert(product.clone(), seqn);
+
+            if previous_seqn.is_none() && self.started {
+                self.pending
+                    .push_back(Ok(SubscriptionEvent::ProductAdded(product.clone())));
+            }
+            if let Some(previous) = self.known.remove(&product) {
+                if distinct_builds(&previous) == 1 && distinct_builds(&fetched) > 1 {
+                    self.pending
+                        .push_back(Ok(SubscriptionEvent::RegionDiverged(fetched.clone())));
+                }
+                old.add_product(previous);
+                new.add_product(fetched.clone());
+            }
+            self.known.insert(product, fetched);
+        }
+        self.started = true;
+
+        if let Some(event) = ChangeEvent::detect(&old, &new) {
+            self.pending
+                .push_back(Ok(SubscriptionEvent::NewBuilds(event)));
+        }
+    }
+}
+
+/// How many different builds a product's regions are on
+fn distinct_builds(product: &Product) -> usize {
+    product
+        .versions()
+        .iter()
+        .map(crate::output::Version::build)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::Endpoint, test_fixtures::TestVersions};
+    use std::{fmt::Write, sync::Arc};
+
+    fn summary(products: &[(&str, u32)]) -> Vec<u8> {
+        let mut raw = "Product!STRING:0|Seqn!DEC:4|Flags!STRING:0\n## seqn = 1".to_owned();
+        for (product, seqn) in products {
+            // writing to a String can't fail
+            let _ = write!(raw, "\n{product}|{seqn}|");
+        }
+        raw.into_bytes()
+    }
+
+    fn versions(builds: &[(&str, &str)]) -> Vec<u8> {
+        builds
+            .iter()
+            .fold(TestVersions::new(), |versions, (region, build)| {
+                versions.region(region, &format!("10.2.6.{build}"))
+            })
+            .psv()
+            .into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        let client = Client::new().with_memory_cache(Duration::from_mins(1));
+        let cache = client.memory_cache.clone().unwrap();
+        let mut subscription = Subscription::new(client, Vec::new(), Duration::from_mins(1));
+
+        cache
+            .insert(Endpoint::Summary, Arc::new(summary(&[("wow", 1)])))
+            .await;
+        cache
+            .insert(
+                Endpoint::Versions("wow".to_owned()),
+                Arc::new(versions(&[("us", "53584"), ("eu", "53584")])),
+            )
+            .await;
+        subscription.poll().await;
+        // the first poll only learns where things stand
+        assert!(subscription.pending.is_empty());
+
+        cache
+            .insert(
+                Endpoint::Summary,
+                Arc::new(summary(&[("wow", 2), ("wowt", 1)])),
+            )
+            .await;
+        cache
+            .insert(
+                Endpoint::Versions("wow".to_owned()),
+                Arc::new(versions(&[("us", "53840"), ("eu", "53584")])),
+            )
+            .await;
+        cache
+            .insert(
+                Endpoint::Versions("wowt".to_owned()),
+                Arc::new(versions(&[("us", "53877")])),
+            )
+            .await;
+        subscription.poll().await;
+        let events: Vec<_> = subscription.pending.drain(..).map(Result::unwrap).collect();
+        assert!(
+            matches!(&events[0], SubscriptionEvent::RegionDiverged(product) if product.name() == "wow")
+        );
+        assert!(
+            matches!(&events[1], SubscriptionEvent::ProductAdded(product) if product == "wowt")
+        );
+        let SubscriptionEvent::NewBuilds(event) = &events[2] else {
+            panic!("expected new builds, got {:?}", events[2]);
+        };
+        assert_eq!(event.changes.len(), 1);
+        assert_eq!(event.changes[0].build, "53840");
+
+        cache
+            .insert(Endpoint::Summary, Arc::new(summary(&[("wowt", 1)])))
+            .await;
+        subscription.poll().await;
+        assert!(matches!(
+            subscription.pending.pop_front(),
+            Some(Ok(SubscriptionEvent::ProductRemoved(product))) if product == "wow"
+        ));
+        assert!(subscription.pending.is_empty());
+    }
+}
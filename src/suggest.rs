@@ -0,0 +1,114 @@
+//! "Did you mean" suggestions for misspelled names
+
+/// The most suggestions to offer for a single name
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Other names products are commonly called by, and their product codes, so a name like `classic`
+/// can be corrected to `wow_classic`
+pub(crate) const PRODUCT_ALIASES: &[(&str, &str)] = &[
+    ("retail", "wow"),
+    ("ptr", "wowt"),
+    ("xptr", "wowxptr"),
+    ("beta", "wow_beta"),
+    ("classic", "wow_classic"),
+    ("classic_ptr", "wow_classic_ptr"),
+    ("classic_beta", "wow_classic_beta"),
+    ("era", "wow_classic_era"),
+    ("classic_era", "wow_classic_era"),
+    ("era_ptr", "wow_classic_era_ptr"),
+    ("classic_era_ptr", "wow_classic_era_ptr"),
+];
+
+/// The Levenshtein distance between two strings, counted in chars.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+
+    // only keep the previous row of the distance matrix around
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution_cost = usize::from(a_char != *b_char);
+            current[j + 1] = (previous[j] + substitution_cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Candidates whose names are close enough to `name` to be a likely misspelling of it, closest
+/// first. Each candidate is a name to compare and what to suggest for it, which differ for
+/// aliases.
+pub(crate) fn suggestions<'a, 'n>(
+    name: &str,
+    candidates: impl IntoIterator<Item = (&'n str, &'a str)>,
+) -> Vec<&'a str> {
+    // allow roughly one typo per three chars, but always at least one
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut scored = candidates
+        .into_iter()
+        .filter(|(_, candidate)| *candidate != name)
+        .map(|(compared, candidate)| (edit_distance(name, compared), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    scored.sort_unstable();
+
+    // a candidate may be close by several names, so keep only its closest
+    let mut suggestions = Vec::new();
+    for (_, candidate) in scored {
+        if !suggestions.contains(&candidate) {
+            suggestions.push(candidate);
+        }
+    }
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("wow", ""), 3);
+        assert_eq!(edit_distance("wow", "wow"), 0);
+        assert_eq!(edit_distance("wow_clasic", "wow_classic"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let candidates = [
+            "wow",
+            "wow_beta",
+            "wow_classic",
+            "wow_classic_era",
+            "wow_classic_ptr",
+            "d3",
+        ]
+        .map(|candidate| (candidate, candidate));
+
+        assert_eq!(suggestions("wow_clasic", candidates), vec!["wow_classic"]);
+        assert_eq!(
+            suggestions("wow_classic_pt", candidates),
+            vec!["wow_classic_ptr", "wow_classic", "wow_classic_era"]
+        );
+        assert!(suggestions("hearthstone", candidates).is_empty());
+        assert!(suggestions("wow", candidates).is_empty());
+
+        // an alias suggests its product, once, however many of its names are close
+        let aliased = candidates
+            .into_iter()
+            .chain([("classic", "wow_classic"), ("wow_clasik", "wow_classic")]);
+        assert_eq!(suggestions("classic", aliased.clone()), vec!["wow_classic"]);
+        assert_eq!(suggestions("clasic", aliased.clone()), vec!["wow_classic"]);
+        assert_eq!(suggestions("wow_clasic", aliased), vec!["wow_classic"]);
+    }
+}
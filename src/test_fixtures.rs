@@ -0,0 +1,82 @@
+// Versions responses for tests, shared by the library's tests and the CLI's, so each includes this
+// file into a module of its own that has the prelude in scope.
+
+/// A versions response for a test, built up a region at a time, each on a build written as a
+/// versions name like `10.2.5.53584`
+#[derive(Debug, Clone)]
+pub(crate) struct TestVersions {
+    seqn: u32,
+    records: Vec<String>,
+}
+
+impl TestVersions {
+    /// A response with seqn 1 and no regions
+    pub(crate) fn new() -> Self {
+        Self {
+            seqn: 1,
+            records: Vec::new(),
+        }
+    }
+
+    /// The response with another seqn
+    pub(crate) fn seqn(mut self, seqn: u32) -> Self {
+        self.seqn = seqn;
+        self
+    }
+
+    /// The response with a region on a build
+    pub(crate) fn region(self, region: &str, versions_name: &str) -> Self {
+        self.region_with_build_config(region, versions_name, "47e9e06f8371afb141e22614a912acc8")
+    }
+
+    /// The response with each of some regions on a build
+    pub(crate) fn regions(self, regions: &[(&str, &str)]) -> Self {
+        regions
+            .iter()
+            .fold(self, |versions, (region, versions_name)| {
+                versions.region(region, versions_name)
+            })
+    }
+
+    /// The response with a region on a build with a build config of its own, such as one a
+    /// server hasn't caught up with
+    pub(crate) fn region_with_build_config(
+        mut self,
+        region: &str,
+        versions_name: &str,
+        build_config: &str,
+    ) -> Self {
+        // the build id is the last part of the versions name
+        let build_id = versions_name.rsplit('.').next().unwrap_or_default();
+        self.records.push(format!(
+            "{region}|{build_config}|74093d42ce367c7a67f2831dbf64088d||{build_id}|{versions_name}|53020d32e1a25648c8e1eafd5771935f"
+        ));
+        self
+    }
+
+    /// The response as the server would send it
+    pub(crate) fn psv(&self) -> String {
+        let mut psv = format!(
+            "Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16\n\
+            ## seqn = {}",
+            self.seqn
+        );
+        for record in &self.records {
+            psv.push('\n');
+            psv.push_str(record);
+        }
+        psv
+    }
+
+    /// The response, parsed
+    pub(crate) fn response(&self) -> VersionsResponse {
+        VersionsResponse::try_from(self.psv().as_str()).unwrap()
+    }
+
+    /// A fetch of just this response, as the versions of a product
+    pub(crate) fn fetch(&self, product: &str) -> VersionsFetch {
+        let mut fetch = VersionsFetch::new();
+        fetch.add_product(Product::try_from((product, &self.response())).unwrap());
+        fetch
+    }
+}
@@ -0,0 +1,93 @@
+//! The timestamps of the output models, from chrono or the time crate, whichever is enabled
+//! (chrono, if both are)
+use serde::Serializer;
+
+/// A point in time in UTC: a `chrono::DateTime<Utc>` with the `chrono` feature, or else a
+/// `time::OffsetDateTime` with the `time` feature
+#[cfg(feature = "chrono")]
+pub type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// A point in time in UTC: a `chrono::DateTime<Utc>` with the `chrono` feature, or else a
+/// `time::OffsetDateTime` with the `time` feature
+#[cfg(not(feature = "chrono"))]
+pub type Timestamp = time::OffsetDateTime;
+
+/// The current time
+#[cfg(feature = "chrono")]
+pub(crate) fn now() -> Timestamp {
+    chrono::Utc::now()
+}
+
+/// The current time
+#[cfg(not(feature = "chrono"))]
+pub(crate) fn now() -> Timestamp {
+    time::OffsetDateTime::now_utc()
+}
+
+/// The whole seconds from `earlier` to `later`, or zero if `earlier` is later.
+#[cfg(feature = "chrono")]
+pub(crate) fn seconds_between(earlier: Timestamp, later: Timestamp) -> u64 {
+    (later - earlier)
+        .to_std()
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The whole seconds from `earlier` to `later`, or zero if `earlier` is later.
+#[cfg(not(feature = "chrono"))]
+pub(crate) fn seconds_between(earlier: Timestamp, later: Timestamp) -> u64 {
+    u64::try_from((later - earlier).whole_seconds()).unwrap_or(0)
+}
+
+/// Serialize a timestamp as RFC 3339, such as `2024-03-19T15:02:00.131Z`.
+#[cfg(feature = "chrono")]
+pub(crate) fn serialize<S: Serializer>(
+    timestamp: &Timestamp,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(timestamp, serializer)
+}
+
+/// Serialize a timestamp as RFC 3339, such as `2024-03-19T15:02:00.131Z`, as chrono does.
+#[cfg(not(feature = "chrono"))]
+pub(crate) fn serialize<S: Serializer>(
+    timestamp: &Timestamp,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let formatted = timestamp
+        .format(&time::format_description::well_known::Rfc3339)
+        .map_err(serde::ser::Error::custom)?;
+    serializer.serialize_str(&formatted)
+}
+
+/// Serialize a timestamp, if there is one, as RFC 3339.
+#[allow(clippy::ref_option)]
+pub(crate) fn serialize_option<S: Serializer>(
+    timestamp: &Option<Timestamp>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match timestamp {
+        Some(timestamp) => serialize(timestamp, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        #[cfg(feature = "chrono")]
+        let timestamp = chrono::DateTime::from_timestamp_millis(1_710_860_520_131).unwrap();
+        #[cfg(not(feature = "chrono"))]
+        let timestamp =
+            time::OffsetDateTime::from_unix_timestamp_nanos(1_710_860_520_131_000_000).unwrap();
+
+        let mut json = Vec::new();
+        serialize(&timestamp, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        assert_eq!(json, br#""2024-03-19T15:02:00.131Z""#);
+        assert_eq!(seconds_between(timestamp, timestamp), 0);
+        assert!(seconds_between(timestamp, now()) > 0);
+        assert_eq!(seconds_between(now(), timestamp), 0);
+    }
+}
@@ -0,0 +1,341 @@
+//! Transports carrying a client's requests to the version server
+#[cfg(feature = "http")]
+use crate::api::Protocol;
+#[cfg(feature = "tcp")]
+use crate::{api::SOCKET_ADDR, instrument, response::base::DEFAULT_MAX_BYTES};
+use crate::{
+    api::{Endpoint, Error, RequestContext, Result},
+    response::base::Limits,
+};
+#[cfg(feature = "tcp")]
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::fmt::Debug;
+#[cfg(feature = "tcp")]
+use std::io;
+
+#[cfg(all(feature = "tcp", not(any(feature = "tokio", feature = "async-io"))))]
+compile_error!("the `tcp` feature needs a runtime to run on: enable `tokio` or `async-io`");
+
+/// Check that a response is no longer than `max_bytes`, with the request's context in the error
+/// if it is.
+fn check_length(
+    raw: &[u8],
+    max_bytes: usize,
+    context: impl FnOnce() -> RequestContext,
+) -> Result<()> {
+    Limits::default()
+        .with_max_bytes(max_bytes)
+        .check_bytes(raw.len())
+        .map_err(|source| Error::Response {
+            context: context(),
+            source,
+        })
+}
+
+/// A way of getting the raw responses of endpoints. Implement this to fetch responses some other
+/// way, such as through a proxy with authentication or from recorded fixtures.
+///
+/// Dropping a `get` future must cancel the request, releasing anything it holds (e.g., closing its
+/// connection).
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+pub trait Transport: Debug + Send + Sync {
+    /// Get the raw, unparsed response of an endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    async fn get(&self, endpoint: &Endpoint) -> Result<Vec<u8>>;
+
+    /// Get the raw response of an endpoint, reading it into `buffer` (which is empty, but may have
+    /// capacity left from an earlier response) if the transport can. Clients call this with
+    /// buffers they reuse, to save allocating one per request. By default, the buffer is unused
+    /// and this is `get`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    async fn get_into(&self, endpoint: &Endpoint, buffer: Vec<u8>) -> Result<Vec<u8>> {
+        drop(buffer);
+        self.get(endpoint).await
+    }
+
+    /// Get the raw response of an endpoint into `buffer`, as `get_into` does, failing with a
+    /// `ResponseError::TooLarge` if it's longer than `max_bytes`. Transports that can should stop
+    /// reading there. By default, the whole response is read with `get_into`, then checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response is too large.
+    async fn get_limited(
+        &self,
+        endpoint: &Endpoint,
+        buffer: Vec<u8>,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>> {
+        let raw = self.get_into(endpoint, buffer).await?;
+        check_length(&raw, max_bytes, || self.context(endpoint))?;
+        Ok(raw)
+    }
+
+    /// Where a request for the endpoint goes, for context in errors
+    fn context(&self, endpoint: &Endpoint) -> RequestContext;
+}
+
+/// The version server's own TCP protocol, with a connection per request. The default, which
+/// connects to the US server.
+///
+/// Connections are made with Tokio, or with the `async-io` feature and without the `tokio`
+/// feature, with async-io, so the transport runs under smol or async-std.
+#[cfg(feature = "tcp")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpTransport {
+    host: String,
+}
+
+#[cfg(feature = "tcp")]
+impl TcpTransport {
+    /// A transport to the version server of a region, such as `eu`, at
+    /// `{region}.version.battle.net:1119`. Each region's server answers for every product, but
+    /// may lag the others while a change propagates.
+    #[must_use]
+    pub fn region(region: &str) -> Self {
+        Self::with_host(format!("{region}.version.battle.net:1119"))
+    }
+
+    /// A transport to a server at a host and port, such as `localhost:1119`
+    #[must_use]
+    pub fn with_host(host: impl Into<String>) -> Self {
+        Self { host: host.into() }
+    }
+
+    /// The host and port connected to
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Default for TcpTransport {
+    fn default() -> Self {
+        Self::with_host(SOCKET_ADDR)
+    }
+}
+
+#[cfg(feature = "tcp")]
+#[async_trait::async_trait]
+impl Transport for TcpTransport {
+    async fn get(&self, endpoint: &Endpoint) -> Result<Vec<u8>> {
+        tcp_send_and_recv(&self.host, endpoint, Vec::new(), DEFAULT_MAX_BYTES).await
+    }
+
+    async fn get_into(&self, endpoint: &Endpoint, buffer: Vec<u8>) -> Result<Vec<u8>> {
+        tcp_send_and_recv(&self.host, endpoint, buffer, DEFAULT_MAX_BYTES).await
+    }
+
+    async fn get_limited(
+        &self,
+        endpoint: &Endpoint,
+        buffer: Vec<u8>,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>> {
+        tcp_send_and_recv(&self.host, endpoint, buffer, max_bytes).await
+    }
+
+    fn context(&self, endpoint: &Endpoint) -> RequestContext {
+        RequestContext {
+            host: self.host.clone(),
+            ..RequestContext::new(endpoint)
+        }
+    }
+}
+
+/// Send a request to a host and read the whole response into `buffer`, unless it's longer than
+/// `max_bytes`. Dropping the future at any point drops the stream, closing the connection.
+#[cfg(feature = "tcp")]
+pub(crate) async fn tcp_send_and_recv(
+    host: &str,
+    endpoint: &Endpoint,
+    buffer: Vec<u8>,
+    max_bytes: usize,
+) -> Result<Vec<u8>> {
+    let start = std::time::Instant::now();
+    let result = send_and_recv(host, endpoint, buffer, max_bytes).await;
+    instrument::record_request(endpoint, start.elapsed(), result.is_ok());
+    result
+}
+
+#[cfg(feature = "tcp")]
+async fn send_and_recv(
+    host: &str,
+    endpoint: &Endpoint,
+    buffer: Vec<u8>,
+    max_bytes: usize,
+) -> Result<Vec<u8>> {
+    let context = || RequestContext {
+        host: host.to_owned(),
+        ..RequestContext::new(endpoint)
+    };
+    let exchange = async { exchange(connect(host).await?, endpoint, buffer, max_bytes).await };
+    let raw = exchange.await.map_err(|source| Error::Io {
+        context: context(),
+        source,
+    })?;
+    check_length(&raw, max_bytes, context)?;
+    Ok(raw)
+}
+
+/// Connect to a server with Tokio, whose streams are adapted to `futures-io`.
+#[cfg(feature = "tokio")]
+async fn connect(host: &str) -> io::Result<impl AsyncRead + AsyncWrite + Unpin> {
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    Ok(tokio::net::TcpStream::connect(host).await?.compat())
+}
+
+/// Connect to a server with async-io, as used by smol and async-std.
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+async fn connect(host: &str) -> io::Result<impl AsyncRead + AsyncWrite + Unpin> {
+    async_net::TcpStream::connect(host).await
+}
+
+/// Send a request over a connected stream, on any runtime, and read the whole response into
+/// `buffer`, stopping a byte past `max_bytes` so a response too long can be told apart.
+#[cfg(feature = "tcp")]
+async fn exchange<S>(
+    mut stream: S,
+    endpoint: &Endpoint,
+    mut buffer: Vec<u8>,
+    max_bytes: usize,
+) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = endpoint.path().into_bytes();
+    request.extend(b"\r\n");
+    stream.write_all(&request).await?;
+
+    buffer.clear();
+    let limit = u64::try_from(max_bytes)
+        .unwrap_or(u64::MAX)
+        .saturating_add(1);
+    stream.take(limit).read_to_end(&mut buffer).await?;
+
+    Ok(buffer)
+}
+
+/// HTTP requests to a proxy of the version server, which answers `GET {base_url}/{path}` (e.g.,
+/// `GET {base_url}/v2/products/wow/versions`) with the endpoint's raw response, as `wownow serve`
+/// does. This is the transport for WebAssembly, where it uses the browser's `fetch`, so the proxy
+/// must allow the page's origin with CORS.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+#[cfg(feature = "http")]
+impl HttpTransport {
+    /// A transport to the proxy at `base_url`, such as `http://localhost:8080`
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+        }
+    }
+
+    async fn fetch(&self, url: &str) -> reqwest::Result<Vec<u8>> {
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(feature = "http")]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+impl Transport for HttpTransport {
+    async fn get(&self, endpoint: &Endpoint) -> Result<Vec<u8>> {
+        let url = format!("{}/{}", self.base_url, endpoint.path());
+        self.fetch(&url).await.map_err(|e| Error::Io {
+            context: self.context(endpoint),
+            source: std::io::Error::other(e),
+        })
+    }
+
+    fn context(&self, endpoint: &Endpoint) -> RequestContext {
+        RequestContext {
+            path: endpoint.path(),
+            host: self.base_url.clone(),
+            protocol: Protocol::Http,
+            attempt: 1,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tcp"))]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    #[tokio::test]
+    async fn test_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 12];
+            stream.read_exact(&mut request).await.unwrap();
+            stream.write_all(b"Product!STRING:0").await.unwrap();
+            request
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap().compat();
+        let response = exchange(stream, &Endpoint::Summary, b"stale".to_vec(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(response, b"Product!STRING:0");
+        assert_eq!(&server.await.unwrap(), b"v2/summary\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_get_limited() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let transport = TcpTransport::with_host(listener.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 12];
+                stream.read_exact(&mut request).await.unwrap();
+                stream.write_all(b"Product!STRING:0").await.unwrap();
+            }
+        });
+
+        let response = transport
+            .get_limited(&Endpoint::Summary, Vec::new(), 16)
+            .await
+            .unwrap();
+        assert_eq!(response, b"Product!STRING:0");
+
+        let error = transport
+            .get_limited(&Endpoint::Summary, Vec::new(), 15)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Response {
+                source: crate::response::base::Error::TooLarge {
+                    what: "bytes",
+                    limit: 15
+                },
+                ..
+            }
+        ));
+    }
+}